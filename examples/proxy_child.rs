@@ -0,0 +1,128 @@
+//! A child process for `ProxyAdapter`, serving an adapter with a single light over its standard
+//! input and output. This is the reference child used by `tests/test_manager.rs`.
+//!
+//! With argument `--linger`, the process keeps running once the host has stopped it, as a
+//! misbehaving child would.
+
+extern crate foxbox_taxonomy;
+
+use foxbox_taxonomy::adapter::{ Adapter, AdapterManagerHandle, AdapterWatchGuard, ResultMap, WatchResult, WatchTarget };
+use foxbox_taxonomy::api::{ Error, InternalError, User };
+use foxbox_taxonomy::proxy_child::{ serve, HostHandle };
+use foxbox_taxonomy::services::*;
+use foxbox_taxonomy::values::{ OnOff, Value };
+
+use std::collections::{ HashMap, HashSet };
+use std::env;
+use std::io;
+use std::sync::{ Arc, Mutex };
+use std::thread;
+use std::time::Duration;
+
+static VERSION: [u32; 4] = [0, 1, 0, 0];
+
+struct LightAdapter {
+    id: Id<AdapterId>,
+    getter: Id<Getter>,
+    setter: Id<Setter>,
+    state: Mutex<OnOff>,
+}
+
+struct LightWatchGuard;
+impl AdapterWatchGuard for LightWatchGuard {}
+
+impl Adapter for LightAdapter {
+    fn id(&self) -> Id<AdapterId> {
+        self.id.clone()
+    }
+
+    fn name(&self) -> &str {
+        "Reference proxied adapter"
+    }
+
+    fn vendor(&self) -> &str {
+        "team@link.mozilla.org"
+    }
+
+    fn version(&self) -> &[u32;4] {
+        &VERSION
+    }
+
+    fn fetch_values(&self, set: Vec<Id<Getter>>, _: User) -> ResultMap<Id<Getter>, Option<Value>, Error> {
+        set.into_iter().map(|id| {
+            if id == self.getter {
+                let state = self.state.lock().unwrap().clone();
+                (id, Ok(Some(Value::OnOff(state))))
+            } else {
+                (id.clone(), Err(Error::InternalError(InternalError::NoSuchGetter(id))))
+            }
+        }).collect()
+    }
+
+    fn send_values(&self, values: HashMap<Id<Setter>, Value>, _: User) -> ResultMap<Id<Setter>, (), Error> {
+        values.into_iter().map(|(id, value)| {
+            match value {
+                Value::OnOff(ref state) if id == self.setter => {
+                    *self.state.lock().unwrap() = state.clone();
+                    (id, Ok(()))
+                }
+                _ => (id.clone(), Err(Error::InternalError(InternalError::NoSuchSetter(id))))
+            }
+        }).collect()
+    }
+
+    fn register_watch(&self, mut watch: Vec<WatchTarget>) -> WatchResult {
+        // The light only changes when we are asked to, so there is nothing to report.
+        watch.drain(..).map(|(id, _, _)| {
+            (id, Ok(Box::new(LightWatchGuard) as Box<AdapterWatchGuard>))
+        }).collect()
+    }
+}
+
+fn main() {
+    let adapter_id = Id::<AdapterId>::new("proxy_child_adapter");
+    let service_id = Id::<ServiceId>::new("proxy_child_light");
+    let adapter = Arc::new(LightAdapter {
+        id: adapter_id.clone(),
+        getter: Id::new("proxy_child_light_is_on"),
+        setter: Id::new("proxy_child_light_turn_on"),
+        state: Mutex::new(OnOff::Off),
+    });
+
+    let host = HostHandle::new(Box::new(io::stdout()));
+    host.add_service(Service::empty(service_id.clone(), adapter_id.clone())).unwrap();
+    host.add_getter(Channel {
+        id: adapter.getter.clone(),
+        service: service_id.clone(),
+        adapter: adapter_id.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::LightOn,
+            constraints: Constraints::default(),
+            capabilities: GetterCapabilities::default(),
+        },
+    }).unwrap();
+    host.add_setter(Channel {
+        id: adapter.setter.clone(),
+        service: service_id.clone(),
+        adapter: adapter_id.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            updated: None,
+            kind: ChannelKind::LightOn,
+            constraints: Constraints::default(),
+            capabilities: SetterCapabilities::default(),
+        },
+    }).unwrap();
+
+    serve(adapter, io::stdin(), &host);
+
+    if env::args().any(|arg| arg == "--linger") {
+        loop {
+            thread::sleep(Duration::from_secs(60));
+        }
+    }
+}
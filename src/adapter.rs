@@ -1,4 +1,5 @@
-use api::{ Error, User };
+use api::{ DiscoveryEvent, Error, User };
+use migration::IdMigration;
 use services::*;
 use values::*;
 
@@ -14,6 +15,13 @@ pub type ResultMap<K, T, E> = HashMap<K, Result<T, E>>;
 pub trait AdapterWatchGuard : Send + Sync {
 }
 
+/// A function creating an instance of an adapter, used to restart the adapter if it misbehaves.
+///
+/// Each instance must have the same id. Upon restart, the new instance takes over the services
+/// and channels of the former instance, and receives the watches registered with it. It may then
+/// add or remove services and channels as needed, but must not add them again.
+pub type AdapterFactory = Arc<Fn() -> Result<Arc<Adapter>, Error> + Send + Sync>;
+
 /// An API that adapter managers must implement
 pub trait AdapterManagerHandle: Send {
     /// Add an adapter to the system.
@@ -23,8 +31,25 @@ pub trait AdapterManagerHandle: Send {
     /// # Errors
     ///
     /// Returns an error if an adapter with the same id is already present.
+    ///
+    /// # Wrappers
+    ///
+    /// The manager evaluates the capabilities of the adapter, i.e. `polls_unwatchable_getters`,
+    /// once, as the adapter is added, and never asks again. An adapter that wraps another
+    /// adapter, e.g. `adapter_utils::MakeSyncAdapter`, must therefore determine its answer
+    /// by the time it is added, typically by asking the wrapped adapter upon construction.
     fn add_adapter(& self, adapter: Arc<Adapter>) -> Result<(), Error>;
 
+    /// Add an adapter to the system, using `factory` to create it.
+    ///
+    /// If the adapter panics repeatedly, it is stopped and replaced with a new instance created
+    /// by `factory`, which takes over its services and channels.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `factory` fails or if an adapter with the same id is already present.
+    fn add_adapter_with_factory(& self, factory: AdapterFactory) -> Result<(), Error>;
+
     /// Remove an adapter from the system, including all its services and channels.
     ///
     /// # Errors
@@ -49,6 +74,22 @@ pub trait AdapterManagerHandle: Send {
     /// - there is no adapter with id `service.lock`.
     fn add_service(& self, service: Service) -> Result<(), Error>;
 
+    /// Add a service to the system, along with all the getters and setters listed in
+    /// `service.getters` and `service.setters`, atomically.
+    ///
+    /// Clients never observe the service without its channels, and each watcher that starts
+    /// watching some of its getters receives a single `WatchEvent::GettersAdded`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of:
+    /// - a channel doesn't belong to `service` or to its adapter;
+    /// - a service or channel with the same identifier is already installed on the system;
+    /// - there is no adapter with id `service.adapter`.
+    ///
+    /// In either case, nothing is added.
+    fn add_service_with_channels(& self, service: Service) -> Result<(), Error>;
+
     /// Remove a service previously registered on the system. Typically, called by
     /// an adapter when a service (e.g. a device) is disconnected.
     ///
@@ -109,6 +150,30 @@ pub trait AdapterManagerHandle: Send {
     /// is not registered. In either case, it attemps to clean as much as possible, even
     /// if the state is inconsistent.
     fn remove_setter(& self, id: &Id<Setter>) -> Result<(), Error>;
+
+    /// Report the connectivity of an adapter, with an explanation suitable for display to the
+    /// user. Adapters are `AdapterState::Online` when they are added.
+    ///
+    /// While an adapter is `AdapterState::Offline`, operations on its channels fail with
+    /// `Error::AdapterOffline` without reaching the adapter.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no adapter with this identifier exists.
+    fn set_adapter_status(& self, id: &Id<AdapterId>, state: AdapterState, reason: Option<String>) -> Result<(), Error>;
+
+    /// Rename identifiers in the taxonomy database, typically once an adapter has changed its
+    /// id scheme, so that tags, configurations and durable watches are not orphaned.
+    ///
+    /// The database is rewritten in a single transaction. Services and channels that are
+    /// already registered under their new id receive the tags of their former id. The
+    /// configuration of a renamed adapter is applied the next time it is added.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the migration is inconsistent (see `IdMigration::check`) or if the
+    /// database cannot be rewritten, in which case it is left unchanged.
+    fn migrate_ids(& self, migration: IdMigration) -> Result<(), Error>;
 }
 
 pub enum WatchEvent {
@@ -171,7 +236,8 @@ pub trait Adapter: Send + Sync {
     /// If a `Range` option is set, the watcher expects to receive `EnterRange`/`ExitRange` events
     /// whenever the value available on the device enters/exits the range. If the `Range` is
     /// a `Range::Eq(x)`, the adapter may decide to reject the request or to interpret it as
-    /// a `Range::BetweenEq { min: x, max: x }`.
+    /// a `Range::BetweenEq { min: x, max: x }`. The `AdapterManager` only ever passes plain
+    /// ranges (see `Range::is_plain`) and evaluates other ranges itself.
     ///
     /// If no `Range` option is set, the watcher expects to receive `EnterRange` events whenever
     /// a new value is available on the device. The adapter may decide to reject the request if
@@ -183,10 +249,82 @@ pub trait Adapter: Send + Sync {
     /// should handle this case, optimizing it if possible.
     ///
     /// Similarly, successive calls to `register_watch` may end up watching the same getter. The
-    /// adapter should handle this case, optimizing it if possible.
+    /// adapter should handle this case, optimizing it if possible. The `AdapterManager` itself
+    /// registers a single watch per getter on behalf of all its watchers, but registrations
+    /// overlap briefly whenever it replaces a watch with a wider or narrower one.
     fn register_watch(&self, Vec<WatchTarget>) ->
             WatchResult;
 
+    /// `true` if the adapter emulates watching for getters that can only be polled, i.e. that
+    /// declare `watchable: false` but `pollable: true`, as `adapter_utils::PollingAdapter` does.
+    /// The `AdapterManager` then forwards watches of these getters to the adapter instead of
+    /// rejecting them with `Error::GetterDoesNotSupportWatching`.
+    ///
+    /// Called only once, when the adapter is added, see `AdapterManagerHandle::add_adapter`.
+    ///
+    /// By default, `false`.
+    fn polls_unwatchable_getters(&self) -> bool {
+        false
+    }
+
+    /// Start looking for new devices, typically upon a user request.
+    ///
+    /// The adapter reports its progress to `on_event`: `DiscoveryEvent::Started`, then
+    /// `DeviceFound` or `DeviceLost` as devices become available for pairing or stop being
+    /// available, then `Stopped` once it is done, whether upon `stop_discovery` or on its own.
+    /// Several calls may overlap, in which case the adapter should report to all of them.
+    ///
+    /// By default, return `Error::AdapterDoesNotSupportDiscovery`.
+    fn start_discovery(&self, _: Box<ExtSender<DiscoveryEvent>>) -> Result<(), Error> {
+        Err(Error::AdapterDoesNotSupportDiscovery(self.id()))
+    }
+
+    /// Stop looking for new devices.
+    fn stop_discovery(&self) {
+        // By default, do nothing.
+    }
+
+    /// The devices found, and not paired yet.
+    fn get_pending_devices(&self) -> Vec<PendingDevice> {
+        vec![]
+    }
+
+    /// Pair a device previously found, with the credentials requested by its
+    /// `PendingDevice::pairing`. Once paired, the adapter adds the services and channels of
+    /// the device through the `AdapterManagerHandle`, as for any other device.
+    ///
+    /// By default, return `Error::AdapterDoesNotSupportDiscovery`.
+    fn pair(&self, _: &Id<DeviceId>, _: Option<Credentials>) -> Result<(), Error> {
+        Err(Error::AdapterDoesNotSupportDiscovery(self.id()))
+    }
+
+    /// Unpair the device offering a service. Once unpaired, the adapter removes the service
+    /// through the `AdapterManagerHandle`.
+    ///
+    /// By default, return `Error::AdapterDoesNotSupportDiscovery`.
+    fn unpair(&self, _: &Id<ServiceId>) -> Result<(), Error> {
+        Err(Error::AdapterDoesNotSupportDiscovery(self.id()))
+    }
+
+    /// The fields accepted by the configuration of the adapter, e.g. the address of a bridge.
+    ///
+    /// By default, none.
+    fn config_schema(&self) -> Vec<ConfigField> {
+        vec![]
+    }
+
+    /// Apply a configuration, already checked against `config_schema` and completed with its
+    /// default values.
+    ///
+    /// The `AdapterManager` calls this method when the adapter is added, with the configuration
+    /// stored in the taxonomy database, if any, then whenever the configuration is changed
+    /// through the API. A configuration is only stored if the adapter accepts it.
+    ///
+    /// By default, accept any configuration.
+    fn set_config(&self, _: &AdapterConfig) -> Result<(), Error> {
+        Ok(())
+    }
+
     /// Signal the adapter that it is time to stop.
     ///
     /// Ideally, the adapter should not return until all its threads have been stopped.
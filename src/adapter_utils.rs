@@ -1,13 +1,17 @@
 //! Utilities for writing adapters.
 
-use api::{ Error, User };
+use api::{ DiscoveryEvent, Error, User };
 use manager::*;
-use services::{ Getter, Setter };
+use services::{ AdapterConfig, ConfigField, Credentials, DeviceId, Getter, PendingDevice, ServiceId, Setter };
 use util::{ Id, AdapterId };
 use values::*;
 
-use std::collections::HashMap;
-use std::sync::{ Arc, Mutex };
+use transformable_channels::mpsc::*;
+
+use std::collections::{ HashMap, VecDeque };
+use std::sync::{ Arc, Condvar, Mutex, Weak };
+use std::thread;
+use std::time::{ Duration as StdDuration, Instant };
 
 /// A simple way of converting an Adapter to an Adapter + Sync.
 ///
@@ -18,6 +22,7 @@ pub struct MakeSyncAdapter<T> where T: Adapter {
     name: String,
     vendor: String,
     version: [u32; 4],
+    polls_unwatchable_getters: bool,
 }
 
 impl<T> MakeSyncAdapter<T> where T: Adapter {
@@ -27,6 +32,7 @@ impl<T> MakeSyncAdapter<T> where T: Adapter {
             name: adapter.name().to_owned(),
             vendor: adapter.vendor().to_owned(),
             version: adapter.version().to_owned(),
+            polls_unwatchable_getters: adapter.polls_unwatchable_getters(),
             lock: Mutex::new(Arc::new(adapter)),
         }
     }
@@ -48,6 +54,10 @@ impl<T> Adapter for MakeSyncAdapter<T> where T: Adapter {
         &self.version
     }
 
+    fn polls_unwatchable_getters(&self) -> bool {
+        self.polls_unwatchable_getters
+    }
+
     fn fetch_values(&self, set: Vec<Id<Getter>>, user: User) -> ResultMap<Id<Getter>, Option<Value>, Error> {
         self.lock.lock().unwrap().fetch_values(set, user)
     }
@@ -59,4 +69,377 @@ impl<T> Adapter for MakeSyncAdapter<T> where T: Adapter {
     fn register_watch(&self, watch: Vec<WatchTarget>) -> WatchResult {
         self.lock.lock().unwrap().register_watch(watch)
     }
+
+    fn start_discovery(&self, on_event: Box<ExtSender<DiscoveryEvent>>) -> Result<(), Error> {
+        self.lock.lock().unwrap().start_discovery(on_event)
+    }
+
+    fn stop_discovery(&self) {
+        self.lock.lock().unwrap().stop_discovery()
+    }
+
+    fn get_pending_devices(&self) -> Vec<PendingDevice> {
+        self.lock.lock().unwrap().get_pending_devices()
+    }
+
+    fn pair(&self, device: &Id<DeviceId>, credentials: Option<Credentials>) -> Result<(), Error> {
+        self.lock.lock().unwrap().pair(device, credentials)
+    }
+
+    fn unpair(&self, service: &Id<ServiceId>) -> Result<(), Error> {
+        self.lock.lock().unwrap().unpair(service)
+    }
+
+    fn config_schema(&self) -> Vec<ConfigField> {
+        self.lock.lock().unwrap().config_schema()
+    }
+
+    fn set_config(&self, config: &AdapterConfig) -> Result<(), Error> {
+        self.lock.lock().unwrap().set_config(config)
+    }
+}
+
+/// A way of watching getters that only support polling.
+///
+/// Watches are forwarded to the underlying adapter. Whenever it rejects a getter with
+/// `Error::GetterDoesNotSupportWatching`, the getter is polled through `fetch_values` instead,
+/// either at the interval specified for this getter or at the default interval, and values are
+/// compared with the range locally. All the watches of a getter share the same poll.
+///
+/// The manager forwards watches of getters that declare `watchable: false` and `pollable: true`
+/// to this adapter, see `Adapter::polls_unwatchable_getters`. The underlying adapter is expected
+/// to reject them with `Error::GetterDoesNotSupportWatching`, so that they are polled.
+///
+/// ```
+/// use foxbox_taxonomy::adapter_utils::*;
+/// use foxbox_taxonomy::fake_adapter::*;
+/// use foxbox_taxonomy::services::*;
+///
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let adapter = FakeAdapter::new(&Id::new("adapter"));
+/// let adapter = PollingAdapter::new(Arc::new(adapter), Duration::from_secs(60))
+///   .with_getter_interval(Id::new("thermometer"), Duration::from_secs(5));
+/// ```
+pub struct PollingAdapter {
+    adapter: Arc<Adapter>,
+
+    /// The interval for getters that do not have a specific interval.
+    interval: StdDuration,
+
+    /// The intervals specific to some getters.
+    intervals: HashMap<Id<Getter>, StdDuration>,
+
+    /// The polls currently running. A poll stops, and is removed, once all its watchers
+    /// have been dropped.
+    polls: Arc<Polls>,
+}
+
+type Polls = Mutex<HashMap<Id<Getter>, Weak<Mutex<Poll>>>>;
+
+impl PollingAdapter {
+    pub fn new(adapter: Arc<Adapter>, interval: StdDuration) -> Self {
+        PollingAdapter {
+            adapter: adapter,
+            interval: interval,
+            intervals: HashMap::new(),
+            polls: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Poll a specific getter at a specific interval.
+    pub fn with_getter_interval(self, id: Id<Getter>, interval: StdDuration) -> Self {
+        let mut intervals = self.intervals;
+        intervals.insert(id, interval);
+        PollingAdapter {
+            intervals: intervals,
+            .. self
+        }
+    }
+
+    /// Add a watcher to the poll of a getter, starting the poll if necessary.
+    fn poll(&self, id: &Id<Getter>, range: Option<Range>, on_event: Box<ExtSender<WatchEvent>>) ->
+        Result<Box<AdapterWatchGuard>, Error>
+    {
+        let existing = self.polls.lock().unwrap().get(id).and_then(|poll| poll.upgrade());
+        let poll = match existing {
+            Some(poll) => poll,
+            None => {
+                // Start from the current value, as a device would. The value is fetched
+                // without holding `polls`, so that a slow device doesn't block the watches
+                // of other getters.
+                let latest = match self.adapter.fetch_values(vec![id.clone()], User::None).remove(id) {
+                    Some(Ok(latest)) => latest,
+                    Some(Err(Error::GetterDoesNotSupportPolling(_))) =>
+                        return Err(Error::GetterDoesNotSupportWatching(id.clone())),
+                    Some(Err(err)) => {
+                        warn!("Could not fetch the initial value of {}: {:?}", id, err);
+                        None
+                    }
+                    None => None
+                };
+                let stop = Arc::new((Mutex::new(false), Condvar::new()));
+                let poll = Arc::new(Mutex::new(Poll {
+                    id: id.clone(),
+                    latest: latest,
+                    watchers: HashMap::new(),
+                    counter: 0,
+                    stop: stop.clone(),
+                    polls: Arc::downgrade(&self.polls),
+                }));
+                // Another watch of the same getter may have started a poll in the meantime.
+                // If so, join it. Our own poll is only dropped once `polls` is unlocked, as
+                // dropping it locks `polls`.
+                let (poll, unused) = {
+                    let mut polls = self.polls.lock().unwrap();
+                    let existing = polls.get(id).and_then(|poll| poll.upgrade());
+                    match existing {
+                        Some(existing) => (existing, Some(poll)),
+                        None => {
+                            polls.insert(id.clone(), Arc::downgrade(&poll));
+                            (poll, None)
+                        }
+                    }
+                };
+                if unused.is_none() {
+                    self.start_polling(id, &poll, stop);
+                }
+                poll
+            }
+        };
+        let key = {
+            let mut guard = poll.lock().unwrap();
+            let this = &mut *guard;
+            let is_met = match (&range, &this.latest) {
+                (&Some(ref range), &Some(ref value)) => range.contains(value),
+                _ => false
+            };
+            this.counter += 1;
+            this.watchers.insert(this.counter, PollWatcher {
+                range: range,
+                is_met: is_met,
+                on_event: on_event,
+            });
+            this.counter
+        };
+        Ok(Box::new(PollGuard {
+            poll: poll,
+            key: key,
+        }))
+    }
+
+    fn start_polling(&self, id: &Id<Getter>, poll: &Arc<Mutex<Poll>>, stop: Arc<(Mutex<bool>, Condvar)>) {
+        let interval = *self.intervals.get(id).unwrap_or(&self.interval);
+        let adapter = self.adapter.clone();
+        let poll = Arc::downgrade(poll);
+        let id = id.clone();
+        thread::spawn(move || {
+            loop {
+                // Wait for the next poll, unless all the watchers are dropped in the meantime.
+                {
+                    let (ref stopped, ref condvar) = *stop;
+                    let mut stopped = stopped.lock().unwrap();
+                    let deadline = Instant::now() + interval;
+                    while !*stopped {
+                        let now = Instant::now();
+                        if now >= deadline {
+                            break;
+                        }
+                        stopped = condvar.wait_timeout(stopped, deadline - now).unwrap().0;
+                    }
+                    if *stopped {
+                        return;
+                    }
+                }
+                let poll = match poll.upgrade() {
+                    None => return, // All the watchers have been dropped.
+                    Some(poll) => poll
+                };
+                match adapter.fetch_values(vec![id.clone()], User::None).remove(&id) {
+                    Some(Ok(Some(value))) => poll.lock().unwrap().update(&id, value),
+                    Some(Err(err)) => warn!("Could not poll {}: {:?}", id, err),
+                    Some(Ok(None)) | None => {}
+                }
+            }
+        });
+    }
+}
+
+impl Adapter for PollingAdapter {
+    fn id(&self) -> Id<AdapterId> {
+        self.adapter.id()
+    }
+
+    fn name(&self) -> &str {
+        self.adapter.name()
+    }
+
+    fn vendor(&self) -> &str {
+        self.adapter.vendor()
+    }
+
+    fn version(&self) -> &[u32;4] {
+        self.adapter.version()
+    }
+
+    fn polls_unwatchable_getters(&self) -> bool {
+        true
+    }
+
+    fn fetch_values(&self, set: Vec<Id<Getter>>, user: User) -> ResultMap<Id<Getter>, Option<Value>, Error> {
+        self.adapter.fetch_values(set, user)
+    }
+
+    fn send_values(&self, values: HashMap<Id<Setter>, Value>, user: User) -> ResultMap<Id<Setter>, (), Error> {
+        self.adapter.send_values(values, user)
+    }
+
+    fn register_watch(&self, watch: Vec<WatchTarget>) -> WatchResult {
+        // Keep a copy of each target, in case we need to poll instead.
+        let mut targets = HashMap::new();
+        for &(ref id, ref range, ref on_event) in &watch {
+            targets.entry(id.clone())
+                .or_insert_with(VecDeque::new)
+                .push_back((range.clone(), on_event.internal_clone()));
+        }
+        let mut results = self.adapter.register_watch(watch);
+        results.drain(..).map(|(id, result)| {
+            let target = targets.get_mut(&id).and_then(|targets| targets.pop_front());
+            match (result, target) {
+                (Err(Error::GetterDoesNotSupportWatching(_)), Some((range, on_event))) => {
+                    let result = self.poll(&id, range, on_event);
+                    (id, result)
+                }
+                (result, _) => (id, result)
+            }
+        }).collect()
+    }
+
+    fn start_discovery(&self, on_event: Box<ExtSender<DiscoveryEvent>>) -> Result<(), Error> {
+        self.adapter.start_discovery(on_event)
+    }
+
+    fn stop_discovery(&self) {
+        self.adapter.stop_discovery()
+    }
+
+    fn get_pending_devices(&self) -> Vec<PendingDevice> {
+        self.adapter.get_pending_devices()
+    }
+
+    fn pair(&self, device: &Id<DeviceId>, credentials: Option<Credentials>) -> Result<(), Error> {
+        self.adapter.pair(device, credentials)
+    }
+
+    fn unpair(&self, service: &Id<ServiceId>) -> Result<(), Error> {
+        self.adapter.unpair(service)
+    }
+
+    fn config_schema(&self) -> Vec<ConfigField> {
+        self.adapter.config_schema()
+    }
+
+    fn set_config(&self, config: &AdapterConfig) -> Result<(), Error> {
+        self.adapter.set_config(config)
+    }
+
+    fn stop(&self) {
+        self.adapter.stop()
+    }
+}
+
+/// The poll of a getter, shared by all its watchers.
+struct Poll {
+    id: Id<Getter>,
+
+    /// The latest value fetched, if any.
+    latest: Option<Value>,
+
+    watchers: HashMap<usize, PollWatcher>,
+
+    /// Used to generate the keys of `watchers`.
+    counter: usize,
+
+    /// Set once the poll is dropped, to wake up and stop the polling thread.
+    stop: Arc<(Mutex<bool>, Condvar)>,
+
+    /// The polls of the adapter, from which this poll is removed once dropped.
+    polls: Weak<Polls>,
+}
+
+impl Drop for Poll {
+    fn drop(&mut self) {
+        {
+            let (ref stopped, ref condvar) = *self.stop;
+            *stopped.lock().unwrap() = true;
+            condvar.notify_all();
+        }
+        if let Some(polls) = self.polls.upgrade() {
+            let mut polls = polls.lock().unwrap();
+            // The entry may already have been replaced by a new poll of the same getter.
+            let is_ours = polls.get(&self.id).map_or(false, |poll| poll.upgrade().is_none());
+            if is_ours {
+                polls.remove(&self.id);
+            }
+        }
+    }
+}
+
+impl Poll {
+    fn update(&mut self, id: &Id<Getter>, value: Value) {
+        let is_new = self.latest.as_ref() != Some(&value);
+        for watcher in self.watchers.values_mut() {
+            let event = match watcher.range {
+                None if is_new => Some(WatchEvent::Enter {
+                    id: id.clone(),
+                    value: value.clone()
+                }),
+                None => None,
+                Some(ref range) => {
+                    let is_met = range.contains(&value);
+                    let event = match (is_met, watcher.is_met) {
+                        (true, false) => Some(WatchEvent::Enter {
+                            id: id.clone(),
+                            value: value.clone()
+                        }),
+                        (false, true) => Some(WatchEvent::Exit {
+                            id: id.clone(),
+                            value: value.clone()
+                        }),
+                        _ => None
+                    };
+                    watcher.is_met = is_met;
+                    event
+                }
+            };
+            if let Some(event) = event {
+                let _ = watcher.on_event.send(event);
+            }
+        }
+        self.latest = Some(value);
+    }
+}
+
+struct PollWatcher {
+    range: Option<Range>,
+
+    /// If `range` is specified, `true` if the latest value was in the range.
+    is_met: bool,
+
+    on_event: Box<ExtSender<WatchEvent>>,
+}
+
+/// Removes a watcher from its poll once dropped.
+struct PollGuard {
+    poll: Arc<Mutex<Poll>>,
+    key: usize,
+}
+
+impl AdapterWatchGuard for PollGuard {}
+
+impl Drop for PollGuard {
+    fn drop(&mut self) {
+        self.poll.lock().unwrap().watchers.remove(&self.key);
+    }
 }
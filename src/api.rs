@@ -16,7 +16,7 @@
 use services::*;
 use selector::*;
 pub use util::{ ResultMap, TargetMap, Targetted };
-use values::{ Value, Range, TypeError };
+use values::{ Duration, TimeStamp, Value, Range, TypeError };
 
 use transformable_channels::mpsc::*;
 
@@ -24,6 +24,7 @@ use std::{ error, fmt };
 use std::error::Error as std_error;
 
 use serde::ser::Serialize;
+use serde_json;
 use serde_json::value::Serializer;
 
 /// An error that arose during interaction with either a device, an adapter or the
@@ -42,6 +43,39 @@ pub enum Error {
     /// In such a case, the adapter should return this error.
     GetterRequiresThresholdForWatching(Id<Getter>),
 
+    /// Attempting to discover or pair devices with an adapter that doesn't support this operation.
+    AdapterDoesNotSupportDiscovery(Id<AdapterId>),
+
+    /// Attempting to pair a device with credentials that it rejects.
+    InvalidCredentials(Id<DeviceId>),
+
+    /// Attempting to use a channel of an adapter that has reported that it is offline.
+    AdapterOffline(Id<AdapterId>),
+
+    /// The adapter has panicked while handling the request.
+    AdapterPanicked(Id<AdapterId>),
+
+    /// Attempting to use an adapter that has been isolated after panicking repeatedly.
+    AdapterFaulted(Id<AdapterId>),
+
+    /// Attempting to restart an adapter that the supervisor does not know how to restart,
+    /// e.g. because it has been removed.
+    AdapterNotRestartable(Id<AdapterId>),
+
+    /// Attempting to load a plugin that is not a valid adapter plugin for this version of the
+    /// taxonomy.
+    InvalidPlugin(String),
+
+    /// Attempting to apply an inconsistent identifier migration. For instance, one that renames
+    /// two identifiers into the same one.
+    InvalidMigration(String),
+
+    /// Attempting to configure an adapter with a field that it doesn't declare.
+    UnknownConfigField(String),
+
+    /// Attempting to configure an adapter without a value for a required field.
+    MissingConfigField(String),
+
     /// Attempting to send a value with a wrong type.
     TypeError(TypeError),
 
@@ -69,12 +103,31 @@ impl ToJSON for Error {
     }
 }
 
+impl Parser<Error> for Error {
+    fn description() -> String {
+        "Error".to_owned()
+    }
+    fn parse(_: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        serde_json::from_value(source.clone()).map_err(ParseError::json)
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Error::GetterDoesNotSupportPolling(ref getter) |
             Error::GetterDoesNotSupportWatching(ref getter) |
             Error::GetterRequiresThresholdForWatching(ref getter) => write!(f, "{}: {}", self.description(), getter),
+            Error::AdapterDoesNotSupportDiscovery(ref adapter) => write!(f, "{}: {}", self.description(), adapter),
+            Error::InvalidCredentials(ref device) => write!(f, "{}: {}", self.description(), device),
+            Error::AdapterOffline(ref adapter) |
+            Error::AdapterPanicked(ref adapter) |
+            Error::AdapterFaulted(ref adapter) |
+            Error::AdapterNotRestartable(ref adapter) => write!(f, "{}: {}", self.description(), adapter),
+            Error::InvalidPlugin(ref reason) |
+            Error::InvalidMigration(ref reason) => write!(f, "{}: {}", self.description(), reason),
+            Error::UnknownConfigField(ref name) |
+            Error::MissingConfigField(ref name) => write!(f, "{}: {}", self.description(), name),
             Error::TypeError(ref err) => write!(f, "{}: {}", self.description(), err),
             Error::RangeError(ref range) => write!(f, "{}: {:?}", self.description(), range),
             Error::InvalidValue(ref value) => write!(f, "{}: {:?}",self.description(), value),
@@ -89,6 +142,16 @@ impl error::Error for Error {
             Error::GetterDoesNotSupportPolling(_) => "Attempting to fetch a value from a Channel<Getter> that doesn't support this operation",
             Error::GetterDoesNotSupportWatching(_) => "Attempting to watch a value from a Channel<Getter> that doesn't support this operation",
             Error::GetterRequiresThresholdForWatching(_) => "Attempting to watch all value from a Channel<Getter> that requires a filter",
+            Error::AdapterDoesNotSupportDiscovery(_) => "Attempting to discover or pair devices with an adapter that doesn't support this operation",
+            Error::InvalidCredentials(_) => "Attempting to pair a device with invalid credentials",
+            Error::AdapterOffline(_) => "Attempting to use a channel of an adapter that is offline",
+            Error::AdapterPanicked(_) => "The adapter has panicked",
+            Error::AdapterFaulted(_) => "Attempting to use an adapter that has been isolated after repeated panics",
+            Error::AdapterNotRestartable(_) => "Attempting to restart an adapter that cannot be restarted",
+            Error::InvalidPlugin(_) => "Attempting to load an invalid plugin",
+            Error::InvalidMigration(_) => "Attempting to apply an inconsistent identifier migration",
+            Error::UnknownConfigField(_) => "Attempting to configure an adapter with an unknown field",
+            Error::MissingConfigField(_) => "Attempting to configure an adapter without a required field",
             Error::TypeError(_) => "Attempting to send a value with a wrong type",
             Error::RangeError(_) => "Attempting to use an inconsistent range",
             Error::InvalidValue(_) => "Attempting to send an invalid value",
@@ -114,6 +177,10 @@ pub enum InternalError {
     NoSuchService(Id<ServiceId>),
     /// Attempting to access an adapter that isn't registered.
     NoSuchAdapter(Id<AdapterId>),
+    /// Attempting to access a durable watch that isn't registered.
+    NoSuchWatch(Id<WatchId>),
+    /// Attempting to pair a device that hasn't been discovered.
+    NoSuchDevice(Id<DeviceId>),
 
     /// Attempting to register a getter with an id that is already used.
     DuplicateGetter(Id<Getter>),
@@ -170,6 +237,16 @@ pub enum WatchEvent {
     /// added. Payload is the id of the device that was added.
     GetterAdded(Id<Getter>),
 
+    /// A service has been added along with its channels in a single operation, and some of
+    /// its getters are now watched. Fired once, instead of one `GetterAdded` per getter.
+    GettersAdded {
+        /// The service that has been added.
+        service: Id<ServiceId>,
+
+        /// The getters of this service that are now watched.
+        getters: Vec<Id<Getter>>
+    },
+
     /// One of the channels encountered an error during initialization.
     /// This channel will not be watched, but other channels will remain
     /// watched.
@@ -177,6 +254,222 @@ pub enum WatchEvent {
         channel: Id<Getter>,
         error: Error
     },
+
+    /// If `initial_state` was specified when we registered for watching, `InitialState` is
+    /// fired once for each getter as soon as it is watched, before any `EnterRange` or
+    /// `ExitRange` from this getter. Otherwise, never fired.
+    InitialState {
+        /// The channel being watched.
+        from: Id<Getter>,
+
+        /// The current value, or `None` if the value could not be determined.
+        value: Option<Value>,
+
+        /// `true` if the current value is in the range being watched. If `Always` was
+        /// specified, `true` if there is a value.
+        is_in_range: bool,
+    },
+
+    /// If `resume_from` was specified when we registered for watching, but some of the
+    /// values reported since then have already been discarded from the event log, this event
+    /// is fired before replaying the events that are still available. Also fired upon
+    /// `attach_durable_watch` if some of the events held for the durable watch have been
+    /// discarded, and if some of the events delayed by a `min_interval` have been discarded.
+    /// Otherwise, never fired.
+    ReplayTruncated {
+        /// The sequence number of the latest value or event discarded.
+        discarded: u64,
+    },
+}
+
+/// An event during device discovery and pairing.
+#[derive(Serialize, Debug, Clone)]
+pub enum DiscoveryEvent {
+    /// An adapter has started looking for devices.
+    Started {
+        adapter: Id<AdapterId>
+    },
+
+    /// An adapter has found a device, which may now be paired.
+    DeviceFound(PendingDevice),
+
+    /// A device found previously cannot be paired anymore, e.g. because it has been unplugged.
+    DeviceLost {
+        adapter: Id<AdapterId>,
+        device: Id<DeviceId>
+    },
+
+    /// A device has been paired. The adapter adds its services and channels as usual.
+    Paired {
+        adapter: Id<AdapterId>,
+        device: Id<DeviceId>
+    },
+
+    /// A device could not be paired.
+    PairingFailed {
+        adapter: Id<AdapterId>,
+        device: Id<DeviceId>,
+        error: Error
+    },
+
+    /// A device has been unpaired. The adapter removes the service as usual.
+    Unpaired {
+        adapter: Id<AdapterId>,
+        service: Id<ServiceId>
+    },
+
+    /// An adapter has stopped looking for devices, either upon request or because it is done.
+    Stopped {
+        adapter: Id<AdapterId>
+    },
+}
+
+/// A `WatchEvent`, numbered by the manager.
+#[derive(Serialize, Debug, Clone)]
+pub struct StampedEvent {
+    /// Increases with each value reported to the manager and with each event that does not
+    /// depend on a value. `EnterRange` and `ExitRange` events carry the number of the value
+    /// that caused them, including when they are replayed. Events delayed by a `Range::Dwell`,
+    /// a `min_interval` or a `debounce` may therefore arrive out of order.
+    pub sequence: u64,
+
+    /// When the manager received the value that caused the event, or else produced the event.
+    pub timestamp: TimeStamp,
+
+    pub event: WatchEvent,
+}
+
+/// Options for watching values.
+///
+/// # JSON
+///
+/// An object in which all fields are optional. Durations are represented as a number of
+/// seconds.
+///
+/// ```
+/// extern crate foxbox_taxonomy;
+///
+/// use foxbox_taxonomy::api::*;
+/// use foxbox_taxonomy::parse::*;
+/// use foxbox_taxonomy::values::Duration;
+///
+/// # fn main() {
+///
+/// let parsed = WatchOptions::from_str("{\"initial_state\": true}").unwrap();
+/// assert!(parsed.initial_state);
+///
+/// let parsed = WatchOptions::from_str("{}").unwrap();
+/// assert_eq!(parsed, WatchOptions::default());
+///
+/// // At most one event per second, keeping only the latest.
+/// let parsed = WatchOptions::from_str("{\"min_interval\": 1, \"coalesce\": true}").unwrap();
+/// assert_eq!(parsed.min_interval, Some(Duration::from_s(1)));
+/// assert!(parsed.coalesce);
+///
+/// // `coalesce` only applies to events delayed by `min_interval`.
+/// assert!(WatchOptions::from_str("{\"coalesce\": true}").is_err());
+///
+/// let serialized = WatchOptions {
+///     debounce: Some(Duration::from_s(2)),
+///     .. WatchOptions::default()
+/// }.to_json();
+/// assert_eq!(serialized.find("debounce").unwrap().as_f64().unwrap(), 2.);
+/// assert_eq!(serialized.find("initial_state").unwrap().as_boolean().unwrap(), false);
+///
+/// // Replay the values received after the event numbered 1234.
+/// let parsed = WatchOptions::from_str("{\"resume_from\": 1234}").unwrap();
+/// assert_eq!(parsed.resume_from, Some(1234));
+/// assert_eq!(parsed.to_json().find("resume_from").unwrap().as_u64().unwrap(), 1234);
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WatchOptions {
+    /// If `true`, fire `WatchEvent::InitialState` for each getter as soon as it is watched,
+    /// using the latest value known to the manager or fetching it from the adapter.
+    pub initial_state: bool,
+
+    /// If specified, `EnterRange` and `ExitRange` events from each getter are sent at most
+    /// once per `min_interval`. Events fired in the meantime are delayed. At most 64 events
+    /// are delayed per getter: older events are dropped, and `WatchEvent::ReplayTruncated`
+    /// is fired before the next delayed event.
+    pub min_interval: Option<Duration>,
+
+    /// If specified, `EnterRange` and `ExitRange` events from each getter are only sent once
+    /// the getter has fired no other event for `debounce`. Only the latest event is sent.
+    pub debounce: Option<Duration>,
+
+    /// If `true`, events that are delayed by `min_interval` are replaced by any later event
+    /// from the same getter, so that only the latest one is sent.
+    ///
+    /// Requires `min_interval`: without it, events are never delayed, so there is nothing to
+    /// coalesce. Parsing rejects `coalesce` without `min_interval`, and the manager ignores it.
+    pub coalesce: bool,
+
+    /// If specified, typically the `sequence` of the latest `StampedEvent` received before a
+    /// disconnection, replay the events that the values reported since then cause for this
+    /// watch, before live delivery continues. The manager keeps a bounded log of the values
+    /// reported for getters that are watched; see `WatchEvent::ReplayTruncated`.
+    pub resume_from: Option<u64>,
+}
+
+impl Parser<WatchOptions> for WatchOptions {
+    fn description() -> String {
+        "WatchOptions".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        if !source.is_object() {
+            return Err(ParseError::type_error("WatchOptions", &path, "object"));
+        }
+        let initial_state = match path.push("initial_state", |path| bool::take_opt(path, source, "initial_state")) {
+            Some(result) => try!(result),
+            None => false
+        };
+        let min_interval = match path.push("min_interval", |path| Duration::take_opt(path, source, "min_interval")) {
+            Some(result) => Some(try!(result)),
+            None => None
+        };
+        let debounce = match path.push("debounce", |path| Duration::take_opt(path, source, "debounce")) {
+            Some(result) => Some(try!(result)),
+            None => None
+        };
+        let coalesce = match path.push("coalesce", |path| bool::take_opt(path, source, "coalesce")) {
+            Some(result) => try!(result),
+            None => false
+        };
+        if coalesce && min_interval.is_none() {
+            return Err(ParseError::missing_field("min_interval", &path));
+        }
+        let resume_from = match path.push("resume_from", |path| u64::take_opt(path, source, "resume_from")) {
+            Some(result) => Some(try!(result)),
+            None => None
+        };
+        Ok(WatchOptions {
+            initial_state: initial_state,
+            min_interval: min_interval,
+            debounce: debounce,
+            coalesce: coalesce,
+            resume_from: resume_from,
+        })
+    }
+}
+
+impl ToJSON for WatchOptions {
+    fn to_json(&self) -> JSON {
+        let mut source = vec![
+            ("initial_state", self.initial_state.to_json()),
+            ("coalesce", self.coalesce.to_json()),
+        ];
+        if let Some(ref min_interval) = self.min_interval {
+            source.push(("min_interval", min_interval.to_json()));
+        }
+        if let Some(ref debounce) = self.debounce {
+            source.push(("debounce", debounce.to_json()));
+        }
+        if let Some(resume_from) = self.resume_from {
+            source.push(("resume_from", resume_from.to_json()));
+        }
+        source.to_json()
+    }
 }
 
 /// User identifier that will be passed from the REST API handlers to the
@@ -310,7 +603,8 @@ pub trait API: Send {
     ///     "service": "some-service-id",
     ///     "updated": "2014-11-28T12:00:09+00:00",
     ///     "mechanism": "setter",
-    ///     "kind": "OnOff"
+    ///     "kind": "OnOff",
+    ///     "capabilities": { "has_feedback": true }
     ///   ]
     /// }]"#;
     /// ```
@@ -472,7 +766,8 @@ pub trait API: Send {
     ///   "service": "some-service-id",
     ///   "updated": "2014-11-28T12:00:09+00:00",
     ///   "mechanism": "getter",
-    ///   "kind": "OnOff"
+    ///   "kind": "OnOff",
+    ///   "capabilities": { "pollable": true, "watchable": true, "requires_threshold": false }
     /// }]"#;
     /// ```
     fn get_getter_channels(& self, selectors: Vec<GetterSelector>) -> Vec<Channel<Getter>>;
@@ -683,12 +978,13 @@ pub trait API: Send {
     /// - If argument `Exactly<Range>` is `Exactly::Exactly(range)`, the watch is interested in
     /// values coming from these channels, if they fall within `range`. This is the most common
     /// case. In this case, `on_event` receives `WatcherEvent::GetterAdded`,
-    /// `WatcherEvent::GetterRemoved` and `WatcherEvent::Value`, whenever a new value is available
+    /// `WatcherEvent::GettersAdded`, `WatcherEvent::GetterRemoved` and `WatcherEvent::Value`, whenever a new value is available
     /// in the range. Values that do not have the same type as `range` are dropped silently.
     ///
     /// - If argument `Exactly<Range>` is `Exactly::Never`, the watch is not interested in the
     /// values coming from these channels, only in connection/disconnection events. Argument
-    /// `on_event` receives `WatchEvent::GetterAdded` and `WatchEvent::GetterRemoved`.
+    /// `on_event` receives `WatchEvent::GetterAdded`, `WatchEvent::GettersAdded` and
+    /// `WatchEvent::GetterRemoved`.
     ///
     /// - If the `Exactly<Range>` argument is `Exactly::Always`, the watch is interested in
     /// receiving *every single value coming from the channels*. This is very rarely a good idea.
@@ -702,6 +998,137 @@ pub trait API: Send {
     fn watch_values(& self, watch: TargetMap<GetterSelector, Exactly<Range>>,
             on_event: Box<ExtSender<WatchEvent>>) -> Self::WatchGuard;
 
+    /// Watch for changes from channels, with options.
+    ///
+    /// Same as `watch_values`, with `WatchOptions::default()` standing for the behavior of
+    /// `watch_values`, except that events are numbered. In particular, if
+    /// `options.initial_state` is `true`, `on_event` also receives one
+    /// `WatchEvent::InitialState` for each getter as soon as it is watched, before any other
+    /// value from this getter.
+    fn watch_values_with_options(& self, watch: TargetMap<GetterSelector, Exactly<Range>>,
+            options: WatchOptions, on_event: Box<ExtSender<StampedEvent>>) -> Self::WatchGuard;
+
+    /// Register a durable watch, identified by `name`.
+    ///
+    /// A durable watch behaves as `watch_values_with_options`, except that it is not attached
+    /// to a `WatchGuard`. Rather, it is stored in the taxonomy database and reinstated whenever
+    /// the manager restarts, so that getters matching `watch` are watched as soon as they are
+    /// added. Its events are held until a consumer attaches with `attach_durable_watch`.
+    /// Option `resume_from` is ignored.
+    ///
+    /// Registering a watch with the name of an existing durable watch replaces it. A consumer
+    /// attached to the former watch remains attached.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the watch cannot be stored in the database.
+    fn register_durable_watch(& self, name: Id<WatchId>, watch: TargetMap<GetterSelector, Exactly<Range>>,
+            options: WatchOptions) -> Result<(), Error>;
+
+    /// Start delivering the events of durable watch `name` to `on_event`.
+    ///
+    /// The events held since the latest consumer was detached are delivered first. If some of
+    /// them had to be discarded, they are preceded by a `WatchEvent::ReplayTruncated`. Any
+    /// consumer previously attached is detached. A consumer is also detached as soon as
+    /// `on_event` is closed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no durable watch with this name.
+    fn attach_durable_watch(& self, name: &Id<WatchId>, on_event: Box<ExtSender<StampedEvent>>) -> Result<(), Error>;
+
+    /// Stop a durable watch and remove it from the taxonomy database.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no durable watch with this name.
+    fn remove_durable_watch(& self, name: &Id<WatchId>) -> Result<(), Error>;
+
+    /// Start looking for new devices, on all adapters.
+    ///
+    /// As long as it is open, `on_event` receives the progress of discovery from all adapters,
+    /// as well as the results of `pair_device` and `unpair_service`. Devices found may be
+    /// listed with `get_pending_devices`.
+    ///
+    /// Returns the result of starting discovery for each adapter. Adapters that do not
+    /// support discovery return `Error::AdapterDoesNotSupportDiscovery`.
+    fn start_discovery(& self, on_event: Box<ExtSender<DiscoveryEvent>>) -> ResultMap<Id<AdapterId>, (), Error>;
+
+    /// Stop looking for new devices, on all adapters.
+    fn stop_discovery(& self);
+
+    /// Get the devices found by all adapters, and not paired yet.
+    fn get_pending_devices(& self) -> Vec<PendingDevice>;
+
+    /// Pair a device found by an adapter, with the credentials requested by its
+    /// `PendingDevice::pairing`, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the adapter is not registered, if it does not support pairing, or
+    /// if it fails to pair the device, e.g. `Error::InvalidCredentials`.
+    fn pair_device(& self, adapter: &Id<AdapterId>, device: &Id<DeviceId>, credentials: Option<Credentials>) -> Result<(), Error>;
+
+    /// Unpair the device offering a service.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the service is not registered, or if its adapter does not support
+    /// pairing or fails to unpair the device.
+    fn unpair_service(& self, service: &Id<ServiceId>) -> Result<(), Error>;
+
+    /// Get the status of all adapters.
+    fn get_adapter_statuses(& self) -> Vec<AdapterStatus>;
+
+    /// Watch the status of adapters.
+    ///
+    /// As long as it is open, `on_event` receives the new status of an adapter whenever the
+    /// adapter reports a change.
+    fn watch_adapter_statuses(& self, on_event: Box<ExtSender<AdapterStatus>>);
+
+    /// Restart an adapter, typically after it has been isolated as `AdapterState::Faulted`.
+    ///
+    /// If the adapter was added with a factory, it is stopped, then replaced with a new
+    /// instance created by its factory. Otherwise, the same instance is resumed, and requests
+    /// reach it again. In either case, the services and channels of the adapter are kept,
+    /// ongoing watches are registered again, and the count of automatic restarts is reset.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the adapter is not registered or if the factory fails. If the
+    /// factory fails, the adapter is removed along with its services and channels.
+    fn restart_adapter(& self, adapter: &Id<AdapterId>) -> Result<(), Error>;
+
+    /// Get the fields accepted by the configuration of an adapter.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the adapter is not registered.
+    fn get_adapter_config_schema(& self, adapter: &Id<AdapterId>) -> Result<Vec<ConfigField>, Error>;
+
+    /// Get the current configuration of an adapter, or an empty configuration if it has never
+    /// been configured. Fields marked as secret in the schema of the adapter are omitted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the adapter is not registered.
+    fn get_adapter_config(& self, adapter: &Id<AdapterId>) -> Result<AdapterConfig, Error>;
+
+    /// Configure an adapter.
+    ///
+    /// The configuration is checked against the fields declared by the adapter (see
+    /// `check_config`) and applied by the adapter. It is then stored in the taxonomy database,
+    /// to be applied again whenever the adapter is added.
+    ///
+    /// Secret fields that the configuration doesn't specify keep their current value, so that
+    /// a configuration obtained with `get_adapter_config` may be modified and set again.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the adapter is not registered, if the configuration doesn't match
+    /// its fields, or if the adapter rejects it.
+    fn set_adapter_config(& self, adapter: &Id<AdapterId>, config: AdapterConfig) -> Result<(), Error>;
+
     /// A value that causes a disconnection once it is dropped.
     type WatchGuard;
 }
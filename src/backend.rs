@@ -3,22 +3,28 @@
 use adapter::{ Adapter, AdapterWatchGuard, ResultMap, WatchEvent as AdapterWatchEvent };
 use transact::InsertInMap;
 
-use api::{ Error, InternalError, TargetMap, Targetted, WatchEvent };
+use api::{ Error, InternalError, StampedEvent, TargetMap, Targetted, User, WatchEvent, WatchOptions };
 use selector::*;
 use services::*;
 use tag_storage::TagStorage;
 use values::*;
 
+use chrono::Duration as ChronoDuration;
+use chrono::UTC;
+
 use sublock::atomlock::*;
 use transformable_channels::mpsc::*;
 
-use std::collections::{ HashMap, HashSet };
+use std::cmp;
+use std::collections::{ BinaryHeap, HashMap, HashSet, VecDeque };
 use std::collections::hash_map::Entry;
 use std::hash::{ Hash, Hasher };
 use std::path::PathBuf;
 use std::ops::{ Deref };
-use std::sync::{ Arc, Mutex, Weak };
+use std::sync::{ Arc, Condvar, Mutex, Weak };
 use std::sync::atomic::{ AtomicBool, Ordering };
+use std::thread;
+use std::time::{ Duration as StdDuration, Instant };
 
 // In release build, log an error and continue.
 // In debug build, log an error and panic.
@@ -39,15 +45,22 @@ macro_rules! log_debug_assert {
 pub type AdapterRequest<T> = HashMap<Id<AdapterId>, (Arc<Adapter>, T)>;
 
 /// A request to an adapter, for performing a `fetch` operation.
-pub type FetchRequest = AdapterRequest<HashMap<Id<Getter>, Type>>;
+///
+/// The kind of each getter is kept to check the values returned by the adapter.
+pub type FetchRequest = AdapterRequest<(HashMap<Id<Getter>, ChannelKind>, ResultMap<Id<Getter>, Option<Value>, Error>)>;
 
 /// A request to an adapter, for performing a `send` operation.
 pub type SendRequest = AdapterRequest<(HashMap<Id<Setter>, Value>, ResultMap<Id<Setter>, (), Error>)>;
 
 /// A request to an adapter, for performing a `watch` operation.
-pub type WatchRequest = AdapterRequest<Vec<(Id<Getter>, Option<Range>, Weak<WatcherData>)>>;
+///
+/// Each getter is watched at most once, on behalf of all its watchers, with the range
+/// to register and the generation of the registration.
+pub type WatchRequest = AdapterRequest<Vec<(Id<Getter>, Option<Range>, usize, Arc<Mutex<Demultiplexer>>)>>;
 
-pub type WatchGuardCommit = Vec<(Weak<WatcherData>, Vec<(Id<Getter>, Box<AdapterWatchGuard>)>)>;
+/// The result of a `watch` operation, for each getter and generation. `None` if the
+/// registration has failed.
+pub type WatchGuardCommit = Vec<(Id<Getter>, usize, Option<Box<AdapterWatchGuard>>)>;
 
 /// Information on a service.
 ///
@@ -148,13 +161,32 @@ struct AdapterData {
 
     /// The services for this adapter.
     services: HashMap<Id<ServiceId>, Arc<SubCell<ServiceData>>>,
+
+    /// The status last reported by the adapter.
+    status: AdapterStatus,
+
+    /// The configuration last accepted by the adapter.
+    config: AdapterConfig,
+
+    /// `true` if the adapter polls the getters that cannot be watched, as answered by the
+    /// adapter when it was added.
+    polls_unwatchable_getters: bool,
 }
 
 impl AdapterData {
     fn new(adapter: Arc<Adapter>) -> Self {
+        let status = AdapterStatus {
+            adapter: adapter.id(),
+            state: AdapterState::Online,
+            reason: None,
+            since: TimeStamp::from_datetime(UTC::now()),
+        };
         AdapterData {
+            polls_unwatchable_getters: adapter.polls_unwatchable_getters(),
             adapter: adapter,
             services: HashMap::new(),
+            status: status,
+            config: AdapterConfig::new(),
         }
     }
 }
@@ -205,6 +237,9 @@ struct GetterData {
 
     /// Watchers that currently watch this channel.
     watchers: HashMap<WatchKey, Weak<WatcherData>>,
+
+    /// The watch registered with the adapter on behalf of these watchers.
+    watch: GetterWatch,
 }
 impl SelectedBy<GetterSelector> for GetterData {
     fn matches(&self, selector: &GetterSelector) -> bool {
@@ -213,11 +248,14 @@ impl SelectedBy<GetterSelector> for GetterData {
 }
 
 impl GetterData {
-    fn new(channel: Channel<Getter>, service_tags: Arc<SubCell<HashSet<Id<TagId>>>>) -> Self {
+    fn new(channel: Channel<Getter>, service_tags: Arc<SubCell<HashSet<Id<TagId>>>>, log: &Arc<Mutex<EventLog>>,
+        timer: &Timer) -> Self
+    {
         GetterData {
             channel: channel,
             service_tags: service_tags.clone(),
             watchers: HashMap::new(),
+            watch: GetterWatch::new(log, timer),
         }
     }
 }
@@ -281,14 +319,19 @@ pub struct WatcherData {
     watch: TargetMap<GetterSelector, Exactly<Range>>,
 
     /// The listener for this watch.
-    on_event: Mutex<Box<ExtSender<WatchEvent>>>,
+    on_event: Mutex<Box<ExtSender<StampedEvent>>>,
+
+    options: WatchOptions,
+
+    /// Numbers the events sent to the listener.
+    log: Arc<Mutex<EventLog>>,
 
     /// A unique key used to locate the `WatcherData` in the
     /// WatchMap.
     key: WatchKey,
 
-    /// The individual guard for each getter currently watched.
-    guards: SubCell<HashMap<Id<Getter>, Vec<Box<AdapterWatchGuard>>>>,
+    /// The getters currently watched.
+    getters: SubCell<HashSet<Id<Getter>>>,
 
     /// `true` once the WatchGuard has dropped. In this
     /// case, the `WatcherData` will shortly be removed
@@ -309,47 +352,897 @@ impl PartialEq for WatcherData {
 }
 
 impl WatcherData {
-    fn new(liveness: &Arc<Liveness>, key: WatchKey, watch:TargetMap<GetterSelector, Exactly<Range>>, on_event: Box<ExtSender<WatchEvent>>) -> Self {
+    fn new(liveness: &Arc<Liveness>, log: &Arc<Mutex<EventLog>>, key: WatchKey, watch:TargetMap<GetterSelector, Exactly<Range>>,
+        options: WatchOptions, on_event: Box<ExtSender<StampedEvent>>) -> Self
+    {
         WatcherData {
             key: key,
             on_event: Mutex::new(on_event),
+            options: options,
+            log: log.clone(),
             watch: watch,
             is_dropped: Arc::new(AtomicBool::new(false)),
-            guards: SubCell::new(liveness, HashMap::new()),
+            getters: SubCell::new(liveness, HashSet::new()),
+        }
+    }
+
+    /// Send an event that does not depend on the values of getters.
+    fn notify(&self, event: WatchEvent) {
+        let event = self.log.lock().unwrap().stamp(event);
+        let _ = self.on_event.lock().unwrap().send(event);
+    }
+}
+
+/// The number of values kept by the `EventLog`.
+const EVENT_LOG_CAPACITY: usize = 1024;
+
+/// The position of an event in the `EventLog`.
+#[derive(Clone, Debug)]
+struct Stamp {
+    sequence: u64,
+    timestamp: TimeStamp,
+}
+
+impl Stamp {
+    fn apply(&self, event: WatchEvent) -> StampedEvent {
+        StampedEvent {
+            sequence: self.sequence,
+            timestamp: self.timestamp.clone(),
+            event: event,
+        }
+    }
+}
+
+/// A value reported by an adapter, as recorded in the `EventLog`.
+#[derive(Clone)]
+struct LogEntry {
+    stamp: Stamp,
+    id: Id<Getter>,
+    value: Value,
+    is_enter: bool,
+}
+
+/// Numbers the events sent to watchers, and keeps the latest values reported for watched
+/// getters, so that watchers may resume after a disconnection. See `WatchOptions::resume_from`.
+pub struct EventLog {
+    /// The sequence number of the latest event.
+    sequence: u64,
+
+    /// The latest values reported, oldest first.
+    entries: VecDeque<LogEntry>,
+
+    /// The sequence number of the latest entry discarded from `entries`, or 0.
+    discarded: u64,
+}
+
+impl EventLog {
+    fn new() -> Self {
+        EventLog {
+            sequence: 0,
+            entries: VecDeque::with_capacity(EVENT_LOG_CAPACITY),
+            discarded: 0,
+        }
+    }
+
+    fn next_stamp(&mut self) -> Stamp {
+        self.sequence += 1;
+        Stamp {
+            sequence: self.sequence,
+            timestamp: TimeStamp::from_datetime(UTC::now()),
         }
     }
 
-    fn push_guard(&self, id: Id<Getter>, guard: Box<AdapterWatchGuard>) {
-        match self.guards.borrow_mut().entry(id) {
-            Entry::Occupied(mut entry) => {
-                entry.get_mut().push(guard);
+    /// Number an event that does not depend on the values of getters.
+    fn stamp(&mut self, event: WatchEvent) -> StampedEvent {
+        self.next_stamp().apply(event)
+    }
+
+    /// Record a value reported by an adapter.
+    fn record(&mut self, id: &Id<Getter>, value: &Value, is_enter: bool) -> Stamp {
+        let stamp = self.next_stamp();
+        if self.entries.len() >= EVENT_LOG_CAPACITY {
+            if let Some(entry) = self.entries.pop_front() {
+                self.discarded = entry.stamp.sequence;
             }
-            Entry::Vacant(entry) => {
-                entry.insert(vec![guard]);
+        }
+        self.entries.push_back(LogEntry {
+            stamp: stamp.clone(),
+            id: id.clone(),
+            value: value.clone(),
+            is_enter: is_enter,
+        });
+        stamp
+    }
+
+    /// The values recorded for a getter: the latest one numbered at most `sequence`, if
+    /// still available, then all the subsequent ones.
+    fn since(&self, id: &Id<Getter>, sequence: u64) -> (Option<Value>, Vec<LogEntry>) {
+        let mut before = None;
+        let mut after = vec![];
+        for entry in &self.entries {
+            if entry.id != *id {
+                continue;
+            }
+            if entry.stamp.sequence <= sequence {
+                before = Some(entry.value.clone());
+            } else {
+                after.push(entry.clone());
+            }
+        }
+        (before, after)
+    }
+}
+
+fn to_std_duration(duration: &Duration) -> StdDuration {
+    let duration : ChronoDuration = duration.clone().into();
+    StdDuration::from_millis(cmp::max(0, duration.num_milliseconds()) as u64)
+}
+
+/// The state of a range evaluated by the manager rather than by the adapter.
+struct LocalRangeState {
+    tracker: RangeTracker,
+
+    /// If specified, how long the value must remain in the range before we fire `EnterRange`.
+    dwell: Option<StdDuration>,
+
+    /// The `EnterRange` to fire once the dwell time has elapsed: when, from which getter
+    /// and with the stamp of the value that has entered the range. Cancelled if the value
+    /// exits the range in the meantime.
+    pending_enter: Option<(Instant, Id<Getter>, Stamp)>,
+
+    /// `true` if we have fired `EnterRange` but not the matching `ExitRange` yet.
+    is_reported: bool,
+}
+
+impl LocalRangeState {
+    fn new(range: Range) -> Self {
+        let (range, dwell) = match range {
+            Range::Dwell { range, duration } => (*range, Some(to_std_duration(&duration))),
+            range => (range, None)
+        };
+        LocalRangeState {
+            tracker: RangeTracker::new(range),
+            dwell: dwell,
+            pending_enter: None,
+            is_reported: false,
+        }
+    }
+}
+
+/// The state of `WatchOptions::min_interval`, `debounce` and `coalesce` for a subscriber.
+/// The number of events delayed by a `min_interval` that are kept for a watcher. Once full,
+/// the oldest events are dropped, and the watcher receives `WatchEvent::ReplayTruncated`.
+const MAX_PENDING_EVENTS: usize = 64;
+
+struct Pacing {
+    min_interval: Option<StdDuration>,
+    debounce: Option<StdDuration>,
+    coalesce: bool,
+
+    /// When we last sent an event.
+    last_sent: Option<Instant>,
+
+    /// The events waiting to be sent, oldest first. At most `MAX_PENDING_EVENTS`.
+    pending: VecDeque<StampedEvent>,
+
+    /// The sequence number of the latest event dropped from `pending` for lack of room, if
+    /// the watcher has not been told yet.
+    truncated: Option<u64>,
+
+    /// When we need to send the oldest pending event, if a flush is scheduled.
+    deadline: Option<Instant>,
+}
+
+impl Pacing {
+    fn new(options: &WatchOptions) -> Option<Self> {
+        // Without `min_interval` nor `debounce`, events are never delayed, so `coalesce`
+        // alone has nothing to coalesce.
+        if options.min_interval.is_none() && options.debounce.is_none() {
+            return None;
+        }
+        Some(Pacing {
+            min_interval: options.min_interval.as_ref().map(to_std_duration),
+            debounce: options.debounce.as_ref().map(to_std_duration),
+            coalesce: options.coalesce,
+            last_sent: None,
+            pending: VecDeque::new(),
+            truncated: None,
+            deadline: None,
+        })
+    }
+
+    /// How long we need to wait before we may send another event.
+    fn until_next(&self, now: Instant) -> StdDuration {
+        match (self.min_interval, self.last_sent) {
+            (Some(min_interval), Some(last_sent)) => {
+                let elapsed = now.duration_since(last_sent);
+                if elapsed >= min_interval {
+                    StdDuration::new(0, 0)
+                } else {
+                    min_interval - elapsed
+                }
+            }
+            _ => StdDuration::new(0, 0)
+        }
+    }
+}
+
+/// Runs the deadlines of subscribers, i.e. dwell times and pacing, on a single thread shared
+/// by all the getters, rather than on a thread per deadline.
+///
+/// The thread stops once all the clones of the `Timer` are dropped.
+#[derive(Clone)]
+struct Timer {
+    handle: Arc<TimerHandle>,
+}
+
+struct TimerHandle {
+    queue: Arc<(Mutex<TimerQueue>, Condvar)>,
+}
+
+impl Drop for TimerHandle {
+    fn drop(&mut self) {
+        let &(ref lock, ref condvar) = &*self.queue;
+        lock.lock().unwrap().is_stopped = true;
+        condvar.notify_one();
+    }
+}
+
+struct TimerQueue {
+    entries: BinaryHeap<TimerEntry>,
+    is_stopped: bool,
+}
+
+struct TimerEntry {
+    deadline: Instant,
+    demux: Weak<Mutex<Demultiplexer>>,
+    key: WatchKey,
+}
+
+impl PartialEq for TimerEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+impl Eq for TimerEntry {}
+impl PartialOrd for TimerEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for TimerEntry {
+    /// Reversed, so that `BinaryHeap` pops the earliest deadline first.
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        other.deadline.cmp(&self.deadline)
+    }
+}
+
+impl Timer {
+    fn new() -> Self {
+        let queue = Arc::new((Mutex::new(TimerQueue {
+            entries: BinaryHeap::new(),
+            is_stopped: false,
+        }), Condvar::new()));
+        let thread_queue = queue.clone();
+        thread::spawn(move || Timer::run(&thread_queue));
+        Timer {
+            handle: Arc::new(TimerHandle {
+                queue: queue
+            })
+        }
+    }
+
+    /// Call `Demultiplexer::on_deadline` for subscriber `key` once `deadline` has expired.
+    fn schedule(&self, deadline: Instant, demux: &Arc<Mutex<Demultiplexer>>, key: WatchKey) {
+        let &(ref lock, ref condvar) = &*self.handle.queue;
+        lock.lock().unwrap().entries.push(TimerEntry {
+            deadline: deadline,
+            demux: Arc::downgrade(demux),
+            key: key,
+        });
+        condvar.notify_one();
+    }
+
+    fn run(queue: &Arc<(Mutex<TimerQueue>, Condvar)>) {
+        let &(ref lock, ref condvar) = &**queue;
+        let mut guard = lock.lock().unwrap();
+        loop {
+            if guard.is_stopped {
+                return;
+            }
+            let now = Instant::now();
+            let next = guard.entries.peek().map(|entry| entry.deadline);
+            match next {
+                Some(deadline) if deadline <= now => {
+                    let entry = guard.entries.pop();
+                    // Release the queue, as subscribers schedule their next deadline
+                    // while holding the lock of their demultiplexer.
+                    drop(guard);
+                    if let Some(entry) = entry {
+                        if let Some(demux) = entry.demux.upgrade() {
+                            Demultiplexer::on_deadline(&demux, entry.key, entry.deadline);
+                        }
+                    }
+                    guard = lock.lock().unwrap();
+                }
+                Some(deadline) => {
+                    guard = condvar.wait_timeout(guard, deadline.duration_since(now)).unwrap().0;
+                }
+                None => {
+                    guard = condvar.wait(guard).unwrap();
+                }
+            }
+        }
+    }
+}
+
+/// A watcher of a getter, as seen by the `Demultiplexer` of this getter.
+struct Subscriber {
+    /// The range requested by the watcher, or `None` to be informed of every value.
+    range: Option<Range>,
+
+    /// The state of `range`, evaluated locally.
+    local: Option<LocalRangeState>,
+
+    /// The value of the latest event sent to the watcher, for `Range::Changed` and
+    /// `Range::Delta`.
+    last_delivered: Option<Value>,
+
+    /// The generation of the adapter watch expected to serve this subscriber. Used to
+    /// report registration errors.
+    joined: usize,
+
+    /// `true` while we are fetching the initial state requested by the watcher. Values
+    /// reported in the meantime are not delivered, as the initial state supersedes them.
+    awaiting_initial_state: bool,
+
+    /// The pace at which events are sent to the watcher, if it has requested one.
+    pacing: Option<Pacing>,
+
+    /// The deadline for which we have queued an entry in `timer`, if any.
+    armed: Option<Instant>,
+
+    /// Wakes us up once the deadlines of `local` and `pacing` expire.
+    timer: Timer,
+
+    on_event: Box<ExtSender<StampedEvent>>,
+    log: Arc<Mutex<EventLog>>,
+    is_dropped: Arc<AtomicBool>,
+}
+
+impl Subscriber {
+    fn new(watcher: &WatcherData, range: Option<Range>, timer: &Timer) -> Self {
+        let local = match range {
+            None | Some(Range::Changed) | Some(Range::Delta(_)) => None,
+            Some(ref range) => Some(LocalRangeState::new(range.clone()))
+        };
+        Subscriber {
+            local: local,
+            last_delivered: None,
+            range: range,
+            joined: 0,
+            awaiting_initial_state: false,
+            pacing: Pacing::new(&watcher.options),
+            armed: None,
+            timer: timer.clone(),
+            on_event: watcher.on_event.lock().unwrap().internal_clone(),
+            log: watcher.log.clone(),
+            is_dropped: watcher.is_dropped.clone(),
+        }
+    }
+
+    /// Send an `EnterRange` or `ExitRange` event to the watcher, respecting the pace it has
+    /// requested. Delayed events are sent by the `Timer`.
+    fn deliver(&mut self, key: WatchKey, demux: &Arc<Mutex<Demultiplexer>>, event: WatchEvent, stamp: &Stamp) {
+        let event = stamp.apply(event);
+        let now = Instant::now();
+        let delay = match self.pacing {
+            None => {
+                let _ = self.on_event.send(event);
+                return;
+            }
+            Some(ref mut pacing) => {
+                if pacing.coalesce || pacing.debounce.is_some() {
+                    // Latest event wins.
+                    pacing.pending.clear();
+                }
+                pacing.pending.push_back(event);
+                if pacing.pending.len() > MAX_PENDING_EVENTS {
+                    if let Some(dropped) = pacing.pending.pop_front() {
+                        pacing.truncated = Some(dropped.sequence);
+                    }
+                }
+                let throttle = pacing.until_next(now);
+                if let Some(debounce) = pacing.debounce {
+                    // Restart the countdown.
+                    cmp::max(debounce, throttle)
+                } else if pacing.deadline.is_some() {
+                    // The event will be sent along with the pending ones.
+                    return;
+                } else {
+                    throttle
+                }
+            }
+        };
+        self.schedule_flush(key, demux, delay);
+    }
+
+    /// Send the oldest pending event after `delay`, replacing any flush scheduled previously.
+    fn schedule_flush(&mut self, key: WatchKey, demux: &Arc<Mutex<Demultiplexer>>, delay: StdDuration) {
+        if delay == StdDuration::new(0, 0) {
+            self.flush(key, demux);
+            return;
+        }
+        match self.pacing {
+            None => return,
+            Some(ref mut pacing) => pacing.deadline = Some(Instant::now() + delay)
+        }
+        self.arm(demux, key);
+    }
+
+    /// Send the oldest pending event now, then schedule the next one, if any.
+    fn flush(&mut self, key: WatchKey, demux: &Arc<Mutex<Demultiplexer>>) {
+        let now = Instant::now();
+        let delay = match self.pacing {
+            None => return,
+            Some(ref mut pacing) => {
+                pacing.deadline = None;
+                if let Some(discarded) = pacing.truncated.take() {
+                    let event = self.log.lock().unwrap().stamp(WatchEvent::ReplayTruncated {
+                        discarded: discarded
+                    });
+                    let _ = self.on_event.send(event);
+                }
+                if let Some(event) = pacing.pending.pop_front() {
+                    let _ = self.on_event.send(event);
+                    pacing.last_sent = Some(now);
+                }
+                if pacing.pending.is_empty() {
+                    return;
+                }
+                pacing.until_next(now)
+            }
+        };
+        self.schedule_flush(key, demux, delay);
+    }
+
+    /// Make sure that the `Timer` wakes us up by the earliest of our deadlines.
+    ///
+    /// We keep at most one entry in the timer: if a deadline is postponed or cancelled, the
+    /// entry expires early and `on_deadline` queues a new one if needed.
+    fn arm(&mut self, demux: &Arc<Mutex<Demultiplexer>>, key: WatchKey) {
+        let dwell = match self.local {
+            Some(LocalRangeState { pending_enter: Some((deadline, _, _)), .. }) => Some(deadline),
+            _ => None
+        };
+        let flush = self.pacing.as_ref().and_then(|pacing| pacing.deadline);
+        let deadline = match (dwell, flush) {
+            (Some(dwell), Some(flush)) => cmp::min(dwell, flush),
+            (Some(deadline), None) | (None, Some(deadline)) => deadline,
+            (None, None) => return
+        };
+        if let Some(armed) = self.armed {
+            if armed <= deadline {
+                return;
+            }
+        }
+        self.armed = Some(deadline);
+        self.timer.schedule(deadline, demux, key);
+    }
+
+    /// Fire the events whose deadline has expired, then wait for the next deadline.
+    fn on_deadline(&mut self, key: WatchKey, demux: &Arc<Mutex<Demultiplexer>>, latest: &Option<Value>) {
+        let now = Instant::now();
+        let entered = match (&mut self.local, latest) {
+            (&mut Some(ref mut local), &Some(ref value)) => {
+                match local.pending_enter.take() {
+                    Some((deadline, id, stamp)) => {
+                        if deadline <= now {
+                            local.is_reported = true;
+                            Some((WatchEvent::EnterRange {
+                                from: id,
+                                value: value.clone()
+                            }, stamp))
+                        } else {
+                            local.pending_enter = Some((deadline, id, stamp));
+                            None
+                        }
+                    }
+                    None => None
+                }
+            }
+            _ => None
+        };
+        if let Some((event, stamp)) = entered {
+            self.deliver(key, demux, event, &stamp);
+        }
+        let is_flush_due = match self.pacing {
+            Some(Pacing { deadline: Some(deadline), .. }) => deadline <= now,
+            _ => false
+        };
+        if is_flush_due {
+            self.flush(key, demux);
+        }
+        self.arm(demux, key);
+    }
+
+    /// Send an event that does not depend on the values of getters.
+    fn notify(&self, event: WatchEvent) {
+        let event = self.log.lock().unwrap().stamp(event);
+        let _ = self.on_event.send(event);
+    }
+
+    /// Start evaluating the range from a value that the watcher is assumed to know, without
+    /// sending any event. Returns `true` if the value is in the range.
+    fn assume_value(&mut self, value: &Value) -> bool {
+        match self.local {
+            Some(ref mut local) => {
+                // Subsequent events report changes from this state. If the range
+                // has a dwell time, the value is considered to have been there long
+                // enough.
+                local.tracker.update(value);
+                local.pending_enter = None;
+                local.is_reported = local.tracker.is_in();
+                local.is_reported
+            }
+            None => {
+                // `Changed`, `Delta` or no range.
+                if self.range.is_some() {
+                    self.last_delivered = Some(value.clone());
+                }
+                true
+            }
+        }
+    }
+
+    /// Start evaluating the range from the current value of the getter, if known, and
+    /// determine the `InitialState` event to send to this subscriber.
+    fn on_initial_state(&mut self, id: &Id<Getter>, value: Option<Value>) -> WatchEvent {
+        self.awaiting_initial_state = false;
+        let is_in_range = match value {
+            None => false,
+            Some(ref value) => self.assume_value(value)
+        };
+        WatchEvent::InitialState {
+            from: id.clone(),
+            value: value,
+            is_in_range: is_in_range
+        }
+    }
+
+    /// Replay the events caused by the values recorded in the `EventLog` after `sequence`.
+    fn replay(&mut self, key: WatchKey, demux: &Arc<Mutex<Demultiplexer>>, id: &Id<Getter>, sequence: u64) {
+        let (before, after) = self.log.lock().unwrap().since(id, sequence);
+        if let Some(ref value) = before {
+            // The watcher received the events caused by this value before disconnecting.
+            self.assume_value(value);
+        }
+        for entry in after {
+            if let Some(event) = self.on_value(id, &entry.value, entry.is_enter, &entry.stamp) {
+                self.deliver(key, demux, event, &entry.stamp);
+            }
+        }
+        self.arm(demux, key);
+    }
+
+    /// Determine the event to send to this subscriber upon a value reported by the adapter.
+    /// If the range has a dwell time, `EnterRange` is delayed: call `arm` once done.
+    fn on_value(&mut self, id: &Id<Getter>, value: &Value, is_enter: bool, stamp: &Stamp) -> Option<WatchEvent> {
+        match self.range {
+            Some(Range::Changed) | Some(Range::Delta(_)) => {
+                let is_significant = match (&self.range, &self.last_delivered) {
+                    (_, &None) => true,
+                    (&Some(Range::Delta(ref delta)), &Some(ref last)) => {
+                        match value.checked_steps(last, delta) {
+                            Ok(ratio) => ratio.abs() > 1.,
+                            // Not a quantity, fall back to `Changed`.
+                            Err(_) => value != last
+                        }
+                    }
+                    (_, &Some(ref last)) => value != last
+                };
+                if !is_significant {
+                    return None;
+                }
+                self.last_delivered = Some(value.clone());
+                return Some(WatchEvent::EnterRange {
+                    from: id.clone(),
+                    value: value.clone()
+                });
+            }
+            _ => {}
+        }
+        let local = match self.local {
+            None => {
+                // No range, the adapter reports every value.
+                return Some(if is_enter {
+                    WatchEvent::EnterRange {
+                        from: id.clone(),
+                        value: value.clone()
+                    }
+                } else {
+                    WatchEvent::ExitRange {
+                        from: id.clone(),
+                        value: value.clone()
+                    }
+                })
+            }
+            Some(ref mut local) => local
+        };
+        match local.tracker.update(value) {
+            None => None,
+            Some(true) => {
+                match local.dwell {
+                    None => {
+                        local.is_reported = true;
+                        Some(WatchEvent::EnterRange {
+                            from: id.clone(),
+                            value: value.clone()
+                        })
+                    }
+                    Some(dwell) => {
+                        // Fire `EnterRange` only if the value is still in the range
+                        // once `dwell` has elapsed, see `on_deadline`.
+                        local.pending_enter = Some((Instant::now() + dwell, id.clone(), stamp.clone()));
+                        None
+                    }
+                }
+            }
+            Some(false) => {
+                // Cancel any pending `EnterRange`.
+                local.pending_enter = None;
+                if local.is_reported {
+                    local.is_reported = false;
+                    Some(WatchEvent::ExitRange {
+                        from: id.clone(),
+                        value: value.clone()
+                    })
+                } else {
+                    None
+                }
             }
         }
     }
 }
 
+/// Dispatches the values reported by the adapter for a getter to all the watchers of this
+/// getter.
+///
+/// The adapter is only expected to understand plain ranges, and only receives one registration
+/// per getter, so each watcher evaluates its own range against the values reported.
+pub struct Demultiplexer {
+    /// The generation of the adapter watch currently in use. Events sent by older
+    /// registrations, which are being replaced, are ignored.
+    active: usize,
+
+    /// The latest value reported by the adapter.
+    latest: Option<Value>,
+
+    /// Where values are recorded as they are reported.
+    log: Arc<Mutex<EventLog>>,
+
+    /// Shared with subscribers, to run their deadlines.
+    timer: Timer,
+
+    subscribers: HashMap<WatchKey, Subscriber>,
+}
+
+impl Demultiplexer {
+    fn new(log: &Arc<Mutex<EventLog>>, timer: &Timer) -> Self {
+        Demultiplexer {
+            active: 0,
+            latest: None,
+            log: log.clone(),
+            timer: timer.clone(),
+            subscribers: HashMap::new(),
+        }
+    }
+
+    /// Called by the `Timer` once a deadline of a subscriber has expired.
+    fn on_deadline(demux: &Arc<Mutex<Demultiplexer>>, key: WatchKey, deadline: Instant) {
+        let mut guard = demux.lock().unwrap();
+        let this = &mut *guard;
+        let subscriber = match this.subscribers.get_mut(&key) {
+            None => return, // The watcher has been removed in the meantime.
+            Some(subscriber) => subscriber
+        };
+        if subscriber.armed == Some(deadline) {
+            subscriber.armed = None;
+        }
+        if subscriber.is_dropped.load(Ordering::Relaxed) {
+            return;
+        }
+        subscriber.on_deadline(key, demux, &this.latest);
+    }
+
+    /// The range to register with the adapter on behalf of all subscribers: `None` if there
+    /// are no subscribers, `Some(None)` if the adapter must report every value.
+    ///
+    /// The adapter is registered with the union of the plain ranges that trigger the
+    /// subscribers, so that it filters out the values that matter to nobody. Each subscriber
+    /// then evaluates its own range against the values reported, using its last known value
+    /// to decide whether it entered or exited its range. If a subscriber has no range, or a
+    /// range that cannot be reduced to plain ranges, we need every value.
+    fn covering_range(&self) -> Option<Option<Range>> {
+        if self.subscribers.is_empty() {
+            return None;
+        }
+        let mut covering : Option<Range> = None;
+        for subscriber in self.subscribers.values() {
+            let triggers = match subscriber.range {
+                None => return Some(None),
+                Some(ref range) => match range.triggers() {
+                    None => return Some(None),
+                    Some(triggers) => triggers
+                }
+            };
+            for trigger in triggers {
+                covering = Some(match covering {
+                    None => trigger,
+                    Some(covering) => covering.union(&trigger)
+                });
+            }
+        }
+        match covering {
+            Some(ref covering) if covering.is_plain() => Some(Some(covering.clone())),
+            _ => Some(None)
+        }
+    }
+
+    /// Send the initial state of the getter to a new subscriber, from the latest value reported
+    /// by the adapter if there is one, otherwise from a value fetched from the adapter, if the
+    /// getter is `pollable`.
+    fn start_initial_state(demux: &Arc<Mutex<Demultiplexer>>, key: WatchKey, id: &Id<Getter>, adapter: &Arc<Adapter>, pollable: bool) {
+        {
+            let mut guard = demux.lock().unwrap();
+            let this = &mut *guard;
+            let subscriber = match this.subscribers.get_mut(&key) {
+                None => return,
+                Some(subscriber) => subscriber
+            };
+            if let Some(ref value) = this.latest {
+                let event = subscriber.on_initial_state(id, Some(value.clone()));
+                subscriber.notify(event);
+                return;
+            }
+            if !pollable {
+                let event = subscriber.on_initial_state(id, None);
+                subscriber.notify(event);
+                return;
+            }
+            subscriber.awaiting_initial_state = true;
+        }
+
+        // Fetching may be slow, so do it out of the lock.
+        let demux = demux.clone();
+        let adapter = adapter.clone();
+        let id = id.clone();
+        thread::spawn(move || {
+            let fetched = match adapter.fetch_values(vec![id.clone()], User::None).remove(&id) {
+                Some(Ok(value)) => value,
+                Some(Err(err)) => {
+                    warn!(target: "Taxonomy-backend", "Could not fetch the initial state of {}: {:?}", id, err);
+                    None
+                }
+                None => None
+            };
+            let mut guard = demux.lock().unwrap();
+            let this = &mut *guard;
+            // If the adapter has reported a value in the meantime, it supersedes the value fetched.
+            let value = match this.latest {
+                Some(ref value) => Some(value.clone()),
+                None => fetched
+            };
+            let subscriber = match this.subscribers.get_mut(&key) {
+                None => return, // The watcher has been removed in the meantime.
+                Some(subscriber) => subscriber
+            };
+            if subscriber.is_dropped.load(Ordering::Relaxed) {
+                return;
+            }
+            let event = subscriber.on_initial_state(&id, value);
+            subscriber.notify(event);
+        });
+    }
+
+    fn dispatch(demux: &Arc<Mutex<Demultiplexer>>, generation: usize, event: AdapterWatchEvent) {
+        let mut guard = demux.lock().unwrap();
+        let this = &mut *guard;
+        if generation != this.active {
+            // This registration is being replaced.
+            return;
+        }
+        let (id, value, is_enter) = match event {
+            AdapterWatchEvent::Enter { id, value } => (id, value, true),
+            AdapterWatchEvent::Exit { id, value } => (id, value, false)
+        };
+        this.latest = Some(value.clone());
+        let stamp = this.log.lock().unwrap().record(&id, &value, is_enter);
+        for (key, subscriber) in &mut this.subscribers {
+            if subscriber.is_dropped.load(Ordering::Relaxed) {
+                // The WatchGuard has already been dropped.
+                // We want to stop propagating messages immediately, even if unregistration
+                // is not necessarily complete yet. Unregistration will be completed after
+                // the call to `stop_watch`.
+                continue;
+            }
+            if subscriber.awaiting_initial_state {
+                continue;
+            }
+            if let Some(event) = subscriber.on_value(&id, &value, is_enter, &stamp) {
+                subscriber.deliver(*key, demux, event, &stamp);
+            }
+            subscriber.arm(demux, *key);
+        }
+    }
+}
+
+/// The channel through which one generation of the adapter watch of a getter reports values.
+struct DemuxSender {
+    demux: Arc<Mutex<Demultiplexer>>,
+    generation: usize,
+}
+
+impl ExtSender<AdapterWatchEvent> for DemuxSender {
+    fn send(&self, event: AdapterWatchEvent) -> Result<(), ()> {
+        Demultiplexer::dispatch(&self.demux, self.generation, event);
+        Ok(())
+    }
+    fn internal_clone(&self) -> Box<ExtSender<AdapterWatchEvent>> {
+        Box::new(DemuxSender {
+            demux: self.demux.clone(),
+            generation: self.generation,
+        })
+    }
+}
+
+/// The watch registered with the adapter on behalf of all the watchers of a getter.
+struct GetterWatch {
+    demux: Arc<Mutex<Demultiplexer>>,
+
+    /// The range last requested from the adapter, if any. `Some(None)` if we have requested
+    /// every value.
+    requested: Option<Option<Range>>,
+
+    /// Incremented with each registration request.
+    generation: usize,
+
+    /// The guard of the registration currently in use.
+    guard: Option<Box<AdapterWatchGuard>>,
+}
+
+impl GetterWatch {
+    fn new(log: &Arc<Mutex<EventLog>>, timer: &Timer) -> Self {
+        GetterWatch {
+            demux: Arc::new(Mutex::new(Demultiplexer::new(log, timer))),
+            requested: None,
+            generation: 0,
+            guard: None,
+        }
+    }
+}
+
 pub struct WatchMap {
     /// A counter of all watchers that have been added to the system.
     /// Used to generate unique keys.
     counter: usize,
     watchers: HashMap<WatchKey, Arc<WatcherData>>,
     liveness: Arc<Liveness>,
+    log: Arc<Mutex<EventLog>>,
 }
 impl WatchMap {
-    fn new(liveness: &Arc<Liveness>) -> Self {
+    fn new(liveness: &Arc<Liveness>, log: &Arc<Mutex<EventLog>>) -> Self {
         WatchMap {
             counter: 0,
             watchers: HashMap::new(),
-            liveness: liveness.clone()
+            liveness: liveness.clone(),
+            log: log.clone(),
         }
     }
-    fn create(&mut self, watch:TargetMap<GetterSelector, Exactly<Range>>, on_event: Box<ExtSender<WatchEvent>>) -> Arc<WatcherData> {
+    fn create(&mut self, watch:TargetMap<GetterSelector, Exactly<Range>>, options: WatchOptions, on_event: Box<ExtSender<StampedEvent>>) -> Arc<WatcherData> {
         let id = WatchKey(self.counter);
         self.counter += 1;
-        let watcher = Arc::new(WatcherData::new(&self.liveness, id, watch, on_event));
+        let watcher = Arc::new(WatcherData::new(&self.liveness, &self.log, id, watch, options, on_event));
         self.watchers.insert(id, watcher.clone());
         watcher
     }
@@ -375,6 +1268,12 @@ pub struct State {
     /// and a when a new value is available from a getter channel.
     watchers: Arc<Mutex<WatchMap>>,
 
+    /// The latest values reported for watched getters.
+    event_log: Arc<Mutex<EventLog>>,
+
+    /// Runs the dwell times and pacing of watchers.
+    timer: Timer,
+
     /// Information on whether the lock holding the state is open/closed,
     /// mutable/immutable.
     liveness: Arc<Liveness>,
@@ -480,7 +1379,9 @@ impl State {
         result
     }
 
-    fn aux_getter_may_need_unregistration(getter_data: &mut GetterData, is_being_removed: bool) {
+    fn aux_getter_may_need_unregistration(getter_data: &mut GetterData, is_being_removed: bool,
+        adapter_by_id: &HashMap<Id<AdapterId>, AdapterData>, per_adapter: &mut WatchRequest)
+    {
         let mut keys_to_drop = vec![];
         {
             for (key, ref watcher) in &getter_data.watchers {
@@ -512,23 +1413,129 @@ impl State {
                 }
 
                 // Inform of topology change
-                let on_event = &watcher.on_event;
-                let _ = on_event.lock()
-                    .unwrap()
-                    .send(WatchEvent::GetterRemoved(getter_data.id.clone()));
+                watcher.notify(WatchEvent::GetterRemoved(getter_data.id.clone()));
 
-                // Drop individual guard.
-                watcher.guards.borrow_mut().remove(&getter_data.id);
+                watcher.getters.borrow_mut().remove(&getter_data.id);
                 keys_to_drop.push(*key);
             }
         }
-        for key in keys_to_drop {
-            getter_data.watchers.remove(&key);
+        if keys_to_drop.is_empty() {
+            return;
+        }
+        {
+            let mut demux = getter_data.watch.demux.lock().unwrap();
+            for key in &keys_to_drop {
+                getter_data.watchers.remove(key);
+                demux.subscribers.remove(key);
+            }
+        }
+        if !is_being_removed {
+            // The remaining watchers may need a narrower watch.
+            let id = getter_data.channel.id.clone();
+            let adapter = getter_data.channel.adapter.clone();
+            Self::aux_update_getter_watch(&id, &adapter, &mut getter_data.watch, adapter_by_id, per_adapter);
+        }
+    }
+
+    /// Make sure that the watch registered with the adapter for a getter matches the needs of
+    /// its current watchers, once watchers have been added or removed.
+    ///
+    /// If necessary, the new registration is added to `per_adapter`. Watchers keep being served
+    /// by the previous registration until the new one is in place.
+    fn aux_update_getter_watch(id: &Id<Getter>, adapter: &Id<AdapterId>, watch: &mut GetterWatch,
+        adapter_by_id: &HashMap<Id<AdapterId>, AdapterData>, per_adapter: &mut WatchRequest)
+    {
+        use std::collections::hash_map::Entry::*;
+
+        let mut demux = watch.demux.lock().unwrap();
+        let range = match demux.covering_range() {
+            None => {
+                // Nobody is interested in this getter anymore, stop watching it.
+                if watch.requested.is_some() || watch.guard.is_some() {
+                    watch.requested = None;
+                    watch.generation += 1;
+                    demux.active = watch.generation;
+                    drop(demux);
+
+                    // Dropping the guard may wait for the adapter, which may itself be
+                    // waiting for `demux`.
+                    watch.guard = None;
+                }
+                return;
+            }
+            Some(range) => range
+        };
+        if let Some(ref requested) = watch.requested {
+            if *requested == range {
+                // The current registration already covers all watchers.
+                return;
+            }
+            if let (&Some(ref range), &Some(ref value)) = (&range, &demux.latest) {
+                if range.contains(value) {
+                    // Adapters do not know the latest value when we register a watch, so
+                    // the next exit from the narrower range could be missed. Keep the current
+                    // registration, which reports a superset of what we need.
+                    return;
+                }
+            }
+        }
+
+        let adapter = match adapter_by_id.get(adapter) {
+            None => {
+                log_debug_assert!(false, "Internal inconsistency: Could not find adapter {:?}", adapter);
+                return;
+            },
+            Some(adapter_data) => adapter_data.adapter.clone()
+        };
+        watch.generation += 1;
+        watch.requested = Some(range.clone());
+
+        let request = (id.clone(), range, watch.generation, watch.demux.clone());
+        match per_adapter.entry(adapter.id()) {
+            Vacant(entry) => {
+                entry.insert((adapter, vec![request]));
+            },
+            Occupied(mut entry) => {
+                (entry.get_mut().1).push(request);
+            }
         }
     }
 
-    fn aux_getters_may_need_registration(&mut self, getters: Vec<Id<Getter>>) -> WatchRequest {
+    /// Start watching the getters that match ongoing watchers.
+    ///
+    /// If the getters have just been added along with `service`, each watcher is informed
+    /// once of all the getters it starts watching, through `WatchEvent::GettersAdded`.
+    /// Otherwise, it is informed once per getter, through `WatchEvent::GetterAdded`.
+    fn aux_getters_may_need_registration(&mut self, getters: Vec<Id<Getter>>, service: Option<&Id<ServiceId>>) -> WatchRequest {
         debug!(target: "Taxonomy-backend", "checking if getters need to be watched {:?}", getters);
+        if let Some(service) = service {
+            // Inform of topology change, before any value may reach the watchers.
+            for watcher in self.watchers.lock().unwrap().watchers.values() {
+                if watcher.is_dropped.load(Ordering::Relaxed) {
+                    continue;
+                }
+                let added: Vec<_> = getters.iter().filter(|id| {
+                    if watcher.getters.borrow().contains(*id) {
+                        return false;
+                    }
+                    match self.getter_by_id.get(*id) {
+                        None => false,
+                        Some(getter_data) => {
+                            let getter_data = getter_data.borrow();
+                            watcher.watch.iter().any(|targetted| {
+                                targetted.select.iter().any(|selector| getter_data.matches(selector))
+                            })
+                        }
+                    }
+                }).cloned().collect();
+                if !added.is_empty() {
+                    watcher.notify(WatchEvent::GettersAdded {
+                        service: service.clone(),
+                        getters: added
+                    });
+                }
+            }
+        }
         let adapter_by_id = &self.adapter_by_id;
         let mut per_adapter = HashMap::new();
         for id in getters {
@@ -542,7 +1549,7 @@ impl State {
 
                     // Determine if the channel matches an ongoing watcher.
                     for watcher in &mut self.watchers.lock().unwrap().watchers.values() {
-                        if watcher.guards.borrow().contains_key(&id) {
+                        if watcher.getters.borrow().contains(&id) {
                             // The watcher already matches this getter.
                             continue;
                         }
@@ -560,12 +1567,13 @@ impl State {
                             }
 
                             // Inform of topology change.
-                            let on_event = &watcher.on_event;
-                            let _ = on_event.lock().unwrap().send(WatchEvent::GetterAdded(id.clone()));
+                            if service.is_none() {
+                                watcher.notify(WatchEvent::GetterAdded(id.clone()));
+                            }
 
                             // Register to be informed of future changes.
                             Self::aux_start_channel_watch(&mut watcher.clone(),
-                                &mut *getter_data, &targetted.payload, adapter_by_id, &mut per_adapter)
+                                &mut *getter_data, &targetted.payload, None, adapter_by_id, &mut per_adapter)
                         }
                     }
                 }
@@ -594,13 +1602,16 @@ impl State {
 
 impl State {
     pub fn new(liveness: &Arc<Liveness>, db_path: Option<PathBuf>) -> Self {
+        let event_log = Arc::new(Mutex::new(EventLog::new()));
         State {
             liveness: liveness.clone(),
             adapter_by_id: HashMap::new(),
             service_by_id: HashMap::new(),
             getter_by_id: HashMap::new(),
             setter_by_id: HashMap::new(),
-            watchers: Arc::new(Mutex::new(WatchMap::new(liveness))),
+            watchers: Arc::new(Mutex::new(WatchMap::new(liveness, &event_log))),
+            event_log: event_log,
+            timer: Timer::new(),
             db_path: db_path,
        }
     }
@@ -640,6 +1651,41 @@ impl State {
         Ok(())
     }
 
+    /// Replace an adapter with a new instance with the same id, typically upon restart.
+    ///
+    /// The services and channels of the former instance are kept. The watches registered
+    /// with the former instance are dropped, and the returned request registers them again
+    /// with the new instance.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no adapter with this identifier exists.
+    pub fn replace_adapter(&mut self, adapter: Arc<Adapter>) -> Result<WatchRequest, Error> {
+        let id = adapter.id();
+        let services = match self.adapter_by_id.remove(&id) {
+            None => return Err(Error::InternalError(InternalError::NoSuchAdapter(id))),
+            Some(AdapterData { services, .. }) => services
+        };
+        let mut data = AdapterData::new(adapter);
+        data.services = services;
+        self.adapter_by_id.insert(id.clone(), data);
+
+        let adapter_by_id = &self.adapter_by_id;
+        let mut per_adapter = HashMap::new();
+        for getter in self.getter_by_id.values_mut() {
+            let mut getter = getter.borrow_mut();
+            if getter.channel.adapter != id {
+                continue;
+            }
+            let getter = &mut *getter;
+            // The former instance won't report anything anymore.
+            getter.watch.requested = None;
+            getter.watch.guard = None;
+            Self::aux_update_getter_watch(&getter.channel.id, &id, &mut getter.watch, adapter_by_id, &mut per_adapter);
+        }
+        Ok(per_adapter)
+    }
+
     /// Add a service to the system. Called by the adapter when a new
     /// service (typically a new device) has been detected/configured.
     ///
@@ -707,6 +1753,121 @@ impl State {
         Ok(())
     }
 
+    /// Add a service to the system, along with all its getters and setters, in a single
+    /// transaction.
+    ///
+    /// Watchers never observe the service without its channels. Each watcher that starts
+    /// watching some of the getters is informed once, through `WatchEvent::GettersAdded`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of:
+    /// - a channel doesn't belong to `service` or to its adapter;
+    /// - a channel has constraints that do not match its kind;
+    /// - a service or channel with the same identifier is already installed on the system;
+    /// - there is no adapter with id `service.adapter`.
+    ///
+    /// In either case, this method reverts all its changes.
+    pub fn add_service_with_channels(&mut self, mut service: Service) -> Result<WatchRequest, Error> {
+        let mut getters: Vec<_> = service.getters.drain().map(|(_, getter)| getter).collect();
+        let mut setters: Vec<_> = service.setters.drain().map(|(_, setter)| setter).collect();
+        for getter in &getters {
+            if getter.service != service.id {
+                return Err(Error::InternalError(InternalError::InvalidInitialService));
+            }
+            if getter.adapter != service.adapter {
+                return Err(Error::InternalError(InternalError::ConflictingAdapter(service.adapter.clone(), getter.adapter.clone())));
+            }
+            try!(getter.mechanism.constraints.ensure_type(&getter.mechanism.kind.get_type())
+                .map_err(Error::TypeError));
+        }
+        for setter in &setters {
+            if setter.service != service.id {
+                return Err(Error::InternalError(InternalError::InvalidInitialService));
+            }
+            if setter.adapter != service.adapter {
+                return Err(Error::InternalError(InternalError::ConflictingAdapter(service.adapter.clone(), setter.adapter.clone())));
+            }
+            try!(setter.mechanism.constraints.ensure_type(&setter.mechanism.kind.get_type())
+                .map_err(Error::TypeError));
+        }
+
+        // Synchronize the tags with the database.
+        if let Some(ref path) = self.db_path {
+            let mut store = TagStorage::new(&path);
+            match store.get_tags_for(&service.id) {
+                Err(err) => return Err(Error::InternalError(InternalError::GenericError(format!("{}", err)))),
+                Ok(tags) => {
+                    for tag in tags {
+                        let _ = service.tags.insert(tag);
+                    }
+                }
+            };
+            for getter in &mut getters {
+                if let Ok(all_tags) = store.get_tags_for(&getter.id) {
+                    getter.insert_tags(&all_tags);
+                }
+            }
+            for setter in &mut setters {
+                if let Ok(all_tags) = store.get_tags_for(&setter.id) {
+                    setter.insert_tags(&all_tags);
+                }
+            }
+        }
+
+        let id = service.id.clone();
+        let mut service = ServiceData::new(&self.liveness, service);
+        let getter_ids: Vec<_> = getters.iter().map(|getter| getter.id.clone()).collect();
+        let getters: Vec<_> = getters.into_iter().map(|getter| {
+            let getter_id = getter.id.clone();
+            (getter_id, Arc::new(SubCell::new(&self.liveness, GetterData::new(getter, service.tags.clone(), &self.event_log, &self.timer))))
+        }).collect();
+        let setters: Vec<_> = setters.into_iter().map(|setter| {
+            let setter_id = setter.id.clone();
+            (setter_id, Arc::new(SubCell::new(&self.liveness, SetterData::new(setter, service.tags.clone()))))
+        }).collect();
+        service.getters = getters.iter().cloned().collect();
+        service.setters = setters.iter().cloned().collect();
+        let adapter = service.adapter.clone();
+        let service = Arc::new(SubCell::new(&self.liveness, service));
+
+        {
+            let services_for_this_adapter =
+                match self.adapter_by_id.get_mut(&adapter) {
+                    None => return Err(Error::InternalError(InternalError::NoSuchAdapter(adapter.clone()))),
+                    Some(&mut AdapterData {ref mut services, ..}) => services
+                };
+            let insert_in_adapters =
+                match InsertInMap::start(services_for_this_adapter, vec![(id.clone(), service.clone())]) {
+                    Err(k) => return Err(Error::InternalError(InternalError::DuplicateService(k))),
+                    Ok(transaction) => transaction
+                };
+            let insert_in_services =
+                match InsertInMap::start(&mut self.service_by_id, vec![(id.clone(), service.clone())]) {
+                    Err(k) => return Err(Error::InternalError(InternalError::DuplicateService(k))),
+                    Ok(transaction) => transaction
+                };
+            let insert_in_getters =
+                match InsertInMap::start(&mut self.getter_by_id, getters) {
+                    Err(k) => return Err(Error::InternalError(InternalError::DuplicateGetter(k))),
+                    Ok(transaction) => transaction
+                };
+            let insert_in_setters =
+                match InsertInMap::start(&mut self.setter_by_id, setters) {
+                    Err(k) => return Err(Error::InternalError(InternalError::DuplicateSetter(k))),
+                    Ok(transaction) => transaction
+                };
+
+            // If we haven't bailed out yet, leave all this stuff in the maps and sets.
+            insert_in_adapters.commit();
+            insert_in_services.commit();
+            insert_in_getters.commit();
+            insert_in_setters.commit();
+        }
+
+        Ok(self.aux_getters_may_need_registration(getter_ids, Some(&id)))
+    }
+
     /// Remove a service previously registered on the system. Typically, called by
     /// an adapter when a service (e.g. a device) is disconnected.
     ///
@@ -744,6 +1905,9 @@ impl State {
     /// registered, or a channel with the same identifier is already registered.
     /// In either cases, this method reverts all its changes.
     pub fn add_getter(&mut self, mut getter: Channel<Getter>) -> Result<WatchRequest, Error> {
+        try!(getter.mechanism.constraints.ensure_type(&getter.mechanism.kind.get_type())
+            .map_err(Error::TypeError));
+
         // Add the database tags to this getter.
         if let Some(ref path) = self.db_path {
             let mut store = TagStorage::new(&path);
@@ -765,7 +1929,7 @@ impl State {
                 return Err(Error::InternalError(InternalError::ConflictingAdapter(service.adapter.clone(), getter.adapter.clone())));
             }
             let getters = &mut service.getters;
-            let getter_data = Arc::new(SubCell::new(&self.liveness, GetterData::new(getter, service.tags.clone())));
+            let getter_data = Arc::new(SubCell::new(&self.liveness, GetterData::new(getter, service.tags.clone(), &self.event_log, &self.timer)));
 
             let insert_in_service = match InsertInMap::start(getters, vec![(id.clone(), getter_data.clone())]) {
                 Ok(transaction) => transaction,
@@ -781,7 +1945,7 @@ impl State {
             insert_in_getters.commit();
         }
 
-        Ok(self.aux_getters_may_need_registration(vec![id]))
+        Ok(self.aux_getters_may_need_registration(vec![id], None))
     }
 
 
@@ -799,7 +1963,9 @@ impl State {
             None => return Err(Error::InternalError(InternalError::NoSuchGetter(id.clone()))),
             Some(getter) => getter
         };
-        Self::aux_getter_may_need_unregistration(&mut *getter.borrow_mut(), true);
+        // The getter is going away along with its adapter watch, nothing to re-register.
+        Self::aux_getter_may_need_unregistration(&mut *getter.borrow_mut(), true,
+            &self.adapter_by_id, &mut HashMap::new());
 
         let service_id = &getter.borrow().channel.service;
         match self.service_by_id.get_mut(&service_id) {
@@ -828,6 +1994,9 @@ impl State {
     /// registered, or a channel with the same identifier is already registered.
     /// In either cases, this method reverts all its changes.
     pub fn add_setter(&mut self, mut setter: Channel<Setter>) -> Result<(), Error> {
+        try!(setter.mechanism.constraints.ensure_type(&setter.mechanism.kind.get_type())
+            .map_err(Error::TypeError));
+
         // Add the database tags to this setter.
         if let Some(ref path) = self.db_path {
             let mut store = TagStorage::new(&path);
@@ -970,7 +2139,7 @@ impl State {
                 size += 1;
             });
         }
-        (self.aux_getters_may_need_registration(channels), size)
+        (self.aux_getters_may_need_registration(channels, None), size)
     }
 
     pub fn add_setter_tags(&mut self, selectors: Vec<SetterSelector>, tags: Vec<Id<TagId>>) -> usize {
@@ -989,9 +2158,11 @@ impl State {
         result
     }
 
-    pub fn remove_getter_tags(&mut self, selectors: Vec<GetterSelector>, tags: Vec<Id<TagId>>) -> usize {
+    pub fn remove_getter_tags(&mut self, selectors: Vec<GetterSelector>, tags: Vec<Id<TagId>>) -> (WatchRequest, usize) {
         let mut result = 0;
         let db_path = self.db_path.clone();
+        let adapter_by_id = &self.adapter_by_id;
+        let mut per_adapter = HashMap::new();
         Self::with_channels_mut(selectors, &mut self.getter_by_id, |mut data| {
             if data.remove_tags(&tags) {
                 if let Some(ref path) = db_path {
@@ -1000,10 +2171,10 @@ impl State {
                          .unwrap_or_else(|err| { error!("Storage remove_tags error: {}", err); });
                 }
             }
-            Self::aux_getter_may_need_unregistration(&mut data, false);
+            Self::aux_getter_may_need_unregistration(&mut data, false, adapter_by_id, &mut per_adapter);
             result += 1;
         });
-        result
+        (per_adapter, result)
     }
     pub fn remove_setter_tags(&mut self, selectors: Vec<SetterSelector>, tags: Vec<Id<TagId>>) -> usize {
         let mut result = 0;
@@ -1030,7 +2201,14 @@ impl State {
         Self::with_channels(selectors, &self.getter_by_id, |data| {
             use std::collections::hash_map::Entry::*;
             let id = data.channel.id.clone();
-            let typ = data.channel.mechanism.kind.get_type();
+            let kind = data.channel.mechanism.kind.clone();
+
+            // Getters that have declared that they cannot be polled never reach the Adapter.
+            let checked = if data.channel.mechanism.capabilities.pollable {
+                Ok(kind)
+            } else {
+                Err(Error::GetterDoesNotSupportPolling(id.clone()))
+            };
             match per_adapter.entry(data.adapter.clone()) {
                 Vacant(entry) => {
                     let adapter = match adapter_by_id.get(&data.channel.adapter) {
@@ -1042,11 +2220,28 @@ impl State {
                             adapter_data.adapter.clone()
                         }
                     };
-                    let mut source = vec![(id, typ)];
-                    entry.insert((adapter, source.drain(..).collect()));
+                    let mut request = HashMap::new();
+                    let mut failures = HashMap::new();
+                    match checked {
+                        Ok(kind) => {
+                            request.insert(id, kind);
+                        }
+                        Err(error) => {
+                            failures.insert(id, Err(error));
+                        }
+                    }
+                    entry.insert((adapter, (request, failures)));
                 }
                 Occupied(mut entry) => {
-                    entry.get_mut().1.insert(id, typ);
+                    let &mut (_, (ref mut request, ref mut failures)) = entry.get_mut();
+                    match checked {
+                        Ok(kind) => {
+                            request.insert(id, kind);
+                        }
+                        Err(error) => {
+                            failures.insert(id, Err(error));
+                        }
+                    }
                 }
             };
         });
@@ -1063,17 +2258,12 @@ impl State {
                 use std::collections::hash_map::Entry::*;
                 let id = data.channel.id.clone();
 
-                // Check that the values we are about to send have the correct type. If they
-                // don't, no need to even send them to the Adapter.
-                let typ = data.channel.mechanism.kind.get_type();
-                let checked = if value.get_type() == typ {
-                    Ok(value.clone())
-                } else {
-                    Err(Error::TypeError(TypeError {
-                        got: value.get_type(),
-                        expected: typ
-                    }))
-                };
+                // Check that the values we are about to send are acceptable for the channel,
+                // starting with their type. If they don't, no need to even send them to the
+                // Adapter.
+                let checked = data.channel.mechanism.kind.ensure_accepts(&value)
+                    .and_then(|()| data.channel.mechanism.constraints.ensure_accepts(&value))
+                    .map(|()| value.clone());
                 match per_adapter.entry(data.channel.adapter.clone()) {
                     Vacant(entry) => {
                         let mut request = HashMap::new();
@@ -1115,11 +2305,10 @@ impl State {
     fn aux_start_channel_watch(watcher: &mut Arc<WatcherData>,
         getter_data: &mut GetterData,
         filter: &Exactly<Range>,
+        resume_from: Option<u64>,
         adapter_by_id: &HashMap<Id<AdapterId>, AdapterData>,
         per_adapter: &mut WatchRequest)
     {
-        use std::collections::hash_map::Entry::*;
-
         let id = getter_data.id.clone();
         let adapter = getter_data.adapter.clone();
 
@@ -1132,6 +2321,7 @@ impl State {
             Ok(transaction) => transaction
         };
 
+        watcher.getters.borrow_mut().insert(id.clone());
         let range = match *filter {
             Exactly::Exactly(ref range) => Some(range.clone()),
             Exactly::Always => None,
@@ -1141,22 +2331,56 @@ impl State {
             }
         };
 
-        match per_adapter.entry(adapter) {
-            Vacant(entry) => {
-                let adapter = match adapter_by_id.get(&getter_data.channel.adapter) {
-                    None => {
-                        log_debug_assert!(false, "Internal inconsistency: Could not find adapter {:?}",
-                            getter_data.channel.adapter);
-                        return;
-                    },
-                    Some(ref adapter_data) => {
-                        adapter_data.adapter.clone()
-                    }
-                };
-                entry.insert((adapter, (vec![(id, range, Arc::downgrade(watcher) )])));
-            },
-            Occupied(mut entry) => {
-                (entry.get_mut().1).push((id, range, Arc::downgrade(watcher)));
+        // Getters that have declared that they cannot be watched this way never reach the
+        // Adapter, unless it polls them instead. The watcher still follows their topology.
+        let refused = {
+            let capabilities = &getter_data.channel.mechanism.capabilities;
+            let is_polled = capabilities.pollable && adapter_by_id.get(&adapter)
+                .map_or(false, |data| data.polls_unwatchable_getters);
+            if !capabilities.watchable && !is_polled {
+                Some(Error::GetterDoesNotSupportWatching(id.clone()))
+            } else if capabilities.requires_threshold && range.is_none() {
+                Some(Error::GetterRequiresThresholdForWatching(id.clone()))
+            } else {
+                None
+            }
+        };
+        if let Some(error) = refused {
+            watcher.notify(WatchEvent::InitializationError {
+                channel: id,
+                error: error
+            });
+            insert_in_getter.commit();
+            return;
+        }
+
+        // Subscribe to the values of the getter, then make sure that the adapter reports
+        // everything this watcher needs.
+        {
+            let mut demux = getter_data.watch.demux.lock().unwrap();
+            let subscriber = Subscriber::new(watcher, range, &demux.timer);
+            demux.subscribers.insert(watcher.key, subscriber);
+        }
+        Self::aux_update_getter_watch(&id, &adapter, &mut getter_data.watch, adapter_by_id, per_adapter);
+        if let Some(subscriber) = getter_data.watch.demux.lock().unwrap().subscribers.get_mut(&watcher.key) {
+            subscriber.joined = getter_data.watch.generation;
+        }
+        if let Some(sequence) = resume_from {
+            let demux = &getter_data.watch.demux;
+            let mut guard = demux.lock().unwrap();
+            if let Some(subscriber) = guard.subscribers.get_mut(&watcher.key) {
+                subscriber.replay(watcher.key, demux, &id, sequence);
+            }
+        }
+        if watcher.options.initial_state {
+            match adapter_by_id.get(&adapter) {
+                None => {
+                    log_debug_assert!(false, "Internal inconsistency: Could not find adapter {:?}", adapter);
+                }
+                Some(adapter_data) => {
+                    Demultiplexer::start_initial_state(&getter_data.watch.demux, watcher.key, &id, &adapter_data.adapter,
+                        getter_data.channel.mechanism.capabilities.pollable)
+                }
             }
         }
 
@@ -1164,14 +2388,25 @@ impl State {
     }
 
     pub fn prepare_channel_watch(&mut self, mut watch: TargetMap<GetterSelector, Exactly<Range>>,
-        on_event: Box<ExtSender<WatchEvent>>) -> (WatchRequest, WatchKey, Arc<AtomicBool>)
+        options: WatchOptions, on_event: Box<ExtSender<StampedEvent>>) -> (WatchRequest, WatchKey, Arc<AtomicBool>)
     {
         // Prepare the watcher and store it. Once we leave the lock, every time a channel is
         // added/removed/updated, this will cause us to reexamine whether the channel should
         // be visible to a watcher.
-        let mut watcher = self.watchers.lock().unwrap().create(watch.clone(), on_event.clone());
+        let mut watcher = self.watchers.lock().unwrap().create(watch.clone(), options, on_event.clone());
         let is_dropped = watcher.is_dropped.clone();
 
+        // If the watcher resumes, tell it whether some of the values it has missed are lost.
+        let resume_from = watcher.options.resume_from;
+        if let Some(sequence) = resume_from {
+            let discarded = self.event_log.lock().unwrap().discarded;
+            if discarded > sequence {
+                watcher.notify(WatchEvent::ReplayTruncated {
+                    discarded: discarded
+                });
+            }
+        }
+
         // Regroup per adapter.
         let mut per_adapter = HashMap::new();
         let adapter_by_id = &self.adapter_by_id;
@@ -1180,7 +2415,7 @@ impl State {
             // the watcher immediately.
             let filter = &filter;
             Self::with_channels_mut(selectors, &mut self.getter_by_id, |mut getter_data| {
-                Self::aux_start_channel_watch(&mut watcher, &mut getter_data, filter,
+                Self::aux_start_channel_watch(&mut watcher, &mut getter_data, filter, resume_from,
                     adapter_by_id, &mut per_adapter)
             });
         }
@@ -1192,10 +2427,14 @@ impl State {
 
     /// Unregister a watch previously registered with `register_channel_watch`.
     ///
-    /// This method is dispatched from `WatchGuard::drop()`.
-    pub fn stop_watch(&mut self, key: WatchKey) {
+    /// This method is dispatched from `WatchGuard::drop()`. The remaining watchers of the
+    /// getters may be served by narrower watches, in which case the result needs to be
+    /// passed to `start_watch`.
+    pub fn stop_watch(&mut self, key: WatchKey) -> WatchRequest {
         // Note: no matter when we arrive here, `is_dropped` is already set to `true`.
 
+        let mut per_adapter = HashMap::new();
+
         // Remove `key` from `watchers`. This will prevent the watcher from being registered
         // automatically with any new getter.
         let watcher_data = match self.watchers.lock().unwrap().remove(key) {
@@ -1204,7 +2443,7 @@ impl State {
                 // This can happen in case of race if `stop_watch` is executed before
                 // `start_watch`. Since `is_dropped` is `true`, `start_watch` will be
                 // a noop for this watcher, so we're good.
-                return
+                return per_adapter
             }
             Some(watcher_data) => watcher_data
         };
@@ -1212,102 +2451,81 @@ impl State {
         log_debug_assert!(watcher_data.is_dropped.load(Ordering::Relaxed), "The watcher should have been dropped by now.");
 
         // Remove the watcher from all getters.
-        for getter_id in watcher_data.guards.borrow().keys() {
+        let adapter_by_id = &self.adapter_by_id;
+        for getter_id in watcher_data.getters.borrow().iter() {
             let getter = match self.getter_by_id.get_mut(getter_id) {
                 None => continue, // Race condition between removing the getter and dropping the watcher.
                 Some(getter) => getter
             };
-            if getter.borrow_mut().watchers.remove(&watcher_data.key).is_none() {
+            let mut getter = getter.borrow_mut();
+            let getter_data = &mut *getter;
+            if getter_data.watchers.remove(&watcher_data.key).is_none() {
                 debug_assert!(false, "Attempting to unregister a watcher that has already been removed from its getter {:?}, {:?}", key, getter_id);
             }
+            getter_data.watch.demux.lock().unwrap().subscribers.remove(&watcher_data.key);
+            let id = getter_data.channel.id.clone();
+            let adapter = getter_data.channel.adapter.clone();
+            Self::aux_update_getter_watch(&id, &adapter, &mut getter_data.watch, adapter_by_id, &mut per_adapter);
         }
 
-        // At this stage, one getter may still have a strong reference to watcher_data, if it has
-        // just been upgraded in `start_watch`. However, this reference will disappear soon. Once
-        // the last reference has disappeared, all `guards` will be dropped.
+        per_adapter
     }
 
     /// Start watching a set of channels.
     pub fn start_watch(mut per_adapter: WatchRequest) -> WatchGuardCommit {
-        // In most cases, stop_watch will take place long after start_watch. It is, however,
-        // possible that the `WatchGuard` is dropped before start_watch is processed for this
-        // channel. In this case, three events take place:
-        // 1. The `WatchGuard` sets `is_dropped` to `true`, atomically.
-        // 2a. The `WatchGuard` dispatches `stop_watch`, which is serialized.
-        // 2b. Someone dispatches `start_watch`, which is serialized.
-        //
-        // Since `start_watch` and `stop_watch` are serialized, either 2a or 2b will win.
+        // Watch requests are prepared while holding the lock, but executed without the
+        // lock. Several requests for the same getter may therefore be executed out of order.
+        // Each request carries a generation, and only the latest generation delivers events
+        // to the watchers. If a watcher has been dropped in the meantime, its `is_dropped`
+        // flag ensures that it doesn't receive events anymore.
         //
-        // A/ If 2a takes place before 2b.
-        //  - The call to `stop_watch` is a noop, as there is nothing to remove.
-        //  - The call to `start_watch` is a noop, as `is_dropped` is `true.`
-        //
-        // B/ If 2b takes place before 2a.
-        //  - The call to `start_watch` is a noop, as `is_dropped` is `true`.
-        //  - The call to `stop_watch` is a noop, as there is nothing to remove.
-        //   by checking whether `is_dropped` is true.
-
+        // Events reach the watchers through `Demultiplexer::dispatch`, which evaluates the
+        // range of each watcher and respects the pace it has requested in its `WatchOptions`.
         let mut to_add = vec![];
         for (_, (adapter, mut adapter_request)) in per_adapter.drain() {
-            for (id, range, weak_watch_data) in adapter_request.drain(..) {
-                let watch_data = match weak_watch_data.upgrade() {
+            let mut targets = Vec::with_capacity(adapter_request.len());
+            let mut demuxes = HashMap::new();
+            for (id, range, generation, demux) in adapter_request.drain(..) {
+                let sender = DemuxSender {
+                    demux: demux.clone(),
+                    generation: generation,
+                };
+                targets.push((id.clone(), range, Box::new(sender) as Box<ExtSender<AdapterWatchEvent>>));
+                demuxes.insert(id, (generation, demux));
+            }
+
+            for (id, result) in adapter.register_watch(targets) {
+                debug!(target: "Taxonomy-backend", "State::start_watch, registered watch for {} => {}.", id, result.is_ok());
+                let (generation, demux) = match demuxes.get(&id) {
                     None => {
-                        // The watch_data has already been dropped, nothing to do.
-                        debug!(target: "Taxonomy-backend", "State::start_watch, the guard has been dropped, cannot upgrade, skipping.");
-                        continue
+                        log_debug_assert!(false, "The adapter has registered an unexpected getter {:?}", id);
+                        continue;
                     }
-                    Some(watch_data) => watch_data
+                    Some(&(generation, ref demux)) => (generation, demux.clone())
                 };
-                let is_dropped = watch_data.is_dropped.clone();
-                if is_dropped.load(Ordering::Relaxed) {
-                    // The WatchGuard has already been dropped.
-                    debug!(target: "Taxonomy-backend", "State::start_watch, the guard has been dropped, is_dropped detected, skipping.");
-                    return continue;
-                }
-                let on_ok = watch_data.on_event.lock().unwrap().filter_map(move |event| {
-                    if is_dropped.load(Ordering::Relaxed) {
-                        debug!(target: "Taxonomy-backend", "State::start_watch, the guard has been dropped, is_dropped detected, don't propagate messages.");
-
-                        // The WatchGuard has already been dropped.
-                        // We want to stop propagating messages immediately, even if unregistration
-                        // is not necessarily complete yet. Unregistration will be completed after
-                        // the call to `stop_watch`.
-                        return None;
+                let mut demux = demux.lock().unwrap();
+                match result {
+                    Ok(guard) => {
+                        if generation > demux.active {
+                            // Start delivering events from this registration.
+                            demux.active = generation;
+                        }
+                        to_add.push((id, generation, Some(guard)));
                     }
-                    Some(match event {
-                        AdapterWatchEvent::Enter { id, value } =>
-                            WatchEvent::EnterRange {
-                                from: id,
-                                value: value
-                            },
-                        AdapterWatchEvent::Exit { id, value } =>
-                            WatchEvent::ExitRange {
-                                from: id,
-                                value: value
-                            },
-                    })
-                });
-
-                let mut guards = vec![];
-                for (id, result) in adapter.register_watch(vec![(id, range, Box::new(on_ok))]) {
-                    debug!(target: "Taxonomy-backend", "State::start_watch, registered watch for {} => {}.", id, result.is_ok());
-
-                    match result {
-                        Err(err) => {
-                            let event = WatchEvent::InitializationError {
-                                channel: id.clone(),
-                                error: err
-                            };
-                            let _ = watch_data.on_event.lock().unwrap().send(event);
-                        },
-                        // Calling `watch_data.push((id, guard))` requires .write(), so we delay
-                        // this until we have grabbed the lock again.
-                        Ok(guard) => guards.push((id, guard))
+                    Err(err) => {
+                        let active = demux.active;
+                        for subscriber in demux.subscribers.values() {
+                            if subscriber.joined <= generation && subscriber.joined > active {
+                                // This subscriber is not served by any registration.
+                                subscriber.notify(WatchEvent::InitializationError {
+                                    channel: id.clone(),
+                                    error: err.clone()
+                                });
+                            }
+                        }
+                        to_add.push((id, generation, None));
                     }
                 }
-                if !guards.is_empty() {
-                    to_add.push((weak_watch_data, guards));
-                }
             }
         }
         to_add
@@ -1316,14 +2534,112 @@ impl State {
     /// Register a bunch of ongoing watches previously started by `start_watch`.
     pub fn register_ongoing_watch(&mut self, mut ongoing: WatchGuardCommit)
     {
-        for (watch_data, mut guards) in ongoing.drain(..) {
-            if let Some(ref watch_data) = watch_data.upgrade() {
-                for (id, guard) in guards.drain(..) {
-                    debug!(target: "Taxonomy-backend", "State::register_ongoing_watch, registered watch for {}", id);
-                    watch_data.push_guard(id, guard)
+        for (id, generation, guard) in ongoing.drain(..) {
+            let getter = match self.getter_by_id.get_mut(&id) {
+                None => continue, // The getter has been removed in the meantime, drop the guard.
+                Some(getter) => getter
+            };
+            let mut getter = getter.borrow_mut();
+            let watch = &mut getter.watch;
+            match guard {
+                None => {
+                    if generation == watch.generation {
+                        // Registration has failed, make sure that we try again next time.
+                        watch.requested = None;
+                    }
                 }
+                Some(guard) => {
+                    let active = watch.demux.lock().unwrap().active;
+                    if generation == active {
+                        debug!(target: "Taxonomy-backend", "State::register_ongoing_watch, registered watch for {}", id);
+                        // This drops the guard of the registration we are replacing, if any.
+                        watch.guard = Some(guard);
+                    }
+                    // Otherwise, this registration has been superseded, drop the guard.
+                }
+            }
+        }
+    }
+}
+
+impl State {
+    /// All the adapters currently registered.
+    pub fn get_adapters(&self) -> Vec<Arc<Adapter>> {
+        self.adapter_by_id.values()
+            .map(|data| data.adapter.clone())
+            .collect()
+    }
+
+    /// A registered adapter.
+    pub fn get_adapter(&self, id: &Id<AdapterId>) -> Result<Arc<Adapter>, Error> {
+        match self.adapter_by_id.get(id) {
+            None => Err(Error::InternalError(InternalError::NoSuchAdapter(id.clone()))),
+            Some(data) => Ok(data.adapter.clone())
+        }
+    }
+
+    /// Record the status reported by an adapter.
+    ///
+    /// Returns the new status, or `None` if the status has not changed.
+    pub fn set_adapter_status(&mut self, id: &Id<AdapterId>, state: AdapterState, reason: Option<String>) ->
+        Result<Option<AdapterStatus>, Error>
+    {
+        let data = match self.adapter_by_id.get_mut(id) {
+            None => return Err(Error::InternalError(InternalError::NoSuchAdapter(id.clone()))),
+            Some(data) => data
+        };
+        if data.status.state == state && data.status.reason == reason {
+            return Ok(None);
+        }
+        data.status = AdapterStatus {
+            adapter: id.clone(),
+            state: state,
+            reason: reason,
+            since: TimeStamp::from_datetime(UTC::now()),
+        };
+        Ok(Some(data.status.clone()))
+    }
+
+    pub fn get_adapter_statuses(&self) -> Vec<AdapterStatus> {
+        self.adapter_by_id.values()
+            .map(|data| data.status.clone())
+            .collect()
+    }
+
+    /// The adapters that have reported that they are offline.
+    pub fn get_offline_adapters(&self) -> HashSet<Id<AdapterId>> {
+        self.adapter_by_id.values()
+            .filter(|data| data.status.state == AdapterState::Offline)
+            .map(|data| data.status.adapter.clone())
+            .collect()
+    }
+
+    pub fn get_adapter_config(&self, id: &Id<AdapterId>) -> Result<AdapterConfig, Error> {
+        match self.adapter_by_id.get(id) {
+            None => Err(Error::InternalError(InternalError::NoSuchAdapter(id.clone()))),
+            Some(data) => Ok(data.config.clone())
+        }
+    }
+
+    /// Record the configuration accepted by an adapter.
+    pub fn set_adapter_config(&mut self, id: &Id<AdapterId>, config: AdapterConfig) -> Result<(), Error> {
+        match self.adapter_by_id.get_mut(id) {
+            None => Err(Error::InternalError(InternalError::NoSuchAdapter(id.clone()))),
+            Some(data) => {
+                data.config = config;
+                Ok(())
+            }
+        }
+    }
+
+    /// The adapter offering a registered service.
+    pub fn get_service_adapter(&self, id: &Id<ServiceId>) -> Result<Arc<Adapter>, Error> {
+        for data in self.adapter_by_id.values() {
+            if data.services.contains_key(id) {
+                return Ok(data.adapter.clone());
             }
         }
+        Err(Error::InternalError(InternalError::NoSuchService(id.clone())))
     }
 }
 
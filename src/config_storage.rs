@@ -0,0 +1,136 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+///! This is the database that holds the configuration of adapters, alongside tags.
+///! Each configuration is stored under the id of its adapter.
+
+use migration::IdMigration;
+use services::AdapterConfig;
+use util::{ AdapterId, Id };
+
+use rusqlite::{ Connection, Result };
+use serde_json;
+use std::path::PathBuf;
+
+/// A lightweight struct to manage the database. Creating these objects is very cheap because the
+/// underlying database is created lazily when we need it.
+pub struct ConfigStorage {
+    db: Option<Connection>,
+    path: PathBuf,
+}
+
+impl ConfigStorage {
+    pub fn new(path: &PathBuf) -> Self {
+        ConfigStorage {
+            db: None,
+            path: path.clone()
+        }
+    }
+
+    // Ensures that we have a database ready. If we fail to open or create the database,
+    // this will panic.
+    fn ensure_db(&mut self) {
+        if self.db.is_some() {
+            return;
+        }
+
+        info!("Opening taxonomy config database at {}", self.path.display());
+        let db = Connection::open(self.path.clone()).unwrap_or_else(|err| {
+            panic!("Unable to open taxonomy config database: {}", err);
+        });
+
+        Self::create_table(&db).unwrap_or_else(|err| {
+            panic!("Unable to create taxonomy config database: {}", err);
+        });
+
+        self.db = Some(db);
+    }
+
+    /// Create the table of configurations, if it doesn't exist yet.
+    pub fn create_table(db: &Connection) -> Result<()> {
+        try!(db.execute("CREATE TABLE IF NOT EXISTS adapter_config (
+                    adapter TEXT NOT NULL PRIMARY KEY,
+                    config  TEXT NOT NULL
+            )", &[]));
+        Ok(())
+    }
+
+    /// Move the configuration of the adapters renamed by `migration` to their new id. A
+    /// configuration already stored for the new id is kept.
+    ///
+    /// This doesn't start a transaction, see `migration::migrate_storage`.
+    pub fn migrate(db: &Connection, migration: &IdMigration) -> Result<()> {
+        for (former, new) in &migration.adapters {
+            try!(db.execute("UPDATE OR IGNORE adapter_config SET adapter=$1 WHERE adapter=$2",
+                            &[&new.to_string(), &former.to_string()]));
+            try!(db.execute("DELETE FROM adapter_config WHERE adapter=$1", &[&former.to_string()]));
+        }
+        Ok(())
+    }
+
+    pub fn put_config(&mut self, adapter: &Id<AdapterId>, config: &AdapterConfig) -> Result<()> {
+        self.ensure_db();
+        // Serialization only fails for maps with non-string keys, which we do not have.
+        let config = serde_json::to_string(config).unwrap();
+        try!(self.db.as_ref().unwrap().execute("INSERT OR REPLACE INTO adapter_config VALUES ($1, $2)",
+                        &[&adapter.to_string(), &config]));
+        Ok(())
+    }
+
+    /// Return the configuration stored for an adapter, or `None` if there is none or if it
+    /// cannot be decoded, e.g. because it was stored by an incompatible version.
+    pub fn get_config(&mut self, adapter: &Id<AdapterId>) -> Result<Option<AdapterConfig>> {
+        self.ensure_db();
+        let mut stmt = try!(self.db.as_ref().unwrap().prepare("SELECT config FROM adapter_config WHERE adapter=$1"));
+        let rows = try!(stmt.query(&[&adapter.to_string()]));
+        for result_row in rows {
+            let row = try!(result_row);
+            match serde_json::from_str(&row.get::<String>(0)) {
+                Ok(config) => return Ok(Some(config)),
+                Err(err) => {
+                    error!("Cannot decode the configuration of adapter {}: {}", adapter, err);
+                    return Ok(None);
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[test]
+#[allow(unused_variables)]
+fn storage_test() {
+    use tag_storage::{ get_db_environment, remove_test_db };
+    use values::{ OnOff, Value };
+
+    // Simple RAII style struct to delete the test db.
+    struct AutoDeleteDb { };
+    impl Drop for AutoDeleteDb {
+        fn drop(&mut self) {
+            remove_test_db();
+        }
+    }
+    let auto_db = AutoDeleteDb { };
+
+    let mut store = ConfigStorage::new(&get_db_environment());
+
+    let adapter = Id::<AdapterId>::new("adapter 1");
+
+    // Start with an empty db.
+    assert_eq!(store.get_config(&adapter).unwrap(), None);
+
+    // Store a configuration.
+    let mut config = AdapterConfig::new();
+    config.insert("enabled".to_owned(), Value::OnOff(OnOff::On));
+    store.put_config(&adapter, &config).unwrap();
+    assert_eq!(store.get_config(&adapter).unwrap(), Some(config.clone()));
+
+    // Storing a configuration for the same adapter replaces it.
+    config.insert("enabled".to_owned(), Value::OnOff(OnOff::Off));
+    store.put_config(&adapter, &config).unwrap();
+    assert_eq!(store.get_config(&adapter).unwrap(), Some(config));
+
+    // Other adapters are not affected.
+    assert_eq!(store.get_config(&Id::new("adapter 2")).unwrap(), None);
+}
@@ -2,7 +2,7 @@
 //! Used for testing.
 use adapter::*;
 
-use api::{ Error, User };
+use api::{ DiscoveryEvent, Error, InternalError, User };
 use selector::*;
 use services::*;
 use values::*;
@@ -24,13 +24,35 @@ pub enum Tweak {
 
     /// Inject an error in a virtual setter. All operations on this setter will
     /// raise the error until `None` is injected instead.
-    InjectSetterError(Id<Setter>, Option<Error>)
+    InjectSetterError(Id<Setter>, Option<Error>),
+
+    /// Inject an error in a virtual getter. All attempts to watch this getter will
+    /// raise the error until `None` is injected instead.
+    InjectWatchError(Id<Getter>, Option<Error>),
+
+    /// Make a virtual device available for pairing, with the credentials it expects.
+    /// The device is reported to discovery, if discovery is in progress.
+    InjectPendingDevice(PendingDevice, Option<Credentials>),
+
+    /// Declare the fields accepted by the configuration of the adapter.
+    InjectConfigSchema(Vec<ConfigField>),
+
+    /// Inject an error in the configuration of the adapter. All attempts to configure the
+    /// adapter will raise the error until `None` is injected instead.
+    InjectConfigError(Option<Error>),
+
+    /// Make the adapter panic. All attempts to fetch or send values will panic until `false`
+    /// is injected instead.
+    InjectPanic(bool),
 }
 
 /// Something that happened to the virtual device, e.g. a value was sent.
 #[derive(Debug)]
 pub enum Effect {
-    ValueSent(Id<Setter>, Value)
+    ValueSent(Id<Setter>, Value),
+    Paired(Id<DeviceId>),
+    Unpaired(Id<ServiceId>),
+    Configured(AdapterConfig),
 }
 
 fn dup<T>(t: T) -> (T, T) where T: Clone {
@@ -61,6 +83,12 @@ pub struct FakeAdapter {
     rx_effect: Mutex<Option<Receiver<Effect>>>,
     values: SyncMap<Id<Getter>, Result<Value, Error>>,
     senders: SyncMap<Id<Setter>, Error>,
+    watch_errors: SyncMap<Id<Getter>, Error>,
+    pending_devices: SyncMap<Id<DeviceId>, (PendingDevice, Option<Credentials>)>,
+    discovery: Arc<Mutex<Vec<Box<ExtSender<DiscoveryEvent>>>>>,
+    config_schema: Arc<Mutex<Vec<ConfigField>>>,
+    config_error: Arc<Mutex<Option<Error>>>,
+    panics: Arc<AtomicBool>,
     watchers: SyncMap<Id<Getter>, Vec<WatcherState>>
 }
 
@@ -71,6 +99,13 @@ impl FakeAdapter {
 
         let (values_main, values_thread) = dup(Arc::new(Mutex::new(HashMap::new())));
         let (senders_main, senders_thread) = dup(Arc::new(Mutex::new(HashMap::new())));
+        let (watch_errors_main, watch_errors_thread) = dup(Arc::new(Mutex::new(HashMap::new())));
+        let (pending_devices_main, pending_devices_thread) = dup(Arc::new(Mutex::new(HashMap::new())));
+        let (discovery_main, discovery_thread) : (Arc<Mutex<Vec<Box<ExtSender<DiscoveryEvent>>>>>, _) =
+            dup(Arc::new(Mutex::new(vec![])));
+        let (config_schema_main, config_schema_thread) = dup(Arc::new(Mutex::new(vec![])));
+        let (config_error_main, config_error_thread) = dup(Arc::new(Mutex::new(None)));
+        let (panics_main, panics_thread) = dup(Arc::new(AtomicBool::new(false)));
         let (watchers_main, watchers_thread) = dup(Arc::new(Mutex::new(HashMap::new())));
 
         let mutex = Arc::new(Mutex::new(tx));
@@ -84,6 +119,12 @@ impl FakeAdapter {
             name: id.as_atom().to_string().clone(),
             values: values_main,
             senders: senders_main,
+            watch_errors: watch_errors_main,
+            pending_devices: pending_devices_main,
+            discovery: discovery_main,
+            config_schema: config_schema_main,
+            config_error: config_error_main,
+            panics: panics_main,
             tweak: Arc::new(tweak),
             tx_effect: Mutex::new(Box::new(tx_effect)),
             rx_effect: Mutex::new(Some(rx_effect)),
@@ -142,6 +183,27 @@ impl FakeAdapter {
                     InjectSetterError(id, Some(err)) => {
                         senders_thread.lock().unwrap().insert(id, err);
                     }
+                    InjectWatchError(id, None) => {
+                        watch_errors_thread.lock().unwrap().remove(&id);
+                    },
+                    InjectWatchError(id, Some(err)) => {
+                        watch_errors_thread.lock().unwrap().insert(id, err);
+                    }
+                    InjectPendingDevice(device, credentials) => {
+                        for on_event in discovery_thread.lock().unwrap().iter() {
+                            let _ = on_event.send(DiscoveryEvent::DeviceFound(device.clone()));
+                        }
+                        pending_devices_thread.lock().unwrap().insert(device.id.clone(), (device, credentials));
+                    }
+                    InjectConfigSchema(schema) => {
+                        *config_schema_thread.lock().unwrap() = schema;
+                    }
+                    InjectConfigError(error) => {
+                        *config_error_thread.lock().unwrap() = error;
+                    }
+                    InjectPanic(panics) => {
+                        panics_thread.store(panics, Ordering::Relaxed);
+                    }
                 }
                 tx.send(()).unwrap();
             }
@@ -183,6 +245,9 @@ impl Adapter for FakeAdapter {
     /// Request a value from a channel. The `FoxBox` (not the adapter)
     /// is in charge of keeping track of the age of values.
     fn fetch_values(&self, mut channels: Vec<Id<Getter>>, _: User) -> ResultMap<Id<Getter>, Option<Value>, Error> {
+        if self.panics.load(Ordering::Relaxed) {
+            panic!("Injected panic in adapter {}", self.id);
+        }
         let map = self.values.lock().unwrap();
         channels.drain(..).map(|id| {
             let result = match map.get(&id) {
@@ -196,6 +261,9 @@ impl Adapter for FakeAdapter {
 
     /// Request that a value be sent to a channel.
     fn send_values(&self, mut values: HashMap<Id<Setter>, Value>, _: User) -> ResultMap<Id<Setter>, (), Error> {
+        if self.panics.load(Ordering::Relaxed) {
+            panic!("Injected panic in adapter {}", self.id);
+        }
         let map = self.senders.lock().unwrap();
         values.drain().map(|(id, value)| {
             let result = match map.get(&id) {
@@ -209,14 +277,79 @@ impl Adapter for FakeAdapter {
         }).collect()
     }
 
+    fn start_discovery(&self, on_event: Box<ExtSender<DiscoveryEvent>>) -> Result<(), Error> {
+        let _ = on_event.send(DiscoveryEvent::Started {
+            adapter: self.id.clone()
+        });
+        for &(ref device, _) in self.pending_devices.lock().unwrap().values() {
+            let _ = on_event.send(DiscoveryEvent::DeviceFound(device.clone()));
+        }
+        self.discovery.lock().unwrap().push(on_event);
+        Ok(())
+    }
+
+    fn stop_discovery(&self) {
+        for on_event in self.discovery.lock().unwrap().drain(..) {
+            let _ = on_event.send(DiscoveryEvent::Stopped {
+                adapter: self.id.clone()
+            });
+        }
+    }
+
+    fn get_pending_devices(&self) -> Vec<PendingDevice> {
+        self.pending_devices.lock().unwrap().values()
+            .map(|&(ref device, _)| device.clone())
+            .collect()
+    }
+
+    fn pair(&self, device: &Id<DeviceId>, credentials: Option<Credentials>) -> Result<(), Error> {
+        let mut pending_devices = self.pending_devices.lock().unwrap();
+        match pending_devices.get(device) {
+            None => return Err(Error::InternalError(InternalError::NoSuchDevice(device.clone()))),
+            Some(&(_, ref expected)) if *expected != credentials =>
+                return Err(Error::InvalidCredentials(device.clone())),
+            Some(_) => {}
+        }
+        pending_devices.remove(device);
+        self.tx_effect.lock().unwrap().send(Effect::Paired(device.clone())).unwrap();
+        Ok(())
+    }
+
+    fn unpair(&self, service: &Id<ServiceId>) -> Result<(), Error> {
+        self.tx_effect.lock().unwrap().send(Effect::Unpaired(service.clone())).unwrap();
+        Ok(())
+    }
+
+    fn config_schema(&self) -> Vec<ConfigField> {
+        self.config_schema.lock().unwrap().clone()
+    }
+
+    fn set_config(&self, config: &AdapterConfig) -> Result<(), Error> {
+        if let Some(ref error) = *self.config_error.lock().unwrap() {
+            return Err(error.clone());
+        }
+        self.tx_effect.lock().unwrap().send(Effect::Configured(config.clone())).unwrap();
+        Ok(())
+    }
+
     fn register_watch(&self, mut watch: Vec<WatchTarget>) -> WatchResult {
         let mut watchers = self.watchers.lock().unwrap();
+        let values = self.values.lock().unwrap();
+        let watch_errors = self.watch_errors.lock().unwrap();
         watch.drain(..).map(|(id, filter, on_event)| {
+            if let Some(error) = watch_errors.get(&id).cloned() {
+                return (id, Err(error));
+            }
             let is_dropped = Arc::new(AtomicBool::new(false));
+            // Start from the current value, as a device would.
+            let is_met = match (&filter, values.get(&id)) {
+                (&Some(ref range), Some(&Ok(ref value))) => range.contains(value),
+                _ => false
+            };
             let watcher = WatcherState {
                 filter: filter,
                 on_event: on_event,
-                is_met: RefCell::new(false),
+                is_met: RefCell::new(is_met),
                 is_dropped: is_dropped.clone()
             };
             match watchers.entry(id.clone()) {
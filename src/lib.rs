@@ -40,6 +40,9 @@ pub mod util;
 /// The back-end thread, in charge of the heavy lifting of managing adapters.
 mod backend;
 
+/// Isolation of adapters that panic.
+mod supervisor;
+
 /// The manager provides an API for (un)registering adapters, services, channels, and
 /// uses these to implements the taxonomy API.
 pub mod manager;
@@ -57,6 +60,27 @@ pub mod transact;
 /// Implementation of the database storing tags.
 pub mod tag_storage;
 
+/// Implementation of the database storing durable watches.
+pub mod watch_storage;
+
+/// Implementation of the database storing the configuration of adapters.
+pub mod config_storage;
+
+/// Renaming identifiers in the taxonomy database.
+pub mod migration;
+
+/// An adapter running in another process, speaking JSON-RPC over a pipe.
+#[cfg(unix)]
+pub mod proxy_adapter;
+
+/// The other side of a `ProxyAdapter`, serving an adapter over a pipe.
+#[cfg(unix)]
+pub mod proxy_child;
+
+/// Adapters built as shared libraries and loaded at runtime.
+#[cfg(unix)]
+pub mod plugin;
+
 /// Implementation of a fake adapter, controlled entirely programmatically. Designed to be used
 /// as a component of tests.
 pub mod fake_adapter;
@@ -6,14 +6,19 @@
 
 pub use adapter::*;
 use api;
-use api::{ API, Error, TargetMap, User };
+use api::{ API, DiscoveryEvent, Error, InternalError, StampedEvent, TargetMap, User, WatchEvent, WatchOptions };
 use backend::*;
 use selector::*;
 use services::*;
 use util::is_sync;
-use values::{ Range, TypeError, Value };
-
-use std::collections::HashMap;
+use values::{ Range, Value };
+use config_storage::ConfigStorage;
+use migration::{ migrate_storage, IdMigration };
+use supervisor::Supervisor;
+use tag_storage::TagStorage;
+use watch_storage::WatchStorage;
+
+use std::collections::{ HashMap, VecDeque };
 use std::path::PathBuf;
 use std::sync::{ Arc, Mutex, Weak };
 use std::sync::atomic::{ AtomicBool, Ordering };
@@ -36,21 +41,45 @@ pub struct AdapterManager {
     back_end: Arc<MainLock<State>>,
 
     tx_watch: Arc<Mutex<RawSender<WatchOp>>>,
+
+    /// The path to the taxonomy database, if any.
+    db_path: Option<PathBuf>,
+
+    /// The durable watches, by name.
+    durable_watches: Mutex<HashMap<Id<WatchId>, DurableWatch>>,
+
+    /// The clients of `start_discovery` that are still open.
+    discovery: Broadcast<DiscoveryEvent>,
+
+    /// The clients of `watch_adapter_statuses` that are still open.
+    statuses: Broadcast<AdapterStatus>,
+
+    /// In charge of isolating and restarting the adapters that panic.
+    supervisor: Arc<Supervisor>,
 }
 
 impl AdapterManager {
     /// Create an empty `AdapterManager`.
-    /// This function does not attempt to load any state from the disk.
+    /// The only state loaded from the disk are the durable watches, which are reinstated.
     pub fn new(db_path: Option<PathBuf>) -> Self {
         // The code should build only if AdapterManager implements Sync.
         is_sync::<AdapterManager>();
 
-        let state = Arc::new(MainLock::new(|liveness| State::new(liveness, db_path)));
+        let state = Arc::new(MainLock::new(|liveness| State::new(liveness, db_path.clone())));
         let tx_watch = Arc::new(Mutex::new(Self::handle_watches(Arc::downgrade(&state))));
-        AdapterManager {
+        let statuses = Broadcast::new();
+        let supervisor = Supervisor::new(&state, db_path.clone(), statuses.internal_clone());
+        let manager = AdapterManager {
             back_end: state,
             tx_watch: tx_watch,
-        }
+            db_path: db_path,
+            durable_watches: Mutex::new(HashMap::new()),
+            discovery: Broadcast::new(),
+            statuses: statuses,
+            supervisor: supervisor,
+        };
+        manager.reinstate_durable_watches();
+        manager
     }
 }
 
@@ -66,8 +95,25 @@ impl AdapterManagerHandle for AdapterManager {
     /// # Errors
     ///
     /// Returns an error if an adapter with the same id is already present.
+    ///
+    /// If a configuration for this adapter is stored in the taxonomy database, it is applied.
+    /// Failing to apply it does not prevent the adapter from being added.
+    ///
+    /// Panics of the adapter are caught and reported as `Error::AdapterPanicked`. An adapter
+    /// that panics repeatedly is isolated as `AdapterState::Faulted`, until it is restarted
+    /// through `API::restart_adapter`.
     fn add_adapter(&self, adapter: Arc<Adapter>) -> Result<(), Error> {
-        self.back_end.write().unwrap().add_adapter(adapter)
+        self.supervisor.add_adapter(adapter)
+    }
+
+    /// Add an adapter to the system, using `factory` to create it.
+    ///
+    /// Same as `add_adapter`, except that an adapter isolated as `AdapterState::Faulted` is
+    /// replaced with a new instance created by `factory`. After a few automatic restarts
+    /// without a period of healthy operation in between, the adapter remains faulted until it
+    /// is restarted through `API::restart_adapter`.
+    fn add_adapter_with_factory(&self, factory: AdapterFactory) -> Result<(), Error> {
+        self.supervisor.add_adapter_with_factory(factory)
     }
 
     /// Remove an adapter from the system, including all its services and channels.
@@ -78,6 +124,7 @@ impl AdapterManagerHandle for AdapterManager {
     /// to cleanup as much as possible, even if for some reason the system is in an
     /// inconsistent state.
     fn remove_adapter(&self, id: &Id<AdapterId>) -> Result<(), Error> {
+        self.supervisor.remove_adapter(id);
         self.back_end.write().unwrap().remove_adapter(id)
     }
 
@@ -101,6 +148,26 @@ impl AdapterManagerHandle for AdapterManager {
         self.back_end.write().unwrap().add_service(service)
     }
 
+    /// Add a service to the system, along with all the getters and setters listed in
+    /// `service.getters` and `service.setters`, in a single transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of:
+    /// - a channel doesn't belong to `service` or to its adapter;
+    /// - a service or channel with the same identifier is already installed on the system;
+    /// - there is no adapter with id `service.adapter`.
+    ///
+    /// In either case, this method reverts all its changes.
+    fn add_service_with_channels(&self, service: Service) -> Result<(), Error> {
+        let request = {
+            // Acquire and release lock asap.
+            try!(self.back_end.write().unwrap().add_service_with_channels(service))
+        };
+        self.register_watches(request);
+        Ok(())
+    }
+
     /// Remove a service previously registered on the system. Typically, called by
     /// an adapter when a service (e.g. a device) is disconnected.
     ///
@@ -179,6 +246,83 @@ impl AdapterManagerHandle for AdapterManager {
     fn remove_setter(&self, id: &Id<Setter>) -> Result<(), Error> {
         self.back_end.write().unwrap().remove_setter(id)
     }
+
+    /// Report the connectivity of an adapter.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no adapter with this identifier exists.
+    fn set_adapter_status(&self, id: &Id<AdapterId>, state: AdapterState, reason: Option<String>) -> Result<(), Error> {
+        let status = try!(self.back_end.write().unwrap().set_adapter_status(id, state, reason));
+        if let Some(status) = status {
+            let _ = self.statuses.send(status);
+        }
+        Ok(())
+    }
+
+    /// Rename identifiers in the taxonomy database.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the migration is inconsistent or if the database cannot be
+    /// rewritten, in which case it is left unchanged.
+    fn migrate_ids(&self, migration: IdMigration) -> Result<(), Error> {
+        try!(migration.check());
+        let path = match self.db_path {
+            None => return Ok(()), // Nothing is persisted.
+            Some(ref path) => path
+        };
+        let watches = {
+            // Tags are written while holding the lock, so holding it prevents other writers
+            // from waiting on the transaction.
+            let _back_end = self.back_end.write().unwrap();
+            try!(migrate_storage(path, &migration)
+                .map_err(|err| Error::InternalError(InternalError::GenericError(format!("{}", err)))))
+        };
+
+        // Services and channels already registered under their new id receive the tags of
+        // their former id.
+        let mut store = TagStorage::new(path);
+        for id in migration.services.values() {
+            match store.get_tags_for(id) {
+                Err(err) => error!("Storage get_tags_for error: {}", err),
+                Ok(tags) => if !tags.is_empty() {
+                    self.add_service_tags(vec![ServiceSelector::new().with_id(id.clone())], tags);
+                }
+            }
+        }
+        for id in migration.getters.values() {
+            match store.get_tags_for(id) {
+                Err(err) => error!("Storage get_tags_for error: {}", err),
+                Ok(tags) => if !tags.is_empty() {
+                    self.add_getter_tags(vec![GetterSelector::new().with_id(id.clone())], tags);
+                }
+            }
+        }
+        for id in migration.setters.values() {
+            match store.get_tags_for(id) {
+                Err(err) => error!("Storage get_tags_for error: {}", err),
+                Ok(tags) => if !tags.is_empty() {
+                    self.add_setter_tags(vec![SetterSelector::new().with_id(id.clone())], tags);
+                }
+            }
+        }
+
+        // Restart the durable watches whose selectors have been rewritten.
+        if !watches.is_empty() {
+            match WatchStorage::new(path).get_watches() {
+                Err(err) => error!("Storage get_watches error: {}", err),
+                Ok(mut stored) => {
+                    for stored in stored.drain(..) {
+                        if watches.contains(&stored.name) {
+                            self.start_durable_watch(stored.name, stored.watch, stored.options);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 /// A handle to the public API.
@@ -277,7 +421,15 @@ impl API for AdapterManager {
     /// Note that this call is _not live_. In other words, if channels
     /// are added after the call, they will not be affected.
     fn remove_getter_tags(&self, selectors: Vec<GetterSelector>, tags: Vec<Id<TagId>>) -> usize {
-        self.back_end.write().unwrap().remove_getter_tags(selectors, tags)
+        let (request, result) = {
+            // Acquire and release the write lock.
+            self.back_end.write().unwrap().remove_getter_tags(selectors, tags)
+        };
+        if !request.is_empty() {
+            debug!(target: "Taxonomy-manager", "manager.remove_getter_tags => need to register watches");
+        }
+        self.register_watches(request);
+        result
     }
     fn remove_setter_tags(&self, selectors: Vec<SetterSelector>, tags: Vec<Id<TagId>>) -> usize {
         self.back_end.write().unwrap().remove_setter_tags(selectors, tags)
@@ -289,32 +441,37 @@ impl API for AdapterManager {
     {
         // First, prepare the request.
         let mut request;
+        let offline;
         {
             // Make sure that the lock is released asap.
-            request = self.back_end.read().unwrap().prepare_fetch_values(selectors);
+            let back_end = self.back_end.read().unwrap();
+            request = back_end.prepare_fetch_values(selectors);
+            offline = back_end.get_offline_adapters();
         }
         // Now fetch the values
         let mut results = HashMap::new();
-        for (_, (adapter, mut getters)) in request.drain() {
-            let (getters, mut types) : (Vec<_>, Vec<_>) = getters.drain().unzip();
+        for (adapter_id, (adapter, (kinds, failures))) in request.drain() {
+            results.extend(failures);
+            if kinds.is_empty() {
+                continue;
+            }
+            if offline.contains(&adapter_id) {
+                results.extend(kinds.keys().map(|id| (id.clone(), Err(Error::AdapterOffline(adapter_id.clone())))));
+                continue;
+            }
+            let getters = kinds.keys().cloned().collect();
             let mut got = adapter
                 .fetch_values(getters, user.clone());
 
+            // Check that the values returned by the adapter are acceptable for their channel.
             let checked = got.drain()
-                .zip(types.drain(..))
-                .map(|(result, typ)| {
-                    if let (id, Ok(Some(value))) = result {
-                        if value.get_type() == typ {
-                            (id, Ok(Some(value)))
-                        } else {
-                            (id, Err(Error::TypeError(TypeError {
-                                expected: typ,
-                                got: value.get_type()
-                            })))
-                        }
-                    } else {
-                        result
-                    }
+                .map(|(id, result)| {
+                    let result = match (result, kinds.get(&id)) {
+                        (Ok(Some(value)), Some(kind)) =>
+                            kind.ensure_accepts(&value).map(|()| Some(value)),
+                        (result, _) => result
+                    };
+                    (id, result)
                 });
 
             results.extend(checked);
@@ -328,14 +485,22 @@ impl API for AdapterManager {
     {
         // First, prepare the request.
         let mut prepared;
+        let offline;
         {
             // Make sure that the lock is released asap.
-            prepared = self.back_end.read().unwrap().prepare_send_values(keyvalues);
+            let back_end = self.back_end.read().unwrap();
+            prepared = back_end.prepare_send_values(keyvalues);
+            offline = back_end.get_offline_adapters();
         }
 
         // Dispatch to adapter
         let mut results = HashMap::new();
-        for (_, (adapter, (request, failures))) in prepared.drain() {
+        for (adapter_id, (adapter, (request, failures))) in prepared.drain() {
+            if offline.contains(&adapter_id) {
+                results.extend(request.keys().map(|id| (id.clone(), Err(Error::AdapterOffline(adapter_id.clone())))));
+                results.extend(failures);
+                continue;
+            }
             let got = adapter.send_values(request, user.clone());
             results.extend(got);
             results.extend(failures);
@@ -347,13 +512,24 @@ impl API for AdapterManager {
     /// Watch for any change
     fn watch_values(&self, watch: TargetMap<GetterSelector, Exactly<Range>>,
         on_event: Box<ExtSender<api::WatchEvent>>) -> Self::WatchGuard
+    {
+        let on_event = on_event.map(|stamped: StampedEvent| stamped.event);
+        self.watch_values_with_options(watch, WatchOptions::default(), Box::new(on_event))
+    }
+
+    /// Watch for changes from channels, with options.
+    ///
+    /// Same as `watch_values`, with `WatchOptions::default()` standing for the behavior of
+    /// `watch_values`, except that events are numbered.
+    fn watch_values_with_options(&self, watch: TargetMap<GetterSelector, Exactly<Range>>,
+        options: WatchOptions, on_event: Box<ExtSender<StampedEvent>>) -> Self::WatchGuard
     {
         let (request, watch_key, is_dropped) =
         {
             // Acquire and release write lock.
             self.back_end.write()
                 .unwrap()
-                .prepare_channel_watch(watch, on_event)
+                .prepare_channel_watch(watch, options, on_event)
         };
 
         if !request.is_empty() {
@@ -363,6 +539,167 @@ impl API for AdapterManager {
         WatchGuard::new(self.tx_watch.lock().unwrap().internal_clone(), watch_key, is_dropped)
     }
 
+    /// Register a durable watch, stored in the taxonomy database.
+    fn register_durable_watch(&self, name: Id<WatchId>, watch: TargetMap<GetterSelector, Exactly<Range>>,
+        options: WatchOptions) -> Result<(), Error>
+    {
+        let options = WatchOptions {
+            resume_from: None,
+            .. options
+        };
+        if let Some(ref path) = self.db_path {
+            let mut store = WatchStorage::new(&path);
+            if let Err(err) = store.put_watch(&name, &watch, &options) {
+                return Err(Error::InternalError(InternalError::GenericError(format!("{}", err))));
+            }
+        }
+        self.start_durable_watch(name, watch, options);
+        Ok(())
+    }
+
+    /// Start delivering the events of a durable watch.
+    fn attach_durable_watch(&self, name: &Id<WatchId>, on_event: Box<ExtSender<StampedEvent>>) -> Result<(), Error> {
+        let mailbox = match self.durable_watches.lock().unwrap().get(name) {
+            None => return Err(Error::InternalError(InternalError::NoSuchWatch(name.clone()))),
+            Some(durable) => durable.mailbox.clone()
+        };
+        mailbox.lock().unwrap().attach(on_event);
+        Ok(())
+    }
+
+    /// Stop a durable watch and remove it from the taxonomy database.
+    fn remove_durable_watch(&self, name: &Id<WatchId>) -> Result<(), Error> {
+        // Dropping the durable watch releases the underlying watch.
+        if self.durable_watches.lock().unwrap().remove(name).is_none() {
+            return Err(Error::InternalError(InternalError::NoSuchWatch(name.clone())));
+        }
+        if let Some(ref path) = self.db_path {
+            let mut store = WatchStorage::new(&path);
+            if let Err(err) = store.remove_watch(name) {
+                return Err(Error::InternalError(InternalError::GenericError(format!("{}", err))));
+            }
+        }
+        Ok(())
+    }
+
+    /// Start looking for new devices, on all adapters.
+    fn start_discovery(&self, on_event: Box<ExtSender<DiscoveryEvent>>) -> ResultMap<Id<AdapterId>, (), Error> {
+        self.discovery.add(on_event);
+        let adapters = self.back_end.read().unwrap().get_adapters();
+        adapters.iter()
+            .map(|adapter| (adapter.id(), adapter.start_discovery(self.discovery.internal_clone())))
+            .collect()
+    }
+
+    /// Stop looking for new devices, on all adapters.
+    fn stop_discovery(&self) {
+        let adapters = self.back_end.read().unwrap().get_adapters();
+        for adapter in adapters {
+            adapter.stop_discovery();
+        }
+    }
+
+    /// Get the devices found by all adapters, and not paired yet.
+    fn get_pending_devices(&self) -> Vec<PendingDevice> {
+        let adapters = self.back_end.read().unwrap().get_adapters();
+        let mut devices = vec![];
+        for adapter in adapters {
+            devices.extend(adapter.get_pending_devices());
+        }
+        devices
+    }
+
+    /// Pair a device found by an adapter.
+    fn pair_device(&self, adapter: &Id<AdapterId>, device: &Id<DeviceId>, credentials: Option<Credentials>) -> Result<(), Error> {
+        let implementation = try!(self.back_end.read().unwrap().get_adapter(adapter));
+        let result = implementation.pair(device, credentials);
+        let _ = self.discovery.send(match result {
+            Ok(()) => DiscoveryEvent::Paired {
+                adapter: adapter.clone(),
+                device: device.clone()
+            },
+            Err(ref error) => DiscoveryEvent::PairingFailed {
+                adapter: adapter.clone(),
+                device: device.clone(),
+                error: error.clone()
+            }
+        });
+        result
+    }
+
+    /// Get the status of all adapters.
+    fn get_adapter_statuses(&self) -> Vec<AdapterStatus> {
+        self.back_end.read().unwrap().get_adapter_statuses()
+    }
+
+    /// Watch the status of adapters.
+    fn watch_adapter_statuses(&self, on_event: Box<ExtSender<AdapterStatus>>) {
+        self.statuses.add(on_event);
+    }
+
+    /// Restart an adapter, with a new instance if it was added with a factory.
+    fn restart_adapter(&self, id: &Id<AdapterId>) -> Result<(), Error> {
+        self.supervisor.restart_adapter(id)
+    }
+
+    /// Get the fields accepted by the configuration of an adapter.
+    fn get_adapter_config_schema(&self, id: &Id<AdapterId>) -> Result<Vec<ConfigField>, Error> {
+        let adapter = try!(self.back_end.read().unwrap().get_adapter(id));
+        Ok(adapter.config_schema())
+    }
+
+    /// Get the current configuration of an adapter, without its secret fields.
+    fn get_adapter_config(&self, id: &Id<AdapterId>) -> Result<AdapterConfig, Error> {
+        let (adapter, mut config) = {
+            let back_end = self.back_end.read().unwrap();
+            (try!(back_end.get_adapter(id)), try!(back_end.get_adapter_config(id)))
+        };
+        for field in adapter.config_schema() {
+            if field.is_secret {
+                config.remove(&field.name);
+            }
+        }
+        Ok(config)
+    }
+
+    /// Configure an adapter, then store the configuration in the taxonomy database.
+    fn set_adapter_config(&self, id: &Id<AdapterId>, mut config: AdapterConfig) -> Result<(), Error> {
+        let (adapter, current) = {
+            let back_end = self.back_end.read().unwrap();
+            (try!(back_end.get_adapter(id)), try!(back_end.get_adapter_config(id)))
+        };
+        let schema = adapter.config_schema();
+        // Secret fields are not exposed by `get_adapter_config`, so keep their current value
+        // if the new configuration doesn't specify one.
+        for field in &schema {
+            if field.is_secret && !config.contains_key(&field.name) {
+                if let Some(value) = current.get(&field.name) {
+                    config.insert(field.name.clone(), value.clone());
+                }
+            }
+        }
+        let config = try!(check_config(&schema, config));
+        try!(adapter.set_config(&config));
+        if let Some(ref path) = self.db_path {
+            let mut store = ConfigStorage::new(&path);
+            try!(store.put_config(id, &config).map_err(|err| {
+                Error::InternalError(InternalError::GenericError(format!("{}", err)))
+            }));
+        }
+        self.back_end.write().unwrap().set_adapter_config(id, config)
+    }
+
+    /// Unpair the device offering a service.
+    fn unpair_service(&self, service: &Id<ServiceId>) -> Result<(), Error> {
+        let adapter = try!(self.back_end.read().unwrap().get_service_adapter(service));
+        try!(adapter.unpair(service));
+        let _ = self.discovery.send(DiscoveryEvent::Unpaired {
+            adapter: adapter.id(),
+            service: service.clone()
+        });
+        Ok(())
+    }
+
     /// A value that causes a disconnection once it is dropped.
     type WatchGuard = WatchGuard;
 }
@@ -407,7 +744,12 @@ impl AdapterManager {
                                 let _ = tx.send(());
                             }
                             WatchOp::Release(request) => {
-                                backend.write().unwrap().stop_watch(request)
+                                // The remaining watchers may be served by narrower watches.
+                                let request = backend.write().unwrap().stop_watch(request);
+                                if !request.is_empty() {
+                                    let add = State::start_watch(request);
+                                    backend.write().unwrap().register_ongoing_watch(add);
+                                }
                             }
                         }
                 }
@@ -452,6 +794,164 @@ impl Drop for WatchGuard {
 }
 
 
+/// The maximal number of events held for a durable watch while no consumer is attached.
+const DURABLE_WATCH_CAPACITY: usize = 1024;
+
+/// A watch registered with `register_durable_watch`.
+struct DurableWatch {
+    /// Releases the watch once dropped.
+    #[allow(dead_code)]
+    guard: WatchGuard,
+
+    mailbox: Arc<Mutex<Mailbox>>,
+}
+
+/// The events of a durable watch, held until a consumer is attached.
+struct Mailbox {
+    /// The consumer currently attached, if any.
+    consumer: Option<Box<ExtSender<StampedEvent>>>,
+
+    /// The events received while no consumer was attached, oldest first.
+    pending: VecDeque<StampedEvent>,
+
+    /// The latest event discarded from `pending` because of its capacity, if any.
+    discarded: Option<StampedEvent>,
+}
+
+impl Mailbox {
+    fn new() -> Self {
+        Mailbox {
+            consumer: None,
+            pending: VecDeque::new(),
+            discarded: None,
+        }
+    }
+
+    fn receive(&mut self, event: StampedEvent) {
+        let is_delivered = match self.consumer {
+            None => false,
+            Some(ref consumer) => consumer.send(event.clone()).is_ok()
+        };
+        if is_delivered {
+            return;
+        }
+        // Either there is no consumer or it has hung up. Hold the event until the next one.
+        self.consumer = None;
+        if self.pending.len() >= DURABLE_WATCH_CAPACITY {
+            self.discarded = self.pending.pop_front();
+        }
+        self.pending.push_back(event);
+    }
+
+    fn attach(&mut self, consumer: Box<ExtSender<StampedEvent>>) {
+        if let Some(discarded) = self.discarded.take() {
+            // The notice stands for the events discarded, so it carries the stamp of the latest.
+            let _ = consumer.send(StampedEvent {
+                sequence: discarded.sequence,
+                timestamp: discarded.timestamp,
+                event: WatchEvent::ReplayTruncated {
+                    discarded: discarded.sequence
+                }
+            });
+        }
+        while let Some(event) = self.pending.pop_front() {
+            if consumer.send(event.clone()).is_err() {
+                // The consumer has already hung up.
+                self.pending.push_front(event);
+                self.consumer = None;
+                return;
+            }
+        }
+        self.consumer = Some(consumer);
+    }
+}
+
+/// The channel through which a durable watch receives its events.
+struct MailboxSender {
+    mailbox: Arc<Mutex<Mailbox>>,
+}
+
+impl ExtSender<StampedEvent> for MailboxSender {
+    fn send(&self, event: StampedEvent) -> Result<(), ()> {
+        self.mailbox.lock().unwrap().receive(event);
+        Ok(())
+    }
+    fn internal_clone(&self) -> Box<ExtSender<StampedEvent>> {
+        Box::new(MailboxSender {
+            mailbox: self.mailbox.clone()
+        })
+    }
+}
+
+/// A channel that forwards events to all its listeners, as long as they are open. Used to
+/// report the progress of discovery and the status of adapters.
+struct Broadcast<T> where T: Clone + Send + 'static {
+    listeners: Arc<Mutex<Vec<Box<ExtSender<T>>>>>,
+}
+
+impl<T> Broadcast<T> where T: Clone + Send + 'static {
+    fn new() -> Self {
+        Broadcast {
+            listeners: Arc::new(Mutex::new(vec![]))
+        }
+    }
+
+    fn add(&self, listener: Box<ExtSender<T>>) {
+        self.listeners.lock().unwrap().push(listener);
+    }
+}
+
+impl<T> ExtSender<T> for Broadcast<T> where T: Clone + Send + 'static {
+    fn send(&self, event: T) -> Result<(), ()> {
+        // Forget the listeners that have hung up.
+        self.listeners.lock().unwrap().retain(|listener| listener.send(event.clone()).is_ok());
+        Ok(())
+    }
+    fn internal_clone(&self) -> Box<ExtSender<T>> {
+        Box::new(Broadcast {
+            listeners: self.listeners.clone()
+        })
+    }
+}
+
+impl AdapterManager {
+    /// Start the durable watches stored in the taxonomy database.
+    fn reinstate_durable_watches(&self) {
+        if let Some(ref path) = self.db_path {
+            let mut store = WatchStorage::new(&path);
+            match store.get_watches() {
+                Err(err) => error!("Storage get_watches error: {}", err),
+                Ok(mut watches) => {
+                    for stored in watches.drain(..) {
+                        debug!(target: "Taxonomy-manager", "manager => reinstating durable watch {}", stored.name);
+                        self.start_durable_watch(stored.name, stored.watch, stored.options);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Start a durable watch, replacing any durable watch with the same name. A consumer
+    /// attached to the former watch remains attached.
+    fn start_durable_watch(&self, name: Id<WatchId>, watch: TargetMap<GetterSelector, Exactly<Range>>,
+        options: WatchOptions)
+    {
+        // Release the former watch before starting the new one, to avoid delivering the same
+        // values twice.
+        let mailbox = match self.durable_watches.lock().unwrap().remove(&name) {
+            None => Arc::new(Mutex::new(Mailbox::new())),
+            Some(former) => former.mailbox.clone()
+        };
+        let guard = self.watch_values_with_options(watch, options, Box::new(MailboxSender {
+            mailbox: mailbox.clone()
+        }));
+        self.durable_watches.lock().unwrap().insert(name, DurableWatch {
+            guard: guard,
+            mailbox: mailbox,
+        });
+    }
+}
+
 impl AdapterManager {
     pub fn stop(&self) {
         self.back_end.write().unwrap().stop()
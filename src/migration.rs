@@ -0,0 +1,147 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+///! Renaming identifiers in the taxonomy database, typically once an adapter has changed its
+///! id scheme, e.g. after a firmware update.
+///!
+///! A migration rewrites the tags of services and channels, the configuration of adapters and
+///! the selectors of durable watches, in a single database transaction.
+
+use api::Error;
+use config_storage::ConfigStorage;
+use selector::GetterSelector;
+use services::{ AdapterId, Getter, Id, ServiceId, Setter };
+use tag_storage::TagStorage;
+use util::{ Exactly, WatchId };
+use watch_storage::WatchStorage;
+
+use rusqlite::{ self, Connection };
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::path::PathBuf;
+
+/// A mapping from former identifiers to new identifiers.
+///
+/// ```
+/// use foxbox_taxonomy::migration::IdMigration;
+/// use foxbox_taxonomy::services::*;
+///
+/// let migration = IdMigration::new()
+///     .with_adapter(Id::new("zwave@link.mozilla.org"), Id::new("zwave-v2@link.mozilla.org"))
+///     .with_service(Id::new("zwave-1"), Id::new("zwave-node-1"))
+///     .with_getter(Id::new("zwave-1/on"), Id::new("zwave-node-1/on"));
+/// assert!(migration.check().is_ok());
+///
+/// // A migration may not rename an identifier that it also introduces.
+/// let chained = IdMigration::new()
+///     .with_service(Id::new("a"), Id::new("b"))
+///     .with_service(Id::new("b"), Id::new("c"));
+/// assert!(chained.check().is_err());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct IdMigration {
+    pub adapters: HashMap<Id<AdapterId>, Id<AdapterId>>,
+    pub services: HashMap<Id<ServiceId>, Id<ServiceId>>,
+    pub getters: HashMap<Id<Getter>, Id<Getter>>,
+    pub setters: HashMap<Id<Setter>, Id<Setter>>,
+}
+
+impl IdMigration {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rename adapter `former` into `new`.
+    pub fn with_adapter(mut self, former: Id<AdapterId>, new: Id<AdapterId>) -> Self {
+        self.adapters.insert(former, new);
+        self
+    }
+
+    /// Rename service `former` into `new`.
+    pub fn with_service(mut self, former: Id<ServiceId>, new: Id<ServiceId>) -> Self {
+        self.services.insert(former, new);
+        self
+    }
+
+    /// Rename getter `former` into `new`.
+    pub fn with_getter(mut self, former: Id<Getter>, new: Id<Getter>) -> Self {
+        self.getters.insert(former, new);
+        self
+    }
+
+    /// Rename setter `former` into `new`.
+    pub fn with_setter(mut self, former: Id<Setter>, new: Id<Setter>) -> Self {
+        self.setters.insert(former, new);
+        self
+    }
+
+    /// Make sure that the migration can be applied in any order, i.e. that no identifier is
+    /// both renamed and introduced, and that no two identifiers are renamed into the same one.
+    pub fn check(&self) -> Result<(), Error> {
+        try!(Self::check_map(&self.adapters));
+        try!(Self::check_map(&self.services));
+        try!(Self::check_map(&self.getters));
+        Self::check_map(&self.setters)
+    }
+
+    fn check_map<T>(map: &HashMap<Id<T>, Id<T>>) -> Result<(), Error> where Id<T>: Hash + Eq {
+        let mut introduced = HashMap::new();
+        for (former, new) in map {
+            if map.contains_key(new) {
+                return Err(Error::InvalidMigration(format!("{} is both renamed and introduced", new)));
+            }
+            if let Some(other) = introduced.insert(new, former) {
+                return Err(Error::InvalidMigration(format!("{} and {} are both renamed into {}", other, former, new)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrite the identifiers mentioned by a selector. Returns `true` if the selector has
+    /// changed.
+    pub fn migrate_getter_selector(&self, selector: &mut GetterSelector) -> bool {
+        let mut changed = false;
+        if let Exactly::Exactly(ref mut id) = selector.id {
+            if let Some(new) = self.getters.get(id) {
+                *id = new.clone();
+                changed = true;
+            }
+        }
+        if let Exactly::Exactly(ref mut id) = selector.parent {
+            if let Some(new) = self.services.get(id) {
+                *id = new.clone();
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+/// Apply a migration to the taxonomy database, in a single transaction.
+///
+/// Returns the names of the durable watches whose selectors have been rewritten.
+pub fn migrate_storage(path: &PathBuf, migration: &IdMigration) -> rusqlite::Result<Vec<Id<WatchId>>> {
+    info!("Migrating identifiers in taxonomy database at {}", path.display());
+    let db = try!(Connection::open(path.clone()));
+    try!(TagStorage::create_table(&db));
+    try!(ConfigStorage::create_table(&db));
+    try!(WatchStorage::create_table(&db));
+
+    // Take the write lock immediately, so that the migration is not interleaved with other
+    // writers.
+    try!(db.execute_batch("BEGIN IMMEDIATE"));
+    let result = TagStorage::migrate(&db, migration)
+        .and_then(|_| ConfigStorage::migrate(&db, migration))
+        .and_then(|_| WatchStorage::migrate(&db, migration));
+    match result {
+        Ok(watches) => {
+            try!(db.execute_batch("COMMIT"));
+            Ok(watches)
+        }
+        Err(err) => {
+            let _ = db.execute_batch("ROLLBACK");
+            Err(err)
+        }
+    }
+}
@@ -294,6 +294,18 @@ impl Parser<u8> for u8 {
     }
 }
 
+impl Parser<u64> for u64 {
+    fn description() -> String {
+        "unsigned integer".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        match source.as_u64() {
+            None => Err(ParseError::type_error("as unsigned integer", &path, "positive integer")),
+            Some(val) => Ok(val)
+        }
+    }
+}
+
 impl<T> Parser<Vec<T>> for Vec<T> where T: Parser<T> {
     fn description() -> String {
         format!("Array<{}>", T::description())
@@ -391,6 +403,12 @@ impl ToJSON for usize {
     }
 }
 
+impl ToJSON for u64 {
+    fn to_json(&self) -> JSON {
+        JSON::U64(*self)
+    }
+}
+
 impl ToJSON for JSON {
     fn to_json(&self) -> JSON {
         self.clone()
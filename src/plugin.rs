@@ -0,0 +1,302 @@
+//! Adapters built as shared libraries and loaded at runtime.
+//!
+//! A plugin is a shared library (`cdylib` or `dylib`) that declares its entry point with
+//! `declare_adapter_plugin!`. The entry point receives a `PluginHost`, through which it first
+//! adds its adapter, then registers the services and channels of the adapter. It returns the
+//! `PluginMetadata` of the plugin. If the entry point fails, the plugin is unloaded.
+//!
+//! Rust offers no stable ABI, and a plugin embeds its own copy of this crate, including its
+//! allocator and the table in which identifiers are interned. Values created on one side are
+//! therefore never used on the other side: the only function called across the boundary takes
+//! a file descriptor, and the host and the plugin then talk through a socket, with the protocol
+//! of module `proxy_adapter`. The plugin side is served by module `proxy_child`.
+//!
+//! The loader checks the version of this crate that the plugin was built against, through a C
+//! symbol, before calling the plugin.
+//!
+//! A plugin is unloaded once its adapter has been removed with `remove_adapter`, or once the
+//! connection is lost. Before unloading, the adapter is stopped, so it must not leave any
+//! thread behind once `stop` has returned.
+//!
+//! ```ignore
+//! #[macro_use]
+//! extern crate foxbox_taxonomy;
+//!
+//! fn create(host: &PluginHost) -> Result<PluginMetadata, Error> {
+//!     let adapter = Arc::new(MyAdapter::new());
+//!     try!(host.add_adapter(adapter.clone()));
+//!     try!(adapter.register_services(host.manager()));
+//!     Ok(PluginMetadata {
+//!         name: "My adapter".to_owned(),
+//!         version: [1, 0, 0, 0],
+//!     })
+//! }
+//!
+//! declare_adapter_plugin!(create);
+//! ```
+
+use adapter::Adapter;
+use api::Error;
+use manager::AdapterManager;
+use proxy_adapter::ProxyAdapter;
+use proxy_child::{ self, HostHandle };
+use services::*;
+
+use libc::{ self, c_char, c_void };
+
+use std::env::consts::DLL_EXTENSION;
+use std::ffi::{ CStr, CString };
+use std::fs;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{ FromRawFd, IntoRawFd, RawFd };
+use std::os::unix::net::UnixStream;
+use std::panic::{ self, AssertUnwindSafe };
+use std::path::{ Path, PathBuf };
+use std::sync::{ Arc, Mutex };
+use std::thread;
+
+/// The version of this crate, as a nul-terminated string. A plugin may only be loaded by a
+/// host built against the same version.
+pub const TAXONOMY_VERSION: &'static str = concat!(env!("CARGO_PKG_VERSION"), "\0");
+
+/// The name of the C symbol returning the `TAXONOMY_VERSION` a plugin was built against.
+pub const VERSION_SYMBOL: &'static str = "foxbox_taxonomy_plugin_version";
+
+/// The name of the C symbol running a plugin, i.e. `extern "C" fn(RawFd) -> i32`. It serves
+/// the adapter of the plugin over the socket until the host closes it or stops the adapter,
+/// and returns 0, or 1 if the plugin has panicked.
+pub const RUN_SYMBOL: &'static str = "foxbox_taxonomy_plugin_run";
+
+/// The signature of the entry point of a plugin, as declared by `declare_adapter_plugin!`.
+pub type PluginEntryPoint = fn(&PluginHost) -> Result<PluginMetadata, Error>;
+
+/// Information on a plugin, returned by its entry point.
+#[derive(Debug, Clone)]
+pub struct PluginMetadata {
+    /// A human-readable name for the plugin.
+    pub name: String,
+
+    /// The version of the plugin. Must match the `version()` of its adapter.
+    pub version: [u32; 4],
+}
+
+/// The host of a plugin, as seen by its entry point.
+pub struct PluginHost {
+    handle: HostHandle,
+
+    /// The adapter added by the plugin, if any.
+    adapter: Mutex<Option<Arc<Adapter>>>,
+}
+
+impl PluginHost {
+    /// The manager, with which the plugin registers its services and channels once it has
+    /// added its adapter. Registrations are forwarded to the host.
+    pub fn manager(&self) -> &HostHandle {
+        &self.handle
+    }
+
+    /// Add the adapter of the plugin. The plugin remains loaded until the adapter is removed
+    /// from the host.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the plugin has already added an adapter.
+    pub fn add_adapter(&self, adapter: Arc<Adapter>) -> Result<(), Error> {
+        let mut added = self.adapter.lock().unwrap();
+        if added.is_some() {
+            return Err(Error::InvalidPlugin("A plugin may only add one adapter".to_owned()));
+        }
+        *added = Some(adapter);
+        Ok(())
+    }
+}
+
+/// Declare the entry point of a plugin.
+///
+/// `$entry` must be a function with the signature of `PluginEntryPoint`. This macro must be
+/// used exactly once per plugin.
+#[macro_export]
+macro_rules! declare_adapter_plugin {
+    ($entry:path) => {
+        #[no_mangle]
+        #[doc(hidden)]
+        pub extern "C" fn foxbox_taxonomy_plugin_version() -> *const u8 {
+            $crate::plugin::TAXONOMY_VERSION.as_ptr()
+        }
+
+        #[no_mangle]
+        #[doc(hidden)]
+        pub extern "C" fn foxbox_taxonomy_plugin_run(fd: ::std::os::unix::io::RawFd) -> i32 {
+            $crate::plugin::run_plugin($entry, fd)
+        }
+    }
+}
+
+/// Run a plugin on the socket `fd`, on behalf of `declare_adapter_plugin!`.
+#[doc(hidden)]
+pub fn run_plugin(entry: PluginEntryPoint, fd: RawFd) -> i32 {
+    let stream = unsafe { UnixStream::from_raw_fd(fd) };
+    // Unwinding across `extern "C"` functions is undefined behavior.
+    match panic::catch_unwind(AssertUnwindSafe(|| serve_plugin(entry, stream))) {
+        Ok(()) => 0,
+        Err(_) => 1
+    }
+}
+
+fn serve_plugin(entry: PluginEntryPoint, stream: UnixStream) {
+    let writer = match stream.try_clone() {
+        Err(_) => return,
+        Ok(writer) => writer
+    };
+    let host = PluginHost {
+        handle: HostHandle::new(Box::new(writer)),
+        adapter: Mutex::new(None),
+    };
+    let result = entry(&host);
+    let adapter = host.adapter.lock().unwrap().take();
+    let adapter = match adapter {
+        None => {
+            let error = match result {
+                Err(err) => err,
+                Ok(_) => Error::InvalidPlugin("The entry point has not added any adapter".to_owned())
+            };
+            return proxy_child::refuse(error, stream, &host.handle);
+        }
+        Some(adapter) => adapter
+    };
+    let error = match result {
+        Err(err) => err,
+        Ok(metadata) => {
+            if *adapter.version() == metadata.version {
+                return proxy_child::serve(adapter, stream, &host.handle);
+            }
+            Error::InvalidPlugin(format!("Plugin {} declares version {:?} but its adapter has version {:?}",
+                metadata.name, metadata.version, adapter.version()))
+        }
+    };
+    adapter.stop();
+    proxy_child::refuse(error, stream, &host.handle)
+}
+
+fn invalid(path: &Path, reason: &str) -> Error {
+    Error::InvalidPlugin(format!("{}: {}", path.display(), reason))
+}
+
+/// A shared library, closed once dropped.
+struct Library {
+    handle: *mut c_void,
+}
+
+// The handle returned by `dlopen` may be used and closed from any thread.
+unsafe impl Send for Library {}
+unsafe impl Sync for Library {}
+
+impl Library {
+    fn open(path: &Path) -> Result<Self, Error> {
+        let name = match CString::new(path.as_os_str().as_bytes()) {
+            Err(_) => return Err(invalid(path, "Invalid path")),
+            Ok(name) => name
+        };
+        let handle = unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        if handle.is_null() {
+            return Err(invalid(path, &Self::last_error()));
+        }
+        Ok(Library {
+            handle: handle
+        })
+    }
+
+    fn symbol(&self, path: &Path, name: &str) -> Result<*mut c_void, Error> {
+        // Symbol names are constants without nul bytes.
+        let name = CString::new(name).unwrap();
+        let symbol = unsafe { libc::dlsym(self.handle, name.as_ptr()) };
+        if symbol.is_null() {
+            return Err(invalid(path, &Self::last_error()));
+        }
+        Ok(symbol)
+    }
+
+    fn last_error() -> String {
+        let message = unsafe { libc::dlerror() };
+        if message.is_null() {
+            return "Unknown error".to_owned();
+        }
+        unsafe { CStr::from_ptr(message) }.to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for Library {
+    fn drop(&mut self) {
+        unsafe { libc::dlclose(self.handle); }
+    }
+}
+
+/// Load a plugin and add its adapter to the manager.
+///
+/// The plugin runs on a thread of its own, and is unloaded once its adapter has stopped, in
+/// particular if its entry point fails.
+pub fn load_plugin(manager: &Arc<AdapterManager>, path: &Path) -> Result<Id<AdapterId>, Error> {
+    let library = try!(Library::open(path));
+
+    let version = try!(library.symbol(path, VERSION_SYMBOL));
+    let version = unsafe {
+        let version: extern "C" fn() -> *const c_char = mem::transmute(version);
+        CStr::from_ptr(version()).to_bytes().to_owned()
+    };
+    let expected = &TAXONOMY_VERSION.as_bytes()[..TAXONOMY_VERSION.len() - 1];
+    if version != expected {
+        return Err(invalid(path, &format!("Built against taxonomy {}, expected {}",
+            String::from_utf8_lossy(&version), String::from_utf8_lossy(expected))));
+    }
+
+    let run = try!(library.symbol(path, RUN_SYMBOL));
+    let run: extern "C" fn(RawFd) -> i32 = unsafe { mem::transmute(run) };
+    let (stream, plugin_stream) = try!(UnixStream::pair().map_err(|err| invalid(path, &format!("{}", err))));
+    let fd = plugin_stream.into_raw_fd();
+    let name = path.display().to_string();
+    let thread = thread::spawn(move || {
+        if run(fd) != 0 {
+            error!(target: "Taxonomy-plugin", "Plugin {} has panicked", name);
+        }
+        // The plugin has stopped its adapter, so none of its code may run anymore.
+        drop(library);
+    });
+
+    match ProxyAdapter::with_stream(manager, stream) {
+        Ok(adapter) => {
+            info!(target: "Taxonomy-plugin", "Loaded plugin {} {:?} from {}", adapter.name(), adapter.version(), path.display());
+            Ok(adapter.id())
+        }
+        Err(err) => {
+            // The connection is closed, so the plugin is about to stop. Wait until it is unloaded.
+            let _ = thread.join();
+            Err(err)
+        }
+    }
+}
+
+/// Load all the plugins of a directory, i.e. all the files with the shared library extension
+/// of the platform.
+///
+/// Returns the result of `load_plugin` for each plugin, so that a faulty plugin doesn't prevent
+/// the others from loading.
+pub fn load_plugins(manager: &Arc<AdapterManager>, dir: &Path) -> Result<Vec<(PathBuf, Result<Id<AdapterId>, Error>)>, Error> {
+    let entries = try!(fs::read_dir(dir).map_err(|err| invalid(dir, &format!("{}", err))));
+    let mut paths = vec![];
+    for entry in entries {
+        let path = try!(entry.map_err(|err| invalid(dir, &format!("{}", err)))).path();
+        if path.is_file() && path.extension().map_or(false, |ext| ext == DLL_EXTENSION) {
+            paths.push(path);
+        }
+    }
+    // Load in a predictable order.
+    paths.sort();
+    Ok(paths.into_iter().map(|path| {
+        let result = load_plugin(manager, &path);
+        if let Err(ref err) = result {
+            warn!(target: "Taxonomy-plugin", "Could not load plugin {}: {}", path.display(), err);
+        }
+        (path, result)
+    }).collect())
+}
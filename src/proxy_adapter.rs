@@ -0,0 +1,788 @@
+//! An adapter running in another process.
+//!
+//! A `ProxyAdapter` forwards the calls of the `AdapterManager` to a child process or through a
+//! Unix socket, and forwards the registrations of the other side to the `AdapterManager`. This
+//! lets adapters be written in other languages, and isolates third-party code that may crash.
+//! See module `proxy_child` for the other side of the pipe.
+//!
+//! # Protocol
+//!
+//! Both sides exchange JSON-RPC 2.0 messages, one per line, encoded with `ToJSON` and decoded
+//! with `Parser`. Every message carries `"jsonrpc": "2.0"`; messages without it are ignored.
+//!
+//! The host sends requests `{"jsonrpc": "2.0", "id": number, "method": string, "params":
+//! object}`, to which the child replies with `{"jsonrpc": "2.0", "id": number, "result": any}`
+//! or `{"jsonrpc": "2.0", "id": number, "error": {"code": number, "message": string, "data":
+//! Error}}`. The `code` is `METHOD_NOT_FOUND`, `INVALID_PARAMS` or `ADAPTER_ERROR`, and the
+//! `data` is decoded as the `Error` of the request, if possible.
+//!
+//! - `hello`, always sent first, without params. The result is an object with fields `id`,
+//!   `name`, `vendor` (strings) and `version` (array of 4 numbers);
+//! - `fetch_values`, with params `{"getters": [string]}`. The result is an object with a field
+//!   per getter, either a `Value`, `null` if no value is available, or `{"Error": Error}`;
+//! - `send_values`, with params `{"values": {string: Value}}`. The result is an object with a
+//!   field per setter, either `null` or `{"Error": Error}`;
+//! - `register_watch`, with params `{"watch": [{"key": number, "getter": string, (optional)
+//!   "range": Range}]}`. The result is an array with an entry per target, either `null` or
+//!   `{"Error": Error}`.
+//!
+//! The host also sends notifications `{"jsonrpc": "2.0", "method": string, "params": object}`,
+//! which have no id and no reply:
+//!
+//! - `unregister_watch`, with params `{"key": number}`, once a watch is dropped;
+//! - `stop`, without params, once the adapter should stop. The host then closes the pipe.
+//!
+//! The child sends notifications:
+//!
+//! - `watch_event`, with params `{"key": number, "event": "Enter" | "Exit", "getter": string,
+//!   "value": Value}`;
+//! - `add_service` with a `Service`, `add_getter` with a `Channel<Getter>`, `add_setter` with a
+//!   `Channel<Setter>`;
+//! - `add_service_with_channels` with a `Service` listing its getters and setters;
+//! - `remove_service`, `remove_getter`, `remove_setter`, with params `{"id": string}`;
+//! - `set_adapter_status`, with params `{"state": AdapterState, (optional) "reason": string}`.
+//!
+//! Registrations are applied once the adapter has been added to the `AdapterManager`, in the
+//! order in which they were received. Registrations that fail are logged. They are applied on a
+//! thread of their own, as registering a getter may cause the manager to call `register_watch`,
+//! whose reply is read from the pipe.
+//!
+//! If the child does not reply to a request within `REPLY_TIMEOUT_SECS` seconds, the request
+//! fails with `Error::AdapterOffline` and the connection is closed, as if the child had hung up.
+//!
+//! Once the connection is closed, a spawned child has `STOP_GRACE_SECS` seconds to exit, after
+//! which it is killed. The example `proxy_child` is a reference implementation of a child.
+
+use adapter::{ Adapter, AdapterManagerHandle, AdapterWatchGuard, ResultMap, WatchEvent, WatchResult, WatchTarget };
+use api::{ Error, InternalError, User };
+use parse::{ JSON, Parser, ParseError, Path, ToJSON };
+use services::*;
+use values::Value;
+
+use transformable_channels::mpsc::*;
+
+use libc;
+use serde_json;
+
+use std::collections::HashMap;
+use std::io::{ self, BufRead, BufReader, Read, Write };
+use std::os::unix::net::UnixStream;
+use std::path::Path as FilePath;
+use std::process::{ Child, Command, Stdio };
+use std::net::Shutdown;
+use std::sync::{ Arc, Condvar, Mutex };
+use std::sync::atomic::{ AtomicBool, AtomicUsize, Ordering };
+use std::thread;
+use std::time::{ Duration, Instant };
+
+/// How long we wait for the child to reply to a request before giving up on it.
+pub const REPLY_TIMEOUT_SECS: u64 = 30;
+
+/// How long a spawned child may keep running once the connection is closed, before it is killed.
+pub const STOP_GRACE_SECS: u64 = 5;
+
+/// The version of JSON-RPC spoken on the pipe.
+pub const JSONRPC_VERSION: &'static str = "2.0";
+
+/// The JSON-RPC error code for a request whose method is unknown.
+pub const METHOD_NOT_FOUND: i64 = -32601;
+
+/// The JSON-RPC error code for a request whose params cannot be decoded.
+pub const INVALID_PARAMS: i64 = -32602;
+
+/// The JSON-RPC error code for a request that the adapter has rejected.
+pub const ADAPTER_ERROR: i64 = -32000;
+
+/// An error caused by a malformed message.
+fn invalid(err: ParseError) -> Error {
+    Error::InternalError(InternalError::GenericError(format!("Invalid message from proxied adapter: {}", err)))
+}
+
+fn io_error(err: io::Error) -> Error {
+    Error::InternalError(InternalError::GenericError(format!("Cannot reach proxied adapter: {}", err)))
+}
+
+/// Remove a field from a JSON object, if possible.
+fn take_json(source: &mut JSON, field_name: &str) -> Option<JSON> {
+    match *source {
+        JSON::Object(ref mut obj) => obj.remove(field_name),
+        _ => None
+    }
+}
+
+/// Encode a JSON-RPC error object, with `error` as data.
+pub fn encode_error(code: i64, error: &Error) -> JSON {
+    vec![
+        ("code", JSON::I64(code)),
+        ("message", format!("{}", error).to_json()),
+        ("data", error.to_json())
+    ].to_json()
+}
+
+/// Decode a JSON-RPC error object, preferably from its data.
+fn decode_error(mut error: JSON) -> Error {
+    if let Some(mut data) = take_json(&mut error, "data") {
+        if let Ok(error) = Error::parse(Path::new(), &mut data) {
+            return error;
+        }
+    }
+    match String::take(Path::new(), &mut error, "message") {
+        Ok(message) => Error::InternalError(InternalError::GenericError(message)),
+        Err(err) => invalid(err)
+    }
+}
+
+/// Build a JSON-RPC message from its fields, other than `jsonrpc`. Null params are omitted.
+pub fn encode_message(mut fields: Vec<(&str, JSON)>) -> JSON {
+    fields.retain(|&(name, ref value)| name != "params" || !value.is_null());
+    fields.insert(0, ("jsonrpc", JSONRPC_VERSION.to_json()));
+    fields.to_json()
+}
+
+/// Check that a message is a JSON-RPC 2.0 message, consuming its `jsonrpc` field.
+pub fn check_version(message: &mut JSON) -> Result<(), ParseError> {
+    let version = try!(String::take(Path::new(), message, "jsonrpc"));
+    if version != JSONRPC_VERSION {
+        return Err(Path::new().push("jsonrpc", |path| ParseError::unknown_constant(&version, &path)));
+    }
+    Ok(())
+}
+
+/// Decode a result, either `{"Error": Error}` or a value decoded by `parse`.
+fn decode_result<T, F>(mut json: JSON, parse: F) -> Result<T, Error>
+    where F: FnOnce(&mut JSON) -> Result<T, ParseError>
+{
+    let is_error = match json {
+        JSON::Object(ref obj) => obj.len() == 1 && obj.contains_key("Error"),
+        _ => false
+    };
+    if is_error {
+        let error = try!(Error::take(Path::new(), &mut json, "Error").map_err(invalid));
+        return Err(error);
+    }
+    parse(&mut json).map_err(invalid)
+}
+
+/// Decode the params of `watch_event`.
+fn parse_watch_event(params: &mut JSON) -> Result<(u64, WatchEvent), ParseError> {
+    let path = Path::new();
+    let key = try!(path.push("key", |path| u64::take(path, params, "key")));
+    let kind = try!(path.push("event", |path| String::take(path, params, "event")));
+    let id = try!(path.push("getter", |path| Id::<Getter>::take(path, params, "getter")));
+    let value = try!(path.push("value", |path| Value::take(path, params, "value")));
+    let event = match &kind as &str {
+        "Enter" => WatchEvent::Enter { id: id, value: value },
+        "Exit" => WatchEvent::Exit { id: id, value: value },
+        _ => return Err(ParseError::unknown_constant(&kind, &path))
+    };
+    Ok((key, event))
+}
+
+/// Decode the result of `hello`.
+fn parse_hello(hello: &mut JSON) -> Result<(Id<AdapterId>, String, String, [u32;4]), ParseError> {
+    let path = Path::new();
+    let id = try!(path.push("id", |path| Id::take(path, hello, "id")));
+    let name = try!(path.push("name", |path| String::take(path, hello, "name")));
+    let vendor = try!(path.push("vendor", |path| String::take(path, hello, "vendor")));
+    let numbers = try!(path.push("version", |path| u64::take_vec(path, hello, "version")));
+    if numbers.len() != 4 {
+        return Err(ParseError::type_error("version", &path, "array of 4 numbers"));
+    }
+    let mut version = [0;4];
+    for (i, number) in numbers.iter().enumerate() {
+        version[i] = *number as u32;
+    }
+    Ok((id, name, vendor, version))
+}
+
+/// The reply to a request, as seen by the thread waiting for it.
+enum Reply {
+    Waiting,
+    Received(Result<JSON, Error>),
+    Closed,
+}
+
+/// What the thread reading from the pipe asks the thread applying registrations to do.
+enum Registration {
+    /// Apply a notification sent by the child, e.g. `add_service`.
+    Apply(String, JSON),
+
+    /// The child has hung up, report the adapter as offline.
+    HungUp,
+}
+
+/// The state shared between the adapter, its watch guards and the threads reading from the pipe
+/// and applying registrations.
+struct Connection {
+    /// Where requests and notifications are written. Replaced with a sink once closed.
+    writer: Mutex<Box<Write + Send>>,
+
+    /// If the other side is a socket, a handle used to shut it down once closed, so that the
+    /// thread reading from it stops.
+    socket: Option<UnixStream>,
+
+    /// The requests awaiting a reply, by id.
+    pending: Mutex<HashMap<u64, Arc<(Mutex<Reply>, Condvar)>>>,
+
+    /// The watches registered with the child, by key.
+    watches: Mutex<HashMap<u64, Box<ExtSender<WatchEvent>>>>,
+
+    /// Where the thread reading from the pipe sends the registrations, in order. `None` once
+    /// the child has hung up.
+    tx_registrations: Mutex<Option<RawSender<Registration>>>,
+
+    /// The other end of `tx_registrations`, until `start` hands it to the thread applying
+    /// registrations, once the adapter has been added to the manager.
+    rx_registrations: Mutex<Option<Receiver<Registration>>>,
+
+    /// The id of the adapter, once known.
+    adapter: Mutex<Option<Id<AdapterId>>>,
+
+    /// The child process, if the adapter has been spawned, until it has been reaped.
+    child: Arc<Mutex<Option<Child>>>,
+
+    /// Used to generate request ids and watch keys.
+    counter: AtomicUsize,
+
+    is_closed: AtomicBool,
+}
+
+impl Connection {
+    fn new(writer: Box<Write + Send>, socket: Option<UnixStream>, child: Option<Child>) -> Self {
+        let (tx_registrations, rx_registrations) = channel();
+        Connection {
+            writer: Mutex::new(writer),
+            socket: socket,
+            pending: Mutex::new(HashMap::new()),
+            watches: Mutex::new(HashMap::new()),
+            tx_registrations: Mutex::new(Some(tx_registrations)),
+            rx_registrations: Mutex::new(Some(rx_registrations)),
+            adapter: Mutex::new(None),
+            child: Arc::new(Mutex::new(child)),
+            counter: AtomicUsize::new(0),
+            is_closed: AtomicBool::new(false),
+        }
+    }
+
+    fn next_key(&self) -> u64 {
+        self.counter.fetch_add(1, Ordering::Relaxed) as u64
+    }
+
+    /// Write a message, closing the connection if this fails.
+    fn write(&self, message: JSON) -> Result<(), ()> {
+        if self.is_closed.load(Ordering::Relaxed) {
+            return Err(());
+        }
+        // Serialization only fails for maps with non-string keys, which we do not have.
+        let line = serde_json::to_string(&message).unwrap() + "\n";
+        let result = {
+            let mut writer = self.writer.lock().unwrap();
+            writer.write_all(line.as_bytes()).and_then(|_| writer.flush())
+        };
+        if let Err(err) = result {
+            warn!(target: "Taxonomy-proxy", "Could not write to proxied adapter: {}", err);
+            self.close();
+            return Err(());
+        }
+        Ok(())
+    }
+
+    fn notify(&self, method: &str, params: JSON) -> Result<(), ()> {
+        self.write(encode_message(vec![("method", method.to_json()), ("params", params)]))
+    }
+
+    /// Send a request, then wait for the reply. Returns `Err(())` if the connection is closed,
+    /// or if the child does not reply within `REPLY_TIMEOUT_SECS`, in which case the connection
+    /// is closed.
+    fn call(&self, method: &str, params: JSON) -> Result<Result<JSON, Error>, ()> {
+        let id = self.next_key();
+        let reply = Arc::new((Mutex::new(Reply::Waiting), Condvar::new()));
+        self.pending.lock().unwrap().insert(id, reply.clone());
+        let request = encode_message(vec![
+            ("id", id.to_json()),
+            ("method", method.to_json()),
+            ("params", params)
+        ]);
+        if self.write(request).is_err() {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(());
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(REPLY_TIMEOUT_SECS);
+        let &(ref lock, ref condvar) = &*reply;
+        let mut state = lock.lock().unwrap();
+        loop {
+            match ::std::mem::replace(&mut *state, Reply::Waiting) {
+                Reply::Received(result) => return Ok(result),
+                Reply::Closed => return Err(()),
+                Reply::Waiting => {}
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            state = condvar.wait_timeout(state, deadline.duration_since(now)).unwrap().0;
+        }
+        drop(state);
+        warn!(target: "Taxonomy-proxy", "Proxied adapter has not replied to {} in time, closing the connection", method);
+        self.close();
+        Err(())
+    }
+
+    /// Close the connection. Pending requests fail and watches stop receiving events. A
+    /// spawned child is killed if it has not exited after `STOP_GRACE_SECS`.
+    fn close(&self) {
+        if !self.is_closed.swap(true, Ordering::Relaxed) && self.child.lock().unwrap().is_some() {
+            let child = self.child.clone();
+            thread::spawn(move || Connection::reap(child));
+        }
+        *self.writer.lock().unwrap() = Box::new(io::sink());
+        if let Some(ref socket) = self.socket {
+            let _ = socket.shutdown(Shutdown::Both);
+        }
+        for (_, reply) in self.pending.lock().unwrap().drain() {
+            Self::set_reply(&reply, Reply::Closed);
+        }
+        self.watches.lock().unwrap().clear();
+    }
+
+    /// Wait until the child has exited, without blocking the manager, and kill it if it is
+    /// still running after `STOP_GRACE_SECS`.
+    fn reap(child: Arc<Mutex<Option<Child>>>) {
+        let deadline = Instant::now() + Duration::from_secs(STOP_GRACE_SECS);
+        loop {
+            {
+                let mut guard = child.lock().unwrap();
+                let pid = match *guard {
+                    None => return,
+                    Some(ref child) => child.id() as libc::pid_t
+                };
+                let mut status = 0;
+                // Either the child has exited, and it is now reaped, or it is not our child
+                // anymore.
+                if unsafe { libc::waitpid(pid, &mut status, libc::WNOHANG) } != 0 {
+                    *guard = None;
+                    return;
+                }
+                if Instant::now() >= deadline {
+                    warn!(target: "Taxonomy-proxy", "Proxied adapter has not exited in time, killing it");
+                    if let Some(ref mut child) = *guard {
+                        let _ = child.kill();
+                        let _ = child.wait();
+                    }
+                    *guard = None;
+                    return;
+                }
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    fn set_reply(reply: &Arc<(Mutex<Reply>, Condvar)>, value: Reply) {
+        let &(ref lock, ref condvar) = &**reply;
+        *lock.lock().unwrap() = value;
+        condvar.notify_one();
+    }
+
+    /// Read messages until the pipe is closed.
+    fn read<R>(&self, reader: R) where R: Read {
+        for line in BufReader::new(reader).lines() {
+            let line = match line {
+                Err(err) => {
+                    warn!(target: "Taxonomy-proxy", "Could not read from proxied adapter: {}", err);
+                    break;
+                }
+                Ok(line) => line
+            };
+            let mut message: JSON = match serde_json::from_str(&line) {
+                Err(err) => {
+                    warn!(target: "Taxonomy-proxy", "Ignoring malformed message from proxied adapter: {}", err);
+                    continue;
+                }
+                Ok(message) => message
+            };
+            if let Err(err) = check_version(&mut message) {
+                warn!(target: "Taxonomy-proxy", "Ignoring message from proxied adapter: {}", err);
+                continue;
+            }
+            match String::take_opt(Path::new(), &mut message, "method") {
+                None => self.on_reply(message),
+                Some(Ok(method)) => {
+                    let params = take_json(&mut message, "params").unwrap_or(JSON::Null);
+                    if method == "watch_event" {
+                        self.on_watch_event(params);
+                        continue;
+                    }
+                    self.enqueue(Registration::Apply(method, params));
+                }
+                Some(Err(err)) => warn!(target: "Taxonomy-proxy", "Ignoring malformed message from proxied adapter: {}", err)
+            }
+        }
+
+        // The other side has hung up. Report it once the registrations received so far
+        // have been applied.
+        self.close();
+        self.enqueue(Registration::HungUp);
+        self.tx_registrations.lock().unwrap().take();
+    }
+
+    fn enqueue(&self, registration: Registration) {
+        if let Some(ref tx) = *self.tx_registrations.lock().unwrap() {
+            let _ = tx.send(registration);
+        }
+    }
+
+    fn on_reply(&self, mut message: JSON) {
+        let id = match u64::take(Path::new(), &mut message, "id") {
+            Err(err) => {
+                warn!(target: "Taxonomy-proxy", "Ignoring malformed reply from proxied adapter: {}", err);
+                return;
+            }
+            Ok(id) => id
+        };
+        let result = match (take_json(&mut message, "result"), take_json(&mut message, "error")) {
+            (_, Some(error)) => Err(decode_error(error)),
+            (Some(result), None) => Ok(result),
+            (None, None) => Ok(JSON::Null)
+        };
+        if let Some(reply) = self.pending.lock().unwrap().remove(&id) {
+            Self::set_reply(&reply, Reply::Received(result));
+        }
+    }
+
+    fn on_watch_event(&self, mut params: JSON) {
+        match parse_watch_event(&mut params) {
+            Err(err) => warn!(target: "Taxonomy-proxy", "Ignoring malformed event from proxied adapter: {}", err),
+            Ok((key, event)) => {
+                if let Some(on_event) = self.watches.lock().unwrap().get(&key) {
+                    let _ = on_event.send(event);
+                }
+            }
+        }
+    }
+
+    /// Apply the registrations received so far, then apply the next ones as they arrive,
+    /// until the child hangs up.
+    fn start<M>(connection: &Arc<Connection>, manager: &Arc<M>)
+        where M: AdapterManagerHandle + Send + Sync + 'static
+    {
+        let rx = match connection.rx_registrations.lock().unwrap().take() {
+            None => return,
+            Some(rx) => rx
+        };
+        while let Ok(registration) = rx.try_recv() {
+            connection.on_registration(&**manager, registration);
+        }
+        let connection = connection.clone();
+        let manager = Arc::downgrade(manager);
+        thread::spawn(move || {
+            for registration in rx {
+                match manager.upgrade() {
+                    None => return, // The manager has been dropped.
+                    Some(manager) => connection.on_registration(&*manager, registration)
+                }
+            }
+        });
+    }
+
+    fn on_registration<M>(&self, manager: &M, registration: Registration) where M: AdapterManagerHandle {
+        match registration {
+            Registration::Apply(method, params) => self.apply(manager, &method, params),
+            Registration::HungUp => {
+                let adapter = self.adapter.lock().unwrap().clone();
+                if let Some(adapter) = adapter {
+                    let _ = manager.set_adapter_status(&adapter, AdapterState::Offline,
+                        Some("The proxied adapter has closed the connection".to_owned()));
+                }
+            }
+        }
+    }
+
+    /// Apply a registration sent by the child.
+    fn apply<M>(&self, manager: &M, method: &str, mut params: JSON) where M: AdapterManagerHandle {
+        let adapter = match *self.adapter.lock().unwrap() {
+            None => return,
+            Some(ref adapter) => adapter.clone()
+        };
+        let path = Path::new();
+        let result = match method {
+            "add_service" => Service::parse(path, &mut params).map_err(invalid).and_then(|service| {
+                if service.adapter != adapter {
+                    return Err(Error::InternalError(InternalError::ConflictingAdapter(adapter.clone(), service.adapter)));
+                }
+                manager.add_service(service)
+            }),
+            "add_service_with_channels" => Service::parse(path, &mut params).map_err(invalid).and_then(|service| {
+                if service.adapter != adapter {
+                    return Err(Error::InternalError(InternalError::ConflictingAdapter(adapter.clone(), service.adapter)));
+                }
+                manager.add_service_with_channels(service)
+            }),
+            "add_getter" => Channel::<Getter>::parse(path, &mut params).map_err(invalid).and_then(|getter| {
+                if getter.adapter != adapter {
+                    return Err(Error::InternalError(InternalError::ConflictingAdapter(adapter.clone(), getter.adapter)));
+                }
+                manager.add_getter(getter)
+            }),
+            "add_setter" => Channel::<Setter>::parse(path, &mut params).map_err(invalid).and_then(|setter| {
+                if setter.adapter != adapter {
+                    return Err(Error::InternalError(InternalError::ConflictingAdapter(adapter.clone(), setter.adapter)));
+                }
+                manager.add_setter(setter)
+            }),
+            "remove_service" => Id::<ServiceId>::take(path, &mut params, "id").map_err(invalid)
+                .and_then(|id| manager.remove_service(&id)),
+            "remove_getter" => Id::<Getter>::take(path, &mut params, "id").map_err(invalid)
+                .and_then(|id| manager.remove_getter(&id)),
+            "remove_setter" => Id::<Setter>::take(path, &mut params, "id").map_err(invalid)
+                .and_then(|id| manager.remove_setter(&id)),
+            "set_adapter_status" => AdapterState::take(path.clone(), &mut params, "state").map_err(invalid)
+                .and_then(|state| {
+                    let reason = match String::take_opt(path, &mut params, "reason") {
+                        None => None,
+                        Some(result) => Some(try!(result.map_err(invalid)))
+                    };
+                    manager.set_adapter_status(&adapter, state, reason)
+                }),
+            _ => Err(Error::InternalError(InternalError::GenericError(format!("Unknown method {}", method))))
+        };
+        if let Err(err) = result {
+            warn!(target: "Taxonomy-proxy", "Could not apply {} from proxied adapter {}: {}", method, adapter, err);
+        }
+    }
+}
+
+/// An adapter running in another process, or on the other side of a Unix socket.
+///
+/// Once the other side has hung up, the adapter is reported as `AdapterState::Offline`,
+/// and operations fail with `Error::AdapterOffline`.
+pub struct ProxyAdapter {
+    id: Id<AdapterId>,
+    name: String,
+    vendor: String,
+    version: [u32;4],
+    connection: Arc<Connection>,
+}
+
+impl ProxyAdapter {
+    /// Spawn a child process speaking the protocol on its standard input and output, then add
+    /// the adapter to `manager`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the process cannot be spawned, if it does not reply to `hello`, or
+    /// if `manager` rejects the adapter.
+    pub fn spawn<M>(manager: &Arc<M>, command: &mut Command) -> Result<Arc<Self>, Error>
+        where M: AdapterManagerHandle + Send + Sync + 'static
+    {
+        let mut child = try!(command.stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(io_error));
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        Self::start(manager, stdout, Box::new(stdin), None, Some(child))
+    }
+
+    /// Connect to a Unix socket speaking the protocol, then add the adapter to `manager`.
+    ///
+    /// # Errors
+    ///
+    /// Same as `spawn`.
+    pub fn connect<M>(manager: &Arc<M>, path: &FilePath) -> Result<Arc<Self>, Error>
+        where M: AdapterManagerHandle + Send + Sync + 'static
+    {
+        let stream = try!(UnixStream::connect(path).map_err(io_error));
+        Self::with_stream(manager, stream)
+    }
+
+    /// Same as `connect`, with a socket that is already connected, e.g. one end of
+    /// `UnixStream::pair`.
+    pub fn with_stream<M>(manager: &Arc<M>, stream: UnixStream) -> Result<Arc<Self>, Error>
+        where M: AdapterManagerHandle + Send + Sync + 'static
+    {
+        let reader = try!(stream.try_clone().map_err(io_error));
+        let socket = try!(stream.try_clone().map_err(io_error));
+        Self::start(manager, reader, Box::new(stream), Some(socket), None)
+    }
+
+    fn start<M, R>(manager: &Arc<M>, reader: R, writer: Box<Write + Send>, socket: Option<UnixStream>,
+        child: Option<Child>) -> Result<Arc<Self>, Error>
+        where M: AdapterManagerHandle + Send + Sync + 'static, R: Read + Send + 'static
+    {
+        let connection = Arc::new(Connection::new(writer, socket, child));
+        {
+            let connection = connection.clone();
+            thread::spawn(move || connection.read(reader));
+        }
+
+        let hello = match connection.call("hello", JSON::Null) {
+            Err(()) => Err(Error::InternalError(InternalError::GenericError("The proxied adapter has closed the connection".to_owned()))),
+            Ok(result) => result
+        };
+        let mut hello = match hello {
+            Err(err) => {
+                connection.close();
+                return Err(err);
+            }
+            Ok(hello) => hello
+        };
+        let (id, name, vendor, version) = match parse_hello(&mut hello) {
+            Err(err) => {
+                connection.close();
+                return Err(invalid(err));
+            }
+            Ok(parsed) => parsed
+        };
+        *connection.adapter.lock().unwrap() = Some(id.clone());
+
+        let proxy = Arc::new(ProxyAdapter {
+            id: id,
+            name: name,
+            vendor: vendor,
+            version: version,
+            connection: connection.clone(),
+        });
+        if let Err(err) = manager.add_adapter(proxy.clone()) {
+            proxy.stop();
+            return Err(err);
+        }
+        Connection::start(&connection, manager);
+        Ok(proxy)
+    }
+
+    /// The process id of the child, if the adapter has been spawned and the child has not
+    /// exited yet.
+    pub fn process_id(&self) -> Option<u32> {
+        self.connection.child.lock().unwrap().as_ref().map(|child| child.id())
+    }
+
+    fn call(&self, method: &str, params: JSON) -> Result<JSON, Error> {
+        match self.connection.call(method, params) {
+            Err(()) => Err(Error::AdapterOffline(self.id.clone())),
+            Ok(result) => result
+        }
+    }
+}
+
+impl Adapter for ProxyAdapter {
+    fn id(&self) -> Id<AdapterId> {
+        self.id.clone()
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn vendor(&self) -> &str {
+        &self.vendor
+    }
+
+    fn version(&self) -> &[u32;4] {
+        &self.version
+    }
+
+    /// Fetch values from the child. The `User` is not transmitted.
+    fn fetch_values(&self, set: Vec<Id<Getter>>, _: User) -> ResultMap<Id<Getter>, Option<Value>, Error> {
+        let mut reply = match self.call("fetch_values", vec![("getters", set.to_json())].to_json()) {
+            Err(err) => return set.into_iter().map(|id| (id, Err(err.clone()))).collect(),
+            Ok(reply) => reply
+        };
+        set.into_iter().map(|id| {
+            let result = match take_json(&mut reply, &id.to_string()) {
+                None => Err(invalid(ParseError::missing_field(&id.to_string(), &Path::new()))),
+                Some(json) => decode_result(json, |json| {
+                    if json.is_null() {
+                        Ok(None)
+                    } else {
+                        Value::parse(Path::new(), json).map(Some)
+                    }
+                })
+            };
+            (id, result)
+        }).collect()
+    }
+
+    /// Send values to the child. The `User` is not transmitted.
+    fn send_values(&self, values: HashMap<Id<Setter>, Value>, _: User) -> ResultMap<Id<Setter>, (), Error> {
+        let ids: Vec<_> = values.keys().cloned().collect();
+        let mut reply = match self.call("send_values", vec![("values", values.to_json())].to_json()) {
+            Err(err) => return ids.into_iter().map(|id| (id, Err(err.clone()))).collect(),
+            Ok(reply) => reply
+        };
+        ids.into_iter().map(|id| {
+            let result = match take_json(&mut reply, &id.to_string()) {
+                None => Err(invalid(ParseError::missing_field(&id.to_string(), &Path::new()))),
+                Some(json) => decode_result(json, |_| Ok(()))
+            };
+            (id, result)
+        }).collect()
+    }
+
+    fn register_watch(&self, mut watch: Vec<WatchTarget>) -> WatchResult {
+        let mut keys = Vec::with_capacity(watch.len());
+        let mut targets = Vec::with_capacity(watch.len());
+        {
+            let mut watches = self.connection.watches.lock().unwrap();
+            for (id, range, on_event) in watch.drain(..) {
+                let key = self.connection.next_key();
+                let mut target = vec![("key", key.to_json()), ("getter", id.to_json())];
+                if let Some(ref range) = range {
+                    target.push(("range", range.to_json()));
+                }
+                targets.push(target.to_json());
+                watches.insert(key, on_event);
+                keys.push((id, key));
+            }
+        }
+        let reply = self.call("register_watch", vec![("watch", JSON::Array(targets))].to_json());
+        let mut results: Vec<Result<(), Error>> = match reply {
+            Ok(JSON::Array(results)) => results.into_iter().map(|json| {
+                decode_result(json, |json| {
+                    if json.is_null() {
+                        Ok(())
+                    } else {
+                        Err(ParseError::type_error("register_watch", &Path::new(), "null"))
+                    }
+                })
+            }).collect(),
+            Ok(_) => vec![],
+            Err(err) => vec![Err(err); keys.len()]
+        };
+        if results.len() != keys.len() {
+            results = vec![Err(invalid(ParseError::type_error("register_watch", &Path::new(), "array with an entry per target"))); keys.len()];
+        }
+        keys.into_iter().zip(results.drain(..)).map(|((id, key), result)| {
+            match result {
+                Ok(()) => (id, Ok(Box::new(ProxyWatchGuard {
+                    key: key,
+                    connection: self.connection.clone()
+                }) as Box<AdapterWatchGuard>)),
+                Err(err) => {
+                    self.connection.watches.lock().unwrap().remove(&key);
+                    (id, Err(err))
+                }
+            }
+        }).collect()
+    }
+
+    fn stop(&self) {
+        let _ = self.connection.notify("stop", JSON::Null);
+        self.connection.close();
+    }
+}
+
+/// Unregisters a watch from the child once dropped.
+struct ProxyWatchGuard {
+    key: u64,
+    connection: Arc<Connection>,
+}
+
+impl AdapterWatchGuard for ProxyWatchGuard {}
+
+impl Drop for ProxyWatchGuard {
+    fn drop(&mut self) {
+        self.connection.watches.lock().unwrap().remove(&self.key);
+        let _ = self.connection.notify("unregister_watch", vec![("key", self.key.to_json())].to_json());
+    }
+}
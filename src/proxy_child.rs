@@ -0,0 +1,317 @@
+//! The other side of a `ProxyAdapter`: serving an `Adapter` over a pipe.
+//!
+//! This is the reference implementation of the protocol described in module `proxy_adapter`.
+//! A child process typically creates a `HostHandle` writing to its standard output, passes it
+//! to its adapter to register services and channels, then calls `serve` with its standard
+//! input. See example `proxy_child` for a complete child process.
+
+use adapter::{ Adapter, AdapterFactory, AdapterManagerHandle, AdapterWatchGuard, WatchEvent };
+use api::{ Error, InternalError, User };
+use migration::IdMigration;
+use parse::{ JSON, Parser, ParseError, Path, ToJSON };
+use proxy_adapter::{ check_version, encode_error, encode_message, ADAPTER_ERROR, INVALID_PARAMS, METHOD_NOT_FOUND };
+use services::*;
+use values::{ Range, Value };
+
+use transformable_channels::mpsc::*;
+
+use serde_json;
+
+use std::collections::HashMap;
+use std::io::{ BufRead, BufReader, Read, Write };
+use std::sync::{ Arc, Mutex };
+
+/// An `AdapterManagerHandle` forwarding registrations to the host.
+///
+/// Registrations are notifications: a registration succeeds as soon as it has been sent. The
+/// host logs the registrations that it cannot apply.
+#[derive(Clone)]
+pub struct HostHandle {
+    writer: Arc<Mutex<Box<Write + Send>>>,
+}
+
+impl HostHandle {
+    pub fn new(writer: Box<Write + Send>) -> Self {
+        HostHandle {
+            writer: Arc::new(Mutex::new(writer))
+        }
+    }
+
+    fn write(&self, message: JSON) -> Result<(), Error> {
+        // Serialization only fails for maps with non-string keys, which we do not have.
+        let line = serde_json::to_string(&message).unwrap() + "\n";
+        let mut writer = self.writer.lock().unwrap();
+        writer.write_all(line.as_bytes())
+            .and_then(|_| writer.flush())
+            .map_err(|err| Error::InternalError(InternalError::GenericError(format!("Cannot reach host: {}", err))))
+    }
+
+    fn notify(&self, method: &str, params: JSON) -> Result<(), Error> {
+        self.write(encode_message(vec![("method", method.to_json()), ("params", params)]))
+    }
+
+    /// Reply to request `id`, with a JSON-RPC error code in case of error.
+    fn reply(&self, id: u64, result: Result<JSON, (i64, Error)>) -> Result<(), Error> {
+        let message = match result {
+            Ok(result) => vec![("id", id.to_json()), ("result", result)],
+            Err((code, err)) => vec![("id", id.to_json()), ("error", encode_error(code, &err))]
+        };
+        self.write(encode_message(message))
+    }
+
+    fn unsupported() -> Error {
+        Error::InternalError(InternalError::GenericError("Not supported by proxied adapters".to_owned()))
+    }
+}
+
+impl AdapterManagerHandle for HostHandle {
+    fn add_adapter(&self, _: Arc<Adapter>) -> Result<(), Error> {
+        Err(Self::unsupported())
+    }
+
+    fn add_adapter_with_factory(&self, _: AdapterFactory) -> Result<(), Error> {
+        Err(Self::unsupported())
+    }
+
+    fn remove_adapter(&self, _: &Id<AdapterId>) -> Result<(), Error> {
+        Err(Self::unsupported())
+    }
+
+    fn migrate_ids(&self, _: IdMigration) -> Result<(), Error> {
+        Err(Self::unsupported())
+    }
+
+    fn add_service(&self, service: Service) -> Result<(), Error> {
+        self.notify("add_service", service.to_json())
+    }
+
+    fn add_service_with_channels(&self, service: Service) -> Result<(), Error> {
+        self.notify("add_service_with_channels", service.to_json())
+    }
+
+    fn remove_service(&self, id: &Id<ServiceId>) -> Result<(), Error> {
+        self.notify("remove_service", vec![("id", id.to_json())].to_json())
+    }
+
+    fn add_getter(&self, getter: Channel<Getter>) -> Result<(), Error> {
+        self.notify("add_getter", getter.to_json())
+    }
+
+    fn remove_getter(&self, id: &Id<Getter>) -> Result<(), Error> {
+        self.notify("remove_getter", vec![("id", id.to_json())].to_json())
+    }
+
+    fn add_setter(&self, setter: Channel<Setter>) -> Result<(), Error> {
+        self.notify("add_setter", setter.to_json())
+    }
+
+    fn remove_setter(&self, id: &Id<Setter>) -> Result<(), Error> {
+        self.notify("remove_setter", vec![("id", id.to_json())].to_json())
+    }
+
+    /// Report the connectivity of the adapter. The id is ignored, as the host knows which
+    /// adapter it is talking to.
+    fn set_adapter_status(&self, _: &Id<AdapterId>, state: AdapterState, reason: Option<String>) -> Result<(), Error> {
+        let mut params = vec![("state", state.to_json())];
+        if let Some(reason) = reason {
+            params.push(("reason", reason.to_json()));
+        }
+        self.notify("set_adapter_status", params.to_json())
+    }
+}
+
+/// Forwards the events of a watch to the host.
+struct WatchEventSender {
+    key: u64,
+    host: HostHandle,
+}
+
+impl ExtSender<WatchEvent> for WatchEventSender {
+    fn send(&self, event: WatchEvent) -> Result<(), ()> {
+        let (kind, id, value) = match event {
+            WatchEvent::Enter { id, value } => ("Enter", id, value),
+            WatchEvent::Exit { id, value } => ("Exit", id, value),
+        };
+        self.host.notify("watch_event", vec![
+            ("key", self.key.to_json()),
+            ("event", kind.to_json()),
+            ("getter", id.to_json()),
+            ("value", value.to_json())
+        ].to_json()).map_err(|_| ())
+    }
+    fn internal_clone(&self) -> Box<ExtSender<WatchEvent>> {
+        Box::new(WatchEventSender {
+            key: self.key,
+            host: self.host.clone()
+        })
+    }
+}
+
+fn invalid(err: ParseError) -> (i64, Error) {
+    (INVALID_PARAMS, Error::InternalError(InternalError::GenericError(format!("Invalid request from host: {}", err))))
+}
+
+/// Remove a field from a JSON object, if possible.
+fn take_json(source: &mut JSON, field_name: &str) -> Option<JSON> {
+    match *source {
+        JSON::Object(ref mut obj) => obj.remove(field_name),
+        _ => None
+    }
+}
+
+/// Serve requests from the host until it asks the adapter to stop or closes the pipe, then
+/// stop the adapter.
+///
+/// The `User` of requests is always `User::None`.
+pub fn serve<R>(adapter: Arc<Adapter>, reader: R, host: &HostHandle) where R: Read {
+    let mut guards: HashMap<u64, Box<AdapterWatchGuard>> = HashMap::new();
+    for line in BufReader::new(reader).lines() {
+        let line = match line {
+            Err(_) => break,
+            Ok(line) => line
+        };
+        let mut message: JSON = match serde_json::from_str(&line) {
+            Err(err) => {
+                warn!(target: "Taxonomy-proxy", "Ignoring malformed message from host: {}", err);
+                continue;
+            }
+            Ok(message) => message
+        };
+        if let Err(err) = check_version(&mut message) {
+            warn!(target: "Taxonomy-proxy", "Ignoring message from host: {}", err);
+            continue;
+        }
+        let id = match u64::take_opt(Path::new(), &mut message, "id") {
+            Some(Ok(id)) => Some(id),
+            _ => None
+        };
+        let method = match String::take(Path::new(), &mut message, "method") {
+            Err(err) => {
+                warn!(target: "Taxonomy-proxy", "Ignoring malformed message from host: {}", err);
+                continue;
+            }
+            Ok(method) => method
+        };
+        let mut params = take_json(&mut message, "params").unwrap_or(JSON::Null);
+        let result = match &method as &str {
+            "hello" => Ok(vec![
+                ("id", adapter.id().to_json()),
+                ("name", adapter.name().to_json()),
+                ("vendor", adapter.vendor().to_json()),
+                ("version", adapter.version().iter().map(|number| *number as u64).collect::<Vec<_>>().to_json())
+            ].to_json()),
+            "fetch_values" => Id::<Getter>::take_vec(Path::new(), &mut params, "getters")
+                .map_err(invalid)
+                .map(|getters| adapter.fetch_values(getters, User::None).to_json()),
+            "send_values" => parse_values(&mut params)
+                .map_err(invalid)
+                .map(|values| adapter.send_values(values, User::None).to_json()),
+            "register_watch" => parse_watch(&mut params, host)
+                .map_err(invalid)
+                .map(|targets| register_watch(&*adapter, targets, &mut guards)),
+            "unregister_watch" => {
+                if let Ok(key) = u64::take(Path::new(), &mut params, "key") {
+                    guards.remove(&key);
+                }
+                continue;
+            }
+            "stop" => break,
+            _ => Err((METHOD_NOT_FOUND, Error::InternalError(InternalError::GenericError(format!("Unknown method {}", method)))))
+        };
+        if let Some(id) = id {
+            if host.reply(id, result).is_err() {
+                break;
+            }
+        }
+    }
+    guards.clear();
+    adapter.stop();
+}
+
+/// Reply to every request from the host with `error`, until the host closes the pipe. Use this
+/// instead of `serve` if the adapter cannot be created, so that the host learns why.
+pub fn refuse<R>(error: Error, reader: R, host: &HostHandle) where R: Read {
+    for line in BufReader::new(reader).lines() {
+        let line = match line {
+            Err(_) => break,
+            Ok(line) => line
+        };
+        let mut message: JSON = match serde_json::from_str(&line) {
+            Err(_) => continue,
+            Ok(message) => message
+        };
+        if let Some(Ok(id)) = u64::take_opt(Path::new(), &mut message, "id") {
+            if host.reply(id, Err((ADAPTER_ERROR, error.clone()))).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Decode the params of `send_values`.
+fn parse_values(params: &mut JSON) -> Result<HashMap<Id<Setter>, Value>, ParseError> {
+    let path = Path::new();
+    let mut values = HashMap::new();
+    match take_json(params, "values") {
+        Some(JSON::Object(mut obj)) => {
+            for (key, json) in obj.iter_mut() {
+                let value = try!(path.push(key, |path| Value::parse(path, json)));
+                values.insert(Id::new(key), value);
+            }
+            Ok(values)
+        }
+        _ => Err(ParseError::type_error("values", &path, "object"))
+    }
+}
+
+/// Decode the params of `register_watch`, as (key, getter, range, sender) for each target.
+fn parse_watch(params: &mut JSON, host: &HostHandle) -> Result<Vec<(u64, Id<Getter>, Option<Range>, WatchEventSender)>, ParseError> {
+    let path = Path::new();
+    let mut targets = match take_json(params, "watch") {
+        Some(JSON::Array(targets)) => targets,
+        _ => return Err(ParseError::type_error("watch", &path, "array"))
+    };
+    let mut result = Vec::with_capacity(targets.len());
+    for (target, i) in targets.iter_mut().zip(0..) {
+        let (key, getter, range) = try!(path.push_index(i, |path| parse_target(path, target)));
+        result.push((key, getter, range, WatchEventSender {
+            key: key,
+            host: host.clone()
+        }));
+    }
+    Ok(result)
+}
+
+fn parse_target(path: Path, target: &mut JSON) -> Result<(u64, Id<Getter>, Option<Range>), ParseError> {
+    let key = try!(path.push("key", |path| u64::take(path, target, "key")));
+    let getter = try!(path.push("getter", |path| Id::take(path, target, "getter")));
+    let range = match path.push("range", |path| Range::take_opt(path, target, "range")) {
+        Some(range) => Some(try!(range)),
+        None => None
+    };
+    Ok((key, getter, range))
+}
+
+/// Register watches with the adapter, keeping the guards, and encode the result of
+/// `register_watch`.
+fn register_watch(adapter: &Adapter, mut targets: Vec<(u64, Id<Getter>, Option<Range>, WatchEventSender)>,
+    guards: &mut HashMap<u64, Box<AdapterWatchGuard>>) -> JSON
+{
+    let keys: Vec<_> = targets.iter().map(|&(key, ref getter, _, _)| (key, getter.clone())).collect();
+    let watch = targets.drain(..)
+        .map(|(_, getter, range, sender)| (getter, range, Box::new(sender) as Box<ExtSender<WatchEvent>>))
+        .collect();
+    // Adapters receive each getter at most once per call, so we can match results by getter.
+    let mut results: HashMap<_, _> = adapter.register_watch(watch).into_iter().collect();
+    let encoded = keys.into_iter().map(|(key, getter)| {
+        match results.remove(&getter) {
+            Some(Ok(guard)) => {
+                guards.insert(key, guard);
+                JSON::Null
+            }
+            Some(Err(err)) => vec![("Error", err)].to_json(),
+            None => vec![("Error", Error::InternalError(InternalError::NoSuchGetter(getter)))].to_json()
+        }
+    }).collect();
+    JSON::Array(encoded)
+}
@@ -296,7 +296,11 @@ impl SelectedBy<ServiceSelector> for Service {
 /// - (optional) array of string `tags`:  accept only channels with all the tags in the array;
 /// - (optional) array of string `service_tags`:  accept only channels of a service with all the
 ///        tags in the array;
-/// - (optional) string|object `kind` (see `ChannelKind`): accept only channels of a given kind.
+/// - (optional) string|object `kind` (see `ChannelKind`): accept only channels of a given kind;
+/// - (optional) bool `pollable`: accept only channels whose `GetterCapabilities` declare this
+///        value for `pollable`;
+/// - (optional) bool `watchable`: accept only channels whose `GetterCapabilities` declare this
+///        value for `watchable`.
 ///
 /// While each field is optional, at least one field must be provided.
 ///
@@ -309,7 +313,9 @@ impl SelectedBy<ServiceSelector> for Service {
 ///   \"service\": \"service 1\",                  \
 ///   \"tags\": [\"tag 1\", \"tag 2\"],            \
 ///   \"service_tags\": [\"tag 3\", \"tag 4\"],    \
-///   \"kind\": \"Ready\"                          \
+///   \"kind\": \"Ready\",                         \
+///   \"pollable\": true,                          \
+///   \"watchable\": true                          \
 /// }";
 ///
 /// GetterSelector::from_str(json_selector).unwrap();
@@ -321,7 +327,7 @@ impl SelectedBy<ServiceSelector> for Service {
 ///   other => panic!("Unexpected result {:?}", other)
 /// }
 /// ```
-#[derive(Clone, Debug, Deserialize, Default)]
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
 pub struct GetterSelector {
     /// If `Exactly(id)`, return only the channel with the corresponding id.
     pub id: Exactly<Id<Getter>>,
@@ -340,6 +346,18 @@ pub struct GetterSelector {
     /// of kind `k`.
     pub kind: Exactly<ChannelKind>,
 
+    /// If `Exactly(b)`, restrict results to channels that declare `pollable == b`.
+    ///
+    /// Durable watches stored before capabilities were introduced lack this field.
+    #[serde(default)]
+    pub pollable: Exactly<bool>,
+
+    /// If `Exactly(b)`, restrict results to channels that declare `watchable == b`.
+    ///
+    /// Durable watches stored before capabilities were introduced lack this field.
+    #[serde(default)]
+    pub watchable: Exactly<bool>,
+
     /// Make sure that we can't instantiate from another crate.
     private: (),
 }
@@ -387,6 +405,20 @@ impl Parser<GetterSelector> for GetterSelector {
                 result
             }
         });
+        let pollable = try!(match path.push("pollable", |path| Exactly::take_opt(path, source, "pollable")) {
+            None => Ok(Exactly::Always),
+            Some(result) => {
+                is_empty = false;
+                result
+            }
+        });
+        let watchable = try!(match path.push("watchable", |path| Exactly::take_opt(path, source, "watchable")) {
+            None => Ok(Exactly::Always),
+            Some(result) => {
+                is_empty = false;
+                result
+            }
+        });
         if is_empty {
             Err(ParseError::empty_object(&path))
         } else {
@@ -396,6 +428,8 @@ impl Parser<GetterSelector> for GetterSelector {
                 tags: tags,
                 service_tags: service_tags,
                 kind: kind,
+                pollable: pollable,
+                watchable: watchable,
                 private: ()
             })
         }
@@ -431,6 +465,22 @@ impl GetterSelector {
         }
     }
 
+    /// Restrict to channels that can (or cannot) be polled.
+    pub fn with_pollable(self, pollable: bool) -> Self {
+        GetterSelector {
+            pollable: self.pollable.and(Exactly::Exactly(pollable)),
+            .. self
+        }
+    }
+
+    /// Restrict to channels that can (or cannot) be watched.
+    pub fn with_watchable(self, watchable: bool) -> Self {
+        GetterSelector {
+            watchable: self.watchable.and(Exactly::Exactly(watchable)),
+            .. self
+        }
+    }
+
     ///  Restrict to channels that have all the tags in `tags`.
     pub fn with_tags(self, tags: Vec<Id<TagId>>) -> Self {
         GetterSelector {
@@ -455,6 +505,8 @@ impl GetterSelector {
             tags: self.tags.union(&other.tags).cloned().collect(),
             service_tags: self.service_tags.union(&other.service_tags).cloned().collect(),
             kind: self.kind.and(other.kind),
+            pollable: self.pollable.and(other.pollable),
+            watchable: self.watchable.and(other.watchable),
             private: (),
         }
     }
@@ -470,6 +522,12 @@ impl GetterSelector {
         if !self.kind.matches(&channel.mechanism.kind) {
             return false;
         }
+        if !self.pollable.matches(&channel.mechanism.capabilities.pollable) {
+            return false;
+        }
+        if !self.watchable.matches(&channel.mechanism.capabilities.watchable) {
+            return false;
+        }
         if !has_selected_tags(&self.tags, &channel.tags) {
             return false;
         }
@@ -491,7 +549,9 @@ impl GetterSelector {
 /// - (optional) array of string `tags`:  accept only channels with all the tags in the array;
 /// - (optional) array of string `service_tags`:  accept only channels of a service with all the
 ///        tags in the array;
-/// - (optional) string|object `kind` (see `ChannelKind`): accept only channels of a given kind.
+/// - (optional) string|object `kind` (see `ChannelKind`): accept only channels of a given kind;
+/// - (optional) bool `has_feedback`: accept only channels whose `SetterCapabilities` declare
+///        this value for `has_feedback`.
 ///
 /// While each field is optional, at least one field must be provided.
 ///
@@ -504,7 +564,8 @@ impl GetterSelector {
 ///   \"service\": \"service 1\",                  \
 ///   \"tags\": [\"tag 1\", \"tag 2\"],            \
 ///   \"service_tags\": [\"tag 3\", \"tag 4\"],    \
-///   \"kind\": \"Ready\"                          \
+///   \"kind\": \"Ready\",                         \
+///   \"has_feedback\": true                       \
 /// }";
 ///
 /// SetterSelector::from_str(json_selector).unwrap();
@@ -535,6 +596,12 @@ pub struct SetterSelector {
     /// of kind `k`.
     pub kind: Exactly<ChannelKind>,
 
+    /// If `Exactly(b)`, restrict results to channels that declare `has_feedback == b`.
+    ///
+    /// Selectors serialized before capabilities were introduced lack this field.
+    #[serde(default)]
+    pub has_feedback: Exactly<bool>,
+
     /// Make sure that we can't instantiate from another crate.
     private: (),
 }
@@ -582,6 +649,13 @@ impl Parser<SetterSelector> for SetterSelector {
                 result
             }
         });
+        let has_feedback = try!(match path.push("has_feedback", |path| Exactly::take_opt(path, source, "has_feedback")) {
+            None => Ok(Exactly::Always),
+            Some(result) => {
+                is_empty = false;
+                result
+            }
+        });
         if is_empty {
             Err(ParseError::empty_object(&path))
         } else {
@@ -591,6 +665,7 @@ impl Parser<SetterSelector> for SetterSelector {
                 tags: tags,
                 service_tags: service_tags,
                 kind: kind,
+                has_feedback: has_feedback,
                 private: ()
             })
         }
@@ -627,6 +702,14 @@ impl SetterSelector {
         }
     }
 
+    /// Selector to channels that do (or do not) report the values they apply.
+    pub fn with_feedback(self, has_feedback: bool) -> Self {
+        SetterSelector {
+            has_feedback: self.has_feedback.and(Exactly::Exactly(has_feedback)),
+            .. self
+        }
+    }
+
     ///  Restrict to channels that have all the tags in `tags`.
     pub fn with_tags(self, tags: Vec<Id<TagId>>) -> Self {
         SetterSelector {
@@ -651,6 +734,7 @@ impl SetterSelector {
             tags: self.tags.union(&other.tags).cloned().collect(),
             service_tags: self.service_tags.union(&other.service_tags).cloned().collect(),
             kind: self.kind.and(other.kind),
+            has_feedback: self.has_feedback.and(other.has_feedback),
             private: (),
         }
     }
@@ -666,6 +750,9 @@ impl SetterSelector {
         if !self.kind.matches(&channel.mechanism.kind) {
             return false;
         }
+        if !self.has_feedback.matches(&channel.mechanism.capabilities.has_feedback) {
+            return false;
+        }
         if !has_selected_tags(&self.tags, &channel.tags) {
             return false;
         }
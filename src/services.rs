@@ -6,15 +6,17 @@
 //! devices may have been added or removed from the `FoxBox` by the time
 //! these data structures are read.
 
+use api::Error;
 use parse::*;
 use values::*;
-pub use util::{ Exactly, Id, AdapterId, ServiceId, KindId, TagId, VendorId };
+pub use util::{ Exactly, Id, AdapterId, DeviceId, ServiceId, KindId, TagId, VendorId, WatchId };
 
 use serde::ser::{ Serialize, Serializer };
-use serde::de::{ Deserialize, Deserializer, Error };
+use serde::de::{ Deserialize, Deserializer };
 
 use std::hash::{ Hash, Hasher };
 use std::collections::{ HashSet, HashMap };
+use std::fmt;
 
 // A helper macro to create a Id<ServiceId> without boilerplate.
 #[macro_export]
@@ -114,6 +116,420 @@ impl ToJSON for Service {
     }
 }
 
+impl Parser<Service> for Service {
+    fn description() -> String {
+        "Service".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        if !source.is_object() {
+            return Err(ParseError::type_error("Service", &path, "object"));
+        }
+        let id = try!(path.push("id", |path| Id::take(path, source, "id")));
+        let adapter = try!(path.push("adapter", |path| Id::take(path, source, "adapter")));
+        let tags = match path.push("tags", |path| Id::take_vec_opt(path, source, "tags")) {
+            Some(result) => try!(result).into_iter().collect(),
+            None => HashSet::new()
+        };
+        let properties = try!(path.push("properties", |path| take_properties(path, source, "properties")));
+        let getters = try!(path.push("getters", |path| take_channels(path, source, "getters")));
+        let setters = try!(path.push("setters", |path| take_channels(path, source, "setters")));
+        Ok(Service {
+            id: id,
+            adapter: adapter,
+            tags: tags,
+            properties: properties,
+            getters: getters,
+            setters: setters,
+        })
+    }
+}
+
+/// Parse an optional object of strings, consuming the field.
+fn take_properties(path: Path, source: &mut JSON, field_name: &str) -> Result<HashMap<String, String>, ParseError> {
+    let mut properties = HashMap::new();
+    let json = match *source {
+        JSON::Object(ref mut obj) => obj.remove(field_name),
+        _ => return Err(ParseError::type_error(field_name, &path, "object"))
+    };
+    match json {
+        None | Some(JSON::Null) => {}
+        Some(JSON::Object(mut obj)) => {
+            for (key, value) in obj.iter_mut() {
+                let value = try!(path.push(key, |path| String::parse(path, value)));
+                properties.insert(key.clone(), value);
+            }
+        }
+        Some(_) => return Err(ParseError::type_error(field_name, &path, "object"))
+    }
+    Ok(properties)
+}
+
+/// Parse an optional object of channels, consuming the field.
+fn take_channels<IO>(path: Path, source: &mut JSON, field_name: &str) -> Result<HashMap<Id<IO>, Channel<IO>>, ParseError>
+    where IO: IOMechanism, Channel<IO>: Parser<Channel<IO>>
+{
+    let mut channels = HashMap::new();
+    let json = match *source {
+        JSON::Object(ref mut obj) => obj.remove(field_name),
+        _ => return Err(ParseError::type_error(field_name, &path, "object"))
+    };
+    match json {
+        None | Some(JSON::Null) => {}
+        Some(JSON::Object(mut obj)) => {
+            for (key, value) in obj.iter_mut() {
+                let channel = try!(path.push(key, |path| Channel::<IO>::parse(path, value)));
+                channels.insert(channel.id.clone(), channel);
+            }
+        }
+        Some(_) => return Err(ParseError::type_error(field_name, &path, "object"))
+    }
+    Ok(channels)
+}
+
+/// The connectivity of an adapter, as reported by the adapter itself.
+///
+/// # JSON
+///
+/// Represented by a string with the name of the variant, e.g. `"Offline"`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AdapterState {
+    /// The adapter works normally.
+    Online,
+
+    /// Some of the devices of the adapter cannot be reached, or some operations fail, e.g.
+    /// because one of several bridges is unreachable.
+    Degraded,
+
+    /// None of the devices of the adapter can be reached, e.g. because its USB dongle is
+    /// unplugged. The services and channels of the adapter remain registered, but operations
+    /// on these channels fail with `Error::AdapterOffline` without reaching the adapter.
+    Offline,
+
+    /// The adapter has panicked repeatedly and has been isolated by the `AdapterManager`.
+    /// Operations on its channels fail with `Error::AdapterFaulted` without reaching the
+    /// adapter, until the adapter is restarted.
+    Faulted,
+}
+
+impl ToJSON for AdapterState {
+    fn to_json(&self) -> JSON {
+        match *self {
+            AdapterState::Online => JSON::String("Online".to_owned()),
+            AdapterState::Degraded => JSON::String("Degraded".to_owned()),
+            AdapterState::Offline => JSON::String("Offline".to_owned()),
+            AdapterState::Faulted => JSON::String("Faulted".to_owned()),
+        }
+    }
+}
+
+impl Parser<AdapterState> for AdapterState {
+    fn description() -> String {
+        "AdapterState".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        match source.as_string() {
+            Some("Online") => Ok(AdapterState::Online),
+            Some("Degraded") => Ok(AdapterState::Degraded),
+            Some("Offline") => Ok(AdapterState::Offline),
+            Some("Faulted") => Ok(AdapterState::Faulted),
+            Some(str) => Err(ParseError::unknown_constant(str, &path)),
+            None => Err(ParseError::type_error("AdapterState", &path, "string"))
+        }
+    }
+}
+
+/// The status of an adapter.
+///
+/// # JSON
+///
+/// A status is represented by an object with the following fields:
+///
+/// - adapter: string;
+/// - state: string (see `AdapterState`);
+/// - (optional) reason: string;
+/// - since: string (see `TimeStamp`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdapterStatus {
+    /// Identifier of the adapter.
+    pub adapter: Id<AdapterId>,
+
+    pub state: AdapterState,
+
+    /// An explanation of the state, suitable for display to the user, e.g. "Bridge unreachable".
+    pub reason: Option<String>,
+
+    /// When the adapter entered this state.
+    pub since: TimeStamp,
+}
+
+impl ToJSON for AdapterStatus {
+    fn to_json(&self) -> JSON {
+        let mut source = vec![
+            ("adapter", self.adapter.to_json()),
+            ("state", self.state.to_json()),
+            ("since", self.since.to_json()),
+        ];
+        if let Some(ref reason) = self.reason {
+            source.push(("reason", reason.to_json()));
+        }
+        source.to_json()
+    }
+}
+
+/// An option in the configuration of an adapter, e.g. the address of a bridge.
+///
+/// # JSON
+///
+/// A field is represented by an object with the following fields:
+///
+/// - name: string;
+/// - type: string (see `Type`);
+/// - description: string;
+/// - (optional) default: value (see `Value`);
+/// - required: bool;
+/// - secret: bool.
+#[derive(Debug, Clone)]
+pub struct ConfigField {
+    /// The name of the field, unique to this adapter.
+    pub name: String,
+
+    /// The type of the values accepted for this field.
+    pub typ: Type,
+
+    /// A description of the field, suitable for display to the user.
+    pub description: String,
+
+    /// The value used if the configuration doesn't specify one.
+    pub default: Option<Value>,
+
+    /// If `true` and there is no `default`, a configuration that doesn't specify a value for
+    /// this field is rejected.
+    pub is_required: bool,
+
+    /// If `true`, the value of this field, e.g. a password, is never exposed by
+    /// `get_adapter_config`.
+    pub is_secret: bool,
+}
+
+impl ToJSON for ConfigField {
+    fn to_json(&self) -> JSON {
+        let mut source = vec![
+            ("name", self.name.to_json()),
+            ("type", self.typ.to_json()),
+            ("description", self.description.to_json()),
+            ("required", self.is_required.to_json()),
+            ("secret", self.is_secret.to_json()),
+        ];
+        if let Some(ref default) = self.default {
+            source.push(("default", default.to_json()));
+        }
+        source.to_json()
+    }
+}
+
+/// The configuration of an adapter, by field name (see `ConfigField`).
+pub type AdapterConfig = HashMap<String, Value>;
+
+/// Check a configuration against the fields declared by an adapter, completing it with the
+/// default values.
+///
+/// ```
+/// use foxbox_taxonomy::api::Error;
+/// use foxbox_taxonomy::services::*;
+/// use foxbox_taxonomy::values::*;
+///
+/// use std::sync::Arc;
+///
+/// let schema = vec![ConfigField {
+///   name: "username".to_owned(),
+///   typ: Type::String,
+///   description: "The user registered with the bridge".to_owned(),
+///   default: Some(Value::String(Arc::new("foxbox".to_owned()))),
+///   is_required: true,
+///   is_secret: false,
+/// }];
+///
+/// let config = check_config(&schema, AdapterConfig::new()).unwrap();
+/// assert_eq!(config.get("username"), Some(&Value::String(Arc::new("foxbox".to_owned()))));
+///
+/// let mut config = AdapterConfig::new();
+/// config.insert("username".to_owned(), Value::OnOff(OnOff::On));
+/// match check_config(&schema, config) {
+///   Err(Error::TypeError(_)) => { /* as expected */ },
+///   other => panic!("Unexpected result {:?}", other)
+/// }
+///
+/// let mut config = AdapterConfig::new();
+/// config.insert("password".to_owned(), Value::String(Arc::new("secret".to_owned())));
+/// match check_config(&schema, config) {
+///   Err(Error::UnknownConfigField(ref name)) if name == "password" => { /* as expected */ },
+///   other => panic!("Unexpected result {:?}", other)
+/// }
+/// ```
+pub fn check_config(schema: &[ConfigField], mut config: AdapterConfig) -> Result<AdapterConfig, Error> {
+    for name in config.keys() {
+        if !schema.iter().any(|field| field.name == *name) {
+            return Err(Error::UnknownConfigField(name.clone()));
+        }
+    }
+    for field in schema {
+        if let Some(value) = config.get(&field.name) {
+            try!(field.typ.ensure_eq(&value.get_type()).map_err(Error::TypeError));
+            continue;
+        }
+        match field.default {
+            Some(ref default) => {
+                config.insert(field.name.clone(), default.clone());
+            }
+            None if field.is_required => return Err(Error::MissingConfigField(field.name.clone())),
+            None => {}
+        }
+    }
+    Ok(config)
+}
+
+/// A device found by an adapter during discovery, but not paired yet. Once paired, the device
+/// is represented by one or more `Service`.
+///
+/// # JSON
+///
+/// A pending device is represented by an object with the following fields:
+///
+/// - id: string - an id unique to this device for this adapter;
+/// - adapter: string;
+/// - name: string;
+/// - pairing: string (see `PairingMethod`);
+/// - properties: object.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingDevice {
+    /// An id unique to this device for this adapter.
+    pub id: Id<DeviceId>,
+
+    /// Identifier of the adapter that found this device.
+    pub adapter: Id<AdapterId>,
+
+    /// A name for the device, suitable for display to the user.
+    pub name: String,
+
+    /// What the user needs to do to pair this device.
+    pub pairing: PairingMethod,
+
+    /// Device properties, e.g. manufacturer, model, etc.
+    pub properties: HashMap<String, String>,
+}
+
+impl ToJSON for PendingDevice {
+    fn to_json(&self) -> JSON {
+        vec![
+            ("id", self.id.to_json()),
+            ("adapter", self.adapter.to_json()),
+            ("name", self.name.to_json()),
+            ("pairing", self.pairing.to_json()),
+            ("properties", self.properties.to_json()),
+        ].to_json()
+    }
+}
+
+/// What the user needs to do to pair a device.
+///
+/// # JSON
+///
+/// Represented by a string with the name of the variant, e.g. `"Pin"`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PairingMethod {
+    /// Nothing, the device can be paired without credentials.
+    None,
+
+    /// Press a button on the device, typically a bridge, before pairing.
+    PressButton,
+
+    /// Provide a PIN, typically displayed by the device or written on it.
+    Pin,
+
+    /// Provide a user name and a password.
+    Password,
+
+    /// Provide a token, typically obtained from a cloud service.
+    Token,
+}
+
+impl ToJSON for PairingMethod {
+    fn to_json(&self) -> JSON {
+        match *self {
+            PairingMethod::None => JSON::String("None".to_owned()),
+            PairingMethod::PressButton => JSON::String("PressButton".to_owned()),
+            PairingMethod::Pin => JSON::String("Pin".to_owned()),
+            PairingMethod::Password => JSON::String("Password".to_owned()),
+            PairingMethod::Token => JSON::String("Token".to_owned()),
+        }
+    }
+}
+
+/// The credentials provided by the user to pair a device.
+///
+/// # JSON
+///
+/// An object with either field `pin`, field `token`, or fields `user` and `password`.
+///
+/// ```
+/// use foxbox_taxonomy::services::*;
+/// use foxbox_taxonomy::parse::*;
+///
+/// let parsed = Credentials::from_str("{\"pin\": \"1234\"}").unwrap();
+/// assert_eq!(parsed, Credentials::Pin("1234".to_owned()));
+///
+/// let parsed = Credentials::from_str("{\"user\": \"admin\", \"password\": \"secret\"}").unwrap();
+/// assert_eq!(parsed, Credentials::Password {
+///   user: "admin".to_owned(),
+///   password: "secret".to_owned()
+/// });
+///
+/// // Credentials are not displayed in logs.
+/// assert_eq!(format!("{:?}", parsed), "Password { user: \"admin\", .. }");
+/// ```
+#[derive(Clone, PartialEq)]
+pub enum Credentials {
+    Pin(String),
+    Password {
+        user: String,
+        password: String,
+    },
+    Token(String),
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Credentials::Pin(_) => write!(f, "Pin(..)"),
+            Credentials::Password { ref user, .. } => write!(f, "Password {{ user: {:?}, .. }}", user),
+            Credentials::Token(_) => write!(f, "Token(..)"),
+        }
+    }
+}
+
+impl Parser<Credentials> for Credentials {
+    fn description() -> String {
+        "Credentials".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        if !source.is_object() {
+            return Err(ParseError::type_error("Credentials", &path, "object"));
+        }
+        if let Some(result) = path.push("pin", |path| String::take_opt(path, source, "pin")) {
+            return Ok(Credentials::Pin(try!(result)));
+        }
+        if let Some(result) = path.push("token", |path| String::take_opt(path, source, "token")) {
+            return Ok(Credentials::Token(try!(result)));
+        }
+        let user = try!(path.push("user", |path| String::take(path, source, "user")));
+        let password = try!(path.push("password", |path| String::take(path, source, "password")));
+        Ok(Credentials::Password {
+            user: user,
+            password: password
+        })
+    }
+}
+
 
 /// The kind of the channel, i.e. a strongly-typed description of
 /// _what_ the channel can do. Used both for locating channels
@@ -415,6 +831,7 @@ pub enum ChannelKind {
     /// - string `adapter`
     /// - string `kind`
     /// - string `type` (see Type)
+    /// - (optional) array of strings `variants`, required if `type` is `ExtEnum`
     ///
     /// ```
     /// use foxbox_taxonomy::services::*;
@@ -430,16 +847,53 @@ pub enum ChannelKind {
     ///
     /// let parsed = ChannelKind::from_str(source).unwrap();
     ///
-    /// if let ChannelKind::Extension { vendor, adapter, kind, typ } = parsed {
+    /// if let ChannelKind::Extension { vendor, adapter, kind, typ, variants } = parsed {
     ///   assert_eq!(vendor.to_string(), "mozilla.org");
     ///   assert_eq!(adapter.to_string(), "foxlink@mozilla.org");
     ///   assert_eq!(kind.to_string(), "GroundHumidity");
     ///   assert_eq!(typ, Type::ExtNumeric);
+    ///   assert!(variants.is_empty());
     /// } else {
     ///   panic!()
     /// }
     ///
     /// ```
+    ///
+    /// An enumeration declares the list of its variants. Values sent to or received from
+    /// the channel are rejected if they are not one of these variants.
+    ///
+    /// ```
+    /// use foxbox_taxonomy::services::*;
+    /// use foxbox_taxonomy::parse::*;
+    /// use foxbox_taxonomy::values::*;
+    ///
+    /// let source = "{
+    ///   \"vendor\": \"mozilla.org\",
+    ///   \"adapter\": \"hvac@mozilla.org\",
+    ///   \"kind\": \"HVACMode\",
+    ///   \"type\": \"ExtEnum\",
+    ///   \"variants\": [\"Heat\", \"Cool\", \"Auto\", \"Off\"]
+    /// }";
+    ///
+    /// let parsed = ChannelKind::from_str(source).unwrap();
+    /// if let ChannelKind::Extension { ref variants, .. } = parsed {
+    ///   assert_eq!(variants.len(), 4);
+    /// } else {
+    ///   panic!()
+    /// }
+    ///
+    /// let serialized = parsed.to_json();
+    /// assert_eq!(serialized.find("variants").unwrap().as_array().unwrap().len(), 4);
+    ///
+    /// // An enumeration without variants is rejected.
+    /// let source = "{
+    ///   \"vendor\": \"mozilla.org\",
+    ///   \"adapter\": \"hvac@mozilla.org\",
+    ///   \"kind\": \"HVACMode\",
+    ///   \"type\": \"ExtEnum\"
+    /// }";
+    /// assert!(ChannelKind::from_str(source).is_err());
+    /// ```
     Extension {
         /// The vendor. Used for namespacing purposes, to avoid
         /// confusing two incompatible extensions with similar
@@ -457,7 +911,11 @@ pub enum ChannelKind {
         kind: Id<KindId>,
 
         /// The data type of the value.
-        typ: Type
+        typ: Type,
+
+        /// If `typ` is `Type::ExtEnum`, the list of values accepted by
+        /// this channel. Empty for all other types.
+        variants: Vec<String>,
     }
 }
 
@@ -501,11 +959,23 @@ impl Parser<ChannelKind> for ChannelKind {
             let adapter = try!(path.push("adapter", |path| Id::take(path, source, "adapter")));
             let kind = try!(path.push("kind", |path| Id::take(path, source, "kind")));
             let typ = try!(path.push("type", |path| Type::take(path, source, "type")));
+            let variants = match path.push("variants", |path| String::take_vec_opt(path, source, "variants")) {
+                None => vec![],
+                Some(result) => try!(result)
+            };
+            match (&typ, variants.is_empty()) {
+                (&Type::ExtEnum, true) =>
+                    return Err(ParseError::type_error("variants", &path, "a non-empty array of strings")),
+                (&Type::ExtEnum, false) | (_, true) => {},
+                (_, false) =>
+                    return Err(ParseError::type_error("variants", &path, "no variants for a non-enumeration type")),
+            }
             Ok(ChannelKind::Extension {
                 vendor: vendor,
                 adapter: adapter,
                 kind: kind,
-                typ: typ
+                typ: typ,
+                variants: variants
             })
         } else {
             Err(ParseError::type_error("ChannelKind", &path, "string|object {vendor, adapter, kind, type}"))
@@ -536,13 +1006,17 @@ impl ToJSON for ChannelKind {
             TakeSnapshot => JSON::String("TakeSnapshot".to_owned()),
             Log => JSON::String("Log".to_owned()),
             WebPushNotify => JSON::String("WebPushNotify".to_owned()),
-            Extension { ref vendor, ref adapter, ref kind, ref typ } => {
-                vec![
+            Extension { ref vendor, ref adapter, ref kind, ref typ, ref variants } => {
+                let mut source = vec![
                     ("vendor", vendor.to_json()),
                     ("adapter", adapter.to_json()),
                     ("kind", kind.to_json()),
                     ("type", typ.to_json()),
-                ].to_json()
+                ];
+                if !variants.is_empty() {
+                    source.push(("variants", variants.to_json()));
+                }
+                source.to_json()
             }
         }
     }
@@ -572,6 +1046,374 @@ impl ChannelKind {
             Extension { ref typ, ..} => typ.clone(),
         }
     }
+
+    /// Ensure that a value may be sent to or received from a channel of this kind.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::TypeError` if the value doesn't have the type of this kind, or
+    /// `Error::InvalidValue` if this kind is an extension and the value belongs to another
+    /// extension (i.e. its vendor, adapter or kind differ) or, for an enumeration, isn't
+    /// among its variants.
+    pub fn ensure_accepts(&self, value: &Value) -> Result<(), Error> {
+        try!(self.get_type().ensure_eq(&value.get_type()).map_err(Error::TypeError));
+        if let ChannelKind::Extension { ref vendor, ref adapter, ref kind, ref variants, .. } = *self {
+            let is_same_extension = match *value {
+                Value::ExtBool(ref ext) =>
+                    ext.vendor == *vendor && ext.adapter == *adapter && ext.kind == *kind,
+                Value::ExtNumeric(ref ext) =>
+                    ext.vendor == *vendor && ext.adapter == *adapter && ext.kind == *kind,
+                Value::ExtEnum(ref ext) =>
+                    ext.vendor == *vendor && ext.adapter == *adapter && ext.kind == *kind &&
+                    variants.contains(&ext.value),
+                _ => true
+            };
+            if !is_same_extension {
+                return Err(Error::InvalidValue(value.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Constraints on the values accepted by a setter or produced by a getter, e.g.
+/// "between 50 and 250°C, in steps of 5°C". All constraints are optional.
+///
+/// Constraints on setters are enforced by the manager before values reach the adapter.
+/// Constraints on getters are advisory, e.g. for user interfaces: values fetched or watched
+/// from getters are not checked against them, as discarding a reading that a device reports
+/// out of its nominal range would hide it from the user.
+///
+/// # JSON
+///
+/// Constraints are represented by an object with optional fields `min`, `max`, `step`
+/// (values) and `allowed` (array of values).
+///
+/// ```
+/// use foxbox_taxonomy::api::Error;
+/// use foxbox_taxonomy::services::*;
+/// use foxbox_taxonomy::values::*;
+/// use foxbox_taxonomy::parse::*;
+///
+/// let source = "{
+///   \"min\": {\"Temperature\": {\"C\": 50}},
+///   \"max\": {\"Temperature\": {\"C\": 250}},
+///   \"step\": {\"Temperature\": {\"C\": 5}}
+/// }";
+/// let constraints = Constraints::from_str(source).unwrap();
+/// assert!(constraints.ensure_type(&Type::Temperature).is_ok());
+///
+/// assert!(constraints.ensure_accepts(&Value::Temperature(Temperature::C(180.))).is_ok());
+///
+/// // Outside of [min, max].
+/// match constraints.ensure_accepts(&Value::Temperature(Temperature::C(300.))) {
+///   Err(Error::RangeError(Range::BetweenEq { .. })) => {},
+///   other => panic!("Unexpected result {:?}", other)
+/// }
+///
+/// // Not on a step.
+/// match constraints.ensure_accepts(&Value::Temperature(Temperature::C(182.))) {
+///   Err(Error::InvalidValue(_)) => {},
+///   other => panic!("Unexpected result {:?}", other)
+/// }
+///
+/// let serialized = constraints.to_json();
+/// assert!(serialized.find("allowed").is_none());
+/// assert!(serialized.find_path(&["step", "Temperature", "C"]).is_some());
+///
+/// // Steps are rejected for types without arithmetic.
+/// let constraints = Constraints {
+///   step: Some(Value::OnOff(OnOff::On)),
+///   ..Constraints::default()
+/// };
+/// assert!(constraints.ensure_type(&Type::OnOff).is_err());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Constraints {
+    /// If specified, the smallest acceptable value.
+    #[serde(default)]
+    pub min: Option<Value>,
+
+    /// If specified, the largest acceptable value.
+    #[serde(default)]
+    pub max: Option<Value>,
+
+    /// If specified, acceptable values are `min + k * step`, for any integer `k`.
+    /// Ignored if `min` is not specified. For channels of type `TimeStamp`, `step` is a
+    /// `Duration`. For channels of type `Temperature`, `step` is a `TemperatureDelta`, or a
+    /// `Temperature` interpreted as a difference of temperatures. Only meaningful for types
+    /// that support arithmetic, i.e. `TimeStamp`, `Duration`, `Temperature`,
+    /// `TemperatureDelta` and `ExtNumeric`: `ensure_type` rejects a `step` for any other type.
+    #[serde(default)]
+    pub step: Option<Value>,
+
+    /// If non-empty, the list of acceptable values.
+    #[serde(default)]
+    pub allowed: Vec<Value>,
+}
+
+impl Constraints {
+    /// `true` if there is no constraint at all, i.e. any value of the right type is
+    /// acceptable.
+    pub fn is_empty(&self) -> bool {
+        self.min.is_none() && self.max.is_none() && self.step.is_none() && self.allowed.is_empty()
+    }
+
+    /// The range of values between `min` and `max`, if any.
+    pub fn get_range(&self) -> Option<Range> {
+        match (&self.min, &self.max) {
+            (&Some(ref min), &Some(ref max)) => Some(Range::BetweenEq {
+                min: min.clone(),
+                max: max.clone()
+            }),
+            (&Some(ref min), &None) => Some(Range::Geq(min.clone())),
+            (&None, &Some(ref max)) => Some(Range::Leq(max.clone())),
+            (&None, &None) => None,
+        }
+    }
+
+    /// Ensure that these constraints are meaningful for values of type `typ`.
+    pub fn ensure_type(&self, typ: &Type) -> Result<(), TypeError> {
+        for value in self.min.iter().chain(self.max.iter()).chain(self.allowed.iter()) {
+            try!(typ.ensure_eq(&value.get_type()));
+        }
+        if let Some(ref step) = self.step {
+            let step_type = match (typ, step.get_type()) {
+                (&Type::TimeStamp, _) => Type::Duration,
+                // For compatibility, temperature steps may be expressed as temperatures.
+                (&Type::Temperature, Type::Temperature) => Type::Temperature,
+                (&Type::Temperature, _) => Type::TemperatureDelta,
+                (&Type::Duration, _) | (&Type::TemperatureDelta, _) | (&Type::ExtNumeric, _) =>
+                    typ.clone(),
+                // Other types have no arithmetic, so no value could ever be checked
+                // against the step.
+                (other, _) => return Err(TypeError {
+                    expected: Type::ExtNumeric,
+                    got: other.clone()
+                })
+            };
+            try!(step_type.ensure_eq(&step.get_type()));
+        }
+        Ok(())
+    }
+
+    /// Ensure that a value satisfies these constraints. The value is expected to have
+    /// already been type-checked.
+    ///
+    /// Produces `Error::RangeError` if the value is not within `min` and `max` and
+    /// `Error::InvalidValue` if it is not on a step or not among the allowed values.
+    pub fn ensure_accepts(&self, value: &Value) -> Result<(), Error> {
+        if let Some(range) = self.get_range() {
+            if !range.contains(value) {
+                return Err(Error::RangeError(range));
+            }
+        }
+        if let (&Some(ref min), &Some(ref step)) = (&self.min, &self.step) {
+            let on_step = value.checked_steps(min, step)
+                .map(|ratio| (ratio - ratio.round()).abs() <= STEP_TOLERANCE)
+                .unwrap_or(false);
+            if !on_step {
+                return Err(Error::InvalidValue(value.clone()));
+            }
+        }
+        if !self.allowed.is_empty() && !self.allowed.contains(value) {
+            return Err(Error::InvalidValue(value.clone()));
+        }
+        Ok(())
+    }
+}
+
+/// Tolerance when checking whether a floating-point value is on a step, to absorb
+/// rounding errors.
+const STEP_TOLERANCE: f64 = 1e-6;
+
+impl Parser<Constraints> for Constraints {
+    fn description() -> String {
+        "Constraints".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        if !source.is_object() {
+            return Err(ParseError::type_error("Constraints", &path, "object"));
+        }
+        let min = match path.push("min", |path| Value::take_opt(path, source, "min")) {
+            Some(result) => Some(try!(result)),
+            None => None
+        };
+        let max = match path.push("max", |path| Value::take_opt(path, source, "max")) {
+            Some(result) => Some(try!(result)),
+            None => None
+        };
+        let step = match path.push("step", |path| Value::take_opt(path, source, "step")) {
+            Some(result) => Some(try!(result)),
+            None => None
+        };
+        let allowed = match path.push("allowed", |path| Value::take_vec_opt(path, source, "allowed")) {
+            Some(result) => try!(result),
+            None => vec![]
+        };
+        Ok(Constraints {
+            min: min,
+            max: max,
+            step: step,
+            allowed: allowed,
+        })
+    }
+}
+
+impl ToJSON for Constraints {
+    fn to_json(&self) -> JSON {
+        let mut source = vec![];
+        if let Some(ref min) = self.min {
+            source.push(("min", min.to_json()));
+        }
+        if let Some(ref max) = self.max {
+            source.push(("max", max.to_json()));
+        }
+        if let Some(ref step) = self.step {
+            source.push(("step", step.to_json()));
+        }
+        if !self.allowed.is_empty() {
+            source.push(("allowed", self.allowed.to_json()));
+        }
+        source.to_json()
+    }
+}
+
+/// The operations supported by a getter, declared when the getter is registered.
+///
+/// The `AdapterManager` rejects the operations that a getter doesn't support, without calling
+/// the adapter.
+///
+/// # JSON
+///
+/// An object with optional boolean fields `pollable`, `watchable` and `requires_threshold`.
+/// Missing fields take their default value.
+///
+/// ```
+/// use foxbox_taxonomy::services::*;
+/// use foxbox_taxonomy::parse::*;
+///
+/// let capabilities = GetterCapabilities::from_str("{\"watchable\": false}").unwrap();
+/// assert!(capabilities.pollable);
+/// assert!(!capabilities.watchable);
+/// assert!(!capabilities.requires_threshold);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetterCapabilities {
+    /// If `true`, the value may be fetched with `fetch_values`. Otherwise, fetching fails with
+    /// `GetterDoesNotSupportPolling`. Default: `true`.
+    pub pollable: bool,
+
+    /// If `true`, the value may be watched with `watch_values`. Otherwise, watching fails with
+    /// `GetterDoesNotSupportWatching`, unless the getter is `pollable` and its adapter polls it
+    /// instead, see `Adapter::polls_unwatchable_getters`. Default: `true`.
+    pub watchable: bool,
+
+    /// If `true`, watching requires a range, as watching all values would be too costly.
+    /// Watching with `Exactly::Always` fails with `GetterRequiresThresholdForWatching`.
+    /// Default: `false`.
+    pub requires_threshold: bool,
+}
+
+impl Default for GetterCapabilities {
+    fn default() -> Self {
+        GetterCapabilities {
+            pollable: true,
+            watchable: true,
+            requires_threshold: false,
+        }
+    }
+}
+
+impl Parser<GetterCapabilities> for GetterCapabilities {
+    fn description() -> String {
+        "GetterCapabilities".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        if !source.is_object() {
+            return Err(ParseError::type_error("GetterCapabilities", &path, "object"));
+        }
+        let default = GetterCapabilities::default();
+        let pollable = match path.push("pollable", |path| bool::take_opt(path, source, "pollable")) {
+            Some(result) => try!(result),
+            None => default.pollable
+        };
+        let watchable = match path.push("watchable", |path| bool::take_opt(path, source, "watchable")) {
+            Some(result) => try!(result),
+            None => default.watchable
+        };
+        let requires_threshold = match path.push("requires_threshold", |path| bool::take_opt(path, source, "requires_threshold")) {
+            Some(result) => try!(result),
+            None => default.requires_threshold
+        };
+        Ok(GetterCapabilities {
+            pollable: pollable,
+            watchable: watchable,
+            requires_threshold: requires_threshold,
+        })
+    }
+}
+
+impl ToJSON for GetterCapabilities {
+    fn to_json(&self) -> JSON {
+        vec![
+            ("pollable", self.pollable.to_json()),
+            ("watchable", self.watchable.to_json()),
+            ("requires_threshold", self.requires_threshold.to_json()),
+        ].to_json()
+    }
+}
+
+/// The behavior of a setter, declared when the setter is registered.
+///
+/// # JSON
+///
+/// An object with an optional boolean field `has_feedback`. Missing fields take their default
+/// value.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetterCapabilities {
+    /// If `true`, the device reports the values it has actually applied, typically through a
+    /// getter of the same service. If `false`, the setter is write-only: a successful
+    /// `send_values` only means that the value has been sent. Default: `true`.
+    ///
+    /// This is information for clients, e.g. to decide whether to confirm a change by
+    /// watching a getter, or to select setters with `SetterSelector::with_feedback`. The
+    /// manager itself sends values the same way in either case.
+    pub has_feedback: bool,
+}
+
+impl Default for SetterCapabilities {
+    fn default() -> Self {
+        SetterCapabilities {
+            has_feedback: true,
+        }
+    }
+}
+
+impl Parser<SetterCapabilities> for SetterCapabilities {
+    fn description() -> String {
+        "SetterCapabilities".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        if !source.is_object() {
+            return Err(ParseError::type_error("SetterCapabilities", &path, "object"));
+        }
+        let has_feedback = match path.push("has_feedback", |path| bool::take_opt(path, source, "has_feedback")) {
+            Some(result) => try!(result),
+            None => SetterCapabilities::default().has_feedback
+        };
+        Ok(SetterCapabilities {
+            has_feedback: has_feedback,
+        })
+    }
+}
+
+impl ToJSON for SetterCapabilities {
+    fn to_json(&self) -> JSON {
+        vec![
+            ("has_feedback", self.has_feedback.to_json()),
+        ].to_json()
+    }
 }
 
 /// A getter operation available on a channel.
@@ -580,6 +1422,15 @@ pub struct Getter {
     /// The kind of value that can be obtained from this channel.
     pub kind: ChannelKind,
 
+    /// Constraints on the values produced by this channel. These are advisory: values
+    /// fetched or watched from this channel are delivered even if they do not satisfy them.
+    #[serde(default)]
+    pub constraints: Constraints,
+
+    /// The operations supported by this channel.
+    #[serde(default)]
+    pub capabilities: GetterCapabilities,
+
     /// Date at which the latest value was received, whether through
     /// polling or through a trigger.
     #[serde(default)]
@@ -595,6 +1446,15 @@ pub struct Setter {
     /// The kind of value that can be sent to this channel.
     pub kind: ChannelKind,
 
+    /// Constraints on the values accepted by this channel. Values that do not satisfy
+    /// them are rejected before reaching the adapter.
+    #[serde(default)]
+    pub constraints: Constraints,
+
+    /// The behavior of this channel.
+    #[serde(default)]
+    pub capabilities: SetterCapabilities,
+
     /// Date at which the latest value was sent to the channel.
     #[serde(default)]
     pub updated: Option<TimeStamp>,
@@ -645,6 +1505,7 @@ impl ToJSON for Channel<Getter> {
             ("service", self.service.to_json()),
             ("mechanism", JSON::String("getter".to_owned())),
             ("kind", self.mechanism.kind.to_json()),
+            ("capabilities", self.mechanism.capabilities.to_json()),
         ];
         if let Some(ref ts) = self.last_seen {
             source.push(("last_seen", ts.to_json()))
@@ -652,6 +1513,9 @@ impl ToJSON for Channel<Getter> {
         if let Some(ref ts) = self.mechanism.updated {
             source.push(("updated", ts.to_json()));
         }
+        if !self.mechanism.constraints.is_empty() {
+            source.push(("constraints", self.mechanism.constraints.to_json()));
+        }
 
         let map = source.drain(..)
             .map(|(key, value)| (key.to_owned(), value))
@@ -670,6 +1534,7 @@ impl ToJSON for Channel<Setter> {
             ("service", self.service.to_json()),
             ("mechanism", JSON::String("setter".to_owned())),
             ("kind", self.mechanism.kind.to_json()),
+            ("capabilities", self.mechanism.capabilities.to_json()),
         ];
         if let Some(ref ts) = self.last_seen {
             source.push(("last_seen", ts.to_json()))
@@ -677,6 +1542,9 @@ impl ToJSON for Channel<Setter> {
         if let Some(ref ts) = self.mechanism.updated {
             source.push(("updated", ts.to_json()));
         }
+        if !self.mechanism.constraints.is_empty() {
+            source.push(("constraints", self.mechanism.constraints.to_json()));
+        }
 
         let map = source.drain(..)
             .map(|(key, value)| (key.to_owned(), value))
@@ -685,6 +1553,88 @@ impl ToJSON for Channel<Setter> {
     }
 }
 
+/// Parse the fields of a channel, once its mechanism has been checked.
+fn parse_channel<IO, F>(path: Path, source: &mut JSON, mechanism: &str, make: F) -> Result<Channel<IO>, ParseError>
+    where IO: IOMechanism, F: FnOnce(Path, &mut JSON, ChannelKind, Constraints, Option<TimeStamp>) -> Result<IO, ParseError>
+{
+    if !source.is_object() {
+        return Err(ParseError::type_error("Channel", &path, "object"));
+    }
+    let found = try!(path.push("mechanism", |path| String::take(path, source, "mechanism")));
+    if found != mechanism {
+        return Err(ParseError::unknown_constant(&found, &path));
+    }
+    let id = try!(path.push("id", |path| Id::take(path, source, "id")));
+    let adapter = try!(path.push("adapter", |path| Id::take(path, source, "adapter")));
+    let service = try!(path.push("service", |path| Id::take(path, source, "service")));
+    let tags = match path.push("tags", |path| Id::take_vec_opt(path, source, "tags")) {
+        Some(result) => try!(result).into_iter().collect(),
+        None => HashSet::new()
+    };
+    let kind = try!(path.push("kind", |path| ChannelKind::take(path, source, "kind")));
+    let constraints = match path.push("constraints", |path| Constraints::take_opt(path, source, "constraints")) {
+        Some(result) => try!(result),
+        None => Constraints::default()
+    };
+    let last_seen = match path.push("last_seen", |path| TimeStamp::take_opt(path, source, "last_seen")) {
+        Some(result) => Some(try!(result)),
+        None => None
+    };
+    let updated = match path.push("updated", |path| TimeStamp::take_opt(path, source, "updated")) {
+        Some(result) => Some(try!(result)),
+        None => None
+    };
+    let mechanism = try!(make(path, source, kind, constraints, updated));
+    Ok(Channel {
+        id: id,
+        adapter: adapter,
+        service: service,
+        tags: tags,
+        last_seen: last_seen,
+        mechanism: mechanism,
+    })
+}
+
+impl Parser<Channel<Getter>> for Channel<Getter> {
+    fn description() -> String {
+        "Channel<Getter>".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        parse_channel(path, source, "getter", |path, source, kind, constraints, updated| {
+            let capabilities = match path.push("capabilities", |path| GetterCapabilities::take_opt(path, source, "capabilities")) {
+                Some(result) => try!(result),
+                None => GetterCapabilities::default()
+            };
+            Ok(Getter {
+                kind: kind,
+                constraints: constraints,
+                capabilities: capabilities,
+                updated: updated,
+            })
+        })
+    }
+}
+
+impl Parser<Channel<Setter>> for Channel<Setter> {
+    fn description() -> String {
+        "Channel<Setter>".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        parse_channel(path, source, "setter", |path, source, kind, constraints, updated| {
+            let capabilities = match path.push("capabilities", |path| SetterCapabilities::take_opt(path, source, "capabilities")) {
+                Some(result) => try!(result),
+                None => SetterCapabilities::default()
+            };
+            Ok(Setter {
+                kind: kind,
+                constraints: constraints,
+                capabilities: capabilities,
+                updated: updated,
+            })
+        })
+    }
+}
+
 impl<IO> Eq for Channel<IO> where IO: IOMechanism {
 }
 
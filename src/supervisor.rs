@@ -0,0 +1,420 @@
+//! Isolation of misbehaving adapters.
+//!
+//! Each adapter added to the `AdapterManager` is wrapped in a `SupervisedAdapter`, which
+//! catches the panics of the adapter and turns them into errors, so that a panic cannot unwind
+//! through the `AdapterManager` or poison its locks. An adapter that panics repeatedly is
+//! marked as faulted and isolated: requests do not reach it anymore. If the adapter was added
+//! with a factory, the `Supervisor` then replaces it with a new instance, which takes over the
+//! services, channels and watches of the former instance. An adapter added without a factory
+//! remains isolated until it is restarted through the API.
+
+use adapter::{ Adapter, AdapterFactory, ResultMap, WatchResult, WatchTarget };
+use api::{ DiscoveryEvent, Error, InternalError, User };
+use backend::State;
+use config_storage::ConfigStorage;
+use services::*;
+use values::Value;
+
+use sublock::atomlock::*;
+use transformable_channels::mpsc::*;
+
+use std::collections::HashMap;
+use std::panic::{ self, AssertUnwindSafe };
+use std::path::PathBuf;
+use std::sync::{ Arc, Mutex, Weak };
+use std::sync::atomic::{ AtomicBool, AtomicUsize, Ordering };
+use std::thread;
+use std::time::{ Duration, Instant };
+
+/// The number of consecutive panics after which an adapter is isolated.
+pub const MAX_CONSECUTIVE_PANICS: usize = 3;
+
+/// The number of times a faulted adapter is restarted without intervention of the user.
+pub const MAX_AUTOMATIC_RESTARTS: usize = 3;
+
+/// The number of seconds after which an adapter that has not faulted since its latest restart
+/// is considered healthy again. Its count of automatic restarts is then reset.
+pub const HEALTHY_PERIOD_SECS: u64 = 600;
+
+/// An adapter whose panics are caught and turned into errors.
+pub struct SupervisedAdapter {
+    adapter: Arc<Adapter>,
+
+    /// The id of `adapter`, so that we don't need to ask a faulted adapter.
+    id: Id<AdapterId>,
+
+    /// The number of panics since the latest call that succeeded.
+    panics: AtomicUsize,
+
+    /// `true` once the adapter has panicked `MAX_CONSECUTIVE_PANICS` times in a row.
+    is_faulted: AtomicBool,
+
+    /// Informed once the adapter is faulted.
+    on_fault: Mutex<Box<ExtSender<Id<AdapterId>>>>,
+}
+
+impl SupervisedAdapter {
+    pub fn new(adapter: Arc<Adapter>, on_fault: Box<ExtSender<Id<AdapterId>>>) -> Self {
+        SupervisedAdapter {
+            id: adapter.id(),
+            adapter: adapter,
+            panics: AtomicUsize::new(0),
+            is_faulted: AtomicBool::new(false),
+            on_fault: Mutex::new(on_fault),
+        }
+    }
+
+    /// Call the adapter, unless it is faulted, turning a panic into an error.
+    fn call<F, T>(&self, call: F) -> Result<T, Error> where F: FnOnce(&Adapter) -> T {
+        if self.is_faulted.load(Ordering::Relaxed) {
+            return Err(Error::AdapterFaulted(self.id.clone()));
+        }
+        let adapter = &*self.adapter;
+        match panic::catch_unwind(AssertUnwindSafe(|| call(adapter))) {
+            Ok(result) => {
+                self.panics.store(0, Ordering::Relaxed);
+                Ok(result)
+            }
+            Err(_) => {
+                let panics = self.panics.fetch_add(1, Ordering::Relaxed) + 1;
+                error!(target: "Taxonomy-supervisor", "Adapter {} has panicked ({} time(s) in a row)", self.id, panics);
+                if panics >= MAX_CONSECUTIVE_PANICS && !self.is_faulted.swap(true, Ordering::Relaxed) {
+                    let _ = self.on_fault.lock().unwrap().send(self.id.clone());
+                }
+                Err(Error::AdapterPanicked(self.id.clone()))
+            }
+        }
+    }
+}
+
+impl Adapter for SupervisedAdapter {
+    fn id(&self) -> Id<AdapterId> {
+        self.id.clone()
+    }
+
+    fn name(&self) -> &str {
+        self.adapter.name()
+    }
+
+    fn vendor(&self) -> &str {
+        self.adapter.vendor()
+    }
+
+    fn version(&self) -> &[u32;4] {
+        self.adapter.version()
+    }
+
+    fn polls_unwatchable_getters(&self) -> bool {
+        self.call(|adapter| adapter.polls_unwatchable_getters())
+            .unwrap_or(false)
+    }
+
+    fn fetch_values(&self, set: Vec<Id<Getter>>, user: User) -> ResultMap<Id<Getter>, Option<Value>, Error> {
+        let ids = set.clone();
+        match self.call(move |adapter| adapter.fetch_values(set, user)) {
+            Ok(results) => results,
+            Err(err) => ids.into_iter().map(|id| (id, Err(err.clone()))).collect()
+        }
+    }
+
+    fn send_values(&self, values: HashMap<Id<Setter>, Value>, user: User) -> ResultMap<Id<Setter>, (), Error> {
+        let ids: Vec<_> = values.keys().cloned().collect();
+        match self.call(move |adapter| adapter.send_values(values, user)) {
+            Ok(results) => results,
+            Err(err) => ids.into_iter().map(|id| (id, Err(err.clone()))).collect()
+        }
+    }
+
+    fn register_watch(&self, watch: Vec<WatchTarget>) -> WatchResult {
+        let ids: Vec<_> = watch.iter().map(|&(ref id, _, _)| id.clone()).collect();
+        match self.call(move |adapter| adapter.register_watch(watch)) {
+            Ok(results) => results,
+            Err(err) => ids.into_iter().map(|id| (id, Err(err.clone()))).collect()
+        }
+    }
+
+    fn start_discovery(&self, on_event: Box<ExtSender<DiscoveryEvent>>) -> Result<(), Error> {
+        self.call(move |adapter| adapter.start_discovery(on_event))
+            .and_then(|result| result)
+    }
+
+    fn stop_discovery(&self) {
+        let _ = self.call(|adapter| adapter.stop_discovery());
+    }
+
+    fn get_pending_devices(&self) -> Vec<PendingDevice> {
+        self.call(|adapter| adapter.get_pending_devices())
+            .unwrap_or_else(|_| vec![])
+    }
+
+    fn pair(&self, device: &Id<DeviceId>, credentials: Option<Credentials>) -> Result<(), Error> {
+        self.call(move |adapter| adapter.pair(device, credentials))
+            .and_then(|result| result)
+    }
+
+    fn unpair(&self, service: &Id<ServiceId>) -> Result<(), Error> {
+        self.call(|adapter| adapter.unpair(service))
+            .and_then(|result| result)
+    }
+
+    fn config_schema(&self) -> Vec<ConfigField> {
+        self.call(|adapter| adapter.config_schema())
+            .unwrap_or_else(|_| vec![])
+    }
+
+    fn set_config(&self, config: &AdapterConfig) -> Result<(), Error> {
+        self.call(|adapter| adapter.set_config(config))
+            .and_then(|result| result)
+    }
+
+    fn stop(&self) {
+        // Even a faulted adapter needs to release its resources.
+        let adapter = &*self.adapter;
+        if panic::catch_unwind(AssertUnwindSafe(|| adapter.stop())).is_err() {
+            error!(target: "Taxonomy-supervisor", "Adapter {} has panicked while stopping", self.id);
+        }
+    }
+}
+
+/// Adds adapters to the back-end, isolates the adapters that panic repeatedly and restarts
+/// them if possible.
+pub struct Supervisor {
+    back_end: Weak<MainLock<State>>,
+
+    /// The path to the taxonomy database, if any, used to configure the adapters.
+    db_path: Option<PathBuf>,
+
+    /// Informed whenever the supervisor changes the status of an adapter.
+    statuses: Mutex<Box<ExtSender<AdapterStatus>>>,
+
+    /// How to restart each adapter.
+    restarts: Mutex<HashMap<Id<AdapterId>, Restarts>>,
+
+    /// Passed to each `SupervisedAdapter`, to report faults.
+    tx_fault: Mutex<RawSender<Id<AdapterId>>>,
+}
+
+impl Supervisor {
+    pub fn new(back_end: &Arc<MainLock<State>>, db_path: Option<PathBuf>,
+        statuses: Box<ExtSender<AdapterStatus>>) -> Arc<Self>
+    {
+        let (tx_fault, rx_fault) = channel();
+        let supervisor = Arc::new(Supervisor {
+            back_end: Arc::downgrade(back_end),
+            db_path: db_path,
+            statuses: Mutex::new(statuses),
+            restarts: Mutex::new(HashMap::new()),
+            tx_fault: Mutex::new(tx_fault),
+        });
+        let weak = Arc::downgrade(&supervisor);
+        thread::spawn(move || {
+            for id in rx_fault {
+                match weak.upgrade() {
+                    None => return, // The manager has been dropped.
+                    Some(supervisor) => supervisor.on_fault(&id)
+                }
+            }
+        });
+        supervisor
+    }
+
+    /// Add an adapter to the back-end, then apply its stored configuration, if any.
+    pub fn add_adapter(&self, adapter: Arc<Adapter>) -> Result<(), Error> {
+        self.add_restartable_adapter(adapter, None)
+    }
+
+    /// Add an adapter created by `factory`, remembering the factory for restarts.
+    pub fn add_adapter_with_factory(&self, factory: AdapterFactory) -> Result<(), Error> {
+        let adapter = try!(Self::create(&factory));
+        self.add_restartable_adapter(adapter, Some(factory))
+    }
+
+    fn add_restartable_adapter(&self, adapter: Arc<Adapter>, factory: Option<AdapterFactory>) -> Result<(), Error> {
+        let back_end = try!(self.get_back_end());
+        let id = adapter.id();
+        let supervised = self.supervise(adapter.clone());
+        try!(back_end.write().unwrap().add_adapter(supervised.clone()));
+        self.restarts.lock().unwrap().insert(id, Restarts {
+            factory: factory,
+            instance: adapter,
+            count: 0,
+            latest: None,
+        });
+        self.restore_adapter_config(&back_end, supervised);
+        Ok(())
+    }
+
+    /// Forget how to restart an adapter that has been removed.
+    pub fn remove_adapter(&self, id: &Id<AdapterId>) {
+        self.restarts.lock().unwrap().remove(id);
+    }
+
+    /// Restart an adapter at the request of the user, which resets its count of automatic
+    /// restarts.
+    pub fn restart_adapter(&self, id: &Id<AdapterId>) -> Result<(), Error> {
+        try!(self.replace_adapter(id));
+        if let Some(restarts) = self.restarts.lock().unwrap().get_mut(id) {
+            restarts.count = 0;
+        }
+        Ok(())
+    }
+
+    /// Replace an adapter with a new instance created by its factory or, if it was added
+    /// without a factory, resume the same instance with its panics forgotten.
+    fn replace_adapter(&self, id: &Id<AdapterId>) -> Result<(), Error> {
+        let (factory, instance) = match self.restarts.lock().unwrap().get(id) {
+            None => return Err(Error::AdapterNotRestartable(id.clone())),
+            Some(restarts) => (restarts.factory.clone(), restarts.instance.clone())
+        };
+        let back_end = try!(self.get_back_end());
+        let former = try!(back_end.read().unwrap().get_adapter(id));
+
+        let adapter = match factory {
+            // The instance cannot be replaced, so it must not be stopped either.
+            None => self.supervise(instance),
+            Some(factory) => {
+                former.stop();
+                match Self::create(&factory) {
+                    Ok(adapter) => {
+                        if let Some(restarts) = self.restarts.lock().unwrap().get_mut(id) {
+                            restarts.instance = adapter.clone();
+                        }
+                        self.supervise(adapter)
+                    }
+                    Err(err) => {
+                        // We have nothing to replace the former instance with.
+                        let _ = back_end.write().unwrap().remove_adapter(id);
+                        return Err(err);
+                    }
+                }
+            }
+        };
+        if let Some(restarts) = self.restarts.lock().unwrap().get_mut(id) {
+            restarts.latest = Some(Instant::now());
+        }
+        // Keep the services and channels, so that watchers keep watching them, but register
+        // the watches again with the new instance.
+        let request = try!(back_end.write().unwrap().replace_adapter(adapter.clone()));
+        let ongoing = State::start_watch(request);
+        back_end.write().unwrap().register_ongoing_watch(ongoing);
+        self.restore_adapter_config(&back_end, adapter);
+        info!(target: "Taxonomy-supervisor", "Adapter {} has been restarted", id);
+
+        let status = back_end.read().unwrap().get_adapter_statuses()
+            .into_iter()
+            .find(|status| status.adapter == *id);
+        if let Some(status) = status {
+            let _ = self.statuses.lock().unwrap().send(status);
+        }
+        Ok(())
+    }
+
+    /// Isolate a faulted adapter, then restart it if possible.
+    fn on_fault(&self, id: &Id<AdapterId>) {
+        let back_end = match self.back_end.upgrade() {
+            None => return,
+            Some(back_end) => back_end
+        };
+        let reason = format!("The adapter has panicked {} times in a row", MAX_CONSECUTIVE_PANICS);
+        let status = back_end.write().unwrap().set_adapter_status(id, AdapterState::Faulted, Some(reason));
+        if let Ok(Some(status)) = status {
+            let _ = self.statuses.lock().unwrap().send(status);
+        }
+
+        let can_restart = match self.restarts.lock().unwrap().get_mut(id) {
+            Some(restarts) => restarts.on_fault(),
+            None => false
+        };
+        if can_restart {
+            if let Err(err) = self.replace_adapter(id) {
+                error!(target: "Taxonomy-supervisor", "Could not restart adapter {}: {}", id, err);
+            }
+        }
+    }
+
+    /// Wrap an adapter, so that its panics are reported to `on_fault`.
+    fn supervise(&self, adapter: Arc<Adapter>) -> Arc<SupervisedAdapter> {
+        let on_fault = self.tx_fault.lock().unwrap().internal_clone();
+        Arc::new(SupervisedAdapter::new(adapter, on_fault))
+    }
+
+    /// Create an adapter, turning a panic of the factory into an error.
+    fn create(factory: &AdapterFactory) -> Result<Arc<Adapter>, Error> {
+        match panic::catch_unwind(AssertUnwindSafe(|| factory())) {
+            Ok(result) => result,
+            Err(_) => Err(Error::InternalError(InternalError::GenericError("The adapter factory has panicked".to_owned())))
+        }
+    }
+
+    fn get_back_end(&self) -> Result<Arc<MainLock<State>>, Error> {
+        self.back_end.upgrade().ok_or_else(|| {
+            Error::InternalError(InternalError::GenericError("The manager has been stopped".to_owned()))
+        })
+    }
+
+    /// Apply the configuration stored in the taxonomy database for an adapter, if any.
+    fn restore_adapter_config(&self, back_end: &Arc<MainLock<State>>, adapter: Arc<Adapter>) {
+        let path = match self.db_path {
+            None => return,
+            Some(ref path) => path
+        };
+        let id = adapter.id();
+        let config = match ConfigStorage::new(&path).get_config(&id) {
+            Err(err) => {
+                error!("Storage get_config error: {}", err);
+                return;
+            }
+            Ok(None) => return,
+            Ok(Some(config)) => config
+        };
+        let config = match check_config(&adapter.config_schema(), config)
+            .and_then(|config| adapter.set_config(&config).map(|_| config))
+        {
+            Err(err) => {
+                warn!("Cannot apply the stored configuration of adapter {}: {}", id, err);
+                return;
+            }
+            Ok(config) => config
+        };
+        // The adapter may have been removed in the meantime, in which case there is nothing
+        // left to record.
+        let _ = back_end.write().unwrap().set_adapter_config(&id, config);
+    }
+}
+
+/// How to restart an adapter.
+struct Restarts {
+    /// The factory of the adapter, or `None` if it was added without a factory, in which case
+    /// it is only restarted at the request of the user, by resuming `instance`.
+    factory: Option<AdapterFactory>,
+
+    /// The current instance of the adapter, without supervision.
+    instance: Arc<Adapter>,
+
+    /// The number of automatic restarts since the adapter was last healthy.
+    count: usize,
+
+    /// When the adapter was last restarted, if ever.
+    latest: Option<Instant>,
+}
+
+impl Restarts {
+    /// Determine whether a faulted adapter may be restarted automatically, counting the
+    /// restart if so.
+    fn on_fault(&mut self) -> bool {
+        if self.factory.is_none() {
+            return false;
+        }
+        let is_healthy = self.latest.map_or(true, |latest| {
+            latest.elapsed() >= Duration::from_secs(HEALTHY_PERIOD_SECS)
+        });
+        if is_healthy {
+            self.count = 0;
+        }
+        if self.count >= MAX_AUTOMATIC_RESTARTS {
+            return false;
+        }
+        self.count += 1;
+        true
+    }
+}
@@ -6,7 +6,10 @@
 ///! It provides an api to manage Id <-> tags relationships.
 ///! All users share the same tags for objects.
 
+use migration::IdMigration;
+
 use rusqlite::{ Connection, Result };
+use std::collections::HashMap;
 use std::path::PathBuf;
 use util::{ Id, TagId };
 
@@ -53,15 +56,53 @@ impl TagStorage {
             panic!("Unable to open taxonomy tags database: {}", err);
         });
 
-        db.execute("CREATE TABLE IF NOT EXISTS tags (
+        Self::create_table(&db).unwrap_or_else(|err| {
+            panic!("Unable to create taxonomy tags database: {}", err);
+        });
+
+        self.db = Some(db);
+    }
+
+    /// Create the table of tags, if it doesn't exist yet.
+    pub fn create_table(db: &Connection) -> Result<()> {
+        try!(db.execute("CREATE TABLE IF NOT EXISTS tags (
                     key    TEXT NOT NULL PRIMARY KEY,
                     id     TEXT NOT NULL,
                     tag    TEXT NOT NULL
-            )", &[]).unwrap_or_else(|err| {
-                panic!("Unable to create taxonomy tags database: {}", err);
-            });
+            )", &[]));
+        Ok(())
+    }
 
-        self.db = Some(db);
+    /// Move the tags of the services and channels renamed by `migration` to their new id.
+    /// Tags already stored for the new id are kept.
+    ///
+    /// This doesn't start a transaction, see `migration::migrate_storage`.
+    pub fn migrate(db: &Connection, migration: &IdMigration) -> Result<()> {
+        try!(Self::migrate_ids(db, &migration.services));
+        try!(Self::migrate_ids(db, &migration.getters));
+        Self::migrate_ids(db, &migration.setters)
+    }
+
+    fn migrate_ids<T>(db: &Connection, ids: &HashMap<Id<T>, Id<T>>) -> Result<()> {
+        for (former, new) in ids {
+            let mut tags = Vec::new();
+            {
+                let mut stmt = try!(db.prepare("SELECT tag FROM tags WHERE id=$1"));
+                let rows = try!(stmt.query(&[&escape(former)]));
+                for result_row in rows {
+                    let row = try!(result_row);
+                    tags.push(row.get::<String>(0));
+                }
+            }
+            for stored in &tags {
+                // Tags are stored escaped, but keys are computed from the original tag.
+                let tag = Id::<TagId>::new(&stored.replace("''", "'"));
+                try!(db.execute("INSERT OR IGNORE INTO tags VALUES ($1, $2, $3)",
+                                &[&create_key(new, &tag), &escape(new), stored]));
+            }
+            try!(db.execute("DELETE FROM tags WHERE id=$1", &[&escape(former)]));
+        }
+        Ok(())
     }
 
     // Debug printing.
@@ -242,4 +283,22 @@ fn storage_test() {
     store.remove_tags(&id1, &[Id::new("tag1"), Id::new("tag2"), Id::new("tag3")]).unwrap();
     tags = store.get_tags_for(&id1).unwrap();
     assert_eq!(tags.len(), 0);
+
+    // Migrating tags to a new id, keeping the tags it already has.
+    store.add_tags(&id1, &[Id::new("tag1"), Id::new("it's")]).unwrap();
+    store.add_tag(&id2, &Id::new("tag2")).unwrap();
+    {
+        use migration::IdMigration;
+        let db = Connection::open(get_db_environment()).unwrap();
+        TagStorage::migrate(&db, &IdMigration::new().with_service(id1.clone(), id2.clone())).unwrap();
+    }
+    tags = store.get_tags_for(&id1).unwrap();
+    assert_eq!(tags.len(), 0);
+    tags = store.get_tags_for(&id2).unwrap();
+    assert_eq!(tags.len(), 3);
+
+    // Migrated tags can be removed like any other.
+    store.remove_tag(&id2, &Id::new("it's")).unwrap();
+    tags = store.get_tags_for(&id2).unwrap();
+    assert_eq!(tags.len(), 2);
 }
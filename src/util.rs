@@ -368,6 +368,15 @@ pub struct AdapterId;
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Hash, Eq)]
 pub struct TagId;
 
+/// A marker for Id.
+/// Only useful for writing `Id<DeviceId>`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Hash, Eq)]
+pub struct DeviceId;
+
+/// A marker for Id.
+/// Only useful for writing `Id<WatchId>`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Hash, Eq)]
+pub struct WatchId;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Hash, Eq)]
 pub struct KindId;
@@ -380,3 +389,86 @@ pub struct MimeTypeId;
 
 /// Helper function, to check that a type implements Sync.
 pub fn is_sync<T: Sync>() {}
+
+const BASE64_ALPHABET: &'static [u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encode binary data as standard base64 (RFC 4648), with padding.
+///
+/// ```
+/// use foxbox_taxonomy::util::*;
+///
+/// assert_eq!(base64_encode(b"foxbox"), "Zm94Ym94");
+/// assert_eq!(base64_encode(b"fox"), "Zm94");
+/// assert_eq!(base64_encode(b"fo"), "Zm8=");
+/// ```
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut result = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let b0 = chunk[0] as usize;
+        let b1 = if chunk.len() > 1 { chunk[1] as usize } else { 0 };
+        let b2 = if chunk.len() > 2 { chunk[2] as usize } else { 0 };
+        result.push(BASE64_ALPHABET[b0 >> 2] as char);
+        result.push(BASE64_ALPHABET[((b0 & 0x03) << 4) | (b1 >> 4)] as char);
+        if chunk.len() > 1 {
+            result.push(BASE64_ALPHABET[((b1 & 0x0f) << 2) | (b2 >> 6)] as char);
+        } else {
+            result.push('=');
+        }
+        if chunk.len() > 2 {
+            result.push(BASE64_ALPHABET[b2 & 0x3f] as char);
+        } else {
+            result.push('=');
+        }
+    }
+    result
+}
+
+/// Decode standard base64 (RFC 4648). Padding is optional and whitespace is ignored.
+/// Returns `None` if `source` is not valid base64.
+///
+/// ```
+/// use foxbox_taxonomy::util::*;
+///
+/// assert_eq!(base64_decode("Zm94Ym94").unwrap(), b"foxbox".to_vec());
+/// assert_eq!(base64_decode("Zm8=").unwrap(), b"fo".to_vec());
+/// assert_eq!(base64_decode("Zm8").unwrap(), b"fo".to_vec());
+/// assert!(base64_decode("Zm8!").is_none());
+/// ```
+pub fn base64_decode(source: &str) -> Option<Vec<u8>> {
+    let mut result = Vec::with_capacity(source.len() / 4 * 3);
+    let mut buffer : u32 = 0;
+    let mut bits = 0;
+    let mut padding = false;
+    for byte in source.bytes() {
+        let sextet = match byte {
+            b'A' ... b'Z' => byte - b'A',
+            b'a' ... b'z' => byte - b'a' + 26,
+            b'0' ... b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => {
+                padding = true;
+                continue;
+            }
+            b' ' | b'\t' | b'\r' | b'\n' => continue,
+            _ => return None
+        };
+        if padding {
+            // Data after padding.
+            return None;
+        }
+        buffer = (buffer << 6) | sextet as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    if bits >= 6 {
+        // A single trailing sextet cannot encode a byte.
+        return None;
+    }
+    Some(result)
+}
@@ -7,14 +7,16 @@ use util::*;
 use std::cmp::{ PartialOrd, Ordering };
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::io::Read;
 use std::str::FromStr;
-use std::sync::Arc;
-use std::{ error, fmt };
+use std::sync::{ Arc, Condvar, Mutex };
+use std::sync::mpsc::{ sync_channel, Receiver, SyncSender };
+use std::{ cmp, error, fmt, io };
 
 use chrono::{ Duration as ChronoDuration, DateTime, Local, TimeZone, UTC };
 
 use serde_json;
-use serde::ser::{ Serialize, Serializer };
+use serde::ser::{ Error as SerError, Serialize, Serializer };
 use serde::de::{ Deserialize, Deserializer, Error, Visitor };
 
 /// Representation of a type error.
@@ -43,6 +45,110 @@ impl error::Error for TypeError {
     }
 }
 
+/// An error arising while performing arithmetic on values.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ArithmeticError {
+    /// The operands do not have the types expected by the operation.
+    TypeError(TypeError),
+
+    /// Both operands are `ExtNumeric`, but belong to distinct extensions, i.e. their
+    /// vendor or kind differ.
+    ExtensionMismatch {
+        left: (Id<VendorId>, Id<KindId>),
+        right: (Id<VendorId>, Id<KindId>),
+    },
+
+    /// The result cannot be represented.
+    Overflow,
+}
+
+impl ArithmeticError {
+    fn ensure_same_extension(a: &ExtValue<f64>, b: &ExtValue<f64>) -> Result<(), ArithmeticError> {
+        if a.vendor != b.vendor || a.kind != b.kind {
+            return Err(ArithmeticError::ExtensionMismatch {
+                left: (a.vendor.clone(), a.kind.clone()),
+                right: (b.vendor.clone(), b.kind.clone()),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ArithmeticError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ArithmeticError::TypeError(ref err) => write!(f, "{}: {}", error::Error::description(self), err),
+            ArithmeticError::ExtensionMismatch { ref left, ref right } =>
+                write!(f, "{}: {}/{} and {}/{}", error::Error::description(self), left.0, left.1, right.0, right.1),
+            ArithmeticError::Overflow => write!(f, "{}", error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for ArithmeticError {
+    fn description(&self) -> &str {
+        match *self {
+            ArithmeticError::TypeError(_) => "Operands have incompatible types",
+            ArithmeticError::ExtensionMismatch { .. } => "Operands belong to distinct extensions",
+            ArithmeticError::Overflow => "Arithmetic overflow",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            ArithmeticError::TypeError(ref err) => Some(err),
+            ArithmeticError::ExtensionMismatch { .. } | ArithmeticError::Overflow => None,
+        }
+    }
+}
+
+impl From<TypeError> for ArithmeticError {
+    fn from(err: TypeError) -> Self {
+        ArithmeticError::TypeError(err)
+    }
+}
+
+/// An error arising while determining the type of the values watched with a `Range`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum RangeTypeError {
+    /// Parts of the range expect values of incompatible types.
+    TypeError(TypeError),
+
+    /// An `And` or `Or` without any range, which does not determine a type.
+    EmptyComposition,
+}
+
+impl fmt::Display for RangeTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RangeTypeError::TypeError(ref err) => write!(f, "{}: {}", error::Error::description(self), err),
+            RangeTypeError::EmptyComposition => write!(f, "{}", error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for RangeTypeError {
+    fn description(&self) -> &str {
+        match *self {
+            RangeTypeError::TypeError(_) => "Range has incompatible types",
+            RangeTypeError::EmptyComposition => "Range composes an empty list of ranges",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            RangeTypeError::TypeError(ref err) => Some(err),
+            RangeTypeError::EmptyComposition => None,
+        }
+    }
+}
+
+impl From<TypeError> for RangeTypeError {
+    fn from(err: TypeError) -> Self {
+        RangeTypeError::TypeError(err)
+    }
+}
+
 ///
 /// The type of values manipulated by endpoints.
 ///
@@ -86,6 +192,11 @@ pub enum Type {
     WebPushNotify,
 
     Temperature,
+
+    /// A difference between two temperatures, e.g. the result of subtracting two
+    /// temperatures, or the step between two accepted temperatures.
+    TemperatureDelta,
+
     String,
     ///
     /// ...
@@ -96,6 +207,15 @@ pub enum Type {
 
     ExtBool,
     ExtNumeric,
+
+    /// A value taken from a list of variants that has not been
+    /// standardized yet. The list of variants is declared by the
+    /// `ChannelKind::Extension` of the channel.
+    ExtEnum,
+
+    /// A geographic location. Used for instance for presence detection
+    /// and geofencing.
+    GeoPoint,
 }
 impl Parser<Type> for Type {
     fn description() -> String {
@@ -112,6 +232,7 @@ impl Parser<Type> for Type {
                 "Duration" => Ok(Duration),
                 "TimeStamp" => Ok(TimeStamp),
                 "Temperature" => Ok(Temperature),
+                "TemperatureDelta" => Ok(TemperatureDelta),
                 "ThinkerbellRule" => Ok(ThinkerbellRule),
                 "WebPushNotify" => Ok(WebPushNotify),
                 "String" => Ok(String),
@@ -120,6 +241,8 @@ impl Parser<Type> for Type {
                 "Binary" => Ok(Binary),
                 "ExtBool" => Ok(ExtBool),
                 "ExtNumeric" => Ok(ExtNumeric),
+                "ExtEnum" => Ok(ExtEnum),
+                "GeoPoint" => Ok(GeoPoint),
                 _ => Err(ParseError::unknown_constant(string, &path))
             },
             _ => Err(ParseError::type_error("Type", &path, "string"))
@@ -137,6 +260,7 @@ impl ToJSON for Type {
             Duration => "Duration",
             TimeStamp => "TimeStamp",
             Temperature => "Temperature",
+            TemperatureDelta => "TemperatureDelta",
             ThinkerbellRule => "ThinkerbellRule",
             WebPushNotify => "WebPushNotify",
             String => "String",
@@ -145,6 +269,8 @@ impl ToJSON for Type {
             Binary => "Binary",
             ExtBool => "ExtBool",
             ExtNumeric => "ExtNumeric",
+            ExtEnum => "ExtEnum",
+            GeoPoint => "GeoPoint",
         };
         JSON::String(key.to_owned())
     }
@@ -157,9 +283,10 @@ impl Type {
     pub fn supports_eq(&self) -> bool {
         use self::Type::*;
         match *self {
-            Duration | TimeStamp | Temperature | ExtNumeric | Color | ThinkerbellRule => false,
+            Duration | TimeStamp | Temperature | TemperatureDelta | ExtNumeric | Color | ThinkerbellRule |
+            GeoPoint => false,
             WebPushNotify | Unit | String | Json | Binary | OnOff | OpenClosed |
-            DoorLocked | ExtBool => true,
+            DoorLocked | ExtBool | ExtEnum => true,
         }
     }
 
@@ -629,12 +756,130 @@ pub enum Temperature {
 impl Temperature {
     /// Get a temperature in Fahrenheit.
     pub fn as_f(&self) -> f64 {
-        unimplemented!();
+        match *self {
+            Temperature::F(val) => val,
+            Temperature::C(val) => val * 9. / 5. + 32.,
+        }
     }
 
     /// Get a temperature in Celcius.
     pub fn as_c(&self) -> f64 {
-        unimplemented!();
+        match *self {
+            Temperature::F(val) => (val - 32.) * 5. / 9.,
+            Temperature::C(val) => val,
+        }
+    }
+
+    /// The difference `self - other`, expressed in the unit of `self`.
+    ///
+    /// ```
+    /// use foxbox_taxonomy::values::*;
+    ///
+    /// let delta = Temperature::F(77.).delta(&Temperature::C(20.));
+    /// assert_eq!(delta, TemperatureDelta::F(9.));
+    /// assert_eq!(delta.as_c(), 5.);
+    /// ```
+    pub fn delta(&self, other: &Temperature) -> TemperatureDelta {
+        match *self {
+            Temperature::F(val) => TemperatureDelta::F(val - other.as_f()),
+            Temperature::C(val) => TemperatureDelta::C(val - other.as_c()),
+        }
+    }
+
+    /// The temperature `self + delta`, expressed in the unit of `self`.
+    pub fn shift(&self, delta: &TemperatureDelta) -> Temperature {
+        match *self {
+            Temperature::F(val) => Temperature::F(val + delta.as_f()),
+            Temperature::C(val) => Temperature::C(val + delta.as_c()),
+        }
+    }
+}
+
+/// A difference between two temperatures.
+///
+/// Unlike a `Temperature`, converting a difference between units doesn't apply the offset
+/// between both scales: a difference of 9 degrees Fahrenheit is a difference of 5 degrees
+/// Celcius.
+///
+/// # JSON
+///
+/// Values of this type are represented by objects `{F; float}` or `{C: float}`, as
+/// temperatures.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TemperatureDelta {
+    /// Degrees Fahrenheit.
+    F(f64),
+
+    /// Degrees Celcius.
+    C(f64),
+}
+
+impl TemperatureDelta {
+    /// The sum `self + other`, expressed in the unit of `self`.
+    pub fn plus(&self, other: &TemperatureDelta) -> TemperatureDelta {
+        match *self {
+            TemperatureDelta::F(val) => TemperatureDelta::F(val + other.as_f()),
+            TemperatureDelta::C(val) => TemperatureDelta::C(val + other.as_c()),
+        }
+    }
+
+    /// The opposite difference.
+    pub fn opposite(&self) -> TemperatureDelta {
+        match *self {
+            TemperatureDelta::F(val) => TemperatureDelta::F(-val),
+            TemperatureDelta::C(val) => TemperatureDelta::C(-val),
+        }
+    }
+
+    /// Get a difference in degrees Fahrenheit.
+    pub fn as_f(&self) -> f64 {
+        match *self {
+            TemperatureDelta::F(val) => val,
+            TemperatureDelta::C(val) => val * 9. / 5.,
+        }
+    }
+
+    /// Get a difference in degrees Celcius.
+    pub fn as_c(&self) -> f64 {
+        match *self {
+            TemperatureDelta::F(val) => val * 5. / 9.,
+            TemperatureDelta::C(val) => val,
+        }
+    }
+}
+
+impl Parser<TemperatureDelta> for TemperatureDelta {
+    fn description() -> String {
+        "TemperatureDelta".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        if !source.is_object() {
+            return Err(ParseError::type_error("TemperatureDelta", &path, "object"));
+        }
+        if let Some(result) = path.push("F", |path| f64::take_opt(path, source, "F")) {
+            return result.map(TemperatureDelta::F);
+        }
+        if let Some(result) = path.push("C", |path| f64::take_opt(path, source, "C")) {
+            return result.map(TemperatureDelta::C);
+        }
+        Err(ParseError::missing_field("C|F", &path))
+    }
+}
+impl ToJSON for TemperatureDelta {
+    fn to_json(&self) -> JSON {
+        match *self {
+            TemperatureDelta::C(val) => {
+                JSON::Object(vec![("C".to_owned(), JSON::F64(val))].iter().cloned().collect())
+            }
+            TemperatureDelta::F(val) => {
+                JSON::Object(vec![("F".to_owned(), JSON::F64(val))].iter().cloned().collect())
+            }
+        }
+    }
+}
+impl PartialOrd for TemperatureDelta {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.as_c().partial_cmp(&other.as_c())
     }
 }
 
@@ -932,7 +1177,46 @@ impl<T> PartialOrd<ExtValue<T>> for ExtValue<T>
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Binary data.
+///
+/// # JSON
+///
+/// Values of this type are represented by objects `{data: string, mimetype: string}`,
+/// where `data` is encoded as base64. For compatibility, `data` may also be an array
+/// of bytes.
+///
+/// Alternatively, values may be represented as a data URI with base64 encoding, either
+/// in place of the entire object or as field `data`, in which case `mimetype` may be
+/// omitted.
+///
+/// ```
+/// use foxbox_taxonomy::values::*;
+/// use foxbox_taxonomy::parse::*;
+///
+/// let source = "{
+///   \"data\": \"AAEC\",
+///   \"mimetype\": \"binary/raw\"
+/// }";
+/// let parsed = Binary::from_str(source).unwrap();
+/// assert_eq!(*parsed.data, vec![0, 1, 2]);
+///
+/// let serialized : JSON = parsed.to_json();
+/// assert_eq!(serialized.find("data").unwrap().as_string().unwrap(), "AAEC");
+///
+/// // Legacy representation.
+/// let source = "{
+///   \"data\": [0, 1, 2],
+///   \"mimetype\": \"binary/raw\"
+/// }";
+/// assert_eq!(Binary::from_str(source).unwrap(), parsed);
+///
+/// // Data URI.
+/// let source = "\"data:image/png;base64,AAEC\"";
+/// let parsed = Binary::from_str(source).unwrap();
+/// assert_eq!(parsed.mimetype.to_string(), "image/png");
+/// assert_eq!(*parsed.data, vec![0, 1, 2]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
 pub struct Binary {
    /// The actual data. We put it behind an `Arc` to make sure
    /// that cloning remains inexpensive.
@@ -942,13 +1226,91 @@ pub struct Binary {
    pub mimetype: Id<MimeTypeId>,
 }
 
+impl Binary {
+    /// Decode a data URI `data:mimetype;base64,payload`.
+    fn parse_data_uri(path: &Path, source: &str) -> Result<(Option<Id<MimeTypeId>>, Vec<u8>), ParseError> {
+        let error = || ParseError::type_error("Binary", path, "data URI with base64 encoding");
+        if !source.starts_with("data:") {
+            return Err(error());
+        }
+        let (header, payload) = match source["data:".len()..].find(',') {
+            None => return Err(error()),
+            Some(index) => (&source["data:".len() .. "data:".len() + index], &source["data:".len() + index + 1 ..])
+        };
+        if !header.ends_with(";base64") {
+            return Err(error());
+        }
+        let mimetype = &header[.. header.len() - ";base64".len()];
+        let mimetype = if mimetype.is_empty() {
+            None
+        } else {
+            Some(Id::new(mimetype))
+        };
+        match base64_decode(payload) {
+            None => Err(error()),
+            Some(data) => Ok((mimetype, data))
+        }
+    }
+
+    /// Represent this value as a data URI `data:mimetype;base64,payload`.
+    ///
+    /// ```
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::util::Id;
+    /// use std::sync::Arc;
+    ///
+    /// let binary = Binary {
+    ///   data: Arc::new(vec![0, 1, 2]),
+    ///   mimetype: Id::new("image/png")
+    /// };
+    /// assert_eq!(binary.to_data_uri(), "data:image/png;base64,AAEC");
+    /// ```
+    pub fn to_data_uri(&self) -> String {
+        format!("data:{};base64,{}", self.mimetype.to_string(), base64_encode(&self.data))
+    }
+}
+
 impl Parser<Binary> for Binary {
     fn description() -> String {
         "Binary".to_owned()
     }
     fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
-        let data = try!(path.push("data", |path| Vec::<u8>::take(path, source, "data")));
-        let mimetype = try!(path.push("mimetype", |path| Id::take(path, source, "mimetype")));
+        if let JSON::String(ref uri) = *source {
+            return match try!(Binary::parse_data_uri(&path, uri)) {
+                (Some(mimetype), data) => Ok(Binary {
+                    data: Arc::new(data),
+                    mimetype: mimetype
+                }),
+                (None, _) => Err(ParseError::missing_field("mimetype", &path))
+            };
+        }
+        let (data, uri_mimetype) = try!(path.push("data", |path| {
+            let data = match source.as_object_mut().and_then(|obj| obj.remove("data")) {
+                None => return Err(ParseError::missing_field("data", &path)),
+                Some(data) => data
+            };
+            match data {
+                JSON::String(string) => {
+                    if string.starts_with("data:") {
+                        let (mimetype, data) = try!(Binary::parse_data_uri(&path, &string));
+                        Ok((data, mimetype))
+                    } else {
+                        match base64_decode(&string) {
+                            None => Err(ParseError::type_error("data", &path, "base64 string")),
+                            Some(data) => Ok((data, None))
+                        }
+                    }
+                }
+                mut other => Ok((try!(Vec::<u8>::parse(path, &mut other)), None))
+            }
+        }));
+        let mimetype = match path.push("mimetype", |path| Id::take_opt(path, source, "mimetype")) {
+            Some(result) => try!(result),
+            None => match uri_mimetype {
+                Some(mimetype) => mimetype,
+                None => return Err(ParseError::missing_field("mimetype", &path))
+            }
+        };
         Ok(Binary {
             data: Arc::new(data),
             mimetype: mimetype
@@ -959,7 +1321,7 @@ impl Parser<Binary> for Binary {
 impl ToJSON for Binary {
     fn to_json(&self) -> JSON {
         let mut source = vec![
-            ("data", JSON::Array(self.data.iter().map(|x| JSON::U64(*x as u64)).collect())),
+            ("data", JSON::String(base64_encode(&self.data))),
             ("mimetype", JSON::String(self.mimetype.to_string()))
         ];
         let map = source.drain(..)
@@ -969,86 +1331,607 @@ impl ToJSON for Binary {
     }
 }
 
-/// Representation of an actual value that can be sent to/received
-/// from a service.
-///
-/// # JSON
-///
-/// Values of this state are represented by an object `{ key: value }`, where key is one of
-/// `Unit`, `OnOff`, `OpenClosed`, ... The `value` for `Unit` is ignored.
-///
-/// # Other forms of (de)serialization
+impl Serialize for Binary {
+    /// Serialize as `to_json`, i.e. with base64-encoded data.
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error> where S: Serializer {
+        self.to_json().serialize(serializer)
+    }
+}
+impl Deserialize for Binary {
+    /// Deserialize any of the representations accepted by `parse`.
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error> where D: Deserializer {
+        let mut source = try!(JSON::deserialize(deserializer));
+        Binary::parse(Path::new(), &mut source).map_err(|err| D::Error::custom(format!("{}", err)))
+    }
+}
+
+/// Binary data that is not available as a whole, but read progressively, e.g. from a
+/// file or as chunks sent by an adapter while it is still receiving them from a device.
 ///
-/// Values of this state are represented by an object `{ key: value }`, where key is one of
-/// `Unit`, `OnOff`, `OpenClosed`, ... The `value` for `Unit` is ignored.
+/// Clones share the same underlying stream, and each reader progresses independently, e.g.
+/// each watcher receiving the value. Data is buffered until every open reader has consumed
+/// it, and the last `STREAM_WINDOW` bytes are kept for readers opened later. A reader opened
+/// once the start of the stream has been discarded fails with an error rather than
+/// silently skipping data.
 ///
 /// ```
-/// extern crate serde;
-/// extern crate serde_json;
-/// extern crate foxbox_taxonomy;
-///
-/// # fn main() {
-/// use foxbox_taxonomy::values::Value::*;
-/// use foxbox_taxonomy::values::OnOff::*;
-/// use foxbox_taxonomy::values::OpenClosed::*;
-///
-/// let unit = serde_json::to_string(&Unit).unwrap();
-/// assert_eq!(unit, "{\"Unit\":[]}");
-///
-/// let unit : foxbox_taxonomy::values::Value = serde_json::from_str("{\"Unit\":[]}").unwrap();
-/// assert_eq!(unit, Unit);
-///
-/// let on = serde_json::to_string(&OnOff(On)).unwrap();
-/// assert_eq!(on, "{\"OnOff\":\"On\"}");
+/// use foxbox_taxonomy::values::*;
+/// use foxbox_taxonomy::util::Id;
+/// use std::io::Read;
+/// use std::thread;
 ///
-/// let on : foxbox_taxonomy::values::Value = serde_json::from_str("{\"OnOff\":\"On\"}").unwrap();
-/// assert_eq!(on, OnOff(On));
+/// let (sender, stream) = BinaryStream::channel(Id::new("image/jpeg"), 4);
+/// thread::spawn(move || {
+///   for chunk in vec![vec![0, 1], vec![2]] {
+///     sender.send(Ok(chunk)).unwrap();
+///   }
+///   // Dropping `sender` ends the stream.
+/// });
 ///
-/// let open = serde_json::to_string(&OpenClosed(Open)).unwrap();
-/// assert_eq!(open, "{\"OpenClosed\":\"Open\"}");
+/// let mut data = vec![];
+/// stream.reader().read_to_end(&mut data).unwrap();
+/// assert_eq!(data, vec![0, 1, 2]);
 ///
-/// let open : foxbox_taxonomy::values::Value = serde_json::from_str("{\"OpenClosed\":\"Open\"}").unwrap();
-/// assert_eq!(open, OpenClosed(Open));
-/// # }
+/// // Reading again, e.g. from a clone, yields the same data, as it fits in the window.
+/// let binary = stream.clone().read_to_binary().unwrap();
+/// assert_eq!(*binary.data, vec![0, 1, 2]);
+/// assert_eq!(binary.mimetype.to_string(), "image/jpeg");
 /// ```
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub enum Value {
-    /// An absolute time and date.
-    ///
-    /// # JSON
-    ///
-    /// Represented as `{"TimeStamp": string}`, where `string` is formatted as RFC 3339 such as
-    /// `"2014-11-28T21:45:59.324310806+09:00"`.
+#[derive(Clone)]
+pub struct BinaryStream {
+    /// The mime type.
+    pub mimetype: Id<MimeTypeId>,
+
+    /// The data, shared by all clones. The condition variable is notified whenever a reader
+    /// is done filling the buffer.
+    source: Arc<(Mutex<StreamSource>, Condvar)>,
+}
+
+/// The number of bytes of a `BinaryStream` kept in memory once every open reader has
+/// consumed them, for the benefit of readers opened later.
+pub const STREAM_WINDOW: usize = 1 << 20;
+
+impl BinaryStream {
+    /// Create a stream reading its data from `reader`.
+    pub fn new<R>(mimetype: Id<MimeTypeId>, reader: R) -> Self
+        where R: Read + Send + 'static
+    {
+        BinaryStream {
+            mimetype: mimetype,
+            source: Arc::new((Mutex::new(StreamSource {
+                buffer: vec![],
+                start: 0,
+                cursors: HashMap::new(),
+                next_cursor: 0,
+                window: STREAM_WINDOW,
+                reader: Some(Box::new(reader)),
+                is_filling: false,
+                error: None,
+            }), Condvar::new())),
+        }
+    }
+
+    /// Create a stream fed by chunks sent through a channel. The stream ends once the
+    /// sender is dropped. Sending an `Err` interrupts the stream with an error.
+    ///
+    /// At most `bound` chunks are waiting to be read, so sending blocks until a consumer
+    /// has caught up.
+    pub fn channel(mimetype: Id<MimeTypeId>, bound: usize) -> (SyncSender<io::Result<Vec<u8>>>, Self) {
+        let (tx, rx) = sync_channel(bound);
+        let reader = ChunkReader {
+            rx: rx,
+            current: vec![],
+            pos: 0,
+        };
+        (tx, BinaryStream::new(mimetype, reader))
+    }
+
+    /// Read the stream from its start. Each reader progresses independently, and data that
+    /// has not been received yet is waited for.
+    pub fn reader(&self) -> BinaryStreamReader {
+        let mut source = self.source.0.lock().unwrap();
+        let cursor = source.next_cursor;
+        source.next_cursor += 1;
+        source.cursors.insert(cursor, 0);
+        BinaryStreamReader {
+            source: self.source.clone(),
+            cursor: cursor,
+            pos: 0,
+        }
+    }
+
+    /// Wait until the end of the stream, collecting its data in memory.
+    pub fn read_to_binary(&self) -> io::Result<Binary> {
+        let mut data = vec![];
+        try!(self.reader().read_to_end(&mut data));
+        Ok(Binary {
+            data: Arc::new(data),
+            mimetype: self.mimetype.clone()
+        })
+    }
+
+    /// The whole data of the stream, if it has been entirely received and is still in
+    /// memory. Never blocks.
     ///
     /// ```
-    /// extern crate foxbox_taxonomy;
-    ///
     /// use foxbox_taxonomy::values::*;
     /// use foxbox_taxonomy::parse::*;
+    /// use foxbox_taxonomy::util::Id;
+    /// use std::io::Read;
     ///
-    /// # fn main() {
-    ///
-    /// let source = "{
-    ///   \"Unit\": []
-    /// }";
-    ///
-    /// let parsed = Value::from_str(source).unwrap();
-    /// if let Value::Unit = parsed {
-    ///   // ok
-    /// } else {
-    ///   panic!();
-    /// }
+    /// let (sender, stream) = BinaryStream::channel(Id::new("image/jpeg"), 4);
+    /// sender.send(Ok(vec![0, 1])).unwrap();
     ///
+    /// // The stream is still open, so it cannot be represented as a whole.
+    /// assert!(stream.try_to_binary().is_none());
+    /// assert_eq!(stream.to_json().find("is_complete"), Some(&JSON::Bool(false)));
     ///
-    /// let serialized: JSON = parsed.to_json();
-    /// if let JSON::Object(ref obj) = serialized {
-    ///   let serialized = obj.get("Unit").unwrap();
-    ///   assert!(serialized.is_null());
-    /// }
-    /// # }
+    /// drop(sender);
+    /// let mut data = vec![];
+    /// stream.reader().read_to_end(&mut data).unwrap();
+    /// assert_eq!(*stream.try_to_binary().unwrap().data, vec![0, 1]);
     /// ```
-    Unit,
-
+    pub fn try_to_binary(&self) -> Option<Binary> {
+        let source = self.source.0.lock().unwrap();
+        if source.start != 0 || source.reader.is_some() || source.is_filling || source.error.is_some() {
+            return None;
+        }
+        Some(Binary {
+            data: Arc::new(source.buffer.clone()),
+            mimetype: self.mimetype.clone()
+        })
+    }
+}
+
+impl From<Binary> for BinaryStream {
+    /// Stream data that is already in memory.
+    fn from(binary: Binary) -> Self {
+        let Binary { data, mimetype } = binary;
+        let data = match Arc::try_unwrap(data) {
+            Ok(data) => data,
+            Err(data) => (*data).clone()
+        };
+        BinaryStream {
+            mimetype: mimetype,
+            source: Arc::new((Mutex::new(StreamSource {
+                // Keep the data in memory for as long as the stream exists.
+                window: cmp::max(data.len(), STREAM_WINDOW),
+                buffer: data,
+                start: 0,
+                cursors: HashMap::new(),
+                next_cursor: 0,
+                reader: None,
+                is_filling: false,
+                error: None,
+            }), Condvar::new())),
+        }
+    }
+}
+
+impl Debug for BinaryStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BinaryStream {{ mimetype: {:?} }}", self.mimetype)
+    }
+}
+
+impl PartialEq for BinaryStream {
+    /// Two streams are equal if they share the same underlying stream.
+    fn eq(&self, other: &Self) -> bool {
+        &*self.source as *const _ == &*other.source as *const _
+    }
+}
+
+impl ToJSON for BinaryStream {
+    /// Represent the stream as a `Binary` if its whole data is available in memory (see
+    /// `try_to_binary`). Otherwise, as JSON cannot represent data progressively and
+    /// serializing must neither block nor consume the stream, produce
+    /// `{mimetype: string, is_complete: false}`.
+    ///
+    /// Consumers that wish to process data progressively should use `reader()` instead.
+    fn to_json(&self) -> JSON {
+        match self.try_to_binary() {
+            Some(binary) => binary.to_json(),
+            None => {
+                let mut source = vec![
+                    ("mimetype", JSON::String(self.mimetype.to_string())),
+                    ("is_complete", JSON::Bool(false))
+                ];
+                let map = source.drain(..)
+                    .map(|(key, value)| (key.to_owned(), value))
+                    .collect();
+                JSON::Object(map)
+            }
+        }
+    }
+}
+
+impl Serialize for BinaryStream {
+    /// Serialize the stream as a `Binary` if its whole data is available in memory,
+    /// otherwise fail, as with `to_json`.
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error>
+        where S: Serializer {
+        match self.try_to_binary() {
+            Some(binary) => binary.serialize(serializer),
+            None => Err(S::Error::custom("Cannot serialize a stream that has not been entirely received"))
+        }
+    }
+}
+
+impl Deserialize for BinaryStream {
+    /// Deserialize a `Binary`, to be streamed from memory.
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error>
+        where D: Deserializer {
+        Ok(BinaryStream::from(try!(Binary::deserialize(deserializer))))
+    }
+}
+
+/// The state shared by the clones of a `BinaryStream`.
+struct StreamSource {
+    /// The data read and not discarded yet.
+    buffer: Vec<u8>,
+
+    /// The position in the stream of the first byte of `buffer`.
+    start: usize,
+
+    /// The position of each open reader.
+    cursors: HashMap<usize, usize>,
+
+    /// The key of the next reader in `cursors`.
+    next_cursor: usize,
+
+    /// The number of bytes kept once all open readers have consumed them.
+    window: usize,
+
+    /// The source of data. `None` once exhausted, or while a reader is filling the buffer
+    /// out of the lock.
+    reader: Option<Box<Read + Send>>,
+
+    /// `true` while a reader is filling the buffer.
+    is_filling: bool,
+
+    /// The error that has interrupted the stream, if any, replayed to every reader.
+    error: Option<(io::ErrorKind, String)>,
+}
+
+impl StreamSource {
+    /// The position in the stream of the end of `buffer`.
+    fn end(&self) -> usize {
+        self.start + self.buffer.len()
+    }
+
+    /// Discard the data consumed by all open readers, except for the last `window` bytes.
+    fn trim(&mut self) {
+        let end = self.end();
+        let consumed = self.cursors.values().cloned().min().unwrap_or(end);
+        let keep_from = cmp::min(consumed, end.saturating_sub(self.window));
+        if keep_from > self.start {
+            let len = keep_from - self.start;
+            self.buffer.drain(..len);
+            self.start = keep_from;
+        }
+    }
+}
+
+/// A reader over a `BinaryStream`, produced by `BinaryStream::reader`.
+pub struct BinaryStreamReader {
+    source: Arc<(Mutex<StreamSource>, Condvar)>,
+    cursor: usize,
+    pos: usize,
+}
+
+impl Read for BinaryStreamReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (ref lock, ref condvar) = *self.source;
+        let mut source = match lock.lock() {
+            Ok(source) => source,
+            Err(_) => return Err(io::Error::new(io::ErrorKind::Other, "Stream source has panicked"))
+        };
+        loop {
+            if self.pos < source.start {
+                return Err(io::Error::new(io::ErrorKind::Other, "The start of the stream has already been discarded"));
+            }
+            if self.pos < source.end() {
+                let offset = self.pos - source.start;
+                let len = cmp::min(buf.len(), source.buffer.len() - offset);
+                buf[..len].copy_from_slice(&source.buffer[offset .. offset + len]);
+                self.pos += len;
+                source.cursors.insert(self.cursor, self.pos);
+                source.trim();
+                return Ok(len);
+            }
+            if let Some((kind, ref message)) = source.error {
+                return Err(io::Error::new(kind, message.clone()));
+            }
+            if source.is_filling {
+                // Another reader is waiting for data, wait for it.
+                source = match condvar.wait(source) {
+                    Ok(source) => source,
+                    Err(_) => return Err(io::Error::new(io::ErrorKind::Other, "Stream source has panicked"))
+                };
+                continue;
+            }
+            let mut reader = match source.reader.take() {
+                None => return Ok(0), // End of the stream.
+                Some(reader) => reader
+            };
+            source.is_filling = true;
+            drop(source);
+
+            // Reading may block, so do it out of the lock.
+            let mut chunk = [0; 4096];
+            let result;
+            loop {
+                match reader.read(&mut chunk) {
+                    Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    other => {
+                        result = other;
+                        break;
+                    }
+                }
+            }
+
+            source = match lock.lock() {
+                Ok(source) => source,
+                Err(_) => return Err(io::Error::new(io::ErrorKind::Other, "Stream source has panicked"))
+            };
+            source.is_filling = false;
+            match result {
+                Ok(0) => {},
+                Ok(len) => {
+                    source.buffer.extend_from_slice(&chunk[..len]);
+                    source.reader = Some(reader);
+                }
+                Err(err) => {
+                    source.error = Some((err.kind(), format!("{}", err)));
+                }
+            }
+            condvar.notify_all();
+        }
+    }
+}
+
+impl Drop for BinaryStreamReader {
+    fn drop(&mut self) {
+        if let Ok(mut source) = self.source.0.lock() {
+            source.cursors.remove(&self.cursor);
+            source.trim();
+        }
+    }
+}
+
+/// A reader over chunks received through a channel.
+struct ChunkReader {
+    rx: Receiver<io::Result<Vec<u8>>>,
+    current: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos >= self.current.len() {
+            match self.rx.recv() {
+                Ok(Ok(chunk)) => {
+                    self.current = chunk;
+                    self.pos = 0;
+                }
+                Ok(Err(err)) => return Err(err),
+                // The sender is gone, this is the end of the stream.
+                Err(_) => return Ok(0)
+            }
+        }
+        let len = cmp::min(buf.len(), self.current.len() - self.pos);
+        buf[..len].copy_from_slice(&self.current[self.pos .. self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// A geographic location, as a latitude and a longitude in degrees (WGS 84).
+///
+/// # JSON
+///
+/// Values of this type are represented by objects
+/// `{latitude: float, longitude: float, accuracy: float}`, where latitude is in [-90, 90],
+/// longitude is in [-180, 180] and the optional accuracy is a radius in meters.
+///
+/// ```
+/// use foxbox_taxonomy::values::*;
+/// use foxbox_taxonomy::parse::*;
+///
+/// let source = "{
+///   \"latitude\": 48.8566,
+///   \"longitude\": 2.3522,
+///   \"accuracy\": 20
+/// }";
+///
+/// let parsed = GeoPoint::from_str(source).unwrap();
+/// assert_eq!(parsed.latitude, 48.8566);
+/// assert_eq!(parsed.longitude, 2.3522);
+/// assert_eq!(parsed.accuracy, Some(20.));
+///
+/// let serialized : JSON = parsed.to_json();
+/// assert_eq!(serialized.find("latitude").unwrap().as_f64().unwrap(), 48.8566);
+///
+/// // This source will not parse, as the latitude is not in [-90, 90].
+/// let source = "{
+///   \"latitude\": 98.8566,
+///   \"longitude\": 2.3522
+/// }";
+/// match GeoPoint::from_str(source) {
+///   Err(ParseError::TypeError{..}) => {},
+///   other => panic!("Unexpected result {:?}", other)
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeoPoint {
+    /// Latitude, in degrees, in [-90, 90].
+    pub latitude: f64,
+
+    /// Longitude, in degrees, in [-180, 180].
+    pub longitude: f64,
+
+    /// If specified, the radius of uncertainty of the location, in meters.
+    #[serde(default)]
+    pub accuracy: Option<f64>,
+}
+
+/// The mean radius of the Earth, in meters.
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+impl GeoPoint {
+    /// The great-circle distance between two points, in meters.
+    pub fn distance_to(&self, other: &GeoPoint) -> f64 {
+        // Haversine formula.
+        let lat_1 = self.latitude.to_radians();
+        let lat_2 = other.latitude.to_radians();
+        let delta_lat = (other.latitude - self.latitude).to_radians();
+        let delta_lon = (other.longitude - self.longitude).to_radians();
+        let a = (delta_lat / 2.).sin().powi(2)
+            + lat_1.cos() * lat_2.cos() * (delta_lon / 2.).sin().powi(2);
+        2. * EARTH_RADIUS * a.sqrt().min(1.).asin()
+    }
+}
+
+impl Parser<GeoPoint> for GeoPoint {
+    fn description() -> String {
+        "GeoPoint".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        let latitude = try!(path.push("latitude", |path| f64::take(path, source, "latitude")));
+        let longitude = try!(path.push("longitude", |path| f64::take(path, source, "longitude")));
+        let accuracy = match path.push("accuracy", |path| f64::take_opt(path, source, "accuracy")) {
+            None => None,
+            Some(result) => Some(try!(result))
+        };
+        if latitude < -90. || latitude > 90. {
+            return Err(ParseError::type_error("latitude", &path, "a number in [-90, 90]"));
+        }
+        if longitude < -180. || longitude > 180. {
+            return Err(ParseError::type_error("longitude", &path, "a number in [-180, 180]"));
+        }
+        if let Some(accuracy) = accuracy {
+            if accuracy < 0. {
+                return Err(ParseError::type_error("accuracy", &path, "a non-negative number"));
+            }
+        }
+        Ok(GeoPoint {
+            latitude: latitude,
+            longitude: longitude,
+            accuracy: accuracy,
+        })
+    }
+}
+
+impl ToJSON for GeoPoint {
+    fn to_json(&self) -> JSON {
+        let mut source = vec![
+            ("latitude", JSON::F64(self.latitude)),
+            ("longitude", JSON::F64(self.longitude)),
+        ];
+        if let Some(accuracy) = self.accuracy {
+            source.push(("accuracy", JSON::F64(accuracy)));
+        }
+        source.to_json()
+    }
+}
+
+impl Into<Value> for GeoPoint {
+    fn into(self) -> Value {
+        Value::GeoPoint(self)
+    }
+}
+
+impl PartialOrd for GeoPoint {
+    /// Two distinct locations are never comparable to each other.
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self == other {
+            Some(Ordering::Equal)
+        } else {
+            None
+        }
+    }
+}
+
+/// Representation of an actual value that can be sent to/received
+/// from a service.
+///
+/// # JSON
+///
+/// Values of this state are represented by an object `{ key: value }`, where key is one of
+/// `Unit`, `OnOff`, `OpenClosed`, ... The `value` for `Unit` is ignored.
+///
+/// # Other forms of (de)serialization
+///
+/// Values of this state are represented by an object `{ key: value }`, where key is one of
+/// `Unit`, `OnOff`, `OpenClosed`, ... The `value` for `Unit` is ignored.
+///
+/// ```
+/// extern crate serde;
+/// extern crate serde_json;
+/// extern crate foxbox_taxonomy;
+///
+/// # fn main() {
+/// use foxbox_taxonomy::values::Value::*;
+/// use foxbox_taxonomy::values::OnOff::*;
+/// use foxbox_taxonomy::values::OpenClosed::*;
+///
+/// let unit = serde_json::to_string(&Unit).unwrap();
+/// assert_eq!(unit, "{\"Unit\":[]}");
+///
+/// let unit : foxbox_taxonomy::values::Value = serde_json::from_str("{\"Unit\":[]}").unwrap();
+/// assert_eq!(unit, Unit);
+///
+/// let on = serde_json::to_string(&OnOff(On)).unwrap();
+/// assert_eq!(on, "{\"OnOff\":\"On\"}");
+///
+/// let on : foxbox_taxonomy::values::Value = serde_json::from_str("{\"OnOff\":\"On\"}").unwrap();
+/// assert_eq!(on, OnOff(On));
+///
+/// let open = serde_json::to_string(&OpenClosed(Open)).unwrap();
+/// assert_eq!(open, "{\"OpenClosed\":\"Open\"}");
+///
+/// let open : foxbox_taxonomy::values::Value = serde_json::from_str("{\"OpenClosed\":\"Open\"}").unwrap();
+/// assert_eq!(open, OpenClosed(Open));
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Value {
+    /// An absolute time and date.
+    ///
+    /// # JSON
+    ///
+    /// Represented as `{"TimeStamp": string}`, where `string` is formatted as RFC 3339 such as
+    /// `"2014-11-28T21:45:59.324310806+09:00"`.
+    ///
+    /// ```
+    /// extern crate foxbox_taxonomy;
+    ///
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// # fn main() {
+    ///
+    /// let source = "{
+    ///   \"Unit\": []
+    /// }";
+    ///
+    /// let parsed = Value::from_str(source).unwrap();
+    /// if let Value::Unit = parsed {
+    ///   // ok
+    /// } else {
+    ///   panic!();
+    /// }
+    ///
+    ///
+    /// let serialized: JSON = parsed.to_json();
+    /// if let JSON::Object(ref obj) = serialized {
+    ///   let serialized = obj.get("Unit").unwrap();
+    ///   assert!(serialized.is_null());
+    /// }
+    /// # }
+    /// ```
+    Unit,
+
     /// An on/off value.
     ///
     /// # JSON
@@ -1271,6 +2154,24 @@ pub enum Value {
     /// ```
     Temperature(Temperature),
 
+    /// A difference between two temperatures, as produced by subtracting two temperatures.
+    ///
+    /// # JSON
+    ///
+    /// Represented by `{TemperatureDelta: {C: float}}` or `{TemperatureDelta: {F: float}}`.
+    ///
+    /// ```
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// let parsed = Value::from_str("{\"TemperatureDelta\": {\"F\": 9}}").unwrap();
+    /// assert_eq!(parsed, Value::TemperatureDelta(TemperatureDelta::F(9.)));
+    ///
+    /// let serialized: JSON = parsed.to_json();
+    /// assert_eq!(serialized.find_path(&["TemperatureDelta", "F"]).unwrap().as_f64().unwrap(), 9.);
+    /// ```
+    TemperatureDelta(TemperatureDelta),
+
     /// A color.
     ///
     /// # JSON
@@ -1354,16 +2255,15 @@ pub enum Value {
     /// standardized yet into the API.
     ExtNumeric(ExtValue<f64>),
 
-    /// A Json value. We put it behind an `Arc` to make sure that
-    /// cloning remains inexpensive.
+    /// A value taken from a list of variants that has not been
+    /// standardized yet into the API, e.g. the mode of a HVAC.
     ///
     /// # JSON
     ///
-    /// Represented by `{Json: JSON}` where `JSON` is a JSON object.
+    /// Represented by `{ExtEnum: {vendor: string, adapter: string, kind: string, value: string}}`.
     ///
     /// ```
     /// extern crate foxbox_taxonomy;
-    /// extern crate chrono;
     ///
     /// use foxbox_taxonomy::values::*;
     /// use foxbox_taxonomy::parse::*;
@@ -1371,34 +2271,37 @@ pub enum Value {
     /// # fn main() {
     ///
     /// let source = "{
-    ///   \"Json\": { \"foo\": \"bar\" }
+    ///   \"ExtEnum\": {
+    ///     \"vendor\": \"mozilla.org\",
+    ///     \"adapter\": \"hvac@mozilla.org\",
+    ///     \"kind\": \"HVACMode\",
+    ///     \"value\": \"Heat\"
+    ///   }
     /// }";
     /// let parsed = Value::from_str(source).unwrap();
-    /// if let Value::Json(ref obj) = parsed {
-    ///   assert_eq!(obj.0.find_path(&["foo"]).unwrap().as_string().unwrap(), "bar")
+    /// if let Value::ExtEnum(ref ext) = parsed {
+    ///   assert_eq!(&ext.value as &str, "Heat");
+    ///   assert_eq!(ext.kind.to_string(), "HVACMode");
     /// } else {
     ///   panic!();
     /// }
     ///
-    ///
     /// let serialized: JSON = parsed.to_json();
-    /// let val = serialized.find_path(&["Json", "foo"]).unwrap().as_string().unwrap();
-    /// assert_eq!(val, "bar");
+    /// let val = serialized.find_path(&["ExtEnum", "value"]).unwrap().as_string().unwrap();
+    /// assert_eq!(val, "Heat");
     /// # }
     /// ```
-    Json(Arc<Json>),
+    ExtEnum(ExtValue<String>),
 
-    /// Binary data.
+    /// A geographic location.
     ///
     /// # JSON
     ///
-    /// Represented by `{Binary: {data: array, mimetype: string}}`.
-    ///
-    /// **This representation is likely to change in the future.**
+    /// Represented by `{GeoPoint: {latitude: float, longitude: float, accuracy: float}}`,
+    /// where `accuracy` is optional.
     ///
     /// ```
     /// extern crate foxbox_taxonomy;
-    /// extern crate chrono;
     ///
     /// use foxbox_taxonomy::values::*;
     /// use foxbox_taxonomy::parse::*;
@@ -1406,32 +2309,114 @@ pub enum Value {
     /// # fn main() {
     ///
     /// let source = "{
-    ///   \"Binary\": { \"data\": [0, 1, 2], \"mimetype\": \"binary/raw\" }
+    ///   \"GeoPoint\": {
+    ///     \"latitude\": 48.8566,
+    ///     \"longitude\": 2.3522
+    ///   }
     /// }";
     /// let parsed = Value::from_str(source).unwrap();
-    /// if let Value::Binary(ref obj) = parsed {
-    ///   assert_eq!(obj.mimetype.to_string(), "binary/raw".to_owned());
-    ///   assert_eq!(*obj.data, vec![0, 1, 2]);
+    /// if let Value::GeoPoint(ref point) = parsed {
+    ///   assert_eq!(point.latitude, 48.8566);
+    ///   assert_eq!(point.accuracy, None);
     /// } else {
     ///   panic!();
     /// }
     ///
-    ///
     /// let serialized: JSON = parsed.to_json();
-    /// let val = serialized.find_path(&["Binary", "mimetype"]).unwrap().as_string().unwrap();
-    /// assert_eq!(val, "binary/raw");
+    /// let val = serialized.find_path(&["GeoPoint", "longitude"]).unwrap().as_f64().unwrap();
+    /// assert_eq!(val, 2.3522);
     /// # }
     /// ```
-    Binary(Binary),
-}
-
+    GeoPoint(GeoPoint),
 
-lazy_static! {
-    static ref VALUE_PARSER:
-        HashMap<&'static str, Box<Fn(Path, &mut JSON) -> Result<Value, ParseError> + Sync>> =
-    {
-        use self::Value::*;
-        use std::string::String as StdString;
+    /// A Json value. We put it behind an `Arc` to make sure that
+    /// cloning remains inexpensive.
+    ///
+    /// # JSON
+    ///
+    /// Represented by `{Json: JSON}` where `JSON` is a JSON object.
+    ///
+    /// ```
+    /// extern crate foxbox_taxonomy;
+    /// extern crate chrono;
+    ///
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// # fn main() {
+    ///
+    /// let source = "{
+    ///   \"Json\": { \"foo\": \"bar\" }
+    /// }";
+    /// let parsed = Value::from_str(source).unwrap();
+    /// if let Value::Json(ref obj) = parsed {
+    ///   assert_eq!(obj.0.find_path(&["foo"]).unwrap().as_string().unwrap(), "bar")
+    /// } else {
+    ///   panic!();
+    /// }
+    ///
+    ///
+    /// let serialized: JSON = parsed.to_json();
+    /// let val = serialized.find_path(&["Json", "foo"]).unwrap().as_string().unwrap();
+    /// assert_eq!(val, "bar");
+    /// # }
+    /// ```
+    Json(Arc<Json>),
+
+    /// Binary data.
+    ///
+    /// # JSON
+    ///
+    /// Represented by `{Binary: {data: string, mimetype: string}}`, where `data` is
+    /// encoded as base64. See `Binary` for the other accepted representations.
+    ///
+    /// ```
+    /// extern crate foxbox_taxonomy;
+    /// extern crate chrono;
+    ///
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// # fn main() {
+    ///
+    /// let source = "{
+    ///   \"Binary\": { \"data\": [0, 1, 2], \"mimetype\": \"binary/raw\" }
+    /// }";
+    /// let parsed = Value::from_str(source).unwrap();
+    /// if let Value::Binary(ref obj) = parsed {
+    ///   assert_eq!(obj.mimetype.to_string(), "binary/raw".to_owned());
+    ///   assert_eq!(*obj.data, vec![0, 1, 2]);
+    /// } else {
+    ///   panic!();
+    /// }
+    ///
+    ///
+    /// let serialized: JSON = parsed.to_json();
+    /// let val = serialized.find_path(&["Binary", "mimetype"]).unwrap().as_string().unwrap();
+    /// assert_eq!(val, "binary/raw");
+    /// let val = serialized.find_path(&["Binary", "data"]).unwrap().as_string().unwrap();
+    /// assert_eq!(val, "AAEC");
+    /// # }
+    /// ```
+    Binary(Binary),
+
+    /// Binary data produced progressively by an adapter, e.g. a large camera snapshot.
+    /// Values of this variant have type `Type::Binary`.
+    ///
+    /// # JSON
+    ///
+    /// Serialized exactly as `Value::Binary`, by consuming the stream. Never produced by
+    /// parsing.
+    BinaryStream(BinaryStream),
+}
+
+
+lazy_static! {
+    static ref VALUE_PARSER:
+        HashMap<&'static str, Box<Fn(Path, &mut JSON) -> Result<Value, ParseError> + Sync>> =
+    {
+        use self::Value::*;
+        use std::string::String as StdString;
         let mut map : HashMap<&'static str, Box<Fn(Path, &mut JSON) -> Result<Value, ParseError> + Sync>> = HashMap::new();
         map.insert("Unit", Box::new(|_, _| Ok(Unit)));
         map.insert("OnOff", Box::new(|path, v| {
@@ -1458,6 +2443,10 @@ lazy_static! {
             let value = try!(path.push("Temperature", |path| self::Temperature::parse(path, v)));
             Ok(Temperature(value))
         }));
+        map.insert("TemperatureDelta", Box::new(|path, v| {
+            let value = try!(path.push("TemperatureDelta", |path| self::TemperatureDelta::parse(path, v)));
+            Ok(TemperatureDelta(value))
+        }));
         map.insert("ThinkerbellRule", Box::new(|path, v| {
             let value = try!(path.push("ThinkerbellRule", |path| self::ThinkerbellRule::parse(path, v)));
             Ok(ThinkerbellRule(value))
@@ -1486,6 +2475,14 @@ lazy_static! {
             let value = try!(path.push("ExtNumeric", |path| self::ExtValue::<f64>::parse(path, v)));
             Ok(ExtNumeric(value))
         }));
+        map.insert("ExtEnum", Box::new(|path, v| {
+            let value = try!(path.push("ExtEnum", |path| self::ExtValue::<StdString>::parse(path, v)));
+            Ok(ExtEnum(value))
+        }));
+        map.insert("GeoPoint", Box::new(|path, v| {
+            let value = try!(path.push("GeoPoint", |path| self::GeoPoint::parse(path, v)));
+            Ok(GeoPoint(value))
+        }));
         map.insert("Binary", Box::new(|path, v| {
             let value = try!(path.push("Binary", |path| self::Binary::parse(path, v)));
             Ok(Binary(value))
@@ -1533,11 +2530,15 @@ impl ToJSON for Value {
             String(ref val) => ("String", val.to_json()),
             Json(ref val) => ("Json", val.to_json()),
             Binary(ref val) => ("Binary", val.to_json()),
+            BinaryStream(ref val) => ("Binary", val.to_json()),
             Temperature(ref val) => ("Temperature", val.to_json()),
+            TemperatureDelta(ref val) => ("TemperatureDelta", val.to_json()),
             ThinkerbellRule(ref val) => ("ThinkerbellRule", val.to_json()),
             WebPushNotify(ref val) => ("WebPushNotify", val.to_json()),
             ExtBool(ref val) => ("ExtBool", val.to_json()),
             ExtNumeric(ref val) => ("ExtNumeric", val.to_json()),
+            ExtEnum(ref val) => ("ExtEnum", val.to_json()),
+            GeoPoint(ref val) => ("GeoPoint", val.to_json()),
         };
         let source = vec![(key.to_owned(), value)];
         JSON::Object(source.iter().cloned().collect())
@@ -1556,11 +2557,14 @@ impl Value {
             Value::Duration(_) => Type::Duration,
             Value::TimeStamp(_) => Type::TimeStamp,
             Value::Temperature(_) => Type::Temperature,
+            Value::TemperatureDelta(_) => Type::TemperatureDelta,
             Value::Color(_) => Type::Color,
             Value::Json(_) => Type::Json,
-            Value::Binary(_) => Type::Binary,
+            Value::Binary(_) | Value::BinaryStream(_) => Type::Binary,
             Value::ExtBool(_) => Type::ExtBool,
             Value::ExtNumeric(_) => Type::ExtNumeric,
+            Value::ExtEnum(_) => Type::ExtEnum,
+            Value::GeoPoint(_) => Type::GeoPoint,
             Value::ThinkerbellRule(_) => Type::ThinkerbellRule,
             Value::WebPushNotify(_) => Type::WebPushNotify,
         }
@@ -1579,6 +2583,273 @@ impl Value {
             _ => Err(TypeError {expected: Type::Duration, got: self.get_type()})
         }
     }
+
+    /// Compute `self + other`.
+    ///
+    /// Supported operations:
+    ///
+    /// - `TimeStamp + Duration` and `Duration + TimeStamp` produce a `TimeStamp`;
+    /// - `Duration + Duration` produces a `Duration`;
+    /// - `Temperature + TemperatureDelta` and `TemperatureDelta + Temperature` produce a
+    ///   `Temperature`;
+    /// - `TemperatureDelta + TemperatureDelta` produces a `TemperatureDelta`;
+    /// - `ExtNumeric + ExtNumeric` produces an `ExtNumeric`, provided that both
+    ///   operands have the same vendor and kind, or an `ExtensionMismatch` otherwise.
+    ///
+    /// Any other combination is a `TypeError`.
+    ///
+    /// ```
+    /// use foxbox_taxonomy::values::*;
+    ///
+    /// let start = Value::TimeStamp(TimeStamp::from_s(1000));
+    /// let delay = Value::Duration(Duration::from_s(60));
+    /// let end = start.checked_add(&delay).unwrap();
+    /// assert_eq!(end, Value::TimeStamp(TimeStamp::from_s(1060)));
+    ///
+    /// match start.checked_add(&start) {
+    ///   Err(ArithmeticError::TypeError(TypeError { expected: Type::Duration, got: Type::TimeStamp })) => {},
+    ///   other => panic!("Unexpected result {:?}", other)
+    /// }
+    /// ```
+    pub fn checked_add(&self, other: &Value) -> Result<Value, ArithmeticError> {
+        use self::Value::*;
+        match (self, other) {
+            (&TimeStamp(ref a), &Duration(ref b)) | (&Duration(ref b), &TimeStamp(ref a)) =>
+                a.checked_add(b).map(TimeStamp).ok_or(ArithmeticError::Overflow),
+            (&Duration(ref a), &Duration(ref b)) =>
+                a.checked_add(b).map(Duration).ok_or(ArithmeticError::Overflow),
+            (&Temperature(ref a), &TemperatureDelta(ref b)) | (&TemperatureDelta(ref b), &Temperature(ref a)) =>
+                Value::finite_temperature(a.shift(b)),
+            (&TemperatureDelta(ref a), &TemperatureDelta(ref b)) =>
+                Value::finite_temperature_delta(a.plus(b)),
+            (&ExtNumeric(ref a), &ExtNumeric(ref b)) =>
+                Value::combine_ext_numeric(a, b, |x, y| x + y),
+            (&TimeStamp(_), _) =>
+                Err(ArithmeticError::TypeError(TypeError {
+                    expected: Type::Duration,
+                    got: other.get_type()
+                })),
+            _ => Err(Value::unsupported_operands(self, other))
+        }
+    }
+
+    /// Compute `self - other`.
+    ///
+    /// Supported operations:
+    ///
+    /// - `TimeStamp - TimeStamp` produces the `Duration` elapsed between both timestamps;
+    /// - `TimeStamp - Duration` produces a `TimeStamp`;
+    /// - `Duration - Duration` produces a `Duration`;
+    /// - `Temperature - Temperature` produces the `TemperatureDelta` between both
+    ///   temperatures, in the unit of `self`;
+    /// - `Temperature - TemperatureDelta` produces a `Temperature`;
+    /// - `TemperatureDelta - TemperatureDelta` produces a `TemperatureDelta`;
+    /// - `ExtNumeric - ExtNumeric` produces an `ExtNumeric`, provided that both
+    ///   operands have the same vendor and kind, or an `ExtensionMismatch` otherwise.
+    ///
+    /// Any other combination is a `TypeError`.
+    ///
+    /// ```
+    /// use foxbox_taxonomy::values::*;
+    ///
+    /// let start = Value::TimeStamp(TimeStamp::from_s(1000));
+    /// let end = Value::TimeStamp(TimeStamp::from_s(1060));
+    /// assert_eq!(end.checked_sub(&start).unwrap(), Value::Duration(Duration::from_s(60)));
+    ///
+    /// let inside = Value::Temperature(Temperature::F(77.));
+    /// let outside = Value::Temperature(Temperature::C(20.));
+    /// assert_eq!(inside.checked_sub(&outside).unwrap(), Value::TemperatureDelta(TemperatureDelta::F(9.)));
+    ///
+    /// match end.checked_sub(&Value::Unit) {
+    ///   Err(ArithmeticError::TypeError(TypeError { expected: Type::TimeStamp, got: Type::Unit })) => {},
+    ///   other => panic!("Unexpected result {:?}", other)
+    /// }
+    /// ```
+    pub fn checked_sub(&self, other: &Value) -> Result<Value, ArithmeticError> {
+        use self::Value::*;
+        match (self, other) {
+            (&TimeStamp(ref a), &TimeStamp(ref b)) =>
+                a.duration_since(b).map(Duration).ok_or(ArithmeticError::Overflow),
+            (&TimeStamp(ref a), &Duration(ref b)) =>
+                a.checked_sub(b).map(TimeStamp).ok_or(ArithmeticError::Overflow),
+            (&Duration(ref a), &Duration(ref b)) =>
+                a.checked_sub(b).map(Duration).ok_or(ArithmeticError::Overflow),
+            (&Temperature(ref a), &Temperature(ref b)) =>
+                Value::finite_temperature_delta(a.delta(b)),
+            (&Temperature(ref a), &TemperatureDelta(ref b)) =>
+                Value::finite_temperature(a.shift(&b.opposite())),
+            (&TemperatureDelta(ref a), &TemperatureDelta(ref b)) =>
+                Value::finite_temperature_delta(a.plus(&b.opposite())),
+            (&ExtNumeric(ref a), &ExtNumeric(ref b)) =>
+                Value::combine_ext_numeric(a, b, |x, y| x - y),
+            _ => Err(Value::unsupported_operands(self, other))
+        }
+    }
+
+    /// Compute `(self - origin) / step`, i.e. the number of steps between `origin` and
+    /// `self`.
+    ///
+    /// Supported for `TimeStamp` (with a `Duration` step), `Duration`, `ExtNumeric` and
+    /// `Temperature` (with a `TemperatureDelta` step). For compatibility, a `Temperature`
+    /// step is also accepted, and interpreted as a difference, so no offset is applied when
+    /// converting units.
+    ///
+    /// ```
+    /// use foxbox_taxonomy::values::*;
+    ///
+    /// let inside = Value::Temperature(Temperature::F(77.));
+    /// let outside = Value::Temperature(Temperature::C(20.));
+    /// let step = Value::TemperatureDelta(TemperatureDelta::C(2.5));
+    /// assert_eq!(inside.checked_steps(&outside, &step).unwrap(), 2.);
+    ///
+    /// let step = Value::Temperature(Temperature::C(2.5));
+    /// assert_eq!(inside.checked_steps(&outside, &step).unwrap(), 2.);
+    /// ```
+    pub fn checked_steps(&self, origin: &Value, step: &Value) -> Result<f64, ArithmeticError> {
+        match (self, origin, step) {
+            (&Value::Temperature(ref a), &Value::Temperature(ref b), &Value::Temperature(ref step)) => {
+                let delta = a.delta(b);
+                let ratio = match *step {
+                    Temperature::C(step) => delta.as_c() / step,
+                    Temperature::F(step) => delta.as_f() / step,
+                };
+                if ratio.is_finite() {
+                    Ok(ratio)
+                } else {
+                    Err(ArithmeticError::Overflow)
+                }
+            }
+            _ => self.checked_sub(origin).and_then(|delta| delta.checked_ratio(step))
+        }
+    }
+
+    /// Multiply a value by a factor.
+    ///
+    /// Supported for `Duration` (with millisecond precision), `TemperatureDelta` and
+    /// `ExtNumeric`. Any other type is a `TypeError`.
+    ///
+    /// ```
+    /// use foxbox_taxonomy::values::*;
+    ///
+    /// let delay = Value::Duration(Duration::from_s(60));
+    /// assert_eq!(delay.checked_scale(1.5).unwrap(), Value::Duration(Duration::from_s(90)));
+    ///
+    /// match Value::Unit.checked_scale(2.) {
+    ///   Err(ArithmeticError::TypeError(_)) => {},
+    ///   other => panic!("Unexpected result {:?}", other)
+    /// }
+    /// ```
+    pub fn checked_scale(&self, factor: f64) -> Result<Value, ArithmeticError> {
+        match *self {
+            Value::Duration(ref a) =>
+                a.checked_mul(factor).map(Value::Duration).ok_or(ArithmeticError::Overflow),
+            Value::TemperatureDelta(TemperatureDelta::C(val)) =>
+                Value::finite_temperature_delta(TemperatureDelta::C(val * factor)),
+            Value::TemperatureDelta(TemperatureDelta::F(val)) =>
+                Value::finite_temperature_delta(TemperatureDelta::F(val * factor)),
+            Value::ExtNumeric(ref a) => {
+                let value = a.value * factor;
+                if value.is_finite() {
+                    Ok(Value::ExtNumeric(ExtValue {
+                        value: value,
+                        .. a.clone()
+                    }))
+                } else {
+                    Err(ArithmeticError::Overflow)
+                }
+            }
+            _ => Err(ArithmeticError::TypeError(TypeError {
+                expected: Type::ExtNumeric,
+                got: self.get_type()
+            }))
+        }
+    }
+
+    /// Compute `self / other`, for two quantities of the same type.
+    ///
+    /// Supported for `Duration`, `ExtNumeric` (provided that both operands have the same
+    /// vendor and kind), `TemperatureDelta` and `Temperature`. Temperatures are interpreted
+    /// as differences, so no offset is applied when converting units.
+    ///
+    /// ```
+    /// use foxbox_taxonomy::values::*;
+    ///
+    /// let a = Value::Temperature(Temperature::C(10.));
+    /// let b = Value::Temperature(Temperature::F(9.));
+    /// assert_eq!(a.checked_ratio(&b).unwrap(), 2.);
+    /// ```
+    pub fn checked_ratio(&self, other: &Value) -> Result<f64, ArithmeticError> {
+        use self::Value::*;
+        let (a, b) = match (self, other) {
+            (&Duration(ref a), &Duration(ref b)) =>
+                (a.0.num_milliseconds() as f64, b.0.num_milliseconds() as f64),
+            (&Temperature(self::Temperature::C(a)), &Temperature(self::Temperature::C(b))) |
+            (&Temperature(self::Temperature::F(a)), &Temperature(self::Temperature::F(b))) =>
+                (a, b),
+            (&Temperature(self::Temperature::C(a)), &Temperature(self::Temperature::F(b))) =>
+                (a, b * 5. / 9.),
+            (&Temperature(self::Temperature::F(a)), &Temperature(self::Temperature::C(b))) =>
+                (a, b * 9. / 5.),
+            (&TemperatureDelta(ref a), &TemperatureDelta(ref b)) =>
+                (a.as_c(), b.as_c()),
+            (&ExtNumeric(ref a), &ExtNumeric(ref b)) => {
+                try!(ArithmeticError::ensure_same_extension(a, b));
+                (a.value, b.value)
+            }
+            _ => return Err(Value::unsupported_operands(self, other))
+        };
+        let ratio = a / b;
+        if ratio.is_finite() {
+            Ok(ratio)
+        } else {
+            Err(ArithmeticError::Overflow)
+        }
+    }
+
+    fn finite_temperature(value: Temperature) -> Result<Value, ArithmeticError> {
+        if value.as_c().is_finite() {
+            Ok(Value::Temperature(value))
+        } else {
+            Err(ArithmeticError::Overflow)
+        }
+    }
+
+    fn finite_temperature_delta(value: TemperatureDelta) -> Result<Value, ArithmeticError> {
+        if value.as_c().is_finite() {
+            Ok(Value::TemperatureDelta(value))
+        } else {
+            Err(ArithmeticError::Overflow)
+        }
+    }
+
+    fn combine_ext_numeric<F>(a: &ExtValue<f64>, b: &ExtValue<f64>, op: F) -> Result<Value, ArithmeticError>
+        where F: Fn(f64, f64) -> f64
+    {
+        try!(ArithmeticError::ensure_same_extension(a, b));
+        let value = op(a.value, b.value);
+        if value.is_finite() {
+            Ok(Value::ExtNumeric(ExtValue {
+                value: value,
+                .. a.clone()
+            }))
+        } else {
+            Err(ArithmeticError::Overflow)
+        }
+    }
+
+    /// The error for a pair of operands that do not support an operation:
+    /// either the types differ, as determined by `Type::ensure_eq`, or the
+    /// type itself does not support arithmetic.
+    fn unsupported_operands(left: &Value, right: &Value) -> ArithmeticError {
+        let typ = left.get_type();
+        match typ.ensure_eq(&right.get_type()) {
+            Err(err) => ArithmeticError::TypeError(err),
+            Ok(()) => ArithmeticError::TypeError(TypeError {
+                expected: Type::ExtNumeric,
+                got: typ
+            })
+        }
+    }
 }
 
 impl PartialOrd for Value {
@@ -1610,6 +2881,9 @@ impl PartialOrd for Value {
             (&Temperature(ref a), &Temperature(ref b)) => a.partial_cmp(b),
             (&Temperature(_), _) => None,
 
+            (&TemperatureDelta(ref a), &TemperatureDelta(ref b)) => a.partial_cmp(b),
+            (&TemperatureDelta(_), _) => None,
+
             (&Color(ref a), &Color(ref b)) => a.partial_cmp(b),
             (&Color(_), _) => None,
 
@@ -1619,6 +2893,13 @@ impl PartialOrd for Value {
             (&ExtNumeric(ref a), &ExtNumeric(ref b)) => a.partial_cmp(b),
             (&ExtNumeric(_), _) => None,
 
+            // Variants of an enumeration are not ordered.
+            (&ExtEnum(ref a), &ExtEnum(ref b)) if a == b => Some(Equal),
+            (&ExtEnum(_), _) => None,
+
+            (&GeoPoint(ref a), &GeoPoint(ref b)) => a.partial_cmp(b),
+            (&GeoPoint(_), _) => None,
+
             (&String(ref a), &String(ref b)) => a.partial_cmp(b),
             (&String(_), _) => None,
 
@@ -1634,6 +2915,9 @@ impl PartialOrd for Value {
             (&Binary(self::Binary {mimetype: ref a_mimetype, data: ref a_data}),
              &Binary(self::Binary {mimetype: ref b_mimetype, data: ref b_data})) if a_mimetype == b_mimetype => a_data.partial_cmp(b_data),
             (&Binary(_), _) => None,
+
+            (&BinaryStream(ref a), &BinaryStream(ref b)) if a == b => Some(Equal),
+            (&BinaryStream(_), _) => None,
         }
     }
 }
@@ -1682,6 +2966,29 @@ impl TimeStamp {
         let date = DateTime::<UTC>::from_utc(naive, UTC);
         TimeStamp(date)
     }
+
+    /// Add a duration to this timestamp, or `None` if the result cannot be represented.
+    pub fn checked_add(&self, duration: &Duration) -> Option<TimeStamp> {
+        self.0.checked_add(duration.0).map(TimeStamp)
+    }
+
+    /// Subtract a duration from this timestamp, or `None` if the result cannot be represented.
+    pub fn checked_sub(&self, duration: &Duration) -> Option<TimeStamp> {
+        self.0.checked_sub(duration.0).map(TimeStamp)
+    }
+
+    /// The duration elapsed between `earlier` and `self`, or `None` if the result cannot be
+    /// represented. Negative if `earlier` is actually later than `self`.
+    pub fn duration_since(&self, earlier: &TimeStamp) -> Option<Duration> {
+        use chrono::Timelike;
+        let seconds = match self.0.timestamp().checked_sub(earlier.0.timestamp()) {
+            // `ChronoDuration::seconds` panics beyond `i64::MAX` milliseconds.
+            Some(seconds) if seconds.checked_mul(1000).is_some() => seconds,
+            _ => return None
+        };
+        let nanos = self.0.nanosecond() as i64 - earlier.0.nanosecond() as i64;
+        ChronoDuration::seconds(seconds).checked_add(&ChronoDuration::nanoseconds(nanos)).map(Duration)
+    }
 }
 impl Parser<TimeStamp> for TimeStamp {
     fn description() -> String {
@@ -1788,59 +3095,520 @@ pub enum Range {
 
     /// Eq(x) accespts any value v such that v == x
     Eq(Value),
-}
 
-impl Parser<Range> for Range {
-    fn description() -> String {
-        "Range".to_owned()
-    }
-    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
-        use self::Range::*;
-        match *source {
-            JSON::Object(ref mut obj) if obj.len() == 1 => {
-                if let Some(leq) = obj.get_mut("Leq") {
-                    return Ok(Leq(try!(path.push("Leq", |path| Value::parse(path, leq)))))
-                }
-                if let Some(geq) = obj.get_mut("Geq") {
-                    return Ok(Geq(try!(path.push("Geq", |path| Value::parse(path, geq)))))
-                }
-                if let Some(eq) = obj.get_mut("Eq") {
-                    return Ok(Eq(try!(path.push("eq", |path| Value::parse(path, eq)))))
-                }
-                if let Some(between) = obj.get_mut("BetweenEq") {
-                    let mut bounds = try!(path.push("BetweenEq", |path| Vec::<Value>::parse(path, between)));
-                    if bounds.len() == 2 {
-                        let max = bounds.pop().unwrap();
-                        let min = bounds.pop().unwrap();
-                        return Ok(BetweenEq {
-                            min: min,
-                            max: max
-                        })
-                    } else {
-                        return Err(ParseError::type_error("BetweenEq", &path, "an array of two values"))
-                    }
-                }
-                if let Some(outof) = obj.get_mut("OutOfStrict") {
-                    let mut bounds = try!(path.push("OutOfStrict", |path| Vec::<Value>::parse(path, outof)));
-                    if bounds.len() == 2 {
-                        let max = bounds.pop().unwrap();
-                        let min = bounds.pop().unwrap();
-                        return Ok(OutOfStrict {
-                            min: min,
-                            max: max
-                        })
-                    } else {
-                        return Err(ParseError::type_error("OutOfStrict", &path, "an array of two values"))
-                    }
-                }
-                Err(ParseError::type_error("Range", &path, "a field Eq, Leq, Geq, BetweenEq or OutOfStrict"))
-            }
-            _ => Err(ParseError::type_error("Range", &path, "object"))
-        }
-    }
-}
+    /// Within { center, meters } accepts any `Value::GeoPoint` whose great-circle
+    /// distance to `center` is at most `meters`.
+    ///
+    /// # JSON
+    ///
+    /// ```
+    /// extern crate foxbox_taxonomy;
+    ///
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// # fn main() {
+    ///
+    /// let source = "{
+    ///   \"Within\": {
+    ///     \"center\": { \"latitude\": 48.8566, \"longitude\": 2.3522 },
+    ///     \"meters\": 500
+    ///   }
+    /// }";
+    ///
+    /// let parsed = Range::from_str(source).unwrap();
+    /// assert_eq!(parsed.get_type().unwrap(), Some(Type::GeoPoint));
+    ///
+    /// // About 150m away.
+    /// let close = Value::GeoPoint(GeoPoint { latitude: 48.8579, longitude: 2.3530, accuracy: None });
+    /// assert!(parsed.contains(&close));
+    ///
+    /// // About 340km away.
+    /// let far = Value::GeoPoint(GeoPoint { latitude: 51.5074, longitude: -0.1278, accuracy: None });
+    /// assert!(!parsed.contains(&far));
+    ///
+    /// let serialized = parsed.to_json();
+    /// assert_eq!(serialized.find_path(&["Within", "meters"]).unwrap().as_f64().unwrap(), 500.);
+    /// # }
+    /// ```
+    Within { center: GeoPoint, meters: f64 },
 
-impl ToJSON for Range {
+    /// Hysteresis { enter, exit } is a range with separate thresholds for entering
+    /// and exiting, designed to avoid a storm of events when a value hovers around
+    /// a single threshold. A value enters the range once it is accepted by `enter`,
+    /// then exits it only once it is accepted by `exit`. Ranges `enter` and `exit`
+    /// are expected to be disjoint, the gap between them acting as a deadband.
+    ///
+    /// Evaluating this range requires remembering whether the value is currently
+    /// in the range. Without this information, `contains()` is the same as
+    /// `enter.contains()`. Use a `RangeTracker` to follow successive values.
+    ///
+    /// # JSON
+    ///
+    /// ```
+    /// extern crate foxbox_taxonomy;
+    ///
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// # fn main() {
+    ///
+    /// let source = "{
+    ///   \"Hysteresis\": {
+    ///     \"enter\": {\"Geq\": {\"Temperature\": {\"C\": 25}}},
+    ///     \"exit\": {\"Leq\": {\"Temperature\": {\"C\": 24}}}
+    ///   }
+    /// }";
+    ///
+    /// let parsed = Range::from_str(source).unwrap();
+    /// assert_eq!(parsed.get_type().unwrap(), Some(Type::Temperature));
+    ///
+    /// let mut tracker = RangeTracker::new(parsed.clone());
+    /// let celsius = |c| Value::Temperature(Temperature::C(c));
+    /// assert_eq!(tracker.update(&celsius(25.2)), Some(true)); // Entered.
+    /// assert_eq!(tracker.update(&celsius(24.8)), None);       // Still in range.
+    /// assert_eq!(tracker.update(&celsius(25.1)), None);       // Still in range.
+    /// assert_eq!(tracker.update(&celsius(23.9)), Some(false)); // Exited.
+    /// assert_eq!(tracker.update(&celsius(24.5)), None);       // Still out of range.
+    ///
+    /// let serialized = parsed.to_json();
+    /// assert!(serialized.find_path(&["Hysteresis", "exit", "Leq"]).is_some());
+    /// # }
+    /// ```
+    Hysteresis { enter: Box<Range>, exit: Box<Range> },
+
+    /// And(ranges) accepts any value accepted by all of `ranges`.
+    ///
+    /// # JSON
+    ///
+    /// ```
+    /// extern crate foxbox_taxonomy;
+    ///
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// # fn main() {
+    ///
+    /// // Below 10 or above 30, but not exactly 20.
+    /// let source = "{
+    ///   \"And\": [
+    ///     {\"Or\": [
+    ///       {\"Leq\": {\"Duration\": 10}},
+    ///       {\"Geq\": {\"Duration\": 30}}
+    ///     ]},
+    ///     {\"Not\": {\"Eq\": {\"Duration\": 20}}}
+    ///   ]
+    /// }";
+    ///
+    /// let parsed = Range::from_str(source).unwrap();
+    /// assert_eq!(parsed.get_type().unwrap(), Some(Type::Duration));
+    ///
+    /// let duration = |s| Value::Duration(Duration::from_s(s));
+    /// assert!(parsed.contains(&duration(5)));
+    /// assert!(!parsed.contains(&duration(20)));
+    /// assert!(parsed.contains(&duration(40)));
+    ///
+    /// let serialized = parsed.to_json();
+    /// assert!(serialized.find_path(&["And"]).unwrap().as_array().unwrap().len() == 2);
+    ///
+    /// // The branches of an `And` must have the same type.
+    /// let source = "{
+    ///   \"And\": [
+    ///     {\"Eq\": {\"OnOff\": \"On\"}},
+    ///     {\"Eq\": {\"String\": \"error\"}}
+    ///   ]
+    /// }";
+    /// let parsed = Range::from_str(source).unwrap();
+    /// match parsed.get_type() {
+    ///   Err(RangeTypeError::TypeError(_)) => {},
+    ///   other => panic!("Unexpected result {:?}", other)
+    /// }
+    ///
+    /// // The branches of an `Or` may have distinct types, each of them applying to the
+    /// // values of its own type.
+    /// let source = "{
+    ///   \"Or\": [
+    ///     {\"Eq\": {\"OnOff\": \"On\"}},
+    ///     {\"Eq\": {\"String\": \"error\"}}
+    ///   ]
+    /// }";
+    /// let parsed = Range::from_str(source).unwrap();
+    /// assert_eq!(parsed.get_type().unwrap(), None);
+    /// assert!(parsed.ensure_type(&Type::OnOff).is_ok());
+    /// assert!(parsed.ensure_type(&Type::String).is_ok());
+    /// assert!(parsed.ensure_type(&Type::Duration).is_err());
+    /// assert!(parsed.contains(&Value::OnOff(OnOff::On)));
+    /// assert!(parsed.contains(&Value::String(std::sync::Arc::new("error".to_owned()))));
+    /// assert!(!parsed.contains(&Value::OnOff(OnOff::Off)));
+    ///
+    /// // Empty compositions are rejected.
+    /// assert!(Range::from_str("{\"Or\": []}").is_err());
+    /// match Range::And(vec![]).get_type() {
+    ///   Err(RangeTypeError::EmptyComposition) => {},
+    ///   other => panic!("Unexpected result {:?}", other)
+    /// }
+    /// # }
+    /// ```
+    And(Vec<Range>),
+
+    /// Or(ranges) accepts any value accepted by at least one of `ranges`.
+    ///
+    /// # JSON
+    ///
+    /// Represented as `{"Or": [range, ...]}`. See `And`.
+    Or(Vec<Range>),
+
+    /// Not(range) accepts any value that is not accepted by `range`.
+    ///
+    /// # JSON
+    ///
+    /// Represented as `{"Not": range}`. See `And`.
+    Not(Box<Range>),
+
+    /// Dwell { range, duration } accepts the same values as `range`, but when watching,
+    /// `EnterRange` is only fired once the value has remained in `range` for `duration`.
+    /// If the value exits `range` earlier, nothing is fired. Once `EnterRange` has been
+    /// fired, `ExitRange` is fired as soon as the value exits `range`.
+    ///
+    /// This is only meaningful as the outermost range of a watch. Parsing rejects a `Dwell`
+    /// anywhere else, e.g. in an `And` or in another `Dwell`.
+    ///
+    /// # JSON
+    ///
+    /// The duration is represented as a number of seconds.
+    ///
+    /// ```
+    /// extern crate foxbox_taxonomy;
+    ///
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// # fn main() {
+    ///
+    /// // Door open for more than 5 minutes.
+    /// let source = "{
+    ///   \"Dwell\": {
+    ///     \"range\": {\"Eq\": {\"OpenClosed\": \"Open\"}},
+    ///     \"duration\": 300
+    ///   }
+    /// }";
+    ///
+    /// let parsed = Range::from_str(source).unwrap();
+    /// assert_eq!(parsed.get_type().unwrap(), Some(Type::OpenClosed));
+    /// assert!(parsed.contains(&Value::OpenClosed(OpenClosed::Open)));
+    ///
+    /// let serialized = parsed.to_json();
+    /// assert_eq!(serialized.find_path(&["Dwell", "duration"]).unwrap().as_f64().unwrap(), 300.);
+    ///
+    /// // Nested dwells are rejected.
+    /// let source = "{
+    ///   \"Not\": {
+    ///     \"Dwell\": {
+    ///       \"range\": {\"Eq\": {\"OpenClosed\": \"Open\"}},
+    ///       \"duration\": 300
+    ///     }
+    ///   }
+    /// }";
+    /// assert!(Range::from_str(source).is_err());
+    /// # }
+    /// ```
+    Dwell { range: Box<Range>, duration: Duration },
+
+    /// Contains(s) accepts any `Value::String` that contains `s`.
+    ///
+    /// # JSON
+    ///
+    /// ```
+    /// extern crate foxbox_taxonomy;
+    ///
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::parse::*;
+    /// use std::sync::Arc;
+    ///
+    /// # fn main() {
+    ///
+    /// let string = |s: &str| Value::String(Arc::new(s.to_owned()));
+    ///
+    /// let parsed = Range::from_str("{\"Contains\": \"error\"}").unwrap();
+    /// assert_eq!(parsed.get_type().unwrap(), Some(Type::String));
+    /// assert!(parsed.contains(&string("fatal error: disk full")));
+    /// assert!(!parsed.contains(&string("all good")));
+    ///
+    /// let parsed = Range::from_str("{\"StartsWith\": \"fatal\"}").unwrap();
+    /// assert!(parsed.contains(&string("fatal error: disk full")));
+    ///
+    /// let parsed = Range::from_str("{\"EndsWith\": \"full\"}").unwrap();
+    /// assert!(parsed.contains(&string("fatal error: disk full")));
+    ///
+    /// let parsed = Range::from_str("{\"EqIgnoreCase\": \"OK\"}").unwrap();
+    /// assert!(parsed.contains(&string("ok")));
+    ///
+    /// let serialized = parsed.to_json();
+    /// assert_eq!(serialized.find("EqIgnoreCase").unwrap().as_string().unwrap(), "OK");
+    /// # }
+    /// ```
+    Contains(String),
+
+    /// StartsWith(s) accepts any `Value::String` that starts with `s`.
+    ///
+    /// # JSON
+    ///
+    /// Represented as `{"StartsWith": string}`. See `Contains`.
+    StartsWith(String),
+
+    /// EndsWith(s) accepts any `Value::String` that ends with `s`.
+    ///
+    /// # JSON
+    ///
+    /// Represented as `{"EndsWith": string}`. See `Contains`.
+    EndsWith(String),
+
+    /// EqIgnoreCase(s) accepts any `Value::String` equal to `s`, ignoring case.
+    ///
+    /// # JSON
+    ///
+    /// Represented as `{"EqIgnoreCase": string}`. See `Contains`.
+    EqIgnoreCase(String),
+
+    /// Matches(pattern) accepts any `Value::String` that matches a regular expression.
+    /// See `Pattern` for the supported syntax.
+    ///
+    /// # JSON
+    ///
+    /// ```
+    /// extern crate foxbox_taxonomy;
+    ///
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::parse::*;
+    /// use std::sync::Arc;
+    ///
+    /// # fn main() {
+    ///
+    /// let parsed = Range::from_str("{\"Matches\": \"^code [0-9]+$\"}").unwrap();
+    /// assert!(parsed.contains(&Value::String(Arc::new("code 1234".to_owned()))));
+    /// assert!(!parsed.contains(&Value::String(Arc::new("code 12a4".to_owned()))));
+    ///
+    /// // Unsupported syntax is rejected.
+    /// assert!(Range::from_str("{\"Matches\": \"(a|b)\"}").is_err());
+    /// # }
+    /// ```
+    Matches(Pattern),
+
+    /// Changed accepts every value, but when watching, `EnterRange` is fired for each value
+    /// that differs from the value of the previous event sent to the same watcher. The first
+    /// value is always sent. `ExitRange` is never fired.
+    ///
+    /// This is only meaningful as the outermost range of a watch. As it accepts values of
+    /// any type, it has no type of its own.
+    ///
+    /// # JSON
+    ///
+    /// ```
+    /// extern crate foxbox_taxonomy;
+    ///
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// # fn main() {
+    ///
+    /// let parsed = Range::from_str("{\"Changed\": null}").unwrap();
+    /// assert_eq!(parsed, Range::Changed);
+    /// assert!(parsed.contains(&Value::OnOff(OnOff::On)));
+    /// assert_eq!(parsed.get_type().unwrap(), None);
+    /// assert!(parsed.ensure_type(&Type::OnOff).is_ok());
+    ///
+    /// let serialized = parsed.to_json();
+    /// assert!(serialized.find("Changed").unwrap().is_null());
+    /// # }
+    /// ```
+    Changed,
+
+    /// Delta(delta) accepts every value, but when watching, `EnterRange` is fired for each
+    /// value that differs from the value of the previous event sent to the same watcher by
+    /// strictly more than `delta`, in either direction. The first value is always sent.
+    /// `ExitRange` is never fired.
+    ///
+    /// Differences are computed with `Value::checked_sub`, so the delta for a `TimeStamp` is
+    /// a `Duration` and temperatures may be expressed in any unit. If the difference cannot be
+    /// computed, e.g. for an `OnOff`, `Delta` behaves as `Changed`.
+    ///
+    /// This is only meaningful as the outermost range of a watch.
+    ///
+    /// # JSON
+    ///
+    /// ```
+    /// extern crate foxbox_taxonomy;
+    ///
+    /// use foxbox_taxonomy::values::*;
+    /// use foxbox_taxonomy::parse::*;
+    ///
+    /// # fn main() {
+    ///
+    /// // Changes of more than half a degree.
+    /// let source = "{
+    ///   \"Delta\": {\"Temperature\": {\"C\": 0.5}}
+    /// }";
+    ///
+    /// let parsed = Range::from_str(source).unwrap();
+    /// assert_eq!(parsed, Range::Delta(Value::Temperature(Temperature::C(0.5))));
+    /// assert_eq!(parsed.get_type().unwrap(), Some(Type::Temperature));
+    ///
+    /// // Timestamps change by durations.
+    /// let parsed = Range::Delta(Value::Duration(Duration::from_s(60)));
+    /// assert!(parsed.ensure_type(&Type::TimeStamp).is_ok());
+    /// assert!(parsed.ensure_type(&Type::Duration).is_ok());
+    /// assert!(parsed.ensure_type(&Type::Temperature).is_err());
+    ///
+    /// let serialized = parsed.to_json();
+    /// assert_eq!(serialized.find_path(&["Delta", "Temperature", "C"]).unwrap().as_f64().unwrap(), 0.5);
+    /// # }
+    /// ```
+    Delta(Value),
+}
+
+impl Parser<Range> for Range {
+    fn description() -> String {
+        "Range".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        use self::Range::*;
+        match *source {
+            JSON::Object(ref mut obj) if obj.len() == 1 => {
+                if let Some(leq) = obj.get_mut("Leq") {
+                    return Ok(Leq(try!(path.push("Leq", |path| Value::parse(path, leq)))))
+                }
+                if let Some(geq) = obj.get_mut("Geq") {
+                    return Ok(Geq(try!(path.push("Geq", |path| Value::parse(path, geq)))))
+                }
+                if let Some(eq) = obj.get_mut("Eq") {
+                    return Ok(Eq(try!(path.push("eq", |path| Value::parse(path, eq)))))
+                }
+                if let Some(between) = obj.get_mut("BetweenEq") {
+                    let mut bounds = try!(path.push("BetweenEq", |path| Vec::<Value>::parse(path, between)));
+                    if bounds.len() == 2 {
+                        let max = bounds.pop().unwrap();
+                        let min = bounds.pop().unwrap();
+                        return Ok(BetweenEq {
+                            min: min,
+                            max: max
+                        })
+                    } else {
+                        return Err(ParseError::type_error("BetweenEq", &path, "an array of two values"))
+                    }
+                }
+                if let Some(outof) = obj.get_mut("OutOfStrict") {
+                    let mut bounds = try!(path.push("OutOfStrict", |path| Vec::<Value>::parse(path, outof)));
+                    if bounds.len() == 2 {
+                        let max = bounds.pop().unwrap();
+                        let min = bounds.pop().unwrap();
+                        return Ok(OutOfStrict {
+                            min: min,
+                            max: max
+                        })
+                    } else {
+                        return Err(ParseError::type_error("OutOfStrict", &path, "an array of two values"))
+                    }
+                }
+                if let Some(within) = obj.get_mut("Within") {
+                    let center = try!(path.push("Within", |path| {
+                        path.push("center", |path| GeoPoint::take(path, within, "center"))
+                    }));
+                    let meters = try!(path.push("Within", |path| {
+                        path.push("meters", |path| f64::take(path, within, "meters"))
+                    }));
+                    if meters < 0. {
+                        return Err(ParseError::type_error("Within", &path, "a non-negative number of meters"))
+                    }
+                    return Ok(Within {
+                        center: center,
+                        meters: meters
+                    })
+                }
+                if let Some(hysteresis) = obj.get_mut("Hysteresis") {
+                    let enter = try!(path.push("Hysteresis", |path| {
+                        path.push("enter", |path| Range::take(path, hysteresis, "enter"))
+                    }));
+                    try!(enter.ensure_not_dwell("Hysteresis", &path));
+                    let exit = try!(path.push("Hysteresis", |path| {
+                        path.push("exit", |path| Range::take(path, hysteresis, "exit"))
+                    }));
+                    try!(exit.ensure_not_dwell("Hysteresis", &path));
+                    return Ok(Hysteresis {
+                        enter: Box::new(enter),
+                        exit: Box::new(exit)
+                    })
+                }
+                if let Some(and) = obj.get_mut("And") {
+                    let ranges = try!(path.push("And", |path| Vec::<Range>::parse(path, and)));
+                    for range in &ranges {
+                        try!(range.ensure_not_dwell("And", &path));
+                    }
+                    if ranges.is_empty() {
+                        return Err(ParseError::type_error("And", &path, "a non-empty array of ranges"))
+                    }
+                    return Ok(And(ranges))
+                }
+                if let Some(or) = obj.get_mut("Or") {
+                    let ranges = try!(path.push("Or", |path| Vec::<Range>::parse(path, or)));
+                    for range in &ranges {
+                        try!(range.ensure_not_dwell("Or", &path));
+                    }
+                    if ranges.is_empty() {
+                        return Err(ParseError::type_error("Or", &path, "a non-empty array of ranges"))
+                    }
+                    return Ok(Or(ranges))
+                }
+                if let Some(not) = obj.get_mut("Not") {
+                    let range = try!(path.push("Not", |path| Range::parse(path, not)));
+                    try!(range.ensure_not_dwell("Not", &path));
+                    return Ok(Not(Box::new(range)))
+                }
+                if let Some(dwell) = obj.get_mut("Dwell") {
+                    let range = try!(path.push("Dwell", |path| {
+                        path.push("range", |path| Range::take(path, dwell, "range"))
+                    }));
+                    try!(range.ensure_not_dwell("Dwell", &path));
+                    let duration = try!(path.push("Dwell", |path| {
+                        path.push("duration", |path| Duration::take(path, dwell, "duration"))
+                    }));
+                    return Ok(Dwell {
+                        range: Box::new(range),
+                        duration: duration
+                    })
+                }
+                if let Some(s) = obj.get_mut("Contains") {
+                    return Ok(Contains(try!(path.push("Contains", |path| String::parse(path, s)))))
+                }
+                if let Some(s) = obj.get_mut("StartsWith") {
+                    return Ok(StartsWith(try!(path.push("StartsWith", |path| String::parse(path, s)))))
+                }
+                if let Some(s) = obj.get_mut("EndsWith") {
+                    return Ok(EndsWith(try!(path.push("EndsWith", |path| String::parse(path, s)))))
+                }
+                if let Some(s) = obj.get_mut("EqIgnoreCase") {
+                    return Ok(EqIgnoreCase(try!(path.push("EqIgnoreCase", |path| String::parse(path, s)))))
+                }
+                if let Some(s) = obj.get_mut("Matches") {
+                    return Ok(Matches(try!(path.push("Matches", |path| Pattern::parse(path, s)))))
+                }
+                if let Some(changed) = obj.get("Changed") {
+                    if changed.is_null() {
+                        return Ok(Changed)
+                    } else {
+                        return Err(ParseError::type_error("Changed", &path, "null"))
+                    }
+                }
+                if let Some(delta) = obj.get_mut("Delta") {
+                    return Ok(Delta(try!(path.push("Delta", |path| Value::parse(path, delta)))))
+                }
+                Err(ParseError::type_error("Range", &path, "a field Eq, Leq, Geq, BetweenEq, OutOfStrict, Within, Hysteresis, And, Or, Not, Dwell, Contains, StartsWith, EndsWith, EqIgnoreCase, Matches, Changed or Delta"))
+            }
+            _ => Err(ParseError::type_error("Range", &path, "object"))
+        }
+    }
+}
+
+impl ToJSON for Range {
     fn to_json(&self) -> JSON {
         let (key, value) = match *self {
             Range::Eq(ref val) => ("Eq", val.to_json()),
@@ -1848,48 +3616,992 @@ impl ToJSON for Range {
             Range::Leq(ref val) => ("Leq", val.to_json()),
             Range::BetweenEq { ref min, ref max } => ("BetweenEq", JSON::Array(vec![min.to_json(), max.to_json()])),
             Range::OutOfStrict { ref min, ref max } => ("OutOfStrict", JSON::Array(vec![min.to_json(), max.to_json()])),
+            Range::Within { ref center, ref meters } => ("Within", vec![
+                ("center", center.to_json()),
+                ("meters", meters.to_json()),
+            ].to_json()),
+            Range::Hysteresis { ref enter, ref exit } => ("Hysteresis", vec![
+                ("enter", enter.to_json()),
+                ("exit", exit.to_json()),
+            ].to_json()),
+            Range::And(ref ranges) => ("And", ranges.to_json()),
+            Range::Or(ref ranges) => ("Or", ranges.to_json()),
+            Range::Not(ref range) => ("Not", range.to_json()),
+            Range::Dwell { ref range, ref duration } => ("Dwell", vec![
+                ("range", range.to_json()),
+                ("duration", duration.to_json()),
+            ].to_json()),
+            Range::Contains(ref s) => ("Contains", s.to_json()),
+            Range::StartsWith(ref s) => ("StartsWith", s.to_json()),
+            Range::EndsWith(ref s) => ("EndsWith", s.to_json()),
+            Range::EqIgnoreCase(ref s) => ("EqIgnoreCase", s.to_json()),
+            Range::Matches(ref pattern) => ("Matches", pattern.to_json()),
+            Range::Changed => ("Changed", JSON::Null),
+            Range::Delta(ref delta) => ("Delta", delta.to_json()),
         };
         vec![(key, value)].to_json()
     }
-}
+}
+
+impl Range {
+    /// While parsing, reject a `Dwell` nested in another range, where it would be ignored.
+    fn ensure_not_dwell(&self, name: &str, path: &Path) -> Result<(), ParseError> {
+        if let Range::Dwell { .. } = *self {
+            return Err(ParseError::type_error(name, path, "a range other than Dwell, which is only accepted as the outermost range"))
+        }
+        Ok(())
+    }
+
+    /// Determine if a value is accepted by this range.
+    pub fn contains(&self, value: &Value) -> bool {
+        use self::Range::*;
+        match *self {
+            Leq(ref max) => value <= max,
+            Geq(ref min) => value >= min,
+            BetweenEq { ref min, ref max } => min <= value && value <= max,
+            OutOfStrict { ref min, ref max } => value < min || max < value,
+            Eq(ref val) => value == val,
+            Within { ref center, meters } => {
+                if let Value::GeoPoint(ref point) = *value {
+                    center.distance_to(point) <= meters
+                } else {
+                    false
+                }
+            }
+            Hysteresis { ref enter, .. } => enter.contains(value),
+            And(ref ranges) => ranges.iter().all(|range| range.contains(value)),
+            Or(ref ranges) => ranges.iter().any(|range| range.contains(value)),
+            Not(ref range) => !range.contains(value),
+            Dwell { ref range, .. } => range.contains(value),
+            Contains(ref s) | StartsWith(ref s) | EndsWith(ref s) | EqIgnoreCase(ref s) => {
+                let string = match *value {
+                    Value::String(ref string) => string,
+                    _ => return false
+                };
+                match *self {
+                    Contains(_) => string.contains(s as &str),
+                    StartsWith(_) => string.starts_with(s as &str),
+                    EndsWith(_) => string.ends_with(s as &str),
+                    _ => string.to_lowercase() == s.to_lowercase()
+                }
+            }
+            Matches(ref pattern) => {
+                match *value {
+                    Value::String(ref string) => pattern.is_match(string),
+                    _ => false
+                }
+            }
+            Changed | Delta(_) => true,
+        }
+    }
+
+    /// Determine whether this range is one of the simple comparisons that adapters are
+    /// expected to understand. Other ranges are evaluated by the manager.
+    pub fn is_plain(&self) -> bool {
+        match *self {
+            Range::Hysteresis { .. } | Range::And(_) | Range::Or(_) | Range::Not(_) |
+            Range::Dwell { .. } | Range::Contains(_) | Range::StartsWith(_) | Range::EndsWith(_) |
+            Range::EqIgnoreCase(_) | Range::Matches(_) | Range::Changed | Range::Delta(_) => false,
+            _ => true
+        }
+    }
+
+    /// Determine whether every value accepted by `other` is also accepted by this range.
+    ///
+    /// The check is conservative: `false` means that containment could not be established,
+    /// not necessarily that `other` accepts values that this range rejects. Ranges that
+    /// depend on the history of values (`Hysteresis`, `Dwell`, `Changed`, `Delta`) only
+    /// include themselves.
+    ///
+    /// ```
+    /// extern crate foxbox_taxonomy;
+    ///
+    /// use foxbox_taxonomy::values::*;
+    ///
+    /// # fn main() {
+    ///
+    /// let celsius = |degrees| Value::Temperature(Temperature::C(degrees));
+    ///
+    /// let above_10 = Range::Geq(celsius(10.));
+    /// let between_15_20 = Range::BetweenEq { min: celsius(15.), max: celsius(20.) };
+    /// assert!(above_10.includes(&between_15_20));
+    /// assert!(!between_15_20.includes(&above_10));
+    ///
+    /// let cold_or_hot = Range::OutOfStrict { min: celsius(15.), max: celsius(25.) };
+    /// assert!(cold_or_hot.includes(&Range::Leq(celsius(10.))));
+    /// assert!(!cold_or_hot.includes(&Range::Leq(celsius(15.))));
+    ///
+    /// // Equivalent ranges include each other.
+    /// let exactly_20 = Range::BetweenEq { min: celsius(20.), max: celsius(20.) };
+    /// assert!(exactly_20.includes(&Range::Eq(celsius(20.))));
+    /// assert!(Range::Eq(celsius(20.)).includes(&exactly_20));
+    /// # }
+    /// ```
+    pub fn includes(&self, other: &Range) -> bool {
+        use self::Range::*;
+        if self == other {
+            return true;
+        }
+        match (self, other) {
+            (&Hysteresis { .. }, _) | (&Dwell { .. }, _) | (&Changed, _) | (&Delta(_), _) |
+            (_, &Hysteresis { .. }) | (_, &Dwell { .. }) | (_, &Changed) | (_, &Delta(_)) => false,
+            (_, &Or(ref others)) => others.iter().all(|other| self.includes(other)),
+            (&And(ref ranges), _) => ranges.iter().all(|range| range.includes(other)),
+            (&Or(ref ranges), _) => ranges.iter().any(|range| range.includes(other)),
+            (_, &And(ref others)) => others.iter().any(|other| self.includes(other)),
+            (&Not(ref range), &Not(ref other)) => other.includes(range),
+            (_, &Eq(ref value)) => self.contains(value),
+            (&Within { ref center, meters }, &Within { center: ref other_center, meters: other_meters }) =>
+                center.distance_to(other_center) + other_meters <= meters,
+            (&Contains(ref s), &Contains(ref other)) |
+            (&Contains(ref s), &StartsWith(ref other)) |
+            (&Contains(ref s), &EndsWith(ref other)) => other.contains(s as &str),
+            (&StartsWith(ref s), &StartsWith(ref other)) => other.starts_with(s as &str),
+            (&EndsWith(ref s), &EndsWith(ref other)) => other.ends_with(s as &str),
+            (&EqIgnoreCase(ref s), &EqIgnoreCase(ref other)) => s.to_lowercase() == other.to_lowercase(),
+            _ => {
+                match (self.intervals(), other.intervals()) {
+                    (Some(mine), Some(theirs)) => theirs.iter().all(|interval| {
+                        mine.iter().any(|candidate| candidate.includes(interval))
+                    }),
+                    _ => false
+                }
+            }
+        }
+    }
+
+    /// A range accepting the values accepted by either `self` or `other`.
+    ///
+    /// Whenever possible, the result is simplified into a plain range. Otherwise, it is an
+    /// `Or` of both ranges.
+    ///
+    /// ```
+    /// extern crate foxbox_taxonomy;
+    ///
+    /// use foxbox_taxonomy::values::*;
+    ///
+    /// # fn main() {
+    ///
+    /// let celsius = |degrees| Value::Temperature(Temperature::C(degrees));
+    ///
+    /// let union = Range::BetweenEq { min: celsius(10.), max: celsius(20.) }
+    ///     .union(&Range::Geq(celsius(15.)));
+    /// assert_eq!(union, Range::Geq(celsius(10.)));
+    ///
+    /// let union = Range::Leq(celsius(10.))
+    ///     .union(&Range::Geq(celsius(20.)));
+    /// assert_eq!(union, Range::Or(vec![Range::Leq(celsius(10.)), Range::Geq(celsius(20.))]));
+    /// # }
+    /// ```
+    pub fn union(&self, other: &Range) -> Range {
+        if self.includes(other) {
+            return self.clone();
+        }
+        if other.includes(self) {
+            return other.clone();
+        }
+        if let (Some(mine), Some(theirs)) = (self.single_interval(), other.single_interval()) {
+            if let Some(range) = mine.union(&theirs).and_then(|interval| interval.to_range()) {
+                return range;
+            }
+        }
+        let mut ranges = vec![];
+        for range in vec![self, other] {
+            match *range {
+                Range::Or(ref more) => ranges.extend(more.iter().cloned()),
+                _ => ranges.push(range.clone())
+            }
+        }
+        Range::Or(ranges)
+    }
 
-impl Range {
-    /// Determine if a value is accepted by this range.
-    pub fn contains(&self, value: &Value) -> bool {
+    /// A range accepting the values accepted by both `self` and `other`.
+    ///
+    /// Whenever possible, the result is simplified into a plain range. Otherwise, it is an
+    /// `And` of both ranges.
+    ///
+    /// ```
+    /// extern crate foxbox_taxonomy;
+    ///
+    /// use foxbox_taxonomy::values::*;
+    ///
+    /// # fn main() {
+    ///
+    /// let celsius = |degrees| Value::Temperature(Temperature::C(degrees));
+    ///
+    /// let intersection = Range::Geq(celsius(10.))
+    ///     .intersection(&Range::Leq(celsius(20.)));
+    /// assert_eq!(intersection, Range::BetweenEq { min: celsius(10.), max: celsius(20.) });
+    ///
+    /// let intersection = Range::BetweenEq { min: celsius(10.), max: celsius(20.) }
+    ///     .intersection(&Range::BetweenEq { min: celsius(20.), max: celsius(30.) });
+    /// assert_eq!(intersection, Range::Eq(celsius(20.)));
+    /// # }
+    /// ```
+    pub fn intersection(&self, other: &Range) -> Range {
+        if self.includes(other) {
+            return other.clone();
+        }
+        if other.includes(self) {
+            return self.clone();
+        }
+        if let (Some(mine), Some(theirs)) = (self.single_interval(), other.single_interval()) {
+            if let Some(range) = mine.intersection(&theirs).and_then(|interval| interval.to_range()) {
+                return range;
+            }
+        }
+        let mut ranges = vec![];
+        for range in vec![self, other] {
+            match *range {
+                Range::And(ref more) => ranges.extend(more.iter().cloned()),
+                _ => ranges.push(range.clone())
+            }
+        }
+        Range::And(ranges)
+    }
+
+    /// The intervals of values accepted by this range, if it is a comparison.
+    fn intervals(&self) -> Option<Vec<Interval>> {
         use self::Range::*;
+        let intervals = match *self {
+            Eq(ref value) => vec![Interval::new(IntervalBound::inclusive(value), IntervalBound::inclusive(value))],
+            Leq(ref max) => vec![Interval::new(None, IntervalBound::inclusive(max))],
+            Geq(ref min) => vec![Interval::new(IntervalBound::inclusive(min), None)],
+            BetweenEq { ref min, ref max } =>
+                vec![Interval::new(IntervalBound::inclusive(min), IntervalBound::inclusive(max))],
+            OutOfStrict { ref min, ref max } => vec![
+                Interval::new(None, IntervalBound::exclusive(min)),
+                Interval::new(IntervalBound::exclusive(max), None)
+            ],
+            _ => return None
+        };
+        Some(intervals)
+    }
+
+    fn single_interval(&self) -> Option<Interval> {
+        match self.intervals() {
+            Some(mut intervals) => {
+                if intervals.len() == 1 {
+                    intervals.pop()
+                } else {
+                    None
+                }
+            }
+            None => None
+        }
+    }
+
+    /// The plain ranges that need to be watched so that every change of membership
+    /// of a value to this range is detected. For a plain range, this is the range
+    /// itself. `None` if detecting changes requires watching every new value, e.g.
+    /// for string predicates.
+    pub fn triggers(&self) -> Option<Vec<Range>> {
         match *self {
-            Leq(ref max) => value <= max,
-            Geq(ref min) => value >= min,
-            BetweenEq { ref min, ref max } => min <= value && value <= max,
-            OutOfStrict { ref min, ref max } => value < min || max < value,
-            Eq(ref val) => value == val,
+            Range::Hysteresis { ref enter, ref exit } => {
+                match (enter.triggers(), exit.triggers()) {
+                    (Some(mut triggers), Some(exit)) => {
+                        triggers.extend(exit);
+                        Some(triggers)
+                    }
+                    _ => None
+                }
+            }
+            Range::And(ref ranges) | Range::Or(ref ranges) => {
+                let mut triggers = vec![];
+                for range in ranges {
+                    match range.triggers() {
+                        Some(more) => triggers.extend(more),
+                        None => return None
+                    }
+                }
+                Some(triggers)
+            }
+            Range::Not(ref range) | Range::Dwell { ref range, .. } => range.triggers(),
+            Range::Contains(_) | Range::StartsWith(_) | Range::EndsWith(_) |
+            Range::EqIgnoreCase(_) | Range::Matches(_) | Range::Changed | Range::Delta(_) => None,
+            _ => Some(vec![self.clone()])
         }
     }
 
-    /// Get the type associated to this range.
+    /// Get the type of the values watched with this range, or `None` if the range applies
+    /// to values of any type (e.g. `Changed`) or of several types (`Delta` of a `Duration`,
+    /// which applies to both `Duration` and `TimeStamp` values, or `Or` of ranges of distinct
+    /// types). See also `ensure_type`.
     ///
-    /// If this range has a `min` and a `max` with conflicting types,
-    /// produce an error.
-    pub fn get_type(&self) -> Result<Type, TypeError> {
+    /// If parts of this range that must apply to the same value have conflicting types,
+    /// e.g. the `min` and `max` of `BetweenEq` or the branches of `And`, or if an `And` or
+    /// `Or` is empty, produce an error.
+    pub fn get_type(&self) -> Result<Option<Type>, RangeTypeError> {
         use self::Range::*;
         match *self {
-            Leq(ref v) | Geq(ref v) | Eq(ref v) => Ok(v.get_type()),
+            Leq(ref v) | Geq(ref v) | Eq(ref v) => Ok(Some(v.get_type())),
+            Within { .. } => Ok(Some(Type::GeoPoint)),
+            Hysteresis { ref enter, ref exit } => Range::common_type(vec![&**enter, &**exit]),
+            Not(ref range) | Dwell { ref range, .. } => range.get_type(),
+            Contains(_) | StartsWith(_) | EndsWith(_) | EqIgnoreCase(_) | Matches(_) => Ok(Some(Type::String)),
+            Delta(ref delta) => {
+                match delta.get_type() {
+                    Type::Duration => Ok(None),
+                    Type::TemperatureDelta => Ok(Some(Type::Temperature)),
+                    typ => Ok(Some(typ))
+                }
+            }
+            Changed => Ok(None),
+            And(ref ranges) | Or(ref ranges) if ranges.is_empty() =>
+                Err(RangeTypeError::EmptyComposition),
+            And(ref ranges) => Range::common_type(ranges.iter().collect()),
+            Or(ref ranges) => {
+                // Each branch applies to the values of its own type, so distinct types
+                // are acceptable, but each branch must be consistent.
+                match Range::common_type(ranges.iter().collect()) {
+                    Err(RangeTypeError::TypeError(_)) => {
+                        for range in ranges {
+                            try!(range.get_type());
+                        }
+                        Ok(None)
+                    }
+                    result => result
+                }
+            }
             BetweenEq {ref min, ref max} | OutOfStrict {ref min, ref max} => {
                 let min_typ = min.get_type();
                 let max_typ = max.get_type();
                 if min_typ == max_typ {
-                    Ok(min_typ)
+                    Ok(Some(min_typ))
                 } else {
-                    Err(TypeError {
+                    Err(RangeTypeError::TypeError(TypeError {
                         expected: min_typ,
                         got: max_typ
+                    }))
+                }
+            }
+        }
+    }
+
+    /// The type shared by several ranges, ignoring those that apply to any type.
+    fn common_type(ranges: Vec<&Range>) -> Result<Option<Type>, RangeTypeError> {
+        let mut result : Option<Type> = None;
+        for range in ranges {
+            let typ = match try!(range.get_type()) {
+                None => continue,
+                Some(typ) => typ
+            };
+            if let Some(ref known) = result {
+                try!(known.ensure_eq(&typ));
+                continue;
+            }
+            result = Some(typ);
+        }
+        Ok(result)
+    }
+
+    /// Ensure that this range may be used to watch values of type `typ`. For `Or`, it is
+    /// sufficient that one of the branches applies to `typ`.
+    pub fn ensure_type(&self, typ: &Type) -> Result<(), RangeTypeError> {
+        use self::Range::*;
+        match *self {
+            Changed => Ok(()),
+            Delta(ref delta) => {
+                // The difference between two timestamps is a duration, and the difference
+                // between two temperatures may be expressed as a temperature, for
+                // compatibility.
+                let delta_typ = match (typ, delta.get_type()) {
+                    (&Type::TimeStamp, _) => Type::Duration,
+                    (&Type::Temperature, Type::Temperature) => Type::Temperature,
+                    (&Type::Temperature, _) => Type::TemperatureDelta,
+                    (other, _) => other.clone()
+                };
+                Ok(try!(delta_typ.ensure_eq(&delta.get_type())))
+            }
+            Not(ref range) | Dwell { ref range, .. } => range.ensure_type(typ),
+            Hysteresis { ref enter, ref exit } => {
+                try!(enter.ensure_type(typ));
+                exit.ensure_type(typ)
+            }
+            And(ref ranges) | Or(ref ranges) if ranges.is_empty() =>
+                Err(RangeTypeError::EmptyComposition),
+            And(ref ranges) => {
+                for range in ranges {
+                    try!(range.ensure_type(typ));
+                }
+                Ok(())
+            }
+            Or(ref ranges) => {
+                // Branches of other types never match, but must still be consistent.
+                try!(self.get_type());
+                let mut first_error = None;
+                for range in ranges {
+                    match range.ensure_type(typ) {
+                        Ok(()) => return Ok(()),
+                        Err(err) => if first_error.is_none() {
+                            first_error = Some(err);
+                        }
+                    }
+                }
+                Err(first_error.unwrap())
+            }
+            _ => match try!(self.get_type()) {
+                None => Ok(()),
+                Some(own) => Ok(try!(typ.ensure_eq(&own)))
+            }
+        }
+    }
+}
+
+/// A simple regular expression, used by `Range::Matches`.
+///
+/// The supported subset is:
+///
+/// - literal characters, and `\` to escape any special character;
+/// - `.`, matching any character;
+/// - character classes `[abc]`, `[a-z]`, `[^0-9]`;
+/// - shorthands `\d`, `\w`, `\s` and their negations `\D`, `\W`, `\S`;
+/// - repetitions `*`, `+`, `?`;
+/// - anchors `^` at the start and `$` at the end of the pattern.
+///
+/// Groups, alternatives and counted repetitions are not supported. Unless anchored,
+/// a pattern matches if it matches any substring of the value.
+///
+/// # JSON
+///
+/// A pattern is represented by its source string.
+///
+/// ```
+/// extern crate foxbox_taxonomy;
+///
+/// use foxbox_taxonomy::values::*;
+/// use foxbox_taxonomy::parse::*;
+///
+/// # fn main() {
+///
+/// let pattern = Pattern::new("^Rule [a-z_]+ (started|stopped)$");
+/// assert!(pattern.is_err());
+///
+/// let pattern = Pattern::new("^Rule [a-z_]+ st.*ed$").unwrap();
+/// assert!(pattern.is_match("Rule night_lights started"));
+/// assert!(pattern.is_match("Rule night_lights stopped"));
+/// assert!(!pattern.is_match("Rule Night lights started"));
+///
+/// let pattern = Pattern::new("\\d+ ?%").unwrap();
+/// assert!(pattern.is_match("Battery at 15%"));
+/// assert!(!pattern.is_match("Battery low"));
+///
+/// // Matching takes linear time, even for patterns that would require backtracking.
+/// let pattern = Pattern::new("a*a*a*a*a*a*a*a*b").unwrap();
+/// let text : String = vec!['a'; 10000].into_iter().collect();
+/// assert!(!pattern.is_match(&text));
+///
+/// let parsed = Pattern::from_str("\"colou?r\"").unwrap();
+/// assert!(parsed.is_match("color"));
+/// assert!(parsed.is_match("colour"));
+/// assert_eq!(parsed.to_json().as_string().unwrap(), "colou?r");
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    source: String,
+    anchored_start: bool,
+    anchored_end: bool,
+    nodes: Vec<PatternNode>,
+}
+
+#[derive(Clone, Debug)]
+enum PatternClassItem {
+    Between(char, char),
+    Digit,
+    Word,
+    Space,
+}
+
+impl PatternClassItem {
+    fn matches(&self, c: char) -> bool {
+        match *self {
+            PatternClassItem::Between(min, max) => min <= c && c <= max,
+            PatternClassItem::Digit => c.is_digit(10),
+            PatternClassItem::Word => c.is_alphanumeric() || c == '_',
+            PatternClassItem::Space => c.is_whitespace(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum PatternAtom {
+    Any,
+    Char(char),
+    Class { negated: bool, items: Vec<PatternClassItem> },
+}
+
+impl PatternAtom {
+    fn matches(&self, c: char) -> bool {
+        match *self {
+            PatternAtom::Any => true,
+            PatternAtom::Char(expected) => c == expected,
+            PatternAtom::Class { negated, ref items } =>
+                items.iter().any(|item| item.matches(c)) != negated
+        }
+    }
+
+    /// Build the atom for a shorthand such as `\d`, or a literal for any other escaped
+    /// character.
+    fn escaped(c: char) -> Self {
+        let (negated, item) = match c {
+            'd' => (false, PatternClassItem::Digit),
+            'D' => (true, PatternClassItem::Digit),
+            'w' => (false, PatternClassItem::Word),
+            'W' => (true, PatternClassItem::Word),
+            's' => (false, PatternClassItem::Space),
+            'S' => (true, PatternClassItem::Space),
+            _ => return PatternAtom::Char(c)
+        };
+        PatternAtom::Class { negated: negated, items: vec![item] }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PatternRepeat {
+    One,
+    ZeroOrOne,
+    ZeroOrMore,
+}
+
+#[derive(Clone, Debug)]
+struct PatternNode {
+    atom: PatternAtom,
+    repeat: PatternRepeat,
+}
+
+impl Pattern {
+    /// Compile a pattern, or return a message explaining why it is not supported.
+    pub fn new(source: &str) -> Result<Self, String> {
+        let mut chars : Vec<char> = source.chars().collect();
+        let anchored_start = chars.first() == Some(&'^');
+        if anchored_start {
+            chars.remove(0);
+        }
+        let anchored_end = chars.last() == Some(&'$') &&
+            (chars.len() < 2 || chars[chars.len() - 2] != '\\');
+        if anchored_end {
+            chars.pop();
+        }
+
+        let mut nodes : Vec<PatternNode> = vec![];
+        let mut iter = chars.into_iter();
+        while let Some(c) = iter.next() {
+            let atom = match c {
+                '.' => PatternAtom::Any,
+                '\\' => match iter.next() {
+                    Some(c) => PatternAtom::escaped(c),
+                    None => return Err("trailing `\\`".to_owned())
+                },
+                '[' => {
+                    let mut negated = false;
+                    let mut items = vec![];
+                    let mut first = true;
+                    loop {
+                        let c = match iter.next() {
+                            Some(c) => c,
+                            None => return Err("unterminated `[`".to_owned())
+                        };
+                        match c {
+                            '^' if first => negated = true,
+                            ']' => break,
+                            '\\' => match iter.next() {
+                                Some(c) => match PatternAtom::escaped(c) {
+                                    PatternAtom::Class { negated: false, items: more } => items.extend(more),
+                                    PatternAtom::Char(c) => items.push(PatternClassItem::Between(c, c)),
+                                    _ => return Err(format!("`\\{}` is not supported in a class", c))
+                                },
+                                None => return Err("unterminated `[`".to_owned())
+                            },
+                            '-' if !items.is_empty() => {
+                                let min = match items.pop() {
+                                    Some(PatternClassItem::Between(min, _)) => min,
+                                    _ => return Err("invalid range in class".to_owned())
+                                };
+                                match iter.next() {
+                                    Some(']') => {
+                                        // A trailing `-` is a literal.
+                                        items.push(PatternClassItem::Between(min, min));
+                                        items.push(PatternClassItem::Between('-', '-'));
+                                        break;
+                                    }
+                                    Some(max) if min <= max => items.push(PatternClassItem::Between(min, max)),
+                                    Some(_) => return Err("invalid range in class".to_owned()),
+                                    None => return Err("unterminated `[`".to_owned())
+                                }
+                            }
+                            _ => items.push(PatternClassItem::Between(c, c))
+                        }
+                        first = false;
+                    }
+                    PatternAtom::Class { negated: negated, items: items }
+                }
+                '*' | '?' => {
+                    let repeat = match c {
+                        '*' => PatternRepeat::ZeroOrMore,
+                        _ => PatternRepeat::ZeroOrOne,
+                    };
+                    if let Some(node) = nodes.last_mut() {
+                        if node.repeat == PatternRepeat::One {
+                            node.repeat = repeat;
+                            continue;
+                        }
+                    }
+                    return Err(format!("`{}` does not follow a character", c))
+                }
+                '+' => {
+                    // `x+` is `xx*`.
+                    let atom = match nodes.last() {
+                        Some(node) if node.repeat == PatternRepeat::One => node.atom.clone(),
+                        _ => return Err("`+` does not follow a character".to_owned())
+                    };
+                    nodes.push(PatternNode {
+                        atom: atom,
+                        repeat: PatternRepeat::ZeroOrMore
+                    });
+                    continue;
+                }
+                '(' | ')' | '|' | '{' | '}' | '^' | '$' =>
+                    return Err(format!("`{}` is not supported", c)),
+                _ => PatternAtom::Char(c)
+            };
+            nodes.push(PatternNode {
+                atom: atom,
+                repeat: PatternRepeat::One
+            });
+        }
+        Ok(Pattern {
+            source: source.to_owned(),
+            anchored_start: anchored_start,
+            anchored_end: anchored_end,
+            nodes: nodes,
+        })
+    }
+
+    /// The source of this pattern.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// `true` if `text` matches this pattern.
+    ///
+    /// This simulates the automaton of the pattern, in which state `i` means that the first
+    /// `i` nodes have matched, so the time is proportional to the length of `text` times the
+    /// length of the pattern, without backtracking.
+    pub fn is_match(&self, text: &str) -> bool {
+        use std::mem;
+        let len = self.nodes.len();
+        let mut current = vec![false; len + 1];
+        let mut next = vec![false; len + 1];
+        current[0] = true;
+        self.skip_optional(&mut current);
+        for c in text.chars() {
+            if current[len] && !self.anchored_end {
+                return true;
+            }
+            if !self.anchored_start {
+                // A match may start anywhere.
+                current[0] = true;
+                self.skip_optional(&mut current);
+            }
+            for state in next.iter_mut() {
+                *state = false;
+            }
+            for (i, node) in self.nodes.iter().enumerate() {
+                if current[i] && node.atom.matches(c) {
+                    match node.repeat {
+                        PatternRepeat::One | PatternRepeat::ZeroOrOne => next[i + 1] = true,
+                        PatternRepeat::ZeroOrMore => next[i] = true,
+                    }
+                }
+            }
+            self.skip_optional(&mut next);
+            mem::swap(&mut current, &mut next);
+        }
+        if !self.anchored_start {
+            // An empty match at the end of the text.
+            current[0] = true;
+            self.skip_optional(&mut current);
+        }
+        current[len]
+    }
+
+    /// Add to `states` the states reached by skipping optional nodes.
+    fn skip_optional(&self, states: &mut [bool]) {
+        // Skipping only moves forward, so a single pass is sufficient.
+        for (i, node) in self.nodes.iter().enumerate() {
+            if states[i] && node.repeat != PatternRepeat::One {
+                states[i + 1] = true;
+            }
+        }
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Parser<Pattern> for Pattern {
+    fn description() -> String {
+        "Pattern".to_owned()
+    }
+    fn parse(path: Path, source: &mut JSON) -> Result<Self, ParseError> {
+        let source = try!(String::parse(path.clone(), source));
+        match Pattern::new(&source) {
+            Ok(pattern) => Ok(pattern),
+            Err(err) => Err(ParseError::type_error("Pattern", &path, &format!("a supported regular expression ({})", err)))
+        }
+    }
+}
+
+impl ToJSON for Pattern {
+    fn to_json(&self) -> JSON {
+        JSON::String(self.source.clone())
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S>(&self, serializer: &mut S) -> Result<(), S::Error> where S: Serializer {
+        self.source.serialize(serializer)
+    }
+}
+
+impl Deserialize for Pattern {
+    fn deserialize<D>(deserializer: &mut D) -> Result<Self, D::Error> where D: Deserializer {
+        let source = try!(String::deserialize(deserializer));
+        Pattern::new(&source).map_err(D::Error::custom)
+    }
+}
+
+/// A bound of an `Interval`.
+#[derive(Clone, Debug)]
+struct IntervalBound {
+    value: Value,
+    inclusive: bool,
+}
+
+impl IntervalBound {
+    fn inclusive(value: &Value) -> Option<Self> {
+        Some(IntervalBound {
+            value: value.clone(),
+            inclusive: true
+        })
+    }
+
+    fn exclusive(value: &Value) -> Option<Self> {
+        Some(IntervalBound {
+            value: value.clone(),
+            inclusive: false
+        })
+    }
+}
+
+/// An interval of values, used to compare ranges. A `None` bound means that the interval is
+/// unbounded on that side.
+#[derive(Clone, Debug)]
+struct Interval {
+    min: Option<IntervalBound>,
+    max: Option<IntervalBound>,
+}
+
+impl Interval {
+    fn new(min: Option<IntervalBound>, max: Option<IntervalBound>) -> Self {
+        Interval {
+            min: min,
+            max: max
+        }
+    }
+
+    /// Compare two lower bounds. `Less` if `a` accepts more values than `b`.
+    fn cmp_min(a: &Option<IntervalBound>, b: &Option<IntervalBound>) -> Option<Ordering> {
+        match (a, b) {
+            (&None, &None) => Some(Ordering::Equal),
+            (&None, &Some(_)) => Some(Ordering::Less),
+            (&Some(_), &None) => Some(Ordering::Greater),
+            (&Some(ref a), &Some(ref b)) => {
+                match a.value.partial_cmp(&b.value) {
+                    Some(Ordering::Equal) => Some(b.inclusive.cmp(&a.inclusive)),
+                    other => other
+                }
+            }
+        }
+    }
+
+    /// Compare two upper bounds. `Greater` if `a` accepts more values than `b`.
+    fn cmp_max(a: &Option<IntervalBound>, b: &Option<IntervalBound>) -> Option<Ordering> {
+        match (a, b) {
+            (&None, &None) => Some(Ordering::Equal),
+            (&None, &Some(_)) => Some(Ordering::Greater),
+            (&Some(_), &None) => Some(Ordering::Less),
+            (&Some(ref a), &Some(ref b)) => {
+                match a.value.partial_cmp(&b.value) {
+                    Some(Ordering::Equal) => Some(a.inclusive.cmp(&b.inclusive)),
+                    other => other
+                }
+            }
+        }
+    }
+
+    /// `Some(true)` if there is no gap between an interval ending with `max` and an interval
+    /// starting with `min`, `None` if the bounds cannot be compared.
+    fn touches(max: &Option<IntervalBound>, min: &Option<IntervalBound>) -> Option<bool> {
+        match (max, min) {
+            (&Some(ref max), &Some(ref min)) => {
+                match max.value.partial_cmp(&min.value) {
+                    Some(Ordering::Greater) => Some(true),
+                    Some(Ordering::Equal) => Some(max.inclusive || min.inclusive),
+                    Some(Ordering::Less) => Some(false),
+                    None => None
+                }
+            }
+            _ => Some(true)
+        }
+    }
+
+    fn includes(&self, other: &Interval) -> bool {
+        Self::cmp_min(&self.min, &other.min).map_or(false, |order| order != Ordering::Greater) &&
+        Self::cmp_max(&self.max, &other.max).map_or(false, |order| order != Ordering::Less)
+    }
+
+    /// The union of two intervals, if it is an interval.
+    fn union(&self, other: &Interval) -> Option<Interval> {
+        if Self::touches(&self.max, &other.min) != Some(true) ||
+           Self::touches(&other.max, &self.min) != Some(true) {
+            return None;
+        }
+        let min = match Self::cmp_min(&self.min, &other.min) {
+            None => return None,
+            Some(Ordering::Greater) => other.min.clone(),
+            Some(_) => self.min.clone()
+        };
+        let max = match Self::cmp_max(&self.max, &other.max) {
+            None => return None,
+            Some(Ordering::Less) => other.max.clone(),
+            Some(_) => self.max.clone()
+        };
+        Some(Interval::new(min, max))
+    }
+
+    /// The intersection of two intervals, if it is not empty.
+    fn intersection(&self, other: &Interval) -> Option<Interval> {
+        let min = match Self::cmp_min(&self.min, &other.min) {
+            None => return None,
+            Some(Ordering::Less) => other.min.clone(),
+            Some(_) => self.min.clone()
+        };
+        let max = match Self::cmp_max(&self.max, &other.max) {
+            None => return None,
+            Some(Ordering::Greater) => other.max.clone(),
+            Some(_) => self.max.clone()
+        };
+        if Self::touches(&max, &min) != Some(true) {
+            return None;
+        }
+        if let (&Some(ref min), &Some(ref max)) = (&min, &max) {
+            if min.value == max.value && !(min.inclusive && max.inclusive) {
+                return None;
+            }
+        }
+        Some(Interval::new(min, max))
+    }
+
+    /// The plain range accepting exactly the values of this interval, if any.
+    fn to_range(&self) -> Option<Range> {
+        match (&self.min, &self.max) {
+            (&Some(ref min), &Some(ref max)) if min.inclusive && max.inclusive => {
+                if min.value == max.value {
+                    Some(Range::Eq(min.value.clone()))
+                } else {
+                    Some(Range::BetweenEq {
+                        min: min.value.clone(),
+                        max: max.value.clone()
                     })
                 }
             }
+            (&None, &Some(ref max)) if max.inclusive => Some(Range::Leq(max.value.clone())),
+            (&Some(ref min), &None) if min.inclusive => Some(Range::Geq(min.value.clone())),
+            _ => None
         }
     }
 }
 
+/// Follows successive values of a getter to determine when they enter or exit a range.
+/// Required for ranges that contain a `Range::Hysteresis`, at any depth, but works with
+/// all ranges.
+#[derive(Clone, Debug)]
+pub struct RangeTracker {
+    range: Range,
+    is_in: bool,
+
+    /// For each `Range::Hysteresis` in `range`, in depth-first order, `true` if the
+    /// latest value is in that range.
+    memory: Vec<bool>,
+}
+
+impl RangeTracker {
+    /// Start tracking. Values are initially considered out of the range.
+    pub fn new(range: Range) -> Self {
+        RangeTracker {
+            range: range,
+            is_in: false,
+            memory: vec![],
+        }
+    }
+
+    /// Evaluate `range` for `value`, updating the memory of all `Range::Hysteresis`.
+    ///
+    /// All branches are evaluated, to ensure that each `Range::Hysteresis` is always
+    /// assigned the same slot in `memory`.
+    fn evaluate(range: &Range, value: &Value, memory: &mut Vec<bool>, slot: &mut usize) -> bool {
+        match *range {
+            Range::Hysteresis { ref enter, ref exit } => {
+                let index = *slot;
+                *slot += 1;
+                if memory.len() <= index {
+                    memory.push(false);
+                }
+                let enters = Self::evaluate(enter, value, memory, slot);
+                let exits = Self::evaluate(exit, value, memory, slot);
+                let is_in = if memory[index] { !exits } else { enters };
+                memory[index] = is_in;
+                is_in
+            }
+            Range::And(ref ranges) => ranges.iter().fold(true, |acc, range| {
+                Self::evaluate(range, value, memory, slot) && acc
+            }),
+            Range::Or(ref ranges) => ranges.iter().fold(false, |acc, range| {
+                Self::evaluate(range, value, memory, slot) || acc
+            }),
+            Range::Not(ref range) => !Self::evaluate(range, value, memory, slot),
+            Range::Dwell { ref range, .. } => Self::evaluate(range, value, memory, slot),
+            _ => range.contains(value)
+        }
+    }
+
+    /// The range against which values are tracked.
+    pub fn range(&self) -> &Range {
+        &self.range
+    }
+
+    /// `true` if the latest value is in the range.
+    pub fn is_in(&self) -> bool {
+        self.is_in
+    }
+
+    /// Take a new value into account. Returns `Some(true)` if the value has just entered
+    /// the range, `Some(false)` if it has just exited the range, `None` otherwise.
+    pub fn update(&mut self, value: &Value) -> Option<bool> {
+        let is_in = Self::evaluate(&self.range, value, &mut self.memory, &mut 0);
+        if is_in == self.is_in {
+            None
+        } else {
+            self.is_in = is_in;
+            Some(is_in)
+        }
+    }
+}
 
 /// A duration, also used to represent a time of day.
 ///
@@ -1921,6 +4633,36 @@ impl Range {
 #[derive(Clone, Debug, PartialOrd, Ord, PartialEq, Eq)]
 pub struct Duration(ChronoDuration);
 
+impl Duration {
+    /// A duration of `s` seconds.
+    pub fn from_s(s: i64) -> Self {
+        Duration(ChronoDuration::seconds(s))
+    }
+
+    /// Add two durations, or `None` if the result cannot be represented.
+    pub fn checked_add(&self, other: &Duration) -> Option<Duration> {
+        self.0.checked_add(&other.0).map(Duration)
+    }
+
+    /// Subtract two durations, or `None` if the result cannot be represented.
+    pub fn checked_sub(&self, other: &Duration) -> Option<Duration> {
+        self.0.checked_sub(&other.0).map(Duration)
+    }
+
+    /// Multiply a duration by a factor, with millisecond precision, or `None`
+    /// if the result cannot be represented.
+    pub fn checked_mul(&self, factor: f64) -> Option<Duration> {
+        use std::i64;
+        let ms = self.0.num_milliseconds() as f64 * factor;
+        // Note that `i64::MAX as f64` is rounded up, hence the strict comparison.
+        if ms.is_nan() || ms < i64::MIN as f64 || ms >= i64::MAX as f64 {
+            None
+        } else {
+            Some(Duration(ChronoDuration::milliseconds(ms as i64)))
+        }
+    }
+}
+
 impl Parser<Duration> for Duration {
     fn description() -> String {
         "Duration".to_owned()
@@ -0,0 +1,220 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+///! This is the database that holds durable watches, alongside tags.
+///! Each watch is stored under its name, with its targets and options.
+
+use api::{ TargetMap, Targetted, WatchOptions };
+use migration::IdMigration;
+use parse::{ Parser, ToJSON };
+use selector::GetterSelector;
+use util::{ Exactly, Id, WatchId };
+use values::Range;
+
+use rusqlite::{ Connection, Result };
+use serde_json;
+use std::path::PathBuf;
+
+/// The targets of a watch, in a format that serde knows how to (de)serialize.
+type StoredTargets = Vec<(Vec<GetterSelector>, Exactly<Range>)>;
+
+/// A durable watch, as stored in the database.
+pub struct StoredWatch {
+    pub name: Id<WatchId>,
+    pub watch: TargetMap<GetterSelector, Exactly<Range>>,
+    pub options: WatchOptions,
+}
+
+/// A lightweight struct to manage the database. Creating these objects is very cheap because the
+/// underlying database is created lazily when we need it.
+pub struct WatchStorage {
+    db: Option<Connection>,
+    path: PathBuf,
+}
+
+impl WatchStorage {
+    pub fn new(path: &PathBuf) -> Self {
+        WatchStorage {
+            db: None,
+            path: path.clone()
+        }
+    }
+
+    // Ensures that we have a database ready. If we fail to open or create the database,
+    // this will panic.
+    fn ensure_db(&mut self) {
+        if self.db.is_some() {
+            return;
+        }
+
+        info!("Opening taxonomy watches database at {}", self.path.display());
+        let db = Connection::open(self.path.clone()).unwrap_or_else(|err| {
+            panic!("Unable to open taxonomy watches database: {}", err);
+        });
+
+        Self::create_table(&db).unwrap_or_else(|err| {
+            panic!("Unable to create taxonomy watches database: {}", err);
+        });
+
+        self.db = Some(db);
+    }
+
+    /// Create the table of watches, if it doesn't exist yet.
+    pub fn create_table(db: &Connection) -> Result<()> {
+        try!(db.execute("CREATE TABLE IF NOT EXISTS watches (
+                    name    TEXT NOT NULL PRIMARY KEY,
+                    watch   TEXT NOT NULL,
+                    options TEXT NOT NULL
+            )", &[]));
+        Ok(())
+    }
+
+    /// Rewrite the selectors of the watches that mention services or getters renamed by
+    /// `migration`. Returns the names of the watches that have been rewritten.
+    ///
+    /// This doesn't start a transaction, see `migration::migrate_storage`.
+    pub fn migrate(db: &Connection, migration: &IdMigration) -> Result<Vec<Id<WatchId>>> {
+        let mut rewritten = Vec::new();
+        {
+            let mut stmt = try!(db.prepare("SELECT name, watch FROM watches"));
+            let rows = try!(stmt.query(&[]));
+            for result_row in rows {
+                let row = try!(result_row);
+                let name = row.get::<String>(0);
+                let mut targets: StoredTargets = match serde_json::from_str(&row.get::<String>(1)) {
+                    Ok(targets) => targets,
+                    Err(err) => {
+                        error!("Cannot decode the targets of durable watch {}: {}", name, err);
+                        continue;
+                    }
+                };
+                let mut changed = false;
+                for &mut (ref mut selectors, _) in &mut targets {
+                    for selector in selectors.iter_mut() {
+                        changed |= migration.migrate_getter_selector(selector);
+                    }
+                }
+                if changed {
+                    rewritten.push((name, targets));
+                }
+            }
+        }
+        let mut names = Vec::with_capacity(rewritten.len());
+        for (name, targets) in rewritten {
+            // Serialization only fails for maps with non-string keys, which we do not have.
+            let watch = serde_json::to_string(&targets).unwrap();
+            try!(db.execute("UPDATE watches SET watch=$1 WHERE name=$2", &[&watch, &name]));
+            names.push(Id::<WatchId>::new(&name));
+        }
+        Ok(names)
+    }
+
+    pub fn put_watch(&mut self, name: &Id<WatchId>, watch: &TargetMap<GetterSelector, Exactly<Range>>,
+        options: &WatchOptions) -> Result<()>
+    {
+        self.ensure_db();
+        let targets: StoredTargets = watch.iter()
+            .map(|target| (target.select.clone(), target.payload.clone()))
+            .collect();
+        // Serialization only fails for maps with non-string keys, which we do not have.
+        let watch = serde_json::to_string(&targets).unwrap();
+        let options = serde_json::to_string(&options.to_json()).unwrap();
+        try!(self.db.as_ref().unwrap().execute("INSERT OR REPLACE INTO watches VALUES ($1, $2, $3)",
+                        &[&name.to_string(), &watch, &options]));
+        Ok(())
+    }
+
+    pub fn remove_watch(&mut self, name: &Id<WatchId>) -> Result<()> {
+        self.ensure_db();
+        try!(self.db.as_ref().unwrap().execute("DELETE FROM watches WHERE name=$1", &[&name.to_string()]));
+        Ok(())
+    }
+
+    /// Return all the watches that can be decoded. Watches that cannot be decoded, e.g. because
+    /// they were stored by an incompatible version, are skipped.
+    pub fn get_watches(&mut self) -> Result<Vec<StoredWatch>> {
+        self.ensure_db();
+        let mut watches = Vec::new();
+        let mut stmt = try!(self.db.as_ref().unwrap().prepare("SELECT name, watch, options FROM watches"));
+        let rows = try!(stmt.query(&[]));
+        for result_row in rows {
+            let row = try!(result_row);
+            let name = Id::<WatchId>::new(&row.get::<String>(0));
+            let targets: StoredTargets = match serde_json::from_str(&row.get::<String>(1)) {
+                Ok(targets) => targets,
+                Err(err) => {
+                    error!("Cannot decode the targets of durable watch {}: {}", name, err);
+                    continue;
+                }
+            };
+            let options = match WatchOptions::from_str(&row.get::<String>(2)) {
+                Ok(options) => options,
+                Err(err) => {
+                    error!("Cannot decode the options of durable watch {}: {:?}", name, err);
+                    continue;
+                }
+            };
+            watches.push(StoredWatch {
+                name: name,
+                watch: targets.into_iter()
+                    .map(|(select, payload)| Targetted::new(select, payload))
+                    .collect(),
+                options: options,
+            });
+        }
+        Ok(watches)
+    }
+}
+
+#[test]
+#[allow(unused_variables)]
+fn storage_test() {
+    use tag_storage::{ get_db_environment, remove_test_db };
+    use values::{ Temperature, Value };
+
+    // Simple RAII style struct to delete the test db.
+    struct AutoDeleteDb { };
+    impl Drop for AutoDeleteDb {
+        fn drop(&mut self) {
+            remove_test_db();
+        }
+    }
+    let auto_db = AutoDeleteDb { };
+
+    let mut store = WatchStorage::new(&get_db_environment());
+
+    let name = Id::<WatchId>::new("watch 1");
+    let hot = Range::Geq(Value::Temperature(Temperature::C(30.)));
+
+    // Start with an empty db.
+    assert_eq!(store.get_watches().unwrap().len(), 0);
+
+    // Store a watch.
+    store.put_watch(&name, &vec![Targetted::new(vec![GetterSelector::new()], Exactly::Exactly(hot.clone()))],
+        &WatchOptions::default()).unwrap();
+    let watches = store.get_watches().unwrap();
+    assert_eq!(watches.len(), 1);
+    assert_eq!(watches[0].name, name);
+    assert_eq!(watches[0].options, WatchOptions::default());
+    assert_eq!(watches[0].watch.len(), 1);
+    match watches[0].watch[0].payload {
+        Exactly::Exactly(ref range) => assert_eq!(*range, hot),
+        ref other => panic!("Unexpected payload {:?}", other)
+    }
+
+    // Storing a watch with the same name replaces it.
+    store.put_watch(&name, &vec![Targetted::new(vec![GetterSelector::new()], Exactly::Always)],
+        &WatchOptions { initial_state: true, .. WatchOptions::default() }).unwrap();
+    let watches = store.get_watches().unwrap();
+    assert_eq!(watches.len(), 1);
+    assert!(watches[0].options.initial_state);
+    match watches[0].watch[0].payload {
+        Exactly::Always => {},
+        ref other => panic!("Unexpected payload {:?}", other)
+    }
+
+    // Remove the watch.
+    store.remove_watch(&name).unwrap();
+    assert_eq!(store.get_watches().unwrap().len(), 0);
+}
@@ -0,0 +1,82 @@
+//! A plugin used by `tests/test_plugin.rs`.
+//!
+//! Its adapter spawns no thread, so the plugin may be unloaded as soon as the adapter is
+//! stopped. Identifiers are long enough not to be stored inline, to check that they are
+//! received as strings by the host.
+
+#![cfg_attr(feature = "wrong_version", allow(dead_code))]
+
+#[macro_use]
+extern crate foxbox_taxonomy;
+
+use foxbox_taxonomy::adapter::{ Adapter, AdapterManagerHandle, AdapterWatchGuard, ResultMap, WatchResult, WatchTarget };
+use foxbox_taxonomy::api::{ Error, User };
+use foxbox_taxonomy::plugin::{ PluginHost, PluginMetadata };
+use foxbox_taxonomy::services::*;
+use foxbox_taxonomy::values::Value;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+static VERSION: [u32; 4] = [1, 2, 3, 4];
+
+struct TestAdapter {
+    id: Id<AdapterId>,
+}
+
+struct TestWatchGuard;
+impl AdapterWatchGuard for TestWatchGuard {}
+
+impl Adapter for TestAdapter {
+    fn id(&self) -> Id<AdapterId> {
+        self.id.clone()
+    }
+
+    fn name(&self) -> &str {
+        "Test plugin adapter"
+    }
+
+    fn vendor(&self) -> &str {
+        "test@foxlink"
+    }
+
+    fn version(&self) -> &[u32;4] {
+        &VERSION
+    }
+
+    fn fetch_values(&self, set: Vec<Id<Getter>>, _: User) -> ResultMap<Id<Getter>, Option<Value>, Error> {
+        set.into_iter().map(|id| (id, Ok(None))).collect()
+    }
+
+    fn send_values(&self, values: HashMap<Id<Setter>, Value>, _: User) -> ResultMap<Id<Setter>, (), Error> {
+        values.into_iter().map(|(id, _)| (id, Ok(()))).collect()
+    }
+
+    fn register_watch(&self, mut watch: Vec<WatchTarget>) -> WatchResult {
+        watch.drain(..).map(|(id, _, _)| {
+            (id, Ok(Box::new(TestWatchGuard) as Box<AdapterWatchGuard>))
+        }).collect()
+    }
+}
+
+fn create(host: &PluginHost) -> Result<PluginMetadata, Error> {
+    let id = Id::new("test_plugin_adapter");
+    try!(host.add_adapter(Arc::new(TestAdapter {
+        id: id.clone()
+    })));
+    // The adapter is in place, so we may register its services.
+    try!(host.manager().add_service(Service::empty(Id::new("test_plugin_service"), id)));
+    Ok(PluginMetadata {
+        name: "Test plugin".to_owned(),
+        version: VERSION,
+    })
+}
+
+#[cfg(not(feature = "wrong_version"))]
+declare_adapter_plugin!(create);
+
+#[cfg(feature = "wrong_version")]
+#[no_mangle]
+pub extern "C" fn foxbox_taxonomy_plugin_version() -> *const u8 {
+    b"0.0.0\0".as_ptr()
+}
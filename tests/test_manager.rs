@@ -1,12 +1,16 @@
 extern crate foxbox_taxonomy;
+extern crate chrono;
 extern crate libc;
 extern crate transformable_channels;
 #[macro_use]
 extern crate assert_matches;
 
+use foxbox_taxonomy::adapter_utils::*;
 use foxbox_taxonomy::manager::*;
+use foxbox_taxonomy::migration::IdMigration;
 use foxbox_taxonomy::fake_adapter::*;
-use foxbox_taxonomy::api::{ API, Error, InternalError, TargetMap, Targetted, User, WatchEvent as Event };
+use foxbox_taxonomy::api::{ API, DiscoveryEvent, Error, InternalError, StampedEvent, TargetMap, Targetted, User, WatchEvent as Event, WatchOptions };
+use foxbox_taxonomy::parse::{ Parser, ToJSON };
 use foxbox_taxonomy::selector::*;
 use foxbox_taxonomy::services::*;
 use foxbox_taxonomy::values::*;
@@ -15,7 +19,7 @@ use transformable_channels::mpsc::*;
 
 use std::collections::{ HashMap, HashSet };
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{ Arc, Mutex };
 use std::thread;
 
 // Trivial utility function to convert the old TargetMap format to the newer one, to avoid
@@ -82,6 +86,8 @@ fn test_tags_in_db() {
         mechanism: Getter {
             updated: None,
             kind: ChannelKind::LightOn,
+            constraints: Constraints::default(),
+            capabilities: GetterCapabilities::default(),
         },
     };
 
@@ -94,6 +100,8 @@ fn test_tags_in_db() {
         mechanism: Setter {
             updated: None,
             kind: ChannelKind::LightOn,
+            constraints: Constraints::default(),
+            capabilities: SetterCapabilities::default(),
         },
     };
 
@@ -311,6 +319,8 @@ fn test_add_remove_services() {
             mechanism: Getter {
                 updated: None,
                 kind: ChannelKind::LightOn,
+                constraints: Constraints::default(),
+                capabilities: GetterCapabilities::default(),
             },
         };
 
@@ -323,6 +333,8 @@ fn test_add_remove_services() {
             mechanism: Setter {
                 updated: None,
                 kind: ChannelKind::LightOn,
+                constraints: Constraints::default(),
+                capabilities: SetterCapabilities::default(),
             },
         };
 
@@ -335,6 +347,8 @@ fn test_add_remove_services() {
             mechanism: Getter {
                 updated: None,
                 kind: ChannelKind::LightOn,
+                constraints: Constraints::default(),
+                capabilities: GetterCapabilities::default(),
             },
         };
 
@@ -347,6 +361,8 @@ fn test_add_remove_services() {
             mechanism: Setter {
                 updated: None,
                 kind: ChannelKind::LightOn,
+                constraints: Constraints::default(),
+                capabilities: SetterCapabilities::default(),
             },
         };
 
@@ -378,6 +394,8 @@ fn test_add_remove_services() {
             mechanism: Getter {
                 updated: None,
                 kind: ChannelKind::LightOn,
+                constraints: Constraints::default(),
+                capabilities: GetterCapabilities::default(),
             },
         };
 
@@ -390,6 +408,8 @@ fn test_add_remove_services() {
             mechanism: Setter {
                 updated: None,
                 kind: ChannelKind::LightOn,
+                constraints: Constraints::default(),
+                capabilities: SetterCapabilities::default(),
             },
         };
 
@@ -623,6 +643,8 @@ fn test_add_remove_tags() {
             mechanism: Getter {
                 updated: None,
                 kind: ChannelKind::LightOn,
+                constraints: Constraints::default(),
+                capabilities: GetterCapabilities::default(),
             },
         };
 
@@ -635,6 +657,8 @@ fn test_add_remove_tags() {
             mechanism: Setter {
                 updated: None,
                 kind: ChannelKind::LightOn,
+                constraints: Constraints::default(),
+                capabilities: SetterCapabilities::default(),
             },
         };
 
@@ -656,6 +680,8 @@ fn test_add_remove_tags() {
             mechanism: Getter {
                 updated: None,
                 kind: ChannelKind::LightOn,
+                constraints: Constraints::default(),
+                capabilities: GetterCapabilities::default(),
             },
         };
 
@@ -668,6 +694,8 @@ fn test_add_remove_tags() {
             mechanism: Setter {
                 updated: None,
                 kind: ChannelKind::LightOn,
+                constraints: Constraints::default(),
+                capabilities: SetterCapabilities::default(),
             },
         };
 
@@ -985,6 +1013,8 @@ fn test_fetch() {
             mechanism: Getter {
                 updated: None,
                 kind: ChannelKind::LightOn,
+                constraints: Constraints::default(),
+                capabilities: GetterCapabilities::default(),
             },
         };
 
@@ -997,6 +1027,8 @@ fn test_fetch() {
             mechanism: Getter {
                 updated: None,
                 kind: ChannelKind::LightOn,
+                constraints: Constraints::default(),
+                capabilities: GetterCapabilities::default(),
             },
         };
 
@@ -1009,6 +1041,8 @@ fn test_fetch() {
             mechanism: Getter {
                 updated: None,
                 kind: ChannelKind::LightOn,
+                constraints: Constraints::default(),
+                capabilities: GetterCapabilities::default(),
             },
         };
 
@@ -1021,6 +1055,8 @@ fn test_fetch() {
             mechanism: Getter {
                 updated: None,
                 kind: ChannelKind::LightOn,
+                constraints: Constraints::default(),
+                capabilities: GetterCapabilities::default(),
             },
         };
 
@@ -1182,6 +1218,8 @@ fn test_send() {
             mechanism: Setter {
                 kind: ChannelKind::LightOn,
                 updated: None,
+                constraints: Constraints::default(),
+                capabilities: SetterCapabilities::default(),
             },
         };
 
@@ -1194,6 +1232,8 @@ fn test_send() {
             mechanism: Setter {
                 kind: ChannelKind::LightOn,
                 updated: None,
+                constraints: Constraints::default(),
+                capabilities: SetterCapabilities::default(),
             },
         };
 
@@ -1206,6 +1246,8 @@ fn test_send() {
             mechanism: Setter {
                 kind: ChannelKind::LightOn,
                 updated: None,
+                constraints: Constraints::default(),
+                capabilities: SetterCapabilities::default(),
             },
         };
 
@@ -1218,6 +1260,8 @@ fn test_send() {
             mechanism: Setter {
                 kind: ChannelKind::LightOn,
                 updated: None,
+                constraints: Constraints::default(),
+                capabilities: SetterCapabilities::default(),
             },
         };
 
@@ -1277,8 +1321,10 @@ fn test_send() {
         println!("* All the values should have been received.");
         let mut data = HashMap::new();
         for _ in 0..3 {
-            let Effect::ValueSent(id, value) = rx_adapter_1.try_recv().unwrap();
-            data.insert(id, value);
+            match rx_adapter_1.try_recv().unwrap() {
+                Effect::ValueSent(id, value) => data.insert(id, value),
+                effect => panic!("Unexpected effect {:?}", effect)
+            };
         }
         assert_eq!(data.len(), 3);
 
@@ -1410,6 +1456,8 @@ fn test_watch() {
             mechanism: Getter {
                 updated: None,
                 kind: ChannelKind::LightOn,
+                constraints: Constraints::default(),
+                capabilities: GetterCapabilities::default(),
             },
         };
 
@@ -1422,6 +1470,8 @@ fn test_watch() {
             mechanism: Getter {
                 updated: None,
                 kind: ChannelKind::LightOn,
+                constraints: Constraints::default(),
+                capabilities: GetterCapabilities::default(),
             },
         };
 
@@ -1434,6 +1484,8 @@ fn test_watch() {
             mechanism: Getter {
                 updated: None,
                 kind: ChannelKind::LightOn,
+                constraints: Constraints::default(),
+                capabilities: GetterCapabilities::default(),
             },
         };
 
@@ -1446,6 +1498,8 @@ fn test_watch() {
             mechanism: Getter {
                 updated: None,
                 kind: ChannelKind::LightOn,
+                constraints: Constraints::default(),
+                capabilities: GetterCapabilities::default(),
             },
         };
 
@@ -1458,6 +1512,8 @@ fn test_watch() {
             mechanism: Getter {
                 updated: None,
                 kind: ChannelKind::LightOn,
+                constraints: Constraints::default(),
+                capabilities: GetterCapabilities::default(),
             },
         };
 
@@ -1691,3 +1747,2088 @@ fn test_watch() {
 
     println!("");
 }
+
+#[test]
+fn test_extension_enum() {
+    println!("");
+
+    let manager = AdapterManager::new(None);
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let setter_id_1 = Id::<Setter>::new("setter id 1");
+
+    let kind = ChannelKind::Extension {
+        vendor: Id::new("mozilla.org"),
+        adapter: id_1.clone(),
+        kind: Id::new("HVACMode"),
+        typ: Type::ExtEnum,
+        variants: vec!["Heat".to_owned(), "Cool".to_owned(), "Off".to_owned()],
+    };
+    let mode = |value: &str| {
+        Value::ExtEnum(ExtValue {
+            value: value.to_owned(),
+            vendor: Id::new("mozilla.org"),
+            adapter: id_1.clone(),
+            kind: Id::new("HVACMode"),
+        })
+    };
+
+    let adapter = FakeAdapter::new(&id_1);
+    let tweak = adapter.get_tweak();
+    let rx_adapter = adapter.take_rx();
+    manager.add_adapter(Arc::new(adapter)).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    manager.add_getter(Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: kind.clone(),
+            constraints: Constraints::default(),
+            capabilities: GetterCapabilities::default(),
+        },
+    }).unwrap();
+    manager.add_setter(Channel {
+        id: setter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            updated: None,
+            kind: kind.clone(),
+            constraints: Constraints::default(),
+            capabilities: SetterCapabilities::default(),
+        },
+    }).unwrap();
+
+    println!("* Sending a declared variant succeeds.");
+    let data = manager.send_values(target_map(vec![(vec![SetterSelector::new()], mode("Cool"))]), User::None);
+    assert_matches!(data.get(&setter_id_1), Some(&Ok(())));
+    match rx_adapter.try_recv().unwrap() {
+        Effect::ValueSent(ref id, Value::ExtEnum(ref ext)) if *id == setter_id_1 && ext.value == "Cool" => {},
+        effect => panic!("Unexpected effect {:?}", effect)
+    }
+
+    println!("* Sending an undeclared variant fails before reaching the adapter.");
+    let data = manager.send_values(target_map(vec![(vec![SetterSelector::new()], mode("Dry"))]), User::None);
+    assert_matches!(data.get(&setter_id_1), Some(&Err(Error::InvalidValue(_))));
+    assert_matches!(rx_adapter.try_recv(), Err(_));
+
+    println!("* Sending a variant of another extension fails before reaching the adapter.");
+    let other = Value::ExtEnum(ExtValue {
+        value: "Cool".to_owned(),
+        vendor: Id::new("example.org"),
+        adapter: id_1.clone(),
+        kind: Id::new("HVACMode"),
+    });
+    let data = manager.send_values(target_map(vec![(vec![SetterSelector::new()], other)]), User::None);
+    assert_matches!(data.get(&setter_id_1), Some(&Err(Error::InvalidValue(_))));
+    assert_matches!(rx_adapter.try_recv(), Err(_));
+
+    println!("* Sending a value of another type fails with a type error.");
+    let data = manager.send_values(target_map(vec![(vec![SetterSelector::new()], Value::OnOff(OnOff::On))]), User::None);
+    assert_matches!(data.get(&setter_id_1), Some(&Err(Error::TypeError(_))));
+    assert_matches!(rx_adapter.try_recv(), Err(_));
+
+    println!("* Fetching a declared variant succeeds.");
+    tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(mode("Heat")))));
+    let data = manager.fetch_values(vec![GetterSelector::new()], User::None);
+    assert_matches!(data.get(&getter_id_1), Some(&Ok(Some(Value::ExtEnum(_)))));
+
+    println!("* Fetching an undeclared variant fails.");
+    tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(mode("Dry")))));
+    let data = manager.fetch_values(vec![GetterSelector::new()], User::None);
+    assert_matches!(data.get(&getter_id_1), Some(&Err(Error::InvalidValue(_))));
+
+    println!("");
+}
+
+#[test]
+fn test_setter_constraints() {
+    println!("");
+
+    let manager = AdapterManager::new(None);
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let setter_id_1 = Id::<Setter>::new("setter id 1");
+    let setter_id_2 = Id::<Setter>::new("setter id 2");
+
+    let celsius = |degrees| Value::Temperature(Temperature::C(degrees));
+    let constraints = Constraints {
+        min: Some(celsius(50.)),
+        max: Some(celsius(250.)),
+        step: Some(celsius(5.)),
+        allowed: vec![],
+    };
+
+    let adapter = FakeAdapter::new(&id_1);
+    let rx_adapter = adapter.take_rx();
+    manager.add_adapter(Arc::new(adapter)).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+
+    println!("* Constraints of the wrong type are rejected at registration.");
+    let result = manager.add_setter(Channel {
+        id: setter_id_2.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            updated: None,
+            kind: ChannelKind::LightOn,
+            constraints: constraints.clone(),
+            capabilities: SetterCapabilities::default(),
+        },
+    });
+    assert_matches!(result, Err(Error::TypeError(_)));
+
+    manager.add_setter(Channel {
+        id: setter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            updated: None,
+            kind: ChannelKind::OvenTemperature,
+            constraints: constraints.clone(),
+            capabilities: SetterCapabilities::default(),
+        },
+    }).unwrap();
+
+    println!("* Constraints are exposed in the channel JSON.");
+    let setters = manager.get_setter_channels(vec![SetterSelector::new()]);
+    assert_eq!(setters.len(), 1);
+    let json = setters[0].to_json();
+    assert!(json.find_path(&["constraints", "step", "Temperature", "C"]).is_some());
+
+    println!("* Sending a value that satisfies the constraints succeeds.");
+    let data = manager.send_values(target_map(vec![(vec![SetterSelector::new()], celsius(180.))]), User::None);
+    assert_matches!(data.get(&setter_id_1), Some(&Ok(())));
+    assert_matches!(rx_adapter.try_recv().unwrap(), Effect::ValueSent(_, Value::Temperature(Temperature::C(180.))));
+
+    println!("* Sending a value out of bounds fails before reaching the adapter.");
+    let data = manager.send_values(target_map(vec![(vec![SetterSelector::new()], celsius(300.))]), User::None);
+    assert_matches!(data.get(&setter_id_1), Some(&Err(Error::RangeError(_))));
+    assert_matches!(rx_adapter.try_recv(), Err(_));
+
+    println!("* Sending a value that is not on a step fails before reaching the adapter.");
+    let data = manager.send_values(target_map(vec![(vec![SetterSelector::new()], celsius(182.))]), User::None);
+    assert_matches!(data.get(&setter_id_1), Some(&Err(Error::InvalidValue(_))));
+    assert_matches!(rx_adapter.try_recv(), Err(_));
+
+    println!("");
+}
+
+#[test]
+fn test_watch_hysteresis() {
+    println!("");
+
+    let manager = AdapterManager::new(None);
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+
+    let celsius = |degrees| Value::Temperature(Temperature::C(degrees));
+
+    let adapter = FakeAdapter::new(&id_1);
+    let tweak = adapter.get_tweak();
+    manager.add_adapter(Arc::new(adapter)).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    manager.add_getter(Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::OvenTemperature,
+            constraints: Constraints::default(),
+            capabilities: GetterCapabilities::default(),
+        },
+    }).unwrap();
+
+    println!("* Watching with separate enter/exit thresholds.");
+    let (tx_watch, rx_watch) = channel();
+    let _guard = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Exactly(Range::Hysteresis {
+            enter: Box::new(Range::Geq(celsius(25.))),
+            exit: Box::new(Range::Leq(celsius(24.))),
+        })
+    )]), Box::new(tx_watch));
+
+    println!("* A value hovering around a threshold only causes one EnterRange and one ExitRange.");
+    for value in vec![24.5, 25.2, 24.8, 25.1, 24.6, 25.3, 23.9, 24.5, 23.5] {
+        tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(celsius(value)))));
+    }
+    match rx_watch.recv().unwrap() {
+        Event::EnterRange { ref from, value: Value::Temperature(Temperature::C(25.2)) } if *from == getter_id_1 => {},
+        other => panic!("Unexpected event {:?}", other)
+    }
+    match rx_watch.recv().unwrap() {
+        Event::ExitRange { ref from, value: Value::Temperature(Temperature::C(23.9)) } if *from == getter_id_1 => {},
+        other => panic!("Unexpected event {:?}", other)
+    }
+
+    println!("* Make sure that we haven't forgotten to eat a message.");
+    thread::sleep(std::time::Duration::new(1, 0));
+    assert_matches!(rx_watch.try_recv(), Err(_));
+
+    println!("");
+}
+
+#[test]
+fn test_watch_composite() {
+    println!("");
+
+    let manager = AdapterManager::new(None);
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+
+    let celsius = |degrees| Value::Temperature(Temperature::C(degrees));
+
+    let adapter = FakeAdapter::new(&id_1);
+    let tweak = adapter.get_tweak();
+    manager.add_adapter(Arc::new(adapter)).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    manager.add_getter(Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::OvenTemperature,
+            constraints: Constraints::default(),
+            capabilities: GetterCapabilities::default(),
+        },
+    }).unwrap();
+
+    println!("* Watching for values below 10 or above 30 but not exactly 20.");
+    let (tx_watch, rx_watch) = channel();
+    let _guard = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Exactly(Range::And(vec![
+            Range::Or(vec![
+                Range::Leq(celsius(10.)),
+                Range::Geq(celsius(30.)),
+            ]),
+            Range::Not(Box::new(Range::Eq(celsius(20.))))
+        ]))
+    )]), Box::new(tx_watch));
+
+    for value in vec![20., 5., 8., 20., 35., 25.] {
+        tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(celsius(value)))));
+    }
+
+    println!("* The manager emits EnterRange/ExitRange for the composite range.");
+    for expected in vec![(true, 5.), (false, 20.), (true, 35.), (false, 25.)] {
+        match (expected, rx_watch.recv().unwrap()) {
+            ((true, expected), Event::EnterRange { value: Value::Temperature(Temperature::C(value)), .. }) |
+            ((false, expected), Event::ExitRange { value: Value::Temperature(Temperature::C(value)), .. })
+                if value == expected => {},
+            (_, other) => panic!("Unexpected event {:?}", other)
+        }
+    }
+
+    println!("* Make sure that we haven't forgotten to eat a message.");
+    thread::sleep(std::time::Duration::new(1, 0));
+    assert_matches!(rx_watch.try_recv(), Err(_));
+
+    println!("");
+}
+
+#[test]
+fn test_watch_dwell() {
+    println!("");
+
+    let manager = AdapterManager::new(None);
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+
+    let adapter = FakeAdapter::new(&id_1);
+    let tweak = adapter.get_tweak();
+    manager.add_adapter(Arc::new(adapter)).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    manager.add_getter(Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::OpenClosed,
+            constraints: Constraints::default(),
+            capabilities: GetterCapabilities::default(),
+        },
+    }).unwrap();
+
+    println!("* Watching for a door that remains open for some time.");
+    let (tx_watch, rx_watch) = channel();
+    let _guard = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Exactly(Range::Dwell {
+            range: Box::new(Range::Eq(Value::OpenClosed(OpenClosed::Open))),
+            duration: Duration::from(chrono::Duration::milliseconds(500)),
+        })
+    )]), Box::new(tx_watch));
+
+    println!("* Exiting the range before the dwell time has elapsed cancels the event.");
+    tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OpenClosed(OpenClosed::Open)))));
+    tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OpenClosed(OpenClosed::Closed)))));
+    thread::sleep(std::time::Duration::new(1, 0));
+    assert_matches!(rx_watch.try_recv(), Err(_));
+
+    println!("* Remaining in the range for the dwell time fires EnterRange.");
+    tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OpenClosed(OpenClosed::Open)))));
+    match rx_watch.recv().unwrap() {
+        Event::EnterRange { ref from, value: Value::OpenClosed(OpenClosed::Open) } if *from == getter_id_1 => {},
+        other => panic!("Unexpected event {:?}", other)
+    }
+
+    println!("* Exiting the range then fires ExitRange immediately.");
+    tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OpenClosed(OpenClosed::Closed)))));
+    match rx_watch.recv().unwrap() {
+        Event::ExitRange { ref from, value: Value::OpenClosed(OpenClosed::Closed) } if *from == getter_id_1 => {},
+        other => panic!("Unexpected event {:?}", other)
+    }
+
+    println!("* Make sure that we haven't forgotten to eat a message.");
+    thread::sleep(std::time::Duration::new(1, 0));
+    assert_matches!(rx_watch.try_recv(), Err(_));
+
+    println!("");
+}
+
+#[test]
+fn test_watch_string_predicates() {
+    println!("");
+
+    let manager = AdapterManager::new(None);
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+
+    let string = |s: &str| Value::String(Arc::new(s.to_owned()));
+
+    let adapter = FakeAdapter::new(&id_1);
+    let tweak = adapter.get_tweak();
+    manager.add_adapter(Arc::new(adapter)).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    manager.add_getter(Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::Log,
+            constraints: Constraints::default(),
+            capabilities: GetterCapabilities::default(),
+        },
+    }).unwrap();
+
+    println!("* Watching for error messages in a log.");
+    let (tx_watch, rx_watch) = channel();
+    let _guard = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Exactly(Range::Or(vec![
+            Range::StartsWith("error:".to_owned()),
+            Range::Matches(Pattern::new("^\\[\\w+\\] fail").unwrap()),
+        ]))
+    )]), Box::new(tx_watch));
+
+    for message in vec!["ok", "error: disk full", "error: disk still full", "ok", "[sensor] failure", "ok"] {
+        tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(string(message)))));
+    }
+
+    println!("* The manager emits EnterRange/ExitRange as messages start or stop matching.");
+    for expected in vec![(true, "error: disk full"), (false, "ok"), (true, "[sensor] failure"), (false, "ok")] {
+        match (expected, rx_watch.recv().unwrap()) {
+            ((true, expected), Event::EnterRange { value: Value::String(ref value), .. }) |
+            ((false, expected), Event::ExitRange { value: Value::String(ref value), .. })
+                if **value == expected => {},
+            (_, other) => panic!("Unexpected event {:?}", other)
+        }
+    }
+
+    println!("* Make sure that we haven't forgotten to eat a message.");
+    thread::sleep(std::time::Duration::new(1, 0));
+    assert_matches!(rx_watch.try_recv(), Err(_));
+
+    println!("");
+}
+
+#[test]
+fn test_watch_changes() {
+    println!("");
+
+    let manager = AdapterManager::new(None);
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+
+    let celsius = |degrees| Value::Temperature(Temperature::C(degrees));
+
+    let adapter = FakeAdapter::new(&id_1);
+    let tweak = adapter.get_tweak();
+    manager.add_adapter(Arc::new(adapter)).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    manager.add_getter(Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::CurrentTemperature,
+            constraints: Constraints::default(),
+            capabilities: GetterCapabilities::default(),
+        },
+    }).unwrap();
+
+    println!("* Watching for changes, and for changes of more than 0.5C, expressed in JSON.");
+    let (tx_changed, rx_changed) = channel();
+    let _guard_changed = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::<Range>::from_str("{\"Changed\": null}").unwrap()
+    )]), Box::new(tx_changed));
+
+    let (tx_delta, rx_delta) = channel();
+    let _guard_delta = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::<Range>::from_str("{\"Delta\": {\"Temperature\": {\"C\": 0.5}}}").unwrap()
+    )]), Box::new(tx_delta));
+
+    for degrees in vec![20., 20., 20.2, 20.4, 20.4, 21., 20.8, 20.] {
+        tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(celsius(degrees)))));
+    }
+
+    println!("* The first value is always delivered, then only changes.");
+    for expected in vec![20., 20.2, 20.4, 21., 20.8, 20.] {
+        match rx_changed.recv().unwrap() {
+            Event::EnterRange { value, .. } => assert_eq!(value, celsius(expected)),
+            other => panic!("Unexpected event {:?}", other)
+        }
+    }
+
+    println!("* Deltas are measured from the latest value delivered.");
+    for expected in vec![20., 21., 20.] {
+        match rx_delta.recv().unwrap() {
+            Event::EnterRange { value, .. } => assert_eq!(value, celsius(expected)),
+            other => panic!("Unexpected event {:?}", other)
+        }
+    }
+
+    println!("* Make sure that we haven't forgotten to eat a message.");
+    thread::sleep(std::time::Duration::new(1, 0));
+    assert_matches!(rx_changed.try_recv(), Err(_));
+    assert_matches!(rx_delta.try_recv(), Err(_));
+
+    println!("");
+}
+
+#[test]
+fn test_watch_initial_state() {
+    println!("");
+
+    let manager = AdapterManager::new(None);
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let getter_id_2 = Id::<Getter>::new("getter id 2");
+
+    let celsius = |degrees| Value::Temperature(Temperature::C(degrees));
+    let hot = Range::Geq(celsius(30.));
+
+    let adapter = FakeAdapter::new(&id_1);
+    let tweak = adapter.get_tweak();
+    manager.add_adapter(Arc::new(adapter)).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    for id in vec![&getter_id_1, &getter_id_2] {
+        manager.add_getter(Channel {
+            id: id.clone(),
+            service: service_id_1.clone(),
+            adapter: id_1.clone(),
+            last_seen: None,
+            tags: HashSet::new(),
+            mechanism: Getter {
+                updated: None,
+                kind: ChannelKind::CurrentTemperature,
+                constraints: Constraints::default(),
+                capabilities: GetterCapabilities::default(),
+            },
+        }).unwrap();
+    }
+    tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(celsius(35.)))));
+
+    println!("* Without the option, we receive nothing until the value changes.");
+    let (tx_plain, rx_plain) = channel();
+    let _guard_plain = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Exactly(hot.clone())
+    )]), Box::new(tx_plain));
+    thread::sleep(std::time::Duration::new(1, 0));
+    assert_matches!(rx_plain.try_recv(), Err(_));
+
+    println!("* With the option, we receive the initial state of each getter, fetched from the adapter.");
+    let options = WatchOptions::from_str("{\"initial_state\": true}").unwrap();
+    let (tx_watch, rx_watch) = channel();
+    let _guard = manager.watch_values_with_options(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Exactly(hot.clone())
+    )]), options.clone(), Box::new(tx_watch));
+
+    let events : HashMap<_, _> = (0..2).map(|_| {
+        match rx_watch.recv().unwrap().event {
+            Event::InitialState { from, value, is_in_range } => (from, (value, is_in_range)),
+            other => panic!("Unexpected event {:?}", other)
+        }
+    }).collect();
+    assert_eq!(events.get(&getter_id_1), Some(&(Some(celsius(35.)), true)));
+    assert_eq!(events.get(&getter_id_2), Some(&(None, false)));
+
+    println!("* Subsequent events are relative to the initial state.");
+    tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(celsius(20.)))));
+    match rx_watch.recv().unwrap().event {
+        Event::ExitRange { ref from, ref value } if *from == getter_id_1 && *value == celsius(20.) => {},
+        other => panic!("Unexpected event {:?}", other)
+    }
+
+    println!("* The initial state may also come from the latest value reported by the adapter.");
+    tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(celsius(40.)))));
+    match rx_watch.recv().unwrap().event {
+        Event::EnterRange { ref from, ref value } if *from == getter_id_1 && *value == celsius(40.) => {},
+        other => panic!("Unexpected event {:?}", other)
+    }
+    match rx_plain.recv().unwrap() {
+        Event::EnterRange { ref from, ref value } if *from == getter_id_1 && *value == celsius(40.) => {},
+        other => panic!("Unexpected event {:?}", other)
+    }
+    let (tx_late, rx_late) = channel();
+    let _guard_late = manager.watch_values_with_options(target_map(vec![(
+        vec![GetterSelector::new().with_id(getter_id_1.clone())],
+        Exactly::Exactly(hot.clone())
+    )]), options, Box::new(tx_late));
+    match rx_late.recv().unwrap().event {
+        Event::InitialState { ref from, value: Some(ref value), is_in_range: true }
+            if *from == getter_id_1 && *value == celsius(40.) => {},
+        other => panic!("Unexpected event {:?}", other)
+    }
+
+    println!("* Make sure that we haven't forgotten to eat a message.");
+    thread::sleep(std::time::Duration::new(1, 0));
+    assert_matches!(rx_plain.try_recv(), Err(_));
+    assert_matches!(rx_watch.try_recv(), Err(_));
+    assert_matches!(rx_late.try_recv(), Err(_));
+
+    println!("");
+}
+
+#[test]
+fn test_watch_pacing() {
+    println!("");
+
+    let manager = AdapterManager::new(None);
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+
+    let celsius = |degrees| Value::Temperature(Temperature::C(degrees));
+
+    let adapter = FakeAdapter::new(&id_1);
+    let tweak = adapter.get_tweak();
+    manager.add_adapter(Arc::new(adapter)).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    manager.add_getter(Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::CurrentTemperature,
+            constraints: Constraints::default(),
+            capabilities: GetterCapabilities::default(),
+        },
+    }).unwrap();
+
+    println!("* Watching a chatty getter with throttling and debouncing.");
+    let (tx_throttled, rx_throttled) = channel();
+    let _guard_throttled = manager.watch_values_with_options(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Always
+    )]), WatchOptions {
+        min_interval: Some(Duration::from_s(1)),
+        coalesce: true,
+        .. WatchOptions::default()
+    }, Box::new(tx_throttled));
+
+    let (tx_debounced, rx_debounced) = channel();
+    let _guard_debounced = manager.watch_values_with_options(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Always
+    )]), WatchOptions {
+        debounce: Some(Duration::from_s(1)),
+        .. WatchOptions::default()
+    }, Box::new(tx_debounced));
+
+    let (tx_queued, rx_queued) = channel();
+    let guard_queued = manager.watch_values_with_options(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Always
+    )]), WatchOptions {
+        min_interval: Some(Duration::from_s(1)),
+        .. WatchOptions::default()
+    }, Box::new(tx_queued));
+
+    for degrees in 1..71 {
+        tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(celsius(degrees as f64)))));
+    }
+
+    println!("* Throttling sends the first value immediately, then the latest one once the interval has elapsed.");
+    for expected in vec![1., 70.] {
+        match rx_throttled.recv().unwrap().event {
+            Event::EnterRange { value, .. } => assert_eq!(value, celsius(expected)),
+            other => panic!("Unexpected event {:?}", other)
+        }
+    }
+
+    println!("* Debouncing only sends the latest value, once the getter has calmed down.");
+    match rx_debounced.recv().unwrap().event {
+        Event::EnterRange { value, .. } => assert_eq!(value, celsius(70.)),
+        other => panic!("Unexpected event {:?}", other)
+    }
+
+    println!("* Throttling without coalescing keeps at most 64 delayed events, and reports the ones it drops.");
+    match rx_queued.recv().unwrap().event {
+        Event::EnterRange { value, .. } => assert_eq!(value, celsius(1.)),
+        other => panic!("Unexpected event {:?}", other)
+    }
+    // Values 2 to 6 have been dropped.
+    assert_matches!(rx_queued.recv().unwrap().event, Event::ReplayTruncated { .. });
+    match rx_queued.recv().unwrap().event {
+        Event::EnterRange { value, .. } => assert_eq!(value, celsius(7.)),
+        other => panic!("Unexpected event {:?}", other)
+    }
+    drop(guard_queued);
+
+    println!("* Make sure that we haven't forgotten to eat a message.");
+    thread::sleep(std::time::Duration::new(2, 0));
+    assert_matches!(rx_throttled.try_recv(), Err(_));
+    assert_matches!(rx_debounced.try_recv(), Err(_));
+
+    println!("");
+}
+
+#[test]
+fn test_watch_resume() {
+    println!("");
+
+    let manager = AdapterManager::new(None);
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+
+    let celsius = |degrees| Value::Temperature(Temperature::C(degrees));
+    let hot = Range::Geq(celsius(30.));
+
+    let adapter = FakeAdapter::new(&id_1);
+    let tweak = adapter.get_tweak();
+    manager.add_adapter(Arc::new(adapter)).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    manager.add_getter(Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::CurrentTemperature,
+            constraints: Constraints::default(),
+            capabilities: GetterCapabilities::default(),
+        },
+    }).unwrap();
+
+    println!("* Another watcher keeps the getter watched throughout the test.");
+    let (tx_all, _rx_all) = channel();
+    let _guard_all = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Always
+    )]), Box::new(tx_all));
+
+    println!("* Events are numbered.");
+    let (tx_watch, rx_watch) = channel();
+    let guard = manager.watch_values_with_options(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Exactly(hot.clone())
+    )]), WatchOptions::default(), Box::new(tx_watch));
+
+    tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(celsius(35.)))));
+    let last_seen = match rx_watch.recv().unwrap() {
+        StampedEvent { sequence, event: Event::EnterRange { ref value, .. }, .. } if *value == celsius(35.) => sequence,
+        other => panic!("Unexpected event {:?}", other)
+    };
+
+    println!("* Values keep coming while the watcher is disconnected.");
+    drop(guard);
+    for degrees in vec![20., 40., 45.] {
+        tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(celsius(degrees)))));
+    }
+
+    println!("* Resuming replays the missed events, then live delivery continues.");
+    let (tx_resumed, rx_resumed) = channel();
+    let _guard_resumed = manager.watch_values_with_options(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Exactly(hot.clone())
+    )]), WatchOptions {
+        resume_from: Some(last_seen),
+        .. WatchOptions::default()
+    }, Box::new(tx_resumed));
+    tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(celsius(10.)))));
+
+    let mut previous = last_seen;
+    for (is_enter, expected) in vec![(false, 20.), (true, 40.), (false, 10.)] {
+        let stamped = rx_resumed.recv().unwrap();
+        assert!(stamped.sequence > previous);
+        previous = stamped.sequence;
+        match (is_enter, stamped.event) {
+            (true, Event::EnterRange { value, .. }) |
+            (false, Event::ExitRange { value, .. }) => assert_eq!(value, celsius(expected)),
+            (_, other) => panic!("Unexpected event {:?}", other)
+        }
+    }
+
+    println!("* Make sure that we haven't forgotten to eat a message.");
+    thread::sleep(std::time::Duration::new(1, 0));
+    assert_matches!(rx_resumed.try_recv(), Err(_));
+
+    println!("");
+}
+
+#[test]
+#[allow(unused_variables)]
+fn test_durable_watch() {
+    println!("");
+
+    // Simple RAII style struct to delete the test db.
+    struct AutoDeleteDb { };
+    impl Drop for AutoDeleteDb {
+        fn drop(&mut self) {
+            remove_test_db();
+        }
+    }
+    let auto_db = AutoDeleteDb { };
+
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let watch_id_1 = Id::<WatchId>::new("watch id 1");
+
+    let celsius = |degrees| Value::Temperature(Temperature::C(degrees));
+    let getter_1 = Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::CurrentTemperature,
+            constraints: Constraints::default(),
+            capabilities: GetterCapabilities::default(),
+        },
+    };
+
+    println!("* Registering a durable watch before any getter exists.");
+    {
+        let manager = AdapterManager::new(Some(get_db_environment()));
+        manager.register_durable_watch(watch_id_1.clone(), target_map(vec![(
+            vec![GetterSelector::new().with_kind(ChannelKind::CurrentTemperature)],
+            Exactly::Exactly(Range::Geq(celsius(30.)))
+        )]), WatchOptions::default()).unwrap();
+        manager.stop();
+    }
+
+    println!("* After a restart, the watch is reinstated once the getter appears.");
+    {
+        let manager = AdapterManager::new(Some(get_db_environment()));
+        let adapter = FakeAdapter::new(&id_1);
+        let tweak = adapter.get_tweak();
+        manager.add_adapter(Arc::new(adapter)).unwrap();
+        manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+        manager.add_getter(getter_1.clone()).unwrap();
+
+        println!("* Events are held until a consumer attaches.");
+        tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(celsius(35.)))));
+        thread::sleep(std::time::Duration::new(1, 0));
+
+        let (tx, rx) = channel();
+        manager.attach_durable_watch(&watch_id_1, Box::new(tx)).unwrap();
+        assert_matches!(rx.recv().unwrap().event, Event::GetterAdded(ref id) if *id == getter_id_1);
+        assert_matches!(rx.recv().unwrap().event, Event::EnterRange { ref from, ref value }
+            if *from == getter_id_1 && *value == celsius(35.));
+
+        println!("* Once attached, events are delivered live.");
+        tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(celsius(20.)))));
+        assert_matches!(rx.recv().unwrap().event, Event::ExitRange { ref value, .. } if *value == celsius(20.));
+
+        println!("* A consumer that hangs up is replaced by the next one.");
+        drop(rx);
+        tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(celsius(40.)))));
+        thread::sleep(std::time::Duration::new(1, 0));
+        let (tx, rx) = channel();
+        manager.attach_durable_watch(&watch_id_1, Box::new(tx)).unwrap();
+        assert_matches!(rx.recv().unwrap().event, Event::EnterRange { ref value, .. } if *value == celsius(40.));
+
+        println!("* Removing the watch.");
+        manager.remove_durable_watch(&watch_id_1).unwrap();
+        let (tx, _) = channel();
+        assert_matches!(manager.attach_durable_watch(&watch_id_1, Box::new(tx)),
+            Err(Error::InternalError(InternalError::NoSuchWatch(ref id))) if *id == watch_id_1);
+        manager.stop();
+    }
+
+    println!("* After another restart, the watch is gone.");
+    {
+        let manager = AdapterManager::new(Some(get_db_environment()));
+        let (tx, _) = channel();
+        assert_matches!(manager.attach_durable_watch(&watch_id_1, Box::new(tx)),
+            Err(Error::InternalError(InternalError::NoSuchWatch(_))));
+        manager.stop();
+    }
+
+    println!("");
+}
+
+#[test]
+fn test_watch_polling() {
+    println!("");
+
+    let manager = AdapterManager::new(None);
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let getter_id_2 = Id::<Getter>::new("getter id 2");
+
+    let celsius = |degrees| Value::Temperature(Temperature::C(degrees));
+
+    println!("* An adapter that cannot watch its getter, wrapped to poll it instead.");
+    let adapter = FakeAdapter::new(&id_1);
+    let tweak = adapter.get_tweak();
+    tweak(Tweak::InjectWatchError(getter_id_1.clone(), Some(Error::GetterDoesNotSupportWatching(getter_id_1.clone()))));
+    tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(celsius(20.)))));
+    let adapter = PollingAdapter::new(Arc::new(adapter), std::time::Duration::from_secs(60))
+        .with_getter_interval(getter_id_1.clone(), std::time::Duration::from_millis(100))
+        .with_getter_interval(getter_id_2.clone(), std::time::Duration::from_millis(100));
+    manager.add_adapter(Arc::new(adapter)).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    manager.add_getter(Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::CurrentTemperature,
+            constraints: Constraints::default(),
+            capabilities: GetterCapabilities::default(),
+        },
+    }).unwrap();
+
+    println!("* Several watchers of the same getter.");
+    let (tx_hot, rx_hot) = channel();
+    let _guard_hot = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Exactly(Range::Geq(celsius(30.)))
+    )]), Box::new(tx_hot));
+    let (tx_all, rx_all) = channel();
+    let _guard_all = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Always
+    )]), Box::new(tx_all));
+    for rx in vec![&rx_hot, &rx_all] {
+        assert_matches!(rx.recv().unwrap(), Event::GetterAdded(ref id) if *id == getter_id_1);
+    }
+
+    println!("* Values are polled and compared with the ranges.");
+    tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(celsius(35.)))));
+    assert_matches!(rx_hot.recv().unwrap(), Event::EnterRange { ref value, .. } if *value == celsius(35.));
+    assert_matches!(rx_all.recv().unwrap(), Event::EnterRange { ref value, .. } if *value == celsius(35.));
+
+    tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(celsius(10.)))));
+    assert_matches!(rx_hot.recv().unwrap(), Event::ExitRange { ref value, .. } if *value == celsius(10.));
+    assert_matches!(rx_all.recv().unwrap(), Event::EnterRange { ref value, .. } if *value == celsius(10.));
+
+    println!("* Getters that declare that they cannot be watched are polled as well.");
+    tweak(Tweak::InjectWatchError(getter_id_2.clone(), Some(Error::GetterDoesNotSupportWatching(getter_id_2.clone()))));
+    tweak(Tweak::InjectGetterValue(getter_id_2.clone(), Ok(Some(celsius(20.)))));
+    manager.add_getter(Channel {
+        id: getter_id_2.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::CurrentTemperature,
+            constraints: Constraints::default(),
+            capabilities: GetterCapabilities { watchable: false, .. GetterCapabilities::default() },
+        },
+    }).unwrap();
+    for rx in vec![&rx_hot, &rx_all] {
+        assert_matches!(rx.recv().unwrap(), Event::GetterAdded(ref id) if *id == getter_id_2);
+    }
+    thread::sleep(std::time::Duration::from_millis(500));
+    tweak(Tweak::InjectGetterValue(getter_id_2.clone(), Ok(Some(celsius(40.)))));
+    assert_matches!(rx_hot.recv().unwrap(), Event::EnterRange { ref from, ref value } if *from == getter_id_2 && *value == celsius(40.));
+    assert_matches!(rx_all.recv().unwrap(), Event::EnterRange { ref from, ref value } if *from == getter_id_2 && *value == celsius(40.));
+
+    println!("* Make sure that we haven't forgotten to eat a message.");
+    thread::sleep(std::time::Duration::new(1, 0));
+    assert_matches!(rx_hot.try_recv(), Err(_));
+    assert_matches!(rx_all.try_recv(), Err(_));
+
+    println!("* Once all the watchers are dropped, a new watcher starts a new poll.");
+    drop(_guard_hot);
+    drop(_guard_all);
+    let (tx_cold, rx_cold) = channel();
+    let _guard_cold = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new().with_id(getter_id_1.clone())],
+        Exactly::Exactly(Range::Leq(celsius(0.)))
+    )]), Box::new(tx_cold));
+    assert_matches!(rx_cold.recv().unwrap(), Event::GetterAdded(ref id) if *id == getter_id_1);
+    tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(celsius(-5.)))));
+    assert_matches!(rx_cold.recv().unwrap(), Event::EnterRange { ref from, ref value } if *from == getter_id_1 && *value == celsius(-5.));
+    assert_matches!(rx_hot.try_recv(), Err(_));
+    assert_matches!(rx_all.try_recv(), Err(_));
+
+    println!("");
+}
+
+#[test]
+fn test_discovery() {
+    println!("");
+
+    let manager = AdapterManager::new(None);
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let device_id_1 = Id::<DeviceId>::new("device id 1");
+
+    let device_1 = PendingDevice {
+        id: device_id_1.clone(),
+        adapter: id_1.clone(),
+        name: "Thermometer".to_owned(),
+        pairing: PairingMethod::Pin,
+        properties: HashMap::new(),
+    };
+
+    let adapter = FakeAdapter::new(&id_1);
+    let tweak = adapter.get_tweak();
+    let rx_effect = adapter.take_rx();
+    manager.add_adapter(Arc::new(adapter)).unwrap();
+
+    println!("* Starting discovery.");
+    let (tx, rx) = channel();
+    let results = manager.start_discovery(Box::new(tx));
+    assert_eq!(results.len(), 1);
+    assert_matches!(results.get(&id_1), Some(&Ok(())));
+    assert_matches!(rx.recv().unwrap(), DiscoveryEvent::Started { ref adapter } if *adapter == id_1);
+
+    println!("* Finding a device.");
+    tweak(Tweak::InjectPendingDevice(device_1.clone(), Some(Credentials::Pin("1234".to_owned()))));
+    assert_matches!(rx.recv().unwrap(), DiscoveryEvent::DeviceFound(ref device) if device.id == device_id_1);
+    let pending = manager.get_pending_devices();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].id, device_id_1);
+    assert_eq!(pending[0].pairing, PairingMethod::Pin);
+
+    println!("* Pairing with the wrong PIN fails.");
+    assert_matches!(manager.pair_device(&id_1, &device_id_1, Some(Credentials::Pin("0000".to_owned()))),
+        Err(Error::InvalidCredentials(ref id)) if *id == device_id_1);
+    assert_matches!(rx.recv().unwrap(), DiscoveryEvent::PairingFailed { ref device, error: Error::InvalidCredentials(_), .. }
+        if *device == device_id_1);
+    assert_eq!(manager.get_pending_devices().len(), 1);
+
+    println!("* Pairing with the right PIN succeeds.");
+    manager.pair_device(&id_1, &device_id_1, Some(Credentials::Pin("1234".to_owned()))).unwrap();
+    assert_matches!(rx.recv().unwrap(), DiscoveryEvent::Paired { ref adapter, ref device }
+        if *adapter == id_1 && *device == device_id_1);
+    assert_matches!(rx_effect.recv().unwrap(), Effect::Paired(ref id) if *id == device_id_1);
+    assert_eq!(manager.get_pending_devices().len(), 0);
+
+    println!("* Pairing with an unknown adapter fails.");
+    assert_matches!(manager.pair_device(&Id::new("unknown adapter"), &device_id_1, None),
+        Err(Error::InternalError(InternalError::NoSuchAdapter(_))));
+
+    println!("* Unpairing a service.");
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    manager.unpair_service(&service_id_1).unwrap();
+    assert_matches!(rx.recv().unwrap(), DiscoveryEvent::Unpaired { ref adapter, ref service }
+        if *adapter == id_1 && *service == service_id_1);
+    assert_matches!(rx_effect.recv().unwrap(), Effect::Unpaired(ref id) if *id == service_id_1);
+
+    println!("* Stopping discovery.");
+    manager.stop_discovery();
+    assert_matches!(rx.recv().unwrap(), DiscoveryEvent::Stopped { ref adapter } if *adapter == id_1);
+
+    println!("* Make sure that we haven't forgotten to eat a message.");
+    thread::sleep(std::time::Duration::new(1, 0));
+    assert_matches!(rx.try_recv(), Err(_));
+
+    manager.stop();
+    println!("");
+}
+
+#[test]
+fn test_adapter_status() {
+    println!("");
+
+    let manager = AdapterManager::new(None);
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let setter_id_1 = Id::<Setter>::new("setter id 1");
+
+    manager.add_adapter(Arc::new(FakeAdapter::new(&id_1))).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    manager.add_getter(Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::LightOn,
+            constraints: Constraints::default(),
+            capabilities: GetterCapabilities::default(),
+        },
+    }).unwrap();
+    manager.add_setter(Channel {
+        id: setter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            updated: None,
+            kind: ChannelKind::LightOn,
+            constraints: Constraints::default(),
+            capabilities: SetterCapabilities::default(),
+        },
+    }).unwrap();
+
+    let fetch = || manager.fetch_values(vec![GetterSelector::new()], User::None);
+    let send = || manager.send_values(target_map(vec![(vec![SetterSelector::new()], Value::OnOff(OnOff::On))]), User::None);
+
+    println!("* Adapters are online once added.");
+    let statuses = manager.get_adapter_statuses();
+    assert_eq!(statuses.len(), 1);
+    assert_eq!(statuses[0].adapter, id_1);
+    assert_eq!(statuses[0].state, AdapterState::Online);
+    assert_eq!(statuses[0].reason, None);
+
+    println!("* Changes of status are reported to watchers.");
+    let (tx, rx) = channel();
+    manager.watch_adapter_statuses(Box::new(tx));
+    manager.set_adapter_status(&id_1, AdapterState::Offline, Some("USB dongle unplugged".to_owned())).unwrap();
+    let status = rx.recv().unwrap();
+    assert_eq!(status.adapter, id_1);
+    assert_eq!(status.state, AdapterState::Offline);
+    assert_eq!(status.reason, Some("USB dongle unplugged".to_owned()));
+    assert_eq!(manager.get_adapter_statuses()[0].state, AdapterState::Offline);
+
+    println!("* Operations on the channels of an offline adapter fail without reaching it.");
+    assert_matches!(fetch().get(&getter_id_1), Some(&Err(Error::AdapterOffline(ref id))) if *id == id_1);
+    assert_matches!(send().get(&setter_id_1), Some(&Err(Error::AdapterOffline(ref id))) if *id == id_1);
+
+    println!("* Reporting the same status again is not a change.");
+    manager.set_adapter_status(&id_1, AdapterState::Offline, Some("USB dongle unplugged".to_owned())).unwrap();
+
+    println!("* Once the adapter is back online, operations reach it again.");
+    manager.set_adapter_status(&id_1, AdapterState::Online, None).unwrap();
+    assert_matches!(rx.recv().unwrap().state, AdapterState::Online);
+    assert_matches!(fetch().get(&getter_id_1), Some(&Ok(None)));
+    assert_matches!(send().get(&setter_id_1), Some(&Ok(())));
+
+    println!("* Reporting the status of an unknown adapter fails.");
+    assert_matches!(manager.set_adapter_status(&Id::new("unknown adapter"), AdapterState::Offline, None),
+        Err(Error::InternalError(InternalError::NoSuchAdapter(_))));
+
+    println!("* Make sure that we haven't forgotten to eat a message.");
+    thread::sleep(std::time::Duration::new(1, 0));
+    assert_matches!(rx.try_recv(), Err(_));
+
+    manager.stop();
+    println!("");
+}
+
+#[test]
+#[allow(unused_variables)]
+fn test_adapter_config() {
+    println!("");
+
+    // Simple RAII style struct to delete the test db.
+    struct AutoDeleteDb { };
+    impl Drop for AutoDeleteDb {
+        fn drop(&mut self) {
+            remove_test_db();
+        }
+    }
+    let auto_db = AutoDeleteDb { };
+
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let string = |s: &str| Value::String(Arc::new(s.to_owned()));
+    let schema = vec![
+        ConfigField {
+            name: "address".to_owned(),
+            typ: Type::String,
+            description: "The address of the bridge".to_owned(),
+            default: None,
+            is_required: true,
+            is_secret: false,
+        },
+        ConfigField {
+            name: "username".to_owned(),
+            typ: Type::String,
+            description: "The user registered with the bridge".to_owned(),
+            default: Some(string("foxbox")),
+            is_required: false,
+            is_secret: false,
+        },
+        ConfigField {
+            name: "password".to_owned(),
+            typ: Type::String,
+            description: "The password of the user".to_owned(),
+            default: None,
+            is_required: true,
+            is_secret: true,
+        },
+    ];
+
+    {
+        let manager = AdapterManager::new(Some(get_db_environment()));
+        let adapter = FakeAdapter::new(&id_1);
+        let tweak = adapter.get_tweak();
+        let rx_adapter_effect = adapter.take_rx();
+        tweak(Tweak::InjectConfigSchema(schema.clone()));
+        manager.add_adapter(Arc::new(adapter)).unwrap();
+
+        println!("* The schema is exposed through the API.");
+        let fields = manager.get_adapter_config_schema(&id_1).unwrap();
+        assert_eq!(fields.len(), 3);
+        assert_eq!(fields[0].name, "address");
+        assert!(fields[2].is_secret);
+
+        println!("* An adapter that has never been configured has an empty configuration.");
+        assert_eq!(manager.get_adapter_config(&id_1).unwrap().len(), 0);
+
+        println!("* Configurations that do not match the schema are rejected.");
+        let mut config = AdapterConfig::new();
+        config.insert("address".to_owned(), Value::OnOff(OnOff::On));
+        assert_matches!(manager.set_adapter_config(&id_1, config), Err(Error::TypeError(_)));
+
+        let mut config = AdapterConfig::new();
+        config.insert("token".to_owned(), string("secret"));
+        assert_matches!(manager.set_adapter_config(&id_1, config),
+            Err(Error::UnknownConfigField(ref name)) if name == "token");
+
+        assert_matches!(manager.set_adapter_config(&id_1, AdapterConfig::new()),
+            Err(Error::MissingConfigField(ref name)) if name == "address");
+
+        println!("* Configurations rejected by the adapter are not recorded.");
+        let mut config = AdapterConfig::new();
+        config.insert("address".to_owned(), string("192.168.0.2"));
+        config.insert("password".to_owned(), string("hunter2"));
+        tweak(Tweak::InjectConfigError(Some(Error::InternalError(InternalError::GenericError("Bridge unreachable".to_owned())))));
+        assert_matches!(manager.set_adapter_config(&id_1, config.clone()), Err(Error::InternalError(_)));
+        assert_eq!(manager.get_adapter_config(&id_1).unwrap().len(), 0);
+
+        println!("* A valid configuration is completed with defaults and applied.");
+        tweak(Tweak::InjectConfigError(None));
+        manager.set_adapter_config(&id_1, config).unwrap();
+        match rx_adapter_effect.recv().unwrap() {
+            Effect::Configured(ref config) => {
+                assert_eq!(config.get("address"), Some(&string("192.168.0.2")));
+                assert_eq!(config.get("username"), Some(&string("foxbox")));
+                assert_eq!(config.get("password"), Some(&string("hunter2")));
+            }
+            effect => panic!("Unexpected effect {:?}", effect)
+        }
+        assert_eq!(manager.get_adapter_config(&id_1).unwrap().get("address"), Some(&string("192.168.0.2")));
+
+        println!("* Secret fields are not exposed, but are kept when the configuration is set again.");
+        let mut config = manager.get_adapter_config(&id_1).unwrap();
+        assert!(config.get("password").is_none());
+        config.insert("address".to_owned(), string("192.168.0.3"));
+        manager.set_adapter_config(&id_1, config).unwrap();
+        match rx_adapter_effect.recv().unwrap() {
+            Effect::Configured(ref config) => {
+                assert_eq!(config.get("address"), Some(&string("192.168.0.3")));
+                assert_eq!(config.get("password"), Some(&string("hunter2")));
+            }
+            effect => panic!("Unexpected effect {:?}", effect)
+        }
+
+        println!("* Configuring an unknown adapter fails.");
+        assert_matches!(manager.set_adapter_config(&Id::new("unknown adapter"), AdapterConfig::new()),
+            Err(Error::InternalError(InternalError::NoSuchAdapter(_))));
+        manager.stop();
+    }
+
+    println!("* After a restart, the configuration is applied again when the adapter is added.");
+    {
+        let manager = AdapterManager::new(Some(get_db_environment()));
+        let adapter = FakeAdapter::new(&id_1);
+        let tweak = adapter.get_tweak();
+        let rx_adapter_effect = adapter.take_rx();
+        tweak(Tweak::InjectConfigSchema(schema.clone()));
+        manager.add_adapter(Arc::new(adapter)).unwrap();
+        match rx_adapter_effect.recv().unwrap() {
+            Effect::Configured(ref config) => assert_eq!(config.get("address"), Some(&string("192.168.0.3"))),
+            effect => panic!("Unexpected effect {:?}", effect)
+        }
+        assert_eq!(manager.get_adapter_config(&id_1).unwrap().get("address"), Some(&string("192.168.0.3")));
+        manager.stop();
+    }
+
+    println!("");
+}
+
+#[test]
+fn test_adapter_supervision() {
+    println!("");
+
+    let manager = AdapterManager::new(None);
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let id_2 = Id::<AdapterId>::new("adapter id 2");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let service_id_2 = Id::<ServiceId>::new("service id 2");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let getter_id_2 = Id::<Getter>::new("getter id 2");
+
+    let getter = |id: &Id<Getter>, service: &Id<ServiceId>, adapter: &Id<AdapterId>| Channel {
+        id: id.clone(),
+        service: service.clone(),
+        adapter: adapter.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::LightOn,
+            constraints: Constraints::default(),
+            capabilities: GetterCapabilities::default(),
+        },
+    };
+    let fetch = |id: &Id<Getter>| manager.fetch_values(vec![GetterSelector::new().with_id(id.clone())], User::None);
+
+    let (tx_status, rx_status) = channel();
+    manager.watch_adapter_statuses(Box::new(tx_status));
+
+    println!("* Panics of an adapter are turned into errors on its channels.");
+    let adapter = FakeAdapter::new(&id_1);
+    let tweak = adapter.get_tweak();
+    manager.add_adapter(Arc::new(adapter)).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    manager.add_getter(getter(&getter_id_1, &service_id_1, &id_1)).unwrap();
+
+    tweak(Tweak::InjectPanic(true));
+    for _ in 0..3 {
+        assert_matches!(fetch(&getter_id_1).get(&getter_id_1), Some(&Err(Error::AdapterPanicked(ref id))) if *id == id_1);
+    }
+
+    println!("* An adapter that panics repeatedly is isolated.");
+    let status = rx_status.recv().unwrap();
+    assert_eq!(status.adapter, id_1);
+    assert_matches!(status.state, AdapterState::Faulted);
+    tweak(Tweak::InjectPanic(false));
+    assert_matches!(fetch(&getter_id_1).get(&getter_id_1), Some(&Err(Error::AdapterFaulted(ref id))) if *id == id_1);
+
+    println!("* An adapter added without a factory is not restarted automatically, but can be resumed through the API.");
+    thread::sleep(std::time::Duration::from_millis(100));
+    assert_matches!(rx_status.try_recv(), Err(_));
+    manager.restart_adapter(&id_1).unwrap();
+    let status = rx_status.recv().unwrap();
+    assert_eq!(status.adapter, id_1);
+    assert_matches!(status.state, AdapterState::Online);
+    tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(OnOff::On)))));
+    assert_matches!(fetch(&getter_id_1).get(&getter_id_1), Some(&Ok(Some(Value::OnOff(OnOff::On)))));
+
+    println!("* An adapter added with a factory is restarted once faulted.");
+    let tweaks = Arc::new(Mutex::new(vec![]));
+    let factory: AdapterFactory = {
+        let id_2 = id_2.clone();
+        let tweaks = tweaks.clone();
+        Arc::new(move || {
+            let adapter = FakeAdapter::new(&id_2);
+            tweaks.lock().unwrap().push(adapter.get_tweak());
+            Ok(Arc::new(adapter) as Arc<Adapter>)
+        })
+    };
+    manager.add_adapter_with_factory(factory).unwrap();
+    manager.add_service(Service::empty(service_id_2.clone(), id_2.clone())).unwrap();
+    manager.add_getter(getter(&getter_id_2, &service_id_2, &id_2)).unwrap();
+    assert_eq!(tweaks.lock().unwrap().len(), 1);
+
+    let (tx_watch, rx_watch) = channel();
+    let _guard = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new().with_id(getter_id_2.clone())],
+        Exactly::Always
+    )]), Box::new(tx_watch));
+
+    tweaks.lock().unwrap()[0](Tweak::InjectPanic(true));
+    for _ in 0..3 {
+        assert_matches!(fetch(&getter_id_2).get(&getter_id_2), Some(&Err(Error::AdapterPanicked(_))));
+    }
+    let status = rx_status.recv().unwrap();
+    assert_eq!(status.adapter, id_2);
+    assert_matches!(status.state, AdapterState::Faulted);
+    let status = rx_status.recv().unwrap();
+    assert_eq!(status.adapter, id_2);
+    assert_matches!(status.state, AdapterState::Online);
+    assert_eq!(tweaks.lock().unwrap().len(), 2);
+
+    println!("* The new instance takes over the services of the former instance.");
+    assert_eq!(manager.get_services(vec![ServiceSelector::new().with_id(service_id_2.clone())]).len(), 1);
+    assert_matches!(fetch(&getter_id_2).get(&getter_id_2), Some(&Ok(None)));
+
+    println!("* Ongoing watches are registered again with the new instance.");
+    tweaks.lock().unwrap()[1](Tweak::InjectGetterValue(getter_id_2.clone(), Ok(Some(Value::OnOff(OnOff::On)))));
+    match rx_watch.recv().unwrap() {
+        Event::EnterRange { ref from, .. } if *from == getter_id_2 => {}
+        other => panic!("Unexpected event {:?}", other)
+    }
+
+    println!("* The adapter can also be restarted through the API.");
+    manager.restart_adapter(&id_2).unwrap();
+    assert_eq!(tweaks.lock().unwrap().len(), 3);
+    let status = rx_status.recv().unwrap();
+    assert_eq!(status.adapter, id_2);
+    assert_matches!(status.state, AdapterState::Online);
+
+    println!("* Make sure that we haven't forgotten to eat a message.");
+    thread::sleep(std::time::Duration::new(1, 0));
+    assert_matches!(rx_status.try_recv(), Err(_));
+
+    manager.stop();
+    println!("");
+}
+
+#[test]
+fn test_proxy_adapter() {
+    use foxbox_taxonomy::proxy_adapter::ProxyAdapter;
+    use foxbox_taxonomy::proxy_child::{ serve, HostHandle };
+    use std::os::unix::net::UnixStream;
+
+    println!("");
+
+    let manager = Arc::new(AdapterManager::new(None));
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let getter_id_2 = Id::<Getter>::new("getter id 2");
+    let setter_id_1 = Id::<Setter>::new("setter id 1");
+
+    println!("* The child registers its services as soon as it starts.");
+    let (host_end, child_end) = UnixStream::pair().unwrap();
+    let adapter = FakeAdapter::new(&id_1);
+    let tweak = adapter.get_tweak();
+    let rx_adapter_effect = adapter.take_rx();
+    let host = HostHandle::new(Box::new(child_end.try_clone().unwrap()));
+    host.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    host.add_getter(Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::LightOn,
+            constraints: Constraints::default(),
+            capabilities: GetterCapabilities::default(),
+        },
+    }).unwrap();
+    host.add_setter(Channel {
+        id: setter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            updated: None,
+            kind: ChannelKind::LightOn,
+            constraints: Constraints::default(),
+            capabilities: SetterCapabilities::default(),
+        },
+    }).unwrap();
+    let child = {
+        let host = host.clone();
+        thread::spawn(move || serve(Arc::new(adapter), child_end, &host))
+    };
+
+    println!("* Once connected, the adapter and its services are registered with the manager.");
+    let proxy = ProxyAdapter::with_stream(&manager, host_end).unwrap();
+    assert_eq!(proxy.id(), id_1);
+    let services = manager.get_services(vec![ServiceSelector::new()]);
+    assert_eq!(services.len(), 1);
+    assert_eq!(services[0].getters.len(), 1);
+    assert_eq!(services[0].setters.len(), 1);
+
+    println!("* Fetching values goes through the pipe.");
+    let fetch = || manager.fetch_values(vec![GetterSelector::new()], User::None);
+    assert_matches!(fetch().get(&getter_id_1), Some(&Ok(None)));
+    tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(OnOff::On)))));
+    assert_matches!(fetch().get(&getter_id_1), Some(&Ok(Some(Value::OnOff(OnOff::On)))));
+    tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Err(Error::InternalError(InternalError::GenericError("Bulb unreachable".to_owned())))));
+    assert_matches!(fetch().get(&getter_id_1), Some(&Err(Error::InternalError(InternalError::GenericError(ref msg)))) if msg == "Bulb unreachable");
+
+    println!("* Sending values goes through the pipe.");
+    let sent = manager.send_values(target_map(vec![(vec![SetterSelector::new()], Value::OnOff(OnOff::Off))]), User::None);
+    assert_matches!(sent.get(&setter_id_1), Some(&Ok(())));
+    assert_matches!(rx_adapter_effect.recv().unwrap(), Effect::ValueSent(ref id, Value::OnOff(OnOff::Off)) if *id == setter_id_1);
+
+    println!("* Watch events go through the pipe.");
+    let (tx, rx) = channel();
+    let guard = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Exactly(Range::Eq(Value::OnOff(OnOff::On)))
+    )]), Box::new(tx));
+    assert_matches!(rx.recv().unwrap(), Event::GetterAdded(ref id) if *id == getter_id_1);
+    thread::sleep(std::time::Duration::new(1, 0));
+    tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(Value::OnOff(OnOff::On)))));
+    assert_matches!(rx.recv().unwrap(), Event::EnterRange { ref from, ref value }
+        if *from == getter_id_1 && *value == Value::OnOff(OnOff::On));
+
+    println!("* Getters registered by the child once a watch exists are watched through the pipe.");
+    host.add_getter(Channel {
+        id: getter_id_2.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::LightOn,
+            constraints: Constraints::default(),
+            capabilities: GetterCapabilities::default(),
+        },
+    }).unwrap();
+    assert_matches!(rx.recv().unwrap(), Event::GetterAdded(ref id) if *id == getter_id_2);
+    thread::sleep(std::time::Duration::new(1, 0));
+    tweak(Tweak::InjectGetterValue(getter_id_2.clone(), Ok(Some(Value::OnOff(OnOff::On)))));
+    assert_matches!(rx.recv().unwrap(), Event::EnterRange { ref from, ref value }
+        if *from == getter_id_2 && *value == Value::OnOff(OnOff::On));
+    assert_matches!(fetch().get(&getter_id_2), Some(&Ok(Some(Value::OnOff(OnOff::On)))));
+    drop(guard);
+
+    println!("* The child can report its status.");
+    host.set_adapter_status(&id_1, AdapterState::Degraded, Some("Bridge unreachable".to_owned())).unwrap();
+    thread::sleep(std::time::Duration::new(1, 0));
+    let statuses = manager.get_adapter_statuses();
+    assert_matches!(statuses[0].state, AdapterState::Degraded);
+
+    println!("* Once stopped, the child exits and the adapter is offline.");
+    drop(host);
+    proxy.stop();
+    child.join().unwrap();
+    thread::sleep(std::time::Duration::new(1, 0));
+    assert_matches!(manager.get_adapter_statuses()[0].state, AdapterState::Offline);
+    assert_matches!(fetch().get(&getter_id_1), Some(&Err(Error::AdapterOffline(_))));
+
+    manager.stop();
+    println!("");
+}
+
+#[test]
+fn test_proxy_adapter_spawn() {
+    use foxbox_taxonomy::proxy_adapter::{ ProxyAdapter, STOP_GRACE_SECS };
+    use std::process::Command;
+
+    println!("");
+
+    // The reference child is built by cargo as example `proxy_child`, next to the directory
+    // of this test.
+    let child_path = std::env::current_exe().unwrap()
+        .parent().unwrap()
+        .parent().unwrap()
+        .join("examples")
+        .join("proxy_child");
+    let light_is_on = Id::<Getter>::new("proxy_child_light_is_on");
+    let light_turn_on = Id::<Setter>::new("proxy_child_light_turn_on");
+
+    // Wait until `cond` holds, for at most `secs` seconds.
+    let wait_until = |secs: u64, cond: &Fn() -> bool| {
+        for _ in 0..secs * 10 {
+            if cond() {
+                return true;
+            }
+            thread::sleep(std::time::Duration::from_millis(100));
+        }
+        cond()
+    };
+
+    println!("* Spawning the reference child registers its adapter and services.");
+    let manager = Arc::new(AdapterManager::new(None));
+    let proxy = ProxyAdapter::spawn(&manager, &mut Command::new(&child_path)).unwrap();
+    assert_eq!(proxy.id(), Id::new("proxy_child_adapter"));
+    assert!(proxy.process_id().is_some());
+    assert!(wait_until(5, &|| manager.get_services(vec![ServiceSelector::new()]).len() == 1));
+
+    println!("* Values go through the pipes of the child.");
+    let sent = manager.send_values(target_map(vec![(vec![SetterSelector::new().with_id(light_turn_on.clone())], Value::OnOff(OnOff::On))]), User::None);
+    assert_matches!(sent.get(&light_turn_on), Some(&Ok(())));
+    let fetched = manager.fetch_values(vec![GetterSelector::new()], User::None);
+    assert_matches!(fetched.get(&light_is_on), Some(&Ok(Some(Value::OnOff(OnOff::On)))));
+
+    println!("* Once stopped, the child exits.");
+    proxy.stop();
+    assert!(wait_until(STOP_GRACE_SECS - 1, &|| proxy.process_id().is_none()));
+    manager.stop();
+
+    println!("* A child that keeps running once stopped is killed after a grace period.");
+    let manager = Arc::new(AdapterManager::new(None));
+    let proxy = ProxyAdapter::spawn(&manager, Command::new(&child_path).arg("--linger")).unwrap();
+    proxy.stop();
+    thread::sleep(std::time::Duration::new(1, 0));
+    assert!(proxy.process_id().is_some());
+    assert!(wait_until(STOP_GRACE_SECS + 2, &|| proxy.process_id().is_none()));
+    manager.stop();
+
+    println!("");
+}
+
+#[test]
+fn test_load_plugins() {
+    use foxbox_taxonomy::plugin::load_plugins;
+    use libc::getpid;
+    use std::env::consts::DLL_EXTENSION;
+    use std::fs;
+    use std::io::Write;
+
+    println!("");
+
+    let manager = Arc::new(AdapterManager::new(None));
+    let dir = PathBuf::from(format!("./plugins_test-{}", unsafe { getpid() }));
+    fs::create_dir_all(&dir).unwrap();
+
+    println!("* Loading a directory without plugins does nothing.");
+    let results = load_plugins(&manager, &dir).unwrap();
+    assert_eq!(results.len(), 0);
+
+    println!("* Files that are not shared libraries are ignored.");
+    fs::File::create(dir.join("README")).unwrap().write_all(b"Not a plugin").unwrap();
+    let results = load_plugins(&manager, &dir).unwrap();
+    assert_eq!(results.len(), 0);
+
+    println!("* Invalid shared libraries are reported, without adding any adapter.");
+    let bad = dir.join(format!("bad.{}", DLL_EXTENSION));
+    fs::File::create(&bad).unwrap().write_all(b"Not a shared library").unwrap();
+    let results = load_plugins(&manager, &dir).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].0, bad);
+    assert_matches!(results[0].1, Err(Error::InvalidPlugin(_)));
+    assert_eq!(manager.get_adapter_statuses().len(), 0);
+
+    println!("* Loading a directory that doesn't exist is an error.");
+    assert_matches!(load_plugins(&manager, &dir.join("nowhere")), Err(Error::InvalidPlugin(_)));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_add_service_with_channels() {
+    println!("");
+
+    let manager = AdapterManager::new(None);
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let id_2 = Id::<AdapterId>::new("adapter id 2");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let service_id_2 = Id::<ServiceId>::new("service id 2");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let getter_id_2 = Id::<Getter>::new("getter id 2");
+    let getter_id_3 = Id::<Getter>::new("getter id 3");
+    let setter_id_1 = Id::<Setter>::new("setter id 1");
+
+    let getter = |id: &Id<Getter>, service: &Id<ServiceId>, adapter: &Id<AdapterId>| Channel {
+        id: id.clone(),
+        service: service.clone(),
+        adapter: adapter.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::OnOff,
+            constraints: Constraints::default(),
+            capabilities: GetterCapabilities::default(),
+        },
+    };
+    let setter = |id: &Id<Setter>, service: &Id<ServiceId>, adapter: &Id<AdapterId>| Channel {
+        id: id.clone(),
+        service: service.clone(),
+        adapter: adapter.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            updated: None,
+            kind: ChannelKind::OnOff,
+            constraints: Constraints::default(),
+            capabilities: SetterCapabilities::default(),
+        },
+    };
+
+    manager.add_adapter(Arc::new(FakeAdapter::new(&id_1))).unwrap();
+    manager.add_adapter(Arc::new(FakeAdapter::new(&id_2))).unwrap();
+
+    let (tx_watch, rx_watch) = channel();
+    let _guard = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Never
+    )]), Box::new(tx_watch));
+
+    println!("* We can add a service along with its channels.");
+    let mut service = Service::empty(service_id_1.clone(), id_1.clone());
+    for id in vec![&getter_id_1, &getter_id_2] {
+        service.getters.insert(id.clone(), getter(id, &service_id_1, &id_1));
+    }
+    service.setters.insert(setter_id_1.clone(), setter(&setter_id_1, &service_id_1, &id_1));
+    manager.add_service_with_channels(service).unwrap();
+
+    let services = manager.get_services(vec![ServiceSelector::new()]);
+    assert_eq!(services.len(), 1);
+    assert_eq!(services[0].getters.len(), 2);
+    assert_eq!(services[0].setters.len(), 1);
+    assert_eq!(manager.get_getter_channels(vec![GetterSelector::new()]).len(), 2);
+    assert_eq!(manager.get_setter_channels(vec![SetterSelector::new()]).len(), 1);
+
+    println!("* Watchers are informed once of all the getters they start watching.");
+    match rx_watch.recv().unwrap().event {
+        Event::GettersAdded { ref service, ref getters } if *service == service_id_1 => {
+            let getters: HashSet<_> = getters.iter().cloned().collect();
+            let expected: HashSet<_> = vec![getter_id_1.clone(), getter_id_2.clone()].into_iter().collect();
+            assert_eq!(getters, expected);
+        }
+        other => panic!("Unexpected event {:?}", other)
+    }
+    thread::sleep(std::time::Duration::new(1, 0));
+    assert_matches!(rx_watch.try_recv(), Err(_));
+
+    println!("* If a channel is already registered, nothing is added.");
+    let mut service = Service::empty(service_id_2.clone(), id_1.clone());
+    service.getters.insert(getter_id_3.clone(), getter(&getter_id_3, &service_id_2, &id_1));
+    service.setters.insert(setter_id_1.clone(), setter(&setter_id_1, &service_id_2, &id_1));
+    assert_matches!(manager.add_service_with_channels(service),
+        Err(Error::InternalError(InternalError::DuplicateSetter(ref id))) if *id == setter_id_1);
+    assert_eq!(manager.get_services(vec![ServiceSelector::new()]).len(), 1);
+    assert_eq!(manager.get_getter_channels(vec![GetterSelector::new()]).len(), 2);
+    assert_eq!(manager.get_getter_channels(vec![GetterSelector::new().with_id(getter_id_3.clone())]).len(), 0);
+
+    println!("* If a channel belongs to another adapter, nothing is added.");
+    let mut service = Service::empty(service_id_2.clone(), id_1.clone());
+    service.getters.insert(getter_id_3.clone(), getter(&getter_id_3, &service_id_2, &id_2));
+    assert_matches!(manager.add_service_with_channels(service),
+        Err(Error::InternalError(InternalError::ConflictingAdapter(_, _))));
+    assert_eq!(manager.get_services(vec![ServiceSelector::new()]).len(), 1);
+
+    println!("* If the service is already registered, nothing is added.");
+    let mut service = Service::empty(service_id_1.clone(), id_1.clone());
+    service.getters.insert(getter_id_3.clone(), getter(&getter_id_3, &service_id_1, &id_1));
+    assert_matches!(manager.add_service_with_channels(service),
+        Err(Error::InternalError(InternalError::DuplicateService(_))));
+    assert_eq!(manager.get_getter_channels(vec![GetterSelector::new()]).len(), 2);
+
+    thread::sleep(std::time::Duration::new(1, 0));
+    assert_matches!(rx_watch.try_recv(), Err(_));
+}
+
+#[test]
+fn test_channel_capabilities() {
+    println!("");
+
+    let manager = AdapterManager::new(None);
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let getter_id_2 = Id::<Getter>::new("getter id 2");
+    let getter_id_3 = Id::<Getter>::new("getter id 3");
+    let setter_id_1 = Id::<Setter>::new("setter id 1");
+
+    let adapter = FakeAdapter::new(&id_1);
+    let tweak = adapter.get_tweak();
+    manager.add_adapter(Arc::new(adapter)).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+
+    let capabilities = vec![
+        (&getter_id_1, GetterCapabilities { pollable: false, .. GetterCapabilities::default() }),
+        (&getter_id_2, GetterCapabilities { requires_threshold: true, .. GetterCapabilities::default() }),
+        (&getter_id_3, GetterCapabilities { watchable: false, .. GetterCapabilities::default() }),
+    ];
+    for (id, capabilities) in capabilities {
+        manager.add_getter(Channel {
+            id: id.clone(),
+            service: service_id_1.clone(),
+            adapter: id_1.clone(),
+            last_seen: None,
+            tags: HashSet::new(),
+            mechanism: Getter {
+                updated: None,
+                kind: ChannelKind::OnOff,
+                constraints: Constraints::default(),
+                capabilities: capabilities,
+            },
+        }).unwrap();
+        tweak(Tweak::InjectGetterValue(id.clone(), Ok(Some(Value::OnOff(OnOff::On)))));
+    }
+    manager.add_setter(Channel {
+        id: setter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Setter {
+            updated: None,
+            kind: ChannelKind::OnOff,
+            constraints: Constraints::default(),
+            capabilities: SetterCapabilities { has_feedback: false },
+        },
+    }).unwrap();
+
+    println!("* Capabilities are exposed in the JSON of channels.");
+    let getters = manager.get_getter_channels(vec![GetterSelector::new().with_id(getter_id_1.clone())]);
+    assert_eq!(getters.len(), 1);
+    let mut json = getters[0].to_json();
+    let parsed = Channel::<Getter>::parse(foxbox_taxonomy::parse::Path::new(), &mut json).unwrap();
+    assert!(!parsed.mechanism.capabilities.pollable);
+    assert!(parsed.mechanism.capabilities.watchable);
+
+    println!("* Channels may be selected by capability.");
+    let selected = manager.get_getter_channels(vec![GetterSelector::new().with_pollable(false)]);
+    assert_eq!(selected.len(), 1);
+    assert_eq!(selected[0].id, getter_id_1);
+    let selected = manager.get_getter_channels(vec![GetterSelector::from_str("{\"watchable\": false}").unwrap()]);
+    assert_eq!(selected.len(), 1);
+    assert_eq!(selected[0].id, getter_id_3);
+    assert_eq!(manager.get_setter_channels(vec![SetterSelector::new().with_feedback(false)]).len(), 1);
+    assert_eq!(manager.get_setter_channels(vec![SetterSelector::new().with_feedback(true)]).len(), 0);
+
+    println!("* Getters that cannot be polled are rejected without asking the adapter.");
+    let results = manager.fetch_values(vec![GetterSelector::new()], User::None);
+    assert_eq!(results.len(), 3);
+    assert_matches!(results.get(&getter_id_1), Some(&Err(Error::GetterDoesNotSupportPolling(_))));
+    assert_matches!(results.get(&getter_id_2), Some(&Ok(Some(Value::OnOff(OnOff::On)))));
+    assert_matches!(results.get(&getter_id_3), Some(&Ok(Some(Value::OnOff(OnOff::On)))));
+
+    println!("* Getters that cannot be watched this way are rejected without asking the adapter.");
+    let (tx_watch, rx_watch) = channel();
+    let _guard = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Always
+    )]), Box::new(tx_watch));
+    thread::sleep(std::time::Duration::new(1, 0));
+    let mut refused = HashMap::new();
+    while let Ok(event) = rx_watch.try_recv() {
+        match event {
+            Event::GetterAdded(_) => {},
+            Event::InitializationError { channel, error } => {
+                refused.insert(channel, error);
+            }
+            other => panic!("Unexpected event {:?}", other)
+        }
+    }
+    assert_eq!(refused.len(), 2);
+    assert_matches!(refused.get(&getter_id_2), Some(&Error::GetterRequiresThresholdForWatching(_)));
+    assert_matches!(refused.get(&getter_id_3), Some(&Error::GetterDoesNotSupportWatching(_)));
+}
+
+#[test]
+#[allow(unused_variables)]
+fn test_migrate_ids() {
+    println!("");
+
+    // Simple RAII style struct to delete the test db.
+    struct AutoDeleteDb { };
+    impl Drop for AutoDeleteDb {
+        fn drop(&mut self) {
+            remove_test_db();
+        }
+    }
+    let auto_db = AutoDeleteDb { };
+
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let id_2 = Id::<AdapterId>::new("adapter id 2");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let service_id_2 = Id::<ServiceId>::new("service id 2");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+    let getter_id_2 = Id::<Getter>::new("getter id 2");
+    let watch_id_1 = Id::<WatchId>::new("watch id 1");
+    let tag_id_1 = Id::<TagId>::new("tag id 1");
+    let tag_id_2 = Id::<TagId>::new("tag id 2");
+
+    let celsius = |degrees| Value::Temperature(Temperature::C(degrees));
+    let getter = |id: &Id<Getter>, service: &Id<ServiceId>, adapter: &Id<AdapterId>| Channel {
+        id: id.clone(),
+        service: service.clone(),
+        adapter: adapter.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::CurrentTemperature,
+            constraints: Constraints::default(),
+            capabilities: GetterCapabilities::default(),
+        },
+    };
+    let migration = IdMigration::new()
+        .with_adapter(id_1.clone(), id_2.clone())
+        .with_service(service_id_1.clone(), service_id_2.clone())
+        .with_getter(getter_id_1.clone(), getter_id_2.clone());
+
+    println!("* Tagging channels and watching them under their former ids.");
+    {
+        let manager = AdapterManager::new(Some(get_db_environment()));
+        manager.add_adapter(Arc::new(FakeAdapter::new(&id_1))).unwrap();
+        manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+        manager.add_getter(getter(&getter_id_1, &service_id_1, &id_1)).unwrap();
+        assert_eq!(manager.add_service_tags(vec![ServiceSelector::new().with_id(service_id_1.clone())],
+            vec![tag_id_1.clone()]), 1);
+        assert_eq!(manager.add_getter_tags(vec![GetterSelector::new().with_id(getter_id_1.clone())],
+            vec![tag_id_2.clone()]), 1);
+        manager.register_durable_watch(watch_id_1.clone(), target_map(vec![(
+            vec![GetterSelector::new().with_id(getter_id_1.clone())],
+            Exactly::Exactly(Range::Geq(celsius(30.)))
+        )]), WatchOptions::default()).unwrap();
+
+        println!("* The adapter comes back with new ids.");
+        manager.remove_adapter(&id_1).unwrap();
+        manager.add_adapter(Arc::new(FakeAdapter::new(&id_2))).unwrap();
+        manager.add_service(Service::empty(service_id_2.clone(), id_2.clone())).unwrap();
+        assert_eq!(manager.get_services(vec![ServiceSelector::new().with_tags(vec![tag_id_1.clone()])]).len(), 0);
+
+        println!("* Migrating retags the live service.");
+        manager.migrate_ids(migration.clone()).unwrap();
+        let services = manager.get_services(vec![ServiceSelector::new().with_tags(vec![tag_id_1.clone()])]);
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].id, service_id_2);
+        manager.stop();
+    }
+
+    println!("* After a restart, tags and durable watches follow the new ids.");
+    {
+        let manager = AdapterManager::new(Some(get_db_environment()));
+        let adapter = FakeAdapter::new(&id_2);
+        let tweak = adapter.get_tweak();
+        manager.add_adapter(Arc::new(adapter)).unwrap();
+        manager.add_service(Service::empty(service_id_2.clone(), id_2.clone())).unwrap();
+        manager.add_getter(getter(&getter_id_2, &service_id_2, &id_2)).unwrap();
+        assert_eq!(manager.get_services(vec![ServiceSelector::new().with_tags(vec![tag_id_1.clone()])]).len(), 1);
+        let getters = manager.get_getter_channels(vec![GetterSelector::new().with_tags(vec![tag_id_2.clone()])]);
+        assert_eq!(getters.len(), 1);
+        assert_eq!(getters[0].id, getter_id_2);
+
+        let (tx, rx) = channel();
+        manager.attach_durable_watch(&watch_id_1, Box::new(tx)).unwrap();
+        assert_matches!(rx.recv().unwrap().event, Event::GetterAdded(ref id) if *id == getter_id_2);
+        tweak(Tweak::InjectGetterValue(getter_id_2.clone(), Ok(Some(celsius(35.)))));
+        assert_matches!(rx.recv().unwrap().event, Event::EnterRange { ref from, ref value }
+            if *from == getter_id_2 && *value == celsius(35.));
+
+        println!("* Migrations are applied only once.");
+        manager.migrate_ids(migration.clone()).unwrap();
+        assert_eq!(manager.get_services(vec![ServiceSelector::new().with_tags(vec![tag_id_1.clone()])]).len(), 1);
+
+        println!("* Inconsistent migrations are rejected.");
+        let chained = IdMigration::new()
+            .with_service(service_id_1.clone(), service_id_2.clone())
+            .with_service(service_id_2.clone(), Id::new("service id 3"));
+        assert_matches!(manager.migrate_ids(chained), Err(Error::InvalidMigration(_)));
+        let merged = IdMigration::new()
+            .with_getter(getter_id_1.clone(), Id::new("getter id 3"))
+            .with_getter(getter_id_2.clone(), Id::new("getter id 3"));
+        assert_matches!(manager.migrate_ids(merged), Err(Error::InvalidMigration(_)));
+        manager.stop();
+    }
+
+    println!("");
+}
+
+#[test]
+fn test_watch_shared_registration() {
+    use foxbox_taxonomy::adapter::{ Adapter, ResultMap, WatchResult, WatchTarget };
+
+    // A `FakeAdapter` that records the watches it is asked to register.
+    struct RecordingAdapter {
+        inner: FakeAdapter,
+        registrations: Arc<Mutex<Vec<Option<Range>>>>,
+    }
+    impl Adapter for RecordingAdapter {
+        fn id(&self) -> Id<AdapterId> {
+            self.inner.id()
+        }
+        fn name(&self) -> &str {
+            self.inner.name()
+        }
+        fn vendor(&self) -> &str {
+            self.inner.vendor()
+        }
+        fn version(&self) -> &[u32;4] {
+            self.inner.version()
+        }
+        fn fetch_values(&self, target: Vec<Id<Getter>>, user: User) -> ResultMap<Id<Getter>, Option<Value>, Error> {
+            self.inner.fetch_values(target, user)
+        }
+        fn send_values(&self, values: HashMap<Id<Setter>, Value>, user: User) -> ResultMap<Id<Setter>, (), Error> {
+            self.inner.send_values(values, user)
+        }
+        fn register_watch(&self, watch: Vec<WatchTarget>) -> WatchResult {
+            {
+                let mut registrations = self.registrations.lock().unwrap();
+                for &(_, ref range, _) in &watch {
+                    registrations.push(range.clone());
+                }
+            }
+            self.inner.register_watch(watch)
+        }
+    }
+
+    println!("");
+
+    let manager = AdapterManager::new(None);
+    let id_1 = Id::<AdapterId>::new("adapter id 1");
+    let service_id_1 = Id::<ServiceId>::new("service id 1");
+    let getter_id_1 = Id::<Getter>::new("getter id 1");
+
+    let celsius = |degrees| Value::Temperature(Temperature::C(degrees));
+    let hot = Range::Geq(celsius(30.));
+
+    let adapter = RecordingAdapter {
+        inner: FakeAdapter::new(&id_1),
+        registrations: Arc::new(Mutex::new(vec![])),
+    };
+    let tweak = adapter.inner.get_tweak();
+    let registrations = adapter.registrations.clone();
+    manager.add_adapter(Arc::new(adapter)).unwrap();
+    manager.add_service(Service::empty(service_id_1.clone(), id_1.clone())).unwrap();
+    manager.add_getter(Channel {
+        id: getter_id_1.clone(),
+        service: service_id_1.clone(),
+        adapter: id_1.clone(),
+        last_seen: None,
+        tags: HashSet::new(),
+        mechanism: Getter {
+            updated: None,
+            kind: ChannelKind::OvenTemperature,
+            constraints: Constraints::default(),
+            capabilities: GetterCapabilities::default(),
+        },
+    }).unwrap();
+
+    println!("* Watchers with the same range share a single registration.");
+    let (tx_hot_1, rx_hot_1) = channel();
+    let _guard_hot_1 = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Exactly(hot.clone())
+    )]), Box::new(tx_hot_1));
+    let (tx_hot_2, rx_hot_2) = channel();
+    let _guard_hot_2 = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Exactly(hot.clone())
+    )]), Box::new(tx_hot_2));
+    assert_eq!(*registrations.lock().unwrap(), vec![Some(hot.clone())]);
+
+    println!("* A watcher that needs every value widens the registration.");
+    let (tx_all, rx_all) = channel();
+    let guard_all = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Always
+    )]), Box::new(tx_all));
+    assert_eq!(*registrations.lock().unwrap(), vec![Some(hot.clone()), None]);
+
+    println!("* Each watcher receives the events for its own range.");
+    for value in vec![35., 20.] {
+        tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(celsius(value)))));
+    }
+    for rx in vec![&rx_hot_1, &rx_hot_2] {
+        for expected in vec![(true, 35.), (false, 20.)] {
+            match (expected, rx.recv().unwrap()) {
+                ((true, expected), Event::EnterRange { value: Value::Temperature(Temperature::C(value)), .. }) |
+                ((false, expected), Event::ExitRange { value: Value::Temperature(Temperature::C(value)), .. })
+                    if value == expected => {},
+                (_, other) => panic!("Unexpected event {:?}", other)
+            }
+        }
+    }
+    for expected in vec![35., 20.] {
+        match rx_all.recv().unwrap() {
+            Event::EnterRange { value: Value::Temperature(Temperature::C(value)), .. } if value == expected => {},
+            other => panic!("Unexpected event {:?}", other)
+        }
+    }
+
+    println!("* Once the watcher is gone, the registration is narrowed again.");
+    drop(guard_all);
+    for _ in 0..100 {
+        if registrations.lock().unwrap().len() == 3 {
+            break;
+        }
+        thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert_eq!(*registrations.lock().unwrap(), vec![Some(hot.clone()), None, Some(hot.clone())]);
+
+    tweak(Tweak::InjectGetterValue(getter_id_1.clone(), Ok(Some(celsius(45.)))));
+    for rx in vec![&rx_hot_1, &rx_hot_2] {
+        match rx.recv().unwrap() {
+            Event::EnterRange { value: Value::Temperature(Temperature::C(value)), .. } if value == 45. => {},
+            other => panic!("Unexpected event {:?}", other)
+        }
+    }
+
+    println!("* A watcher with an equivalent range shares the registration.");
+    let (tx_hot_3, _rx_hot_3) = channel();
+    let _guard_hot_3 = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Exactly(Range::Geq(Value::Temperature(Temperature::F(86.))))
+    )]), Box::new(tx_hot_3));
+    assert_eq!(registrations.lock().unwrap().len(), 3);
+
+    println!("* A watcher with a narrower range is covered by the registration.");
+    let (tx_hotter, _rx_hotter) = channel();
+    let _guard_hotter = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Exactly(Range::Geq(celsius(40.)))
+    )]), Box::new(tx_hotter));
+    assert_eq!(registrations.lock().unwrap().len(), 3);
+
+    println!("* A watcher with a wider range widens the registration to the union of the ranges.");
+    let warm = Range::Geq(celsius(20.));
+    let (tx_warm, _rx_warm) = channel();
+    let _guard_warm = manager.watch_values(target_map(vec![(
+        vec![GetterSelector::new()],
+        Exactly::Exactly(warm.clone())
+    )]), Box::new(tx_warm));
+    assert_eq!(*registrations.lock().unwrap(), vec![Some(hot.clone()), None, Some(hot.clone()), Some(warm.clone())]);
+
+    println!("* Make sure that we haven't forgotten to eat a message.");
+    thread::sleep(std::time::Duration::new(1, 0));
+    assert_matches!(rx_hot_1.try_recv(), Err(_));
+    assert_matches!(rx_hot_2.try_recv(), Err(_));
+    assert_matches!(rx_all.try_recv(), Err(_));
+
+    println!("");
+}
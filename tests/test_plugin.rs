@@ -0,0 +1,117 @@
+#![cfg(unix)]
+
+// Plugins are built as part of these tests, which is slow, so they live in their own binary.
+extern crate foxbox_taxonomy;
+extern crate libc;
+#[macro_use]
+extern crate assert_matches;
+
+use foxbox_taxonomy::api::{ API, Error };
+use foxbox_taxonomy::manager::*;
+use foxbox_taxonomy::plugin::load_plugin;
+use foxbox_taxonomy::selector::*;
+use foxbox_taxonomy::services::*;
+
+use libc::getpid;
+
+use std::env::consts::DLL_EXTENSION;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::process::Command;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Build the fixture plugin of `tests/fixtures/plugin` in `target/`, then copy it to `dest`.
+fn build_plugin(features: &[&str], dest: &Path) {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let target_dir = root.join("target").join("plugin_fixture");
+    let mut command = Command::new("cargo");
+    command.arg("build")
+        .arg("--manifest-path").arg(root.join("tests/fixtures/plugin/Cargo.toml"))
+        .env("CARGO_TARGET_DIR", &target_dir);
+    if !features.is_empty() {
+        command.arg("--features").arg(features.join(" "));
+    }
+    let status = command.status().unwrap();
+    assert!(status.success(), "Could not build the fixture plugin");
+    let built = target_dir.join("debug").join(format!("libtaxonomy_test_plugin.{}", DLL_EXTENSION));
+    fs::copy(&built, dest).unwrap();
+}
+
+/// `true` if the shared library at `path` is currently loaded.
+fn is_loaded(path: &Path) -> bool {
+    let path = fs::canonicalize(path).unwrap();
+    let mut maps = String::new();
+    fs::File::open("/proc/self/maps").unwrap().read_to_string(&mut maps).unwrap();
+    maps.contains(&*path.to_string_lossy())
+}
+
+/// Wait until the shared library at `path` has been unloaded, which happens on the thread of
+/// the plugin.
+fn wait_unloaded(path: &Path) -> bool {
+    for _ in 0..100 {
+        if !is_loaded(path) {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    false
+}
+
+#[test]
+fn test_plugin_fixture() {
+    println!("");
+
+    let manager = Arc::new(AdapterManager::new(None));
+    let dir = std::env::temp_dir().join(format!("foxbox_taxonomy_plugin_test-{}", unsafe { getpid() }));
+    fs::create_dir_all(&dir).unwrap();
+
+    println!("* Building the fixture plugin.");
+    let good = dir.join(format!("good.{}", DLL_EXTENSION));
+    let wrong_version = dir.join(format!("wrong_version.{}", DLL_EXTENSION));
+    build_plugin(&[], &good);
+    build_plugin(&["wrong_version"], &wrong_version);
+
+    println!("* A plugin built against another version of the taxonomy is rejected and unloaded.");
+    assert_matches!(load_plugin(&manager, &wrong_version), Err(Error::InvalidPlugin(ref msg)) if msg.contains("0.0.0"));
+    assert_eq!(manager.get_adapter_statuses().len(), 0);
+    assert!(!is_loaded(&wrong_version));
+
+    println!("* The entry point adds the adapter, then registers its services.");
+    let id = load_plugin(&manager, &good).unwrap();
+    assert_eq!(id, Id::new("test_plugin_adapter"));
+    let statuses = manager.get_adapter_statuses();
+    assert_eq!(statuses.len(), 1);
+    assert_eq!(statuses[0].adapter, id);
+    // Registrations are applied on a thread of their own.
+    let mut services = vec![];
+    for _ in 0..100 {
+        services = manager.get_services(vec![ServiceSelector::new()]);
+        if !services.is_empty() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    assert_eq!(services.len(), 1);
+    assert_eq!(services[0].id, Id::new("test_plugin_service"));
+    assert_eq!(services[0].adapter, id);
+    assert!(is_loaded(&good));
+
+    println!("* A plugin whose adapter is rejected is unloaded.");
+    // The adapter is already present, so the manager rejects the second instance.
+    let again = dir.join(format!("again.{}", DLL_EXTENSION));
+    fs::copy(&good, &again).unwrap();
+    assert_matches!(load_plugin(&manager, &again), Err(_));
+    assert_eq!(manager.get_adapter_statuses().len(), 1);
+    assert!(!is_loaded(&again));
+
+    println!("* The plugin is unloaded once its adapter is removed.");
+    manager.remove_adapter(&id).unwrap();
+    assert_eq!(manager.get_services(vec![ServiceSelector::new()]).len(), 0);
+    assert!(wait_unloaded(&good));
+
+    manager.stop();
+    fs::remove_dir_all(&dir).unwrap();
+}